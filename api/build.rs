@@ -10,7 +10,7 @@ fn set_build_id() {
     // Get the current git commit hash for the build id
     // If the command fails, generate a random string
     let build_id = Command::new("git")
-        .args(&["rev-parse", "HEAD"])
+        .args(["rev-parse", "HEAD"])
         .output()
         .map(|output| String::from_utf8(output.stdout).ok())
         .ok()
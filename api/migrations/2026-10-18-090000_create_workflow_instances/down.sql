@@ -0,0 +1,18 @@
+/*
+|-------------------------------------------------------------------------------
+| Drop Workflow Instance Tables
+|-------------------------------------------------------------------------------
+|
+| This migration drops the workflow instance and instance task tables.
+|
+| @date 2026-10-18
+|
+*/
+
+-- Drop workflow instance tasks table
+DROP TRIGGER IF EXISTS update_workflow_instance_tasks_updated_at ON workflow_instance_tasks;
+DROP TABLE IF EXISTS workflow_instance_tasks;
+
+-- Drop workflow instances table
+DROP TRIGGER IF EXISTS update_workflow_instances_updated_at ON workflow_instances;
+DROP TABLE IF EXISTS workflow_instances;
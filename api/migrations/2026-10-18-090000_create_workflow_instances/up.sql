@@ -0,0 +1,62 @@
+/*
+|-------------------------------------------------------------------------------
+| Create Workflow Instance Tables
+|-------------------------------------------------------------------------------
+|
+| This migration creates the workflow instance and instance task tables. An
+| instance is a single run of a workflow, and a task is opened each time the
+| instance enters a state so that assignment can be tracked per state.
+|
+| @date 2026-10-18
+|
+*/
+
+-- Create workflow instances table
+CREATE TABLE workflow_instances (
+    id BIGSERIAL PRIMARY KEY,
+    tenant_id INT NOT NULL REFERENCES tenants(id) ON DELETE CASCADE,
+    workflow_id BIGINT NOT NULL REFERENCES workflows(id) ON DELETE CASCADE,
+    current_state_id UUID NOT NULL,
+    data JSONB NOT NULL DEFAULT '{}',
+    created_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    assignee_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMP WITH TIME ZONE,
+    deleted_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX workflow_instances_workflow_id_idx ON workflow_instances (workflow_id);
+CREATE INDEX workflow_instances_assignee_id_idx ON workflow_instances (assignee_id);
+
+-- Track updated_at column
+CREATE TRIGGER update_workflow_instances_updated_at
+  BEFORE UPDATE
+  ON
+    workflow_instances
+  FOR EACH ROW
+EXECUTE PROCEDURE track_updated_at();
+
+-- Create workflow instance tasks table
+CREATE TABLE workflow_instance_tasks (
+    id BIGSERIAL PRIMARY KEY,
+    instance_id BIGINT NOT NULL REFERENCES workflow_instances(id) ON DELETE CASCADE,
+    state_id UUID NOT NULL,
+    assignee_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    completed_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    option_id UUID,
+    comment TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX workflow_instance_tasks_instance_id_idx ON workflow_instance_tasks (instance_id);
+
+-- Track updated_at column
+CREATE TRIGGER update_workflow_instance_tasks_updated_at
+  BEFORE UPDATE
+  ON
+    workflow_instance_tasks
+  FOR EACH ROW
+EXECUTE PROCEDURE track_updated_at();
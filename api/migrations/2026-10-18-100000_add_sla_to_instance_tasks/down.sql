@@ -0,0 +1,17 @@
+/*
+|-------------------------------------------------------------------------------
+| Remove SLA Tracking from Instance Tasks
+|-------------------------------------------------------------------------------
+|
+| This migration drops the escalations table and the due date and next
+| escalation of instance tasks.
+|
+| @date 2026-10-18
+|
+*/
+
+DROP TABLE IF EXISTS workflow_instance_escalations;
+
+ALTER TABLE workflow_instance_tasks
+    DROP COLUMN next_escalation_at,
+    DROP COLUMN due_at;
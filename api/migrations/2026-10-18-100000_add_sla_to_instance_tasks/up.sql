@@ -0,0 +1,32 @@
+/*
+|-------------------------------------------------------------------------------
+| Add SLA Tracking to Instance Tasks
+|-------------------------------------------------------------------------------
+|
+| This migration adds a due date to instance tasks, along with when their next
+| escalation is due so the timer only looks at tasks with an escalation left to
+| fire, and a table recording which escalations have already fired for a task,
+| so the timer only fires each escalation once.
+|
+| @date 2026-10-18
+|
+*/
+
+ALTER TABLE workflow_instance_tasks
+    ADD COLUMN due_at TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN next_escalation_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX workflow_instance_tasks_next_escalation_at_idx
+    ON workflow_instance_tasks (next_escalation_at)
+    WHERE completed_at IS NULL;
+
+-- Create workflow instance escalations table
+CREATE TABLE workflow_instance_escalations (
+    id BIGSERIAL PRIMARY KEY,
+    task_id BIGINT NOT NULL REFERENCES workflow_instance_tasks(id) ON DELETE CASCADE,
+    escalation_id UUID NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX workflow_instance_escalations_unique
+    ON workflow_instance_escalations (task_id, escalation_id);
@@ -0,0 +1,16 @@
+/*
+|-------------------------------------------------------------------------------
+| Drop Workflow Instance Tokens Table
+|-------------------------------------------------------------------------------
+|
+| This migration detaches instance tasks from their tokens and drops the
+| instance tokens table.
+|
+| @date 2026-10-18
+|
+*/
+
+ALTER TABLE workflow_instance_tasks
+    DROP COLUMN token_id;
+
+DROP TABLE IF EXISTS workflow_instance_tokens;
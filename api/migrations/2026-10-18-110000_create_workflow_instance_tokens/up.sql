@@ -0,0 +1,55 @@
+/*
+|-------------------------------------------------------------------------------
+| Create Workflow Instance Tokens Table
+|-------------------------------------------------------------------------------
+|
+| This migration creates the instance tokens table. A token marks a position
+| of an instance in its workflow; an instance starts with a single root token
+| and a fork spawns one child token per branch, which are merged back into
+| their parent at the join. Every existing instance is given a root token and
+| its tasks are attached to it.
+|
+| @date 2026-10-18
+|
+*/
+
+-- Create workflow instance tokens table
+CREATE TABLE workflow_instance_tokens (
+    id BIGSERIAL PRIMARY KEY,
+    instance_id BIGINT NOT NULL REFERENCES workflow_instances(id) ON DELETE CASCADE,
+    parent_id BIGINT REFERENCES workflow_instance_tokens(id) ON DELETE CASCADE,
+    state_id UUID NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX workflow_instance_tokens_instance_id_idx ON workflow_instance_tokens (instance_id);
+CREATE INDEX workflow_instance_tokens_parent_id_idx ON workflow_instance_tokens (parent_id);
+
+-- Track updated_at column
+CREATE TRIGGER update_workflow_instance_tokens_updated_at
+  BEFORE UPDATE
+  ON
+    workflow_instance_tokens
+  FOR EACH ROW
+EXECUTE PROCEDURE track_updated_at();
+
+-- Give every existing instance its root token
+INSERT INTO workflow_instance_tokens (instance_id, state_id, created_at, completed_at)
+SELECT id, current_state_id, created_at, completed_at
+FROM workflow_instances;
+
+-- Attach tasks to the token that opened them
+ALTER TABLE workflow_instance_tasks
+    ADD COLUMN token_id BIGINT REFERENCES workflow_instance_tokens(id) ON DELETE CASCADE;
+
+UPDATE workflow_instance_tasks
+SET token_id = workflow_instance_tokens.id
+FROM workflow_instance_tokens
+WHERE workflow_instance_tokens.instance_id = workflow_instance_tasks.instance_id;
+
+ALTER TABLE workflow_instance_tasks
+    ALTER COLUMN token_id SET NOT NULL;
+
+CREATE INDEX workflow_instance_tasks_token_id_idx ON workflow_instance_tasks (token_id);
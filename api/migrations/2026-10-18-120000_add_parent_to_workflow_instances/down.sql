@@ -0,0 +1,15 @@
+/*
+|-------------------------------------------------------------------------------
+| Remove Parent from Workflow Instances
+|-------------------------------------------------------------------------------
+|
+| This migration drops the link between child instances and the instance that
+| started them.
+|
+| @date 2026-10-18
+|
+*/
+
+ALTER TABLE workflow_instances
+    DROP COLUMN parent_token_id,
+    DROP COLUMN parent_id;
@@ -0,0 +1,18 @@
+/*
+|-------------------------------------------------------------------------------
+| Add Parent to Workflow Instances
+|-------------------------------------------------------------------------------
+|
+| This migration links instances started by a sub-workflow state to the
+| instance and token that started them, so the parent can be resumed once the
+| child instance completes.
+|
+| @date 2026-10-18
+|
+*/
+
+ALTER TABLE workflow_instances
+    ADD COLUMN parent_id BIGINT REFERENCES workflow_instances(id) ON DELETE SET NULL,
+    ADD COLUMN parent_token_id BIGINT REFERENCES workflow_instance_tokens(id) ON DELETE SET NULL;
+
+CREATE INDEX workflow_instances_parent_id_idx ON workflow_instances (parent_id);
@@ -0,0 +1,13 @@
+/*
+|-------------------------------------------------------------------------------
+| Drop Webhook Tables
+|-------------------------------------------------------------------------------
+|
+| This migration drops the webhook delivery and subscription tables.
+|
+| @date 2026-10-18
+|
+*/
+
+DROP TABLE IF EXISTS webhook_deliveries;
+DROP TABLE IF EXISTS webhook_subscriptions;
@@ -0,0 +1,65 @@
+/*
+|-------------------------------------------------------------------------------
+| Create Webhook Tables
+|-------------------------------------------------------------------------------
+|
+| This migration creates the webhook subscription and delivery tables. A
+| subscription receives the events of its tenant it is filtered to, and each
+| event is written to the deliveries table in the same transaction as the
+| change that raised it. The dispatcher works through the pending deliveries,
+| retrying failed ones with backoff until they are dead-lettered.
+|
+| @date 2026-10-18
+|
+*/
+
+-- Create webhook subscriptions table
+CREATE TABLE webhook_subscriptions (
+    id BIGSERIAL PRIMARY KEY,
+    tenant_id INT NOT NULL REFERENCES tenants(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    events TEXT[] NOT NULL DEFAULT '{}',
+    secret VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    deleted_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX webhook_subscriptions_tenant_id_idx ON webhook_subscriptions (tenant_id);
+
+-- Track updated_at column
+CREATE TRIGGER update_webhook_subscriptions_updated_at
+  BEFORE UPDATE
+  ON
+    webhook_subscriptions
+  FOR EACH ROW
+EXECUTE PROCEDURE track_updated_at();
+
+-- Create webhook deliveries table
+CREATE TABLE webhook_deliveries (
+    id BIGSERIAL PRIMARY KEY,
+    subscription_id BIGINT NOT NULL REFERENCES webhook_subscriptions(id) ON DELETE CASCADE,
+    event VARCHAR(100) NOT NULL,
+    payload JSONB NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'Pending',
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    response_status INT,
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX webhook_deliveries_subscription_id_idx ON webhook_deliveries (subscription_id);
+CREATE INDEX webhook_deliveries_next_attempt_at_idx
+    ON webhook_deliveries (next_attempt_at)
+    WHERE status = 'Pending';
+
+-- Track updated_at column
+CREATE TRIGGER update_webhook_deliveries_updated_at
+  BEFORE UPDATE
+  ON
+    webhook_deliveries
+  FOR EACH ROW
+EXECUTE PROCEDURE track_updated_at();
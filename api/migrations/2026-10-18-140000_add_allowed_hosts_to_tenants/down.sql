@@ -0,0 +1,13 @@
+/*
+|-------------------------------------------------------------------------------
+| Remove Allowed Hosts from Tenants
+|-------------------------------------------------------------------------------
+|
+| This migration drops the hosts allowed for HTTP actions from tenants.
+|
+| @date 2026-10-18
+|
+*/
+
+ALTER TABLE tenants
+    DROP COLUMN allowed_hosts;
@@ -0,0 +1,15 @@
+/*
+|-------------------------------------------------------------------------------
+| Add Allowed Hosts to Tenants
+|-------------------------------------------------------------------------------
+|
+| This migration adds the list of hosts the HTTP actions of a tenant's
+| workflows may call. Tenants start with an empty list, which blocks every
+| call until hosts are explicitly allowed.
+|
+| @date 2026-10-18
+|
+*/
+
+ALTER TABLE tenants
+    ADD COLUMN allowed_hosts TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,12 @@
+/*
+|-------------------------------------------------------------------------------
+| Drop Workflow Instance Calls Table
+|-------------------------------------------------------------------------------
+|
+| This migration drops the instance calls table.
+|
+| @date 2026-10-18
+|
+*/
+
+DROP TABLE IF EXISTS workflow_instance_calls;
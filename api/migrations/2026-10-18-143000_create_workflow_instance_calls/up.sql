@@ -0,0 +1,48 @@
+/*
+|-------------------------------------------------------------------------------
+| Create Workflow Instance Calls Table
+|-------------------------------------------------------------------------------
+|
+| This migration creates the instance calls table. A token that reaches an
+| HTTP action waits on a call, which is made once the transaction that queued
+| it has committed. The call records where the token was going when it left
+| its state, so the instance can be moved on in a second transaction once the
+| endpoint has responded.
+|
+| @date 2026-10-18
+|
+*/
+
+-- Create workflow instance calls table
+CREATE TABLE workflow_instance_calls (
+    id BIGSERIAL PRIMARY KEY,
+    instance_id BIGINT NOT NULL REFERENCES workflow_instances(id) ON DELETE CASCADE,
+    token_id BIGINT NOT NULL REFERENCES workflow_instance_tokens(id) ON DELETE CASCADE,
+    state_id UUID NOT NULL,
+    action_id UUID NOT NULL,
+    exiting BOOLEAN NOT NULL,
+    target_state_id UUID,
+    completed_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    option_id UUID,
+    comment TEXT,
+    status VARCHAR(20) NOT NULL DEFAULT 'Pending',
+    error TEXT,
+    locked_until TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX workflow_instance_calls_instance_id_idx ON workflow_instance_calls (instance_id);
+CREATE INDEX workflow_instance_calls_token_id_idx ON workflow_instance_calls (token_id);
+CREATE INDEX workflow_instance_calls_locked_until_idx
+    ON workflow_instance_calls (locked_until)
+    WHERE status = 'Pending';
+
+-- Track updated_at column
+CREATE TRIGGER update_workflow_instance_calls_updated_at
+  BEFORE UPDATE
+  ON
+    workflow_instance_calls
+  FOR EACH ROW
+EXECUTE PROCEDURE track_updated_at();
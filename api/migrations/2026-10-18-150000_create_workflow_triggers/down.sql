@@ -0,0 +1,13 @@
+/*
+|-------------------------------------------------------------------------------
+| Drop Workflow Trigger Tables
+|-------------------------------------------------------------------------------
+|
+| This migration drops the idempotency key and workflow trigger tables.
+|
+| @date 2026-10-18
+|
+*/
+
+DROP TABLE IF EXISTS idempotency_keys;
+DROP TABLE IF EXISTS workflow_triggers;
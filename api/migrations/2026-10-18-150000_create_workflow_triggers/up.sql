@@ -0,0 +1,54 @@
+/*
+|-------------------------------------------------------------------------------
+| Create Workflow Trigger Tables
+|-------------------------------------------------------------------------------
+|
+| This migration creates the inbound trigger and idempotency key tables. A
+| trigger gives external systems a URL, identified by an unguessable token, to
+| start instances of a workflow or to resume instances waiting in one of its
+| states. Requests are authenticated with the trigger's secret, either as an
+| HMAC signature or as an API key. Idempotency keys record the response to a
+| request so that retries of it are answered without repeating its effects.
+|
+| @date 2026-10-18
+|
+*/
+
+-- Create workflow triggers table
+CREATE TABLE workflow_triggers (
+    id BIGSERIAL PRIMARY KEY,
+    workflow_id BIGINT NOT NULL REFERENCES workflows(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    state_id UUID,
+    transition_id UUID,
+    token VARCHAR(64) NOT NULL UNIQUE,
+    secret VARCHAR(255) NOT NULL,
+    auth VARCHAR(20) NOT NULL DEFAULT 'Hmac',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    deleted_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX workflow_triggers_workflow_id_idx ON workflow_triggers (workflow_id);
+
+-- Track updated_at column
+CREATE TRIGGER update_workflow_triggers_updated_at
+  BEFORE UPDATE
+  ON
+    workflow_triggers
+  FOR EACH ROW
+EXECUTE PROCEDURE track_updated_at();
+
+-- Create idempotency keys table
+CREATE TABLE idempotency_keys (
+    id BIGSERIAL PRIMARY KEY,
+    scope VARCHAR(255) NOT NULL,
+    key VARCHAR(255) NOT NULL,
+    fingerprint VARCHAR(64) NOT NULL,
+    response_status INT,
+    response_body JSONB,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (scope, key)
+);
+
+CREATE INDEX idempotency_keys_created_at_idx ON idempotency_keys (created_at);
@@ -0,0 +1,15 @@
+/*
+|-------------------------------------------------------------------------------
+| Remove the Lease and Response Headers from Idempotency Keys
+|-------------------------------------------------------------------------------
+|
+| This migration drops the lease and the response headers of idempotency
+| keys.
+|
+| @date 2026-10-18
+|
+*/
+
+ALTER TABLE idempotency_keys
+    DROP COLUMN response_headers,
+    DROP COLUMN locked_until;
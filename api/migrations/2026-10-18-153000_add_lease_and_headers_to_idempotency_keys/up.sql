@@ -0,0 +1,23 @@
+/*
+|-------------------------------------------------------------------------------
+| Add a Lease and Response Headers to Idempotency Keys
+|-------------------------------------------------------------------------------
+|
+| This migration records until when a reserved idempotency key is held by the
+| request it was reserved for, so that a key whose request never completed,
+| such as one whose server went away, can be reserved again once the lease has
+| run out. It also records the headers of the stored response, which are sent
+| again along with it. Keys still in progress are given a lease from when they
+| were reserved.
+|
+| @date 2026-10-18
+|
+*/
+
+ALTER TABLE idempotency_keys
+    ADD COLUMN locked_until TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN response_headers JSONB;
+
+UPDATE idempotency_keys
+SET locked_until = created_at + INTERVAL '5 minutes'
+WHERE response_status IS NULL;
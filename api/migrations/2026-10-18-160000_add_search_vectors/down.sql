@@ -0,0 +1,14 @@
+/*
+|-------------------------------------------------------------------------------
+| Drop Search Vectors
+|-------------------------------------------------------------------------------
+|
+| This migration drops the full-text search vectors and their indexes.
+|
+| @date 2026-10-18
+|
+*/
+
+ALTER TABLE users DROP COLUMN IF EXISTS search_vector;
+ALTER TABLE workflow_instances DROP COLUMN IF EXISTS search_vector;
+ALTER TABLE workflows DROP COLUMN IF EXISTS search_vector;
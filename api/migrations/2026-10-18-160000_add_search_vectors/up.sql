@@ -0,0 +1,39 @@
+/*
+|-------------------------------------------------------------------------------
+| Add Search Vectors
+|-------------------------------------------------------------------------------
+|
+| This migration adds generated full-text search vectors, indexed with GIN, to
+| workflows, workflow instances and users. Workflow names and descriptions use
+| the English configuration so words are stemmed, while instance data, user
+| names and emails use the simple configuration since they hold identifiers
+| and proper names. Email addresses are also indexed split on their `@` and
+| dots, so they match on the mailbox or domain alone.
+|
+| @date 2026-10-18
+|
+*/
+
+ALTER TABLE workflows
+    ADD COLUMN search_vector TSVECTOR NOT NULL GENERATED ALWAYS AS (
+        setweight(to_tsvector('english', name), 'A') ||
+        setweight(to_tsvector('english', coalesce(description, '')), 'B')
+    ) STORED;
+
+CREATE INDEX workflows_search_vector_idx ON workflows USING GIN (search_vector);
+
+ALTER TABLE workflow_instances
+    ADD COLUMN search_vector TSVECTOR NOT NULL GENERATED ALWAYS AS (
+        jsonb_to_tsvector('simple', data, '["string", "numeric"]')
+    ) STORED;
+
+CREATE INDEX workflow_instances_search_vector_idx ON workflow_instances USING GIN (search_vector);
+
+ALTER TABLE users
+    ADD COLUMN search_vector TSVECTOR NOT NULL GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', coalesce(name, '')), 'A') ||
+        setweight(to_tsvector('simple', email), 'A') ||
+        setweight(to_tsvector('simple', translate(email, '@.', '  ')), 'B')
+    ) STORED;
+
+CREATE INDEX users_search_vector_idx ON users USING GIN (search_vector);
@@ -0,0 +1,12 @@
+/*
+|-------------------------------------------------------------------------------
+| Drop Workflow Versions Table
+|-------------------------------------------------------------------------------
+|
+| This migration drops the workflow versions table.
+|
+| @date 2026-10-18
+|
+*/
+
+DROP TABLE IF EXISTS workflow_versions;
@@ -0,0 +1,26 @@
+/*
+|-------------------------------------------------------------------------------
+| Create Workflow Versions Table
+|-------------------------------------------------------------------------------
+|
+| This migration creates the workflow versions table, which keeps every
+| definition a workflow has had so that changes to it can be reviewed. The
+| current definition of each existing workflow becomes its first version.
+|
+| @date 2026-10-18
+|
+*/
+
+-- Create workflow versions table
+CREATE TABLE workflow_versions (
+    id BIGSERIAL PRIMARY KEY,
+    workflow_id BIGINT NOT NULL REFERENCES workflows(id) ON DELETE CASCADE,
+    version INT NOT NULL,
+    definition JSONB NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (workflow_id, version)
+);
+
+-- Record the current definitions
+INSERT INTO workflow_versions (workflow_id, version, definition, created_at)
+SELECT id, 1, definition, updated_at FROM workflows;
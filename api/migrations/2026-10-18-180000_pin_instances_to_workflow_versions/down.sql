@@ -0,0 +1,18 @@
+/*
+|-------------------------------------------------------------------------------
+| Unpin Workflow Instances from Workflow Versions
+|-------------------------------------------------------------------------------
+|
+| This migration drops the instance migrations table and the version each
+| instance runs, so instances run the current definition of their workflow
+| again.
+|
+| @date 2026-10-18
+|
+*/
+
+-- Drop the workflow instance migrations table
+DROP TABLE IF EXISTS workflow_instance_migrations;
+
+-- Drop the version each instance runs
+ALTER TABLE workflow_instances DROP COLUMN IF EXISTS workflow_version;
@@ -0,0 +1,43 @@
+/*
+|-------------------------------------------------------------------------------
+| Pin Workflow Instances to Workflow Versions
+|-------------------------------------------------------------------------------
+|
+| This migration records the version of the workflow each instance runs, so
+| that changing a workflow no longer changes the definition of the instances
+| already running it. Existing instances run the latest version. Moving an
+| instance to another version is recorded in the instance migrations table,
+| one row for every token moved.
+|
+| @date 2026-10-18
+|
+*/
+
+-- Pin instances to the latest version of their workflow
+ALTER TABLE workflow_instances ADD COLUMN workflow_version INT;
+
+UPDATE workflow_instances i
+SET workflow_version = (
+    SELECT MAX(v.version) FROM workflow_versions v WHERE v.workflow_id = i.workflow_id
+);
+
+ALTER TABLE workflow_instances
+    ALTER COLUMN workflow_version SET NOT NULL,
+    ADD FOREIGN KEY (workflow_id, workflow_version)
+        REFERENCES workflow_versions(workflow_id, version);
+
+-- Create workflow instance migrations table
+CREATE TABLE workflow_instance_migrations (
+    id BIGSERIAL PRIMARY KEY,
+    instance_id BIGINT NOT NULL REFERENCES workflow_instances(id) ON DELETE CASCADE,
+    token_id BIGINT NOT NULL REFERENCES workflow_instance_tokens(id) ON DELETE CASCADE,
+    from_version INT NOT NULL,
+    to_version INT NOT NULL,
+    from_state_id UUID NOT NULL,
+    to_state_id UUID NOT NULL,
+    migrated_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX workflow_instance_migrations_instance_id_idx
+    ON workflow_instance_migrations (instance_id);
@@ -0,0 +1,17 @@
+/*
+|-------------------------------------------------------------------------------
+| Drop Workflow Templates Table
+|-------------------------------------------------------------------------------
+|
+| This migration drops the workflow templates table. Workflows instantiated
+| from templates are kept.
+|
+| @date 2026-10-18
+|
+*/
+
+-- Drop the trigger on the workflow templates table
+DROP TRIGGER IF EXISTS update_workflow_templates_updated_at ON workflow_templates;
+
+-- Drop the workflow templates table
+DROP TABLE IF EXISTS workflow_templates;
@@ -0,0 +1,31 @@
+/*
+|-------------------------------------------------------------------------------
+| Create Workflow Templates Table
+|-------------------------------------------------------------------------------
+|
+| This migration creates the workflow templates table, a catalogue of
+| definitions shared by every tenant. A tenant starts a workflow from a
+| template by instantiating it, which copies the definition with new ids.
+|
+| @date 2026-10-18
+|
+*/
+
+-- Create workflow templates table
+CREATE TABLE workflow_templates (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    definition JSONB NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    deleted_at TIMESTAMP WITH TIME ZONE
+);
+
+-- Track updated_at column
+CREATE TRIGGER update_workflow_templates_updated_at
+  BEFORE UPDATE
+  ON
+    workflow_templates
+  FOR EACH ROW
+EXECUTE PROCEDURE track_updated_at();
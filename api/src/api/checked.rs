@@ -0,0 +1,45 @@
+//! A JSON body checked against the published schema of its type before it is
+//! read, so that a client sending an invalid body is told where each error is
+//! rather than only the first serde could not get past.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use actix_web::dev::Payload;
+use actix_web::web::Json;
+use actix_web::{FromRequest, HttpRequest};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::json_schema;
+use crate::result::AppError;
+
+pub struct Checked<T>(pub T);
+
+impl<T: DeserializeOwned + JsonSchema> Checked<T> {
+    /// Checks a body that was read some other way, such as a YAML document,
+    /// once it is held as JSON.
+    pub fn from_value(value: Value) -> Result<Self, AppError> {
+        json_schema::check::<T>(&value)?;
+
+        serde_json::from_value(value)
+            .map(Checked)
+            .map_err(AppError::validation_error)
+    }
+}
+
+impl<T: DeserializeOwned + JsonSchema + 'static> FromRequest for Checked<T> {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let json = Json::<Value>::from_request(req, payload);
+
+        Box::pin(async move {
+            let Json(value) = json.await?;
+
+            Ok(Self::from_value(value)?)
+        })
+    }
+}
@@ -0,0 +1,38 @@
+//! Optimistic concurrency for resources that are edited in place. Responses
+//! carry an `ETag` derived from the resource's `updated_at`, and updates sent
+//! with an `If-Match` header are only applied while it still matches.
+
+use actix_web::http::header::{EntityTag, IfMatch, ETAG};
+use actix_web::web::Json;
+use actix_web::{CustomizeResponder, Responder};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::result::AppError;
+
+/// The entity tag of the version of a resource last updated at the time.
+pub fn etag(updated_at: DateTime<Utc>) -> EntityTag {
+    EntityTag::new_strong(format!("{:x}", updated_at.timestamp_micros()))
+}
+
+/// Responds with the resource as JSON, tagged with its version.
+pub fn tagged<T: Serialize>(value: T, updated_at: DateTime<Utc>) -> CustomizeResponder<Json<T>> {
+    Json(value)
+        .customize()
+        .insert_header((ETAG, etag(updated_at).to_string()))
+}
+
+/// Checks that the `If-Match` header, when sent, matches the current version
+/// of the resource. Requests without the header are not checked.
+pub fn check(if_match: Option<&IfMatch>, updated_at: DateTime<Utc>) -> Result<(), AppError> {
+    let current = etag(updated_at);
+
+    match if_match {
+        None | Some(IfMatch::Any) => Ok(()),
+        Some(IfMatch::Items(tags)) if tags.iter().any(|tag| tag.strong_eq(&current)) => Ok(()),
+        Some(_) => Err(AppError::precondition_failed(format!(
+            "The resource has been modified, its current version is {}",
+            current
+        ))),
+    }
+}
@@ -0,0 +1,248 @@
+use actix_web::web::{block, Data, Json, Path, Query};
+
+use crate::bulk::{BulkOptions, BulkResult};
+use crate::database::PoolManager;
+use crate::instances::{
+    AssignInstance, BulkAssign, BulkTransition, BulkTransitionResult, InstanceCall,
+    InstanceMigration, InstanceQuery, InstanceTask, InstanceToken, StartInstance,
+    TransitionInstance, WorkflowInstance,
+};
+use crate::middleware::bearer::UserClaims;
+use crate::result::{AppError, JsonResult};
+
+use super::Paginated;
+
+pub async fn list(
+    _: UserClaims,
+    Query(query): Query<InstanceQuery>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Paginated<WorkflowInstance>>> {
+    let instances = block(move || -> Result<_, AppError> {
+        let mut conn = pool.get()?;
+        let total = WorkflowInstance::count(&mut conn, query.clone())?;
+        let data = WorkflowInstance::list(&mut conn, query.clone())?;
+
+        Ok(Paginated {
+            total: Some(total),
+            page: query.page,
+            page_size: query.page_size,
+            next_cursor: None,
+            data,
+        })
+    })
+    .await??;
+
+    Ok(Json(instances))
+}
+
+pub async fn mine(
+    claims: UserClaims,
+    Query(query): Query<InstanceQuery>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Paginated<WorkflowInstance>>> {
+    let query = InstanceQuery {
+        assignee_id: Some(claims.sub),
+        completed: query.completed.or(Some(false)),
+        ..query
+    };
+    let instances = block(move || -> Result<_, AppError> {
+        let mut conn = pool.get()?;
+        let total = WorkflowInstance::count(&mut conn, query.clone())?;
+        let data = WorkflowInstance::list(&mut conn, query.clone())?;
+
+        Ok(Paginated {
+            total: Some(total),
+            page: query.page,
+            page_size: query.page_size,
+            next_cursor: None,
+            data,
+        })
+    })
+    .await??;
+
+    Ok(Json(instances))
+}
+
+pub async fn create(
+    claims: UserClaims,
+    Json(request): Json<StartInstance>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WorkflowInstance>> {
+    let instance = block(move || {
+        let mut conn = pool.get()?;
+        let instance = WorkflowInstance::start(&mut conn, Some(claims.sub), request)?;
+        WorkflowInstance::settle(&mut conn, instance)
+    })
+    .await??;
+
+    Ok(Json(instance))
+}
+
+pub async fn find(
+    _: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WorkflowInstance>> {
+    let id = id.into_inner();
+    let instance = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowInstance::find(&mut conn, id)?
+            .ok_or_else(|| AppError::not_found("WorkflowInstance", &id.to_string()))
+    })
+    .await??;
+
+    Ok(Json(instance))
+}
+
+pub async fn tasks(
+    _: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Vec<InstanceTask>>> {
+    let id = id.into_inner();
+    let tasks = block(move || {
+        let mut conn = pool.get()?;
+        InstanceTask::list_for_instance(&mut conn, id)
+    })
+    .await??;
+
+    Ok(Json(tasks))
+}
+
+pub async fn tokens(
+    _: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Vec<InstanceToken>>> {
+    let id = id.into_inner();
+    let tokens = block(move || {
+        let mut conn = pool.get()?;
+        InstanceToken::list_for_instance(&mut conn, id)
+    })
+    .await??;
+
+    Ok(Json(tokens))
+}
+
+pub async fn calls(
+    _: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Vec<InstanceCall>>> {
+    let id = id.into_inner();
+    let calls = block(move || {
+        let mut conn = pool.get()?;
+        InstanceCall::list(&mut conn, id)
+    })
+    .await??;
+
+    Ok(Json(calls))
+}
+
+pub async fn migrations(
+    _: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Vec<InstanceMigration>>> {
+    let id = id.into_inner();
+    let migrations = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowInstance::migrations(&mut conn, id)
+    })
+    .await??;
+
+    Ok(Json(migrations))
+}
+
+pub async fn transition(
+    claims: UserClaims,
+    id: Path<i64>,
+    Json(request): Json<TransitionInstance>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WorkflowInstance>> {
+    let id = id.into_inner();
+    let instance = block(move || {
+        let mut conn = pool.get()?;
+        let instance = WorkflowInstance::transition(&mut conn, id, Some(claims.sub), request)?;
+        WorkflowInstance::settle(&mut conn, instance)
+    })
+    .await??;
+
+    Ok(Json(instance))
+}
+
+pub async fn assign(
+    _: UserClaims,
+    id: Path<i64>,
+    Json(request): Json<AssignInstance>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WorkflowInstance>> {
+    let id = id.into_inner();
+    let instance = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowInstance::assign(&mut conn, id, Some(request.user_id))
+    })
+    .await??;
+
+    Ok(Json(instance))
+}
+
+pub async fn bulk_assign(
+    _: UserClaims,
+    Query(options): Query<BulkOptions>,
+    Json(request): Json<BulkAssign>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<BulkResult<WorkflowInstance>>> {
+    let result = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowInstance::bulk_assign(&mut conn, request, options)
+    })
+    .await??;
+
+    Ok(Json(result))
+}
+
+pub async fn bulk_transition(
+    claims: UserClaims,
+    Query(options): Query<BulkOptions>,
+    Json(request): Json<BulkTransition>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<BulkResult<BulkTransitionResult>>> {
+    let result = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowInstance::bulk_transition(&mut conn, claims.sub, request, options)
+    })
+    .await??;
+
+    Ok(Json(result))
+}
+
+pub async fn claim(
+    claims: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WorkflowInstance>> {
+    let id = id.into_inner();
+    let instance = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowInstance::claim(&mut conn, id, claims.sub)
+    })
+    .await??;
+
+    Ok(Json(instance))
+}
+
+pub async fn unclaim(
+    claims: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WorkflowInstance>> {
+    let id = id.into_inner();
+    let instance = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowInstance::unclaim(&mut conn, id, claims.sub)
+    })
+    .await??;
+
+    Ok(Json(instance))
+}
@@ -1,43 +1,132 @@
-use actix_web::web::{get, patch, post, scope, ServiceConfig};
+use actix_web::web::{delete, get, patch, post, scope, ServiceConfig};
+use actix_web::Scope;
+use schemars::gen::SchemaGenerator;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tsync::tsync;
 
-use crate::{config::AppSettings, middleware::bearer::JwtAuth};
+use crate::config::AppSettings;
+use crate::middleware::{bearer::JwtAuth, idempotency::Idempotency};
 
+mod checked;
+mod etag;
+pub mod instances;
+pub mod openapi;
+pub mod schemas;
+pub mod search;
+pub mod templates;
 pub mod tenants;
+pub mod triggers;
 pub mod users;
+pub mod versions;
+pub mod webhooks;
 pub mod workflows;
 
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize)]
 #[tsync]
 pub struct Paginated<T> {
-    pub total: i64,
+    /// The number of matching rows, `None` when counting was skipped.
+    pub total: Option<i64>,
     pub page: i64,
-    pub per_page: i64,
+    pub page_size: i64,
+    /// The cursor of the next page, for lists that support cursors.
+    pub next_cursor: Option<String>,
     pub data: Vec<T>,
 }
 
+/// Declares the routes of the API, from which both the services and the
+/// operations of the OpenAPI document are built.
+macro_rules! routes {
+    ($($method:ident $path:literal => $handler:path),* $(,)?) => {
+        /// Adds the routes of the API to the scope.
+        pub fn routes(scope: Scope) -> Scope {
+            scope$(.route($path, $method().to($handler)))*
+        }
+
+        /// Describes the operation of each route, by method and path.
+        pub fn operations(gen: &mut SchemaGenerator) -> Vec<(&'static str, &'static str, Value)> {
+            vec![$((
+                stringify!($method),
+                $path,
+                openapi::operation(gen, $path, stringify!($handler), &$handler),
+            )),*]
+        }
+    };
+}
+
+routes! {
+    get "/users" => users::list,
+    post "/users" => users::create,
+    get "/users/me" => users::me,
+    post "/users/bulk" => users::bulk,
+    post "/users/authenticate" => users::authenticate,
+    get "/users/{id}" => users::find,
+    patch "/users/{id}" => users::update,
+    get "/tenants" => tenants::list,
+    post "/tenants" => tenants::create,
+    get "/tenants/{id}" => tenants::find,
+    patch "/tenants/{id}" => tenants::update,
+    get "/workflows" => workflows::list,
+    post "/workflows" => workflows::create,
+    post "/workflows/import" => workflows::import,
+    get "/workflows/{id}" => workflows::find,
+    patch "/workflows/{id}" => workflows::update,
+    post "/workflows/{id}/clone" => workflows::clone,
+    get "/workflows/{id}/export" => workflows::export,
+    get "/workflows/{id}/lint" => workflows::lint,
+    post "/workflows/{id}/migrate" => versions::migrate,
+    get "/workflows/{id}/render" => workflows::render,
+    post "/workflows/{id}/simulate" => workflows::simulate,
+    get "/workflows/{id}/triggers" => triggers::list,
+    post "/workflows/{id}/triggers" => triggers::create,
+    delete "/workflows/{id}/triggers/{trigger_id}" => triggers::delete,
+    get "/workflows/{id}/versions" => versions::list,
+    get "/workflows/{id}/versions/{version}" => versions::find,
+    get "/workflows/{id}/versions/{version}/diff" => versions::diff,
+    get "/workflow-templates" => templates::list,
+    post "/workflow-templates" => templates::create,
+    get "/workflow-templates/{id}" => templates::find,
+    patch "/workflow-templates/{id}" => templates::update,
+    delete "/workflow-templates/{id}" => templates::delete,
+    post "/workflow-templates/{id}/instantiate" => templates::instantiate,
+    get "/instances" => instances::list,
+    post "/instances" => instances::create,
+    get "/instances/mine" => instances::mine,
+    post "/instances/bulk/assign" => instances::bulk_assign,
+    post "/instances/bulk/transition" => instances::bulk_transition,
+    get "/instances/{id}" => instances::find,
+    get "/instances/{id}/tasks" => instances::tasks,
+    get "/instances/{id}/migrations" => instances::migrations,
+    get "/instances/{id}/tokens" => instances::tokens,
+    get "/instances/{id}/calls" => instances::calls,
+    post "/instances/{id}/transition" => instances::transition,
+    post "/instances/{id}/assign" => instances::assign,
+    post "/instances/{id}/claim" => instances::claim,
+    post "/instances/{id}/unclaim" => instances::unclaim,
+    get "/openapi.json" => openapi::spec,
+    get "/docs" => openapi::docs,
+    get "/schemas" => schemas::list,
+    get "/schemas/{name}" => schemas::find,
+    get "/search" => search::search,
+    post "/triggers/{token}" => triggers::fire,
+    get "/webhooks" => webhooks::list,
+    post "/webhooks" => webhooks::create,
+    get "/webhooks/{id}" => webhooks::find,
+    patch "/webhooks/{id}" => webhooks::update,
+    delete "/webhooks/{id}" => webhooks::delete,
+    get "/webhooks/{id}/deliveries" => webhooks::deliveries,
+    post "/webhooks/{id}/deliveries/{delivery_id}/replay" => webhooks::replay,
+}
+
 pub fn api_routes(
     settings: AppSettings,
 ) -> impl FnOnce(&mut ServiceConfig) + Send + Clone + 'static {
     move |cfg: &mut ServiceConfig| {
         cfg.service(
-            scope("/api")
-                .wrap(JwtAuth::new(settings.jwt.pub_key.clone()))
-                .route("/users", get().to(users::list))
-                .route("/users", post().to(users::create))
-                .route("/users/me", get().to(users::me))
-                .route("/users/authenticate", post().to(users::authenticate))
-                .route("/users/{id}", get().to(users::find))
-                .route("/users/{id}", patch().to(users::update))
-                .route("/tenants", get().to(tenants::list))
-                .route("/tenants", post().to(tenants::create))
-                .route("/tenants/{id}", get().to(tenants::find))
-                .route("/tenants/{id}", patch().to(tenants::update))
-                .route("/workflows", get().to(workflows::list))
-                .route("/workflows", post().to(workflows::create))
-                .route("/workflows/{id}", get().to(workflows::find))
-                .route("/workflows/{id}", patch().to(workflows::update)),
+            routes(scope("/api"))
+                .wrap(Idempotency::new(settings.idempotency.window))
+                .wrap(JwtAuth::new(settings.jwt.pub_key.clone())),
         );
     }
 }
@@ -0,0 +1,326 @@
+//! The OpenAPI document of the API, built from the routes declared in
+//! [`super::routes`] and the signatures of their handlers. Each argument of a
+//! handler says what the operation reads from the request, a path, a query, a
+//! body or a token, and its return type says what it responds with. The
+//! schemas come from the same types as the TypeScript declarations.
+
+use std::sync::OnceLock;
+
+use actix_web::http::header::IfMatch;
+use actix_web::web::{Bytes, Data, Header, Json, Path, Query};
+use actix_web::{CustomizeResponder, Handler, HttpRequest, HttpResponse};
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::{Schema, SingleOrVec};
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+
+use crate::middleware::bearer::UserClaims;
+use crate::result::{ErrorBody, JsonErrorResponse, JsonResult, ParseErrorBody};
+
+use super::checked::Checked;
+
+/// A page rendering the document with Redoc.
+const DOCS: &str = include_str!("docs.html");
+
+static DOCUMENT: OnceLock<Value> = OnceLock::new();
+
+/// What the signature of a handler says about its operation.
+#[derive(Default)]
+pub struct Operation {
+    parameters: Vec<Value>,
+    request_body: Option<Value>,
+    responses: Map<String, Value>,
+    secured: bool,
+}
+
+/// An argument of a handler, which adds what it reads from the request to the
+/// operation of the route at the path.
+pub trait DescribeArg {
+    fn describe(operation: &mut Operation, gen: &mut SchemaGenerator, path: &str);
+}
+
+/// The arguments of a handler.
+pub trait DescribeArgs {
+    fn describe(operation: &mut Operation, gen: &mut SchemaGenerator, path: &str);
+}
+
+/// The return type of a handler, which adds its responses to the operation.
+pub trait DescribeResponse {
+    fn describe(operation: &mut Operation, gen: &mut SchemaGenerator);
+}
+
+macro_rules! describe_args {
+    ($($arg:ident),*) => {
+        impl<$($arg: DescribeArg),*> DescribeArgs for ($($arg,)*) {
+            #[allow(unused_variables)]
+            fn describe(operation: &mut Operation, gen: &mut SchemaGenerator, path: &str) {
+                $($arg::describe(operation, gen, path);)*
+            }
+        }
+    };
+}
+
+describe_args!();
+describe_args!(A);
+describe_args!(A, B);
+describe_args!(A, B, C);
+describe_args!(A, B, C, D);
+describe_args!(A, B, C, D, E);
+describe_args!(A, B, C, D, E, F);
+describe_args!(A, B, C, D, E, F, G);
+
+impl DescribeArg for UserClaims {
+    fn describe(operation: &mut Operation, _: &mut SchemaGenerator, _: &str) {
+        operation.secured = true;
+    }
+}
+
+/// The parameters of the path are named by the route and typed by the
+/// handler, a tuple when there are several.
+impl<T: JsonSchema> DescribeArg for Path<T> {
+    fn describe(operation: &mut Operation, gen: &mut SchemaGenerator, path: &str) {
+        let names = path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+            .collect::<Vec<_>>();
+        let schema = T::json_schema(gen).into_object();
+        let schemas = match schema.array.as_ref().and_then(|a| a.items.as_ref()) {
+            Some(SingleOrVec::Vec(items)) => items.clone(),
+            _ => vec![Schema::Object(schema)],
+        };
+        assert_eq!(
+            names.len(),
+            schemas.len(),
+            "the route {} has {} path parameters, but its handler reads {}",
+            path,
+            names.len(),
+            schemas.len()
+        );
+
+        for (name, schema) in names.into_iter().zip(schemas) {
+            operation.parameters.push(json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": visited(gen, schema),
+            }));
+        }
+    }
+}
+
+/// Each field of the query is a parameter.
+impl<T: JsonSchema> DescribeArg for Query<T> {
+    fn describe(operation: &mut Operation, gen: &mut SchemaGenerator, _: &str) {
+        let object = T::json_schema(gen).into_object().object.unwrap_or_default();
+
+        for (name, schema) in object.properties {
+            let mut schema = visited(gen, schema);
+            let mut parameter = json!({
+                "name": name,
+                "in": "query",
+                "required": object.required.contains(&name),
+            });
+            if let Some(description) = schema
+                .as_object_mut()
+                .and_then(|schema| schema.remove("description"))
+            {
+                parameter["description"] = description;
+            }
+            parameter["schema"] = schema;
+            operation.parameters.push(parameter);
+        }
+    }
+}
+
+impl<T: JsonSchema> DescribeArg for Json<T> {
+    fn describe(operation: &mut Operation, gen: &mut SchemaGenerator, _: &str) {
+        json_body::<T>(operation, gen);
+    }
+}
+
+impl<T: JsonSchema> DescribeArg for Checked<T> {
+    fn describe(operation: &mut Operation, gen: &mut SchemaGenerator, _: &str) {
+        json_body::<T>(operation, gen);
+    }
+}
+
+/// A body the handler reads itself, in a format given by its content type.
+impl DescribeArg for Bytes {
+    fn describe(operation: &mut Operation, _: &mut SchemaGenerator, _: &str) {
+        operation.request_body = Some(json!({
+            "required": true,
+            "content": { "*/*": { "schema": { "type": "string", "format": "binary" } } },
+        }));
+    }
+}
+
+impl DescribeArg for Option<Header<IfMatch>> {
+    fn describe(operation: &mut Operation, _: &mut SchemaGenerator, _: &str) {
+        operation.parameters.push(json!({
+            "name": "If-Match",
+            "in": "header",
+            "required": false,
+            "description": "The entity tag of the version of the resource the change is based on.",
+            "schema": { "type": "string" },
+        }));
+    }
+}
+
+impl<T: ?Sized> DescribeArg for Data<T> {
+    fn describe(_: &mut Operation, _: &mut SchemaGenerator, _: &str) {}
+}
+
+impl DescribeArg for HttpRequest {
+    fn describe(_: &mut Operation, _: &mut SchemaGenerator, _: &str) {}
+}
+
+impl<T: DescribeResponse> DescribeResponse for Result<T, JsonErrorResponse> {
+    fn describe(operation: &mut Operation, gen: &mut SchemaGenerator) {
+        T::describe(operation, gen);
+    }
+}
+
+impl<T: JsonSchema> DescribeResponse for Json<T> {
+    fn describe(operation: &mut Operation, gen: &mut SchemaGenerator) {
+        operation.responses.insert(
+            "200".to_string(),
+            json!({
+                "description": "OK",
+                "content": { "application/json": { "schema": subschema::<T>(gen) } },
+            }),
+        );
+    }
+}
+
+/// A resource tagged with its version, see [`super::etag`].
+impl<T: JsonSchema> DescribeResponse for CustomizeResponder<Json<T>> {
+    fn describe(operation: &mut Operation, gen: &mut SchemaGenerator) {
+        <Json<T> as DescribeResponse>::describe(operation, gen);
+        operation.responses["200"]["headers"] = json!({
+            "ETag": {
+                "description": "The entity tag of the version of the resource.",
+                "schema": { "type": "string" },
+            },
+        });
+    }
+}
+
+/// A response the handler builds itself, in a format depending on the request.
+impl DescribeResponse for HttpResponse {
+    fn describe(operation: &mut Operation, _: &mut SchemaGenerator) {
+        operation
+            .responses
+            .insert("200".to_string(), json!({ "description": "OK" }));
+    }
+}
+
+/// Converts the schema to JSON with the changes the generator makes to
+/// schemas for OpenAPI, which it only makes to root schemas by itself.
+fn visited(gen: &mut SchemaGenerator, mut schema: Schema) -> Value {
+    for visitor in gen.visitors_mut() {
+        visitor.visit_schema(&mut schema);
+    }
+    json!(schema)
+}
+
+fn subschema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Value {
+    let schema = gen.subschema_for::<T>();
+    visited(gen, schema)
+}
+
+fn json_body<T: JsonSchema>(operation: &mut Operation, gen: &mut SchemaGenerator) {
+    operation.request_body = Some(json!({
+        "required": true,
+        "content": { "application/json": { "schema": subschema::<T>(gen) } },
+    }));
+    operation.responses.insert(
+        "422".to_string(),
+        json!({
+            "description": "The body is not valid JSON or does not match its type.",
+            "content": {
+                "application/json": { "schema": subschema::<ParseErrorBody>(gen) },
+            },
+        }),
+    );
+}
+
+/// Describes the operation of the handler of the route at the path. The
+/// handler is named as it is in the route, `module::function`.
+pub fn operation<F, Args>(gen: &mut SchemaGenerator, path: &str, name: &str, _: &F) -> Value
+where
+    F: Handler<Args>,
+    Args: DescribeArgs,
+    F::Output: DescribeResponse,
+{
+    let mut operation = Operation::default();
+    Args::describe(&mut operation, gen, path);
+    F::Output::describe(&mut operation, gen);
+
+    let error = json!({
+        "description": "The error the request failed with.",
+        "content": { "application/json": { "schema": subschema::<ErrorBody>(gen) } },
+    });
+    if operation.secured {
+        operation.responses.insert("401".to_string(), error.clone());
+    }
+    operation.responses.insert("default".to_string(), error);
+
+    let mut description = json!({
+        "operationId": name.replace("::", "_"),
+        "tags": [name.split("::").next()],
+        "parameters": operation.parameters,
+        "responses": operation.responses,
+    });
+    if let Some(body) = operation.request_body {
+        description["requestBody"] = body;
+    }
+    if operation.secured {
+        description["security"] = json!([{ "bearer": [] }]);
+    }
+
+    description
+}
+
+/// The OpenAPI document of the API.
+pub fn document() -> &'static Value {
+    DOCUMENT.get_or_init(|| {
+        let mut gen = SchemaSettings::openapi3().into_generator();
+
+        let mut paths = Map::new();
+        for (method, path, operation) in super::operations(&mut gen) {
+            paths
+                .entry(format!("/api{}", path))
+                .or_insert_with(|| json!({}))[method] = operation;
+        }
+        let schemas = gen
+            .take_definitions()
+            .into_iter()
+            .map(|(name, schema)| (name, visited(&mut gen, schema)))
+            .collect::<Map<_, _>>();
+
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "Daedalus",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "paths": paths,
+            "components": {
+                "schemas": schemas,
+                "securitySchemes": {
+                    "bearer": { "type": "http", "scheme": "bearer", "bearerFormat": "JWT" },
+                },
+            },
+        })
+    })
+}
+
+pub async fn spec() -> JsonResult<Json<&'static Value>> {
+    Ok(Json(document()))
+}
+
+pub async fn docs() -> JsonResult<HttpResponse> {
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(DOCS))
+}
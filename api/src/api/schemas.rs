@@ -0,0 +1,19 @@
+use actix_web::web::{Json, Path};
+
+use crate::json_schema;
+use crate::result::{AppError, JsonResult};
+
+/// Lists the names of the published schemas. They are public, since they
+/// describe the API rather than anything stored in it.
+pub async fn list() -> JsonResult<Json<Vec<&'static str>>> {
+    Ok(Json(json_schema::names()))
+}
+
+pub async fn find(name: Path<String>) -> JsonResult<Json<&'static serde_json::Value>> {
+    let name = name.into_inner();
+
+    match json_schema::find(&name) {
+        Some(schema) => Ok(Json(schema)),
+        None => Err(AppError::not_found("Schema", &name).into()),
+    }
+}
@@ -0,0 +1,20 @@
+use actix_web::web::{block, Data, Json, Query};
+
+use crate::database::PoolManager;
+use crate::middleware::bearer::UserClaims;
+use crate::result::JsonResult;
+use crate::search::{self, SearchHit, SearchQuery};
+
+pub async fn search(
+    _: UserClaims,
+    Query(query): Query<SearchQuery>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Vec<SearchHit>>> {
+    let hits = block(move || {
+        let mut conn = pool.get()?;
+        search::search(&mut conn, query)
+    })
+    .await??;
+
+    Ok(Json(hits))
+}
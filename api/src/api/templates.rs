@@ -0,0 +1,118 @@
+use actix_web::web::{block, Data, Json, Path, Query};
+
+use crate::database::PoolManager;
+use crate::middleware::bearer::UserClaims;
+use crate::pagination::Page;
+use crate::result::{AppError, JsonResult};
+use crate::workflows::{
+    InstantiateTemplate, NewWorkflowTemplate, TemplateQuery, UpdateWorkflowTemplate, Workflow,
+    WorkflowTemplate,
+};
+
+use super::{checked::Checked, Paginated};
+
+pub async fn list(
+    _: UserClaims,
+    Query(query): Query<TemplateQuery>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Paginated<WorkflowTemplate>>> {
+    let templates = block(move || -> Result<_, AppError> {
+        let mut conn = pool.get()?;
+        let total = match query.count {
+            true => Some(WorkflowTemplate::count(&mut conn, query.clone())?),
+            false => None,
+        };
+        let Page { data, next_cursor } = WorkflowTemplate::list(&mut conn, query.clone())?;
+
+        Ok(Paginated {
+            total,
+            page: query.page,
+            page_size: query.page_size,
+            next_cursor,
+            data,
+        })
+    })
+    .await??;
+
+    Ok(Json(templates))
+}
+
+pub async fn create(
+    _: UserClaims,
+    Checked(request): Checked<NewWorkflowTemplate>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WorkflowTemplate>> {
+    let template = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowTemplate::create(&mut conn, request)
+    })
+    .await??;
+
+    Ok(Json(template))
+}
+
+pub async fn find(
+    _: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WorkflowTemplate>> {
+    let id = id.into_inner();
+    let template = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowTemplate::find(&mut conn, id)
+    })
+    .await??;
+
+    match template {
+        Some(template) => Ok(Json(template)),
+        None => Err(AppError::not_found("WorkflowTemplate", &id.to_string()).into()),
+    }
+}
+
+pub async fn update(
+    _: UserClaims,
+    id: Path<i64>,
+    Checked(request): Checked<UpdateWorkflowTemplate>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WorkflowTemplate>> {
+    let id = id.into_inner();
+    let template = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowTemplate::update(&mut conn, id, request)
+    })
+    .await??;
+
+    Ok(Json(template))
+}
+
+pub async fn delete(
+    _: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WorkflowTemplate>> {
+    let id = id.into_inner();
+    let template = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowTemplate::delete(&mut conn, id)
+    })
+    .await??;
+
+    Ok(Json(template))
+}
+
+/// Creates a workflow in a tenant from the template.
+pub async fn instantiate(
+    _: UserClaims,
+    id: Path<i64>,
+    Json(request): Json<InstantiateTemplate>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Workflow>> {
+    let id = id.into_inner();
+    let workflow = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowTemplate::instantiate(&mut conn, id, request)
+    })
+    .await??;
+
+    Ok(Json(workflow))
+}
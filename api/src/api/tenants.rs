@@ -1,11 +1,15 @@
-use actix_web::web::{block, Data, Json, Path, Query};
+use actix_web::http::header::IfMatch;
+use actix_web::web::{block, Data, Header, Json, Path, Query};
+use actix_web::CustomizeResponder;
+use diesel::Connection;
 
 use crate::database::PoolManager;
 use crate::middleware::bearer::UserClaims;
+use crate::pagination::Page;
 use crate::result::{AppError, JsonResult};
 use crate::tenants::{CreateTenant, Tenant, TenantQuery, UpdateTenant};
 
-use super::Paginated;
+use super::{etag, Paginated};
 
 pub async fn list(
     _: UserClaims,
@@ -14,13 +18,17 @@ pub async fn list(
 ) -> JsonResult<Json<Paginated<Tenant>>> {
     let tenants = block(move || -> Result<_, AppError> {
         let mut conn = pool.get()?;
-        let total = Tenant::count(&mut conn, query.clone())?;
-        let data = Tenant::list(&mut conn, query.clone())?;
+        let total = match query.count {
+            true => Some(Tenant::count(&mut conn, query.clone())?),
+            false => None,
+        };
+        let Page { data, next_cursor } = Tenant::list(&mut conn, query.clone())?;
 
         Ok(Paginated {
             total,
             page: query.page,
-            per_page: query.page_size,
+            page_size: query.page_size,
+            next_cursor,
             data,
         })
     })
@@ -36,7 +44,7 @@ pub async fn create(
 ) -> JsonResult<Json<Tenant>> {
     let new_tenant: Tenant = block(move || {
         let mut conn = pool.get()?;
-        Tenant::create(&mut conn, request.into())
+        Tenant::create(&mut conn, request)
     })
     .await??;
 
@@ -47,7 +55,7 @@ pub async fn find(
     _: UserClaims,
     id: Path<i32>,
     pool: Data<PoolManager>,
-) -> JsonResult<Json<Tenant>> {
+) -> JsonResult<CustomizeResponder<Json<Tenant>>> {
     let id = id.into_inner();
     let tenant: Tenant = block(move || {
         let mut conn = pool.get()?;
@@ -58,21 +66,30 @@ pub async fn find(
     })
     .await??;
 
-    Ok(Json(tenant))
+    let updated_at = tenant.updated_at;
+    Ok(etag::tagged(tenant, updated_at))
 }
 
 pub async fn update(
     _: UserClaims,
     id: Path<i32>,
+    if_match: Option<Header<IfMatch>>,
     Json(request): Json<UpdateTenant>,
     pool: Data<PoolManager>,
-) -> JsonResult<Json<Tenant>> {
+) -> JsonResult<CustomizeResponder<Json<Tenant>>> {
     let id = id.into_inner();
     let updated_tenant: Tenant = block(move || {
         let mut conn = pool.get()?;
-        Tenant::update(&mut conn, id, request.into())
+        conn.transaction(|conn| {
+            let tenant = Tenant::lock(conn, id)?
+                .ok_or_else(|| AppError::not_found("Tenant", &id.to_string()))?;
+            etag::check(if_match.as_deref(), tenant.updated_at)?;
+
+            Tenant::update(conn, id, request)
+        })
     })
     .await??;
 
-    Ok(Json(updated_tenant))
+    let updated_at = updated_tenant.updated_at;
+    Ok(etag::tagged(updated_tenant, updated_at))
 }
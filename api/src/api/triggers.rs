@@ -0,0 +1,105 @@
+use actix_web::http::StatusCode;
+use actix_web::web::{block, Bytes, Data, Json, Path};
+use actix_web::{HttpRequest, HttpResponse};
+
+use crate::config::AppSettings;
+use crate::database::PoolManager;
+use crate::idempotency;
+use crate::middleware::bearer::UserClaims;
+use crate::result::{AppError, JsonResult};
+use crate::triggers::{CreateWorkflowTrigger, TriggerCredentials, WorkflowTrigger};
+
+pub async fn list(
+    _: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Vec<WorkflowTrigger>>> {
+    let id = id.into_inner();
+    let triggers = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowTrigger::list(&mut conn, id)
+    })
+    .await??;
+
+    Ok(Json(triggers))
+}
+
+pub async fn create(
+    _: UserClaims,
+    id: Path<i64>,
+    Json(request): Json<CreateWorkflowTrigger>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<TriggerCredentials>> {
+    let id = id.into_inner();
+    let credentials = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowTrigger::create(&mut conn, id, request)
+    })
+    .await??;
+
+    Ok(Json(credentials))
+}
+
+pub async fn delete(
+    _: UserClaims,
+    path: Path<(i64, i64)>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WorkflowTrigger>> {
+    let (id, trigger_id) = path.into_inner();
+    let trigger = block(move || -> Result<_, AppError> {
+        let mut conn = pool.get()?;
+
+        match WorkflowTrigger::find(&mut conn, trigger_id)? {
+            Some(trigger) if trigger.workflow_id == id => {
+                WorkflowTrigger::delete(&mut conn, trigger.id)
+            }
+            _ => Err(AppError::not_found(
+                "WorkflowTrigger",
+                &trigger_id.to_string(),
+            )),
+        }
+    })
+    .await??;
+
+    Ok(Json(trigger))
+}
+
+/// Handles a request from an external system to a trigger's URL. It is
+/// authenticated by the trigger's secret rather than a user session.
+pub async fn fire(
+    req: HttpRequest,
+    token: Path<String>,
+    body: Bytes,
+    pool: Data<PoolManager>,
+    settings: Data<AppSettings>,
+) -> JsonResult<HttpResponse> {
+    let token = token.into_inner();
+    let window = chrono::Duration::from_std(settings.idempotency.window).unwrap_or_default();
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let signature = header("X-Daedalus-Signature");
+    let api_key = header("X-Api-Key");
+    let idempotency_key = header(idempotency::HEADER);
+
+    let response = block(move || -> Result<_, AppError> {
+        let mut conn = pool.get()?;
+        let trigger = WorkflowTrigger::find_by_token(&mut conn, &token)?
+            .ok_or_else(|| AppError::not_found("WorkflowTrigger", &token))?;
+
+        trigger.authenticate(signature.as_deref(), api_key.as_deref(), &body)?;
+        trigger.fire(&mut conn, &body, idempotency_key.as_deref(), window)
+    })
+    .await??;
+
+    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::OK);
+    let mut builder = HttpResponse::build(status);
+    if response.replayed {
+        builder.insert_header(("Idempotent-Replayed", "true"));
+    }
+
+    Ok(builder.json(response.body))
+}
@@ -1,18 +1,22 @@
 use std::fs::read_to_string;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use actix_web::web::{block, Data, Json, Path, Query};
-use actix_web::HttpRequest;
+use actix_web::http::header::IfMatch;
+use actix_web::web::{self, block, Bytes, Data, Json, Path, Query};
+use actix_web::{CustomizeResponder, HttpMessage, HttpRequest};
+use diesel::Connection;
 use jsonwebtoken::{encode, EncodingKey, Header};
 use serde_json::json;
 
+use crate::bulk::{self, BulkOptions, BulkResult};
 use crate::config::AppSettings;
 use crate::middleware::bearer::UserClaims;
+use crate::pagination::Page;
 use crate::result::{AppError, JsonResult};
-use crate::users::{CreateUser, User, UserCredentials, UserQuery};
+use crate::users::{CreateUser, User, UserCredentials, UserOperation, UserQuery};
 use crate::{database::PoolManager, users::UpdateUser};
 
-use super::Paginated;
+use super::{etag, Paginated};
 
 pub async fn list(
     _: UserClaims,
@@ -21,13 +25,17 @@ pub async fn list(
 ) -> JsonResult<Json<Paginated<User>>> {
     let users = block(move || -> Result<_, AppError> {
         let mut conn = pool.get()?;
-        let total = User::count(&mut conn, filter.clone())?;
-        let data = User::list(&mut conn, filter.clone())?;
+        let total = match filter.count {
+            true => Some(User::count(&mut conn, filter.clone())?),
+            false => None,
+        };
+        let Page { data, next_cursor } = User::list(&mut conn, filter.clone())?;
 
         Ok(Paginated {
             total,
             page: filter.page,
-            per_page: filter.page_size,
+            page_size: filter.page_size,
+            next_cursor,
             data,
         })
     })
@@ -49,20 +57,51 @@ pub async fn create(
     Ok(Json(new_user))
 }
 
+/// Applies a batch of operations on users, sent as a JSON array or as CSV.
+pub async fn bulk(
+    _: UserClaims,
+    req: HttpRequest,
+    Query(options): Query<BulkOptions>,
+    body: Bytes,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<BulkResult<User>>> {
+    let operations = match req.content_type() {
+        "text/csv" => UserOperation::from_csv(&body)?,
+        _ => serde_json::from_slice::<Vec<UserOperation>>(&body)
+            .map_err(|e| AppError::validation_error(format!("Invalid operations: {}", e)))?,
+    };
+
+    let result = block(move || {
+        let mut conn = pool.get()?;
+        bulk::run(&mut conn, operations, options, User::apply)
+    })
+    .await??;
+
+    Ok(Json(result))
+}
+
 pub async fn update(
     _: UserClaims,
     Json(request): Json<UpdateUser>,
     id: Path<i64>,
+    if_match: Option<web::Header<IfMatch>>,
     pool: Data<PoolManager>,
-) -> JsonResult<Json<User>> {
+) -> JsonResult<CustomizeResponder<Json<User>>> {
     let id = id.into_inner();
     let updated_user = block(move || {
         let mut conn = pool.get()?;
-        User::update(&mut conn, id, request)
+        conn.transaction(|conn| {
+            let user = User::lock(conn, id)?
+                .ok_or_else(|| AppError::not_found("User", &id.to_string()))?;
+            etag::check(if_match.as_deref(), user.updated_at)?;
+
+            User::update(conn, id, request)
+        })
     })
     .await??;
 
-    Ok(Json(updated_user))
+    let updated_at = updated_user.updated_at;
+    Ok(etag::tagged(updated_user, updated_at))
 }
 
 pub async fn authenticate(
@@ -81,7 +120,7 @@ pub async fn authenticate(
     let key = EncodingKey::from_rsa_pem(priv_key.as_bytes())?;
     let header = Header::new(jsonwebtoken::Algorithm::RS256);
     let exp = SystemTime::now()
-        .checked_add(setting.jwt.lifetime.clone())
+        .checked_add(setting.jwt.lifetime)
         .ok_or(AppError::server_error("Failed to set token expiration"))?
         .duration_since(UNIX_EPOCH)?
         .as_secs() as usize;
@@ -104,7 +143,11 @@ pub async fn me(claims: UserClaims, pool: Data<PoolManager>) -> JsonResult<Json<
     Ok(Json(user))
 }
 
-pub async fn find(_: UserClaims, id: Path<i64>, pool: Data<PoolManager>) -> JsonResult<Json<User>> {
+pub async fn find(
+    _: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<CustomizeResponder<Json<User>>> {
     let id = id.into_inner();
     let user = block(move || {
         let mut conn = pool.get()?;
@@ -115,5 +158,6 @@ pub async fn find(_: UserClaims, id: Path<i64>, pool: Data<PoolManager>) -> Json
     })
     .await??;
 
-    Ok(Json(user))
+    let updated_at = user.updated_at;
+    Ok(etag::tagged(user, updated_at))
 }
@@ -0,0 +1,90 @@
+use actix_web::web::{block, Data, Json, Path, Query};
+
+use crate::bulk::{BulkOptions, BulkResult};
+use crate::database::PoolManager;
+use crate::instances::{MigrateInstances, MigratedInstance, WorkflowInstance};
+use crate::middleware::bearer::UserClaims;
+use crate::result::{AppError, JsonResult};
+use crate::workflows::{DefinitionDiff, VersionDiffQuery, WorkflowVersion};
+
+pub async fn list(
+    _: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Vec<WorkflowVersion>>> {
+    let id = id.into_inner();
+    let versions = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowVersion::list(&mut conn, id)
+    })
+    .await??;
+
+    Ok(Json(versions))
+}
+
+pub async fn find(
+    _: UserClaims,
+    path: Path<(i64, i32)>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WorkflowVersion>> {
+    let (id, version) = path.into_inner();
+    let version = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowVersion::get(&mut conn, id, version)
+    })
+    .await??;
+
+    Ok(Json(version))
+}
+
+/// Compares the version with an earlier one, by default the one before it.
+pub async fn diff(
+    _: UserClaims,
+    path: Path<(i64, i32)>,
+    Query(query): Query<VersionDiffQuery>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<DefinitionDiff>> {
+    let (id, version) = path.into_inner();
+    let base = match query.base {
+        Some(base) if base < 1 => {
+            return Err(AppError::bad_request("Versions are numbered from 1").into())
+        }
+        Some(base) => base,
+        None if version <= 1 => {
+            return Err(AppError::bad_request(format!(
+                "Version {} has no version before it to compare with",
+                version
+            ))
+            .into())
+        }
+        None => version - 1,
+    };
+
+    let diff = block(move || -> Result<_, AppError> {
+        let mut conn = pool.get()?;
+        let old = WorkflowVersion::get(&mut conn, id, base)?;
+        let new = WorkflowVersion::get(&mut conn, id, version)?;
+        Ok(old.definition.diff(&new.definition))
+    })
+    .await??;
+
+    Ok(Json(diff))
+}
+
+/// Moves running instances of the workflow to another version.
+pub async fn migrate(
+    claims: UserClaims,
+    id: Path<i64>,
+    Query(options): Query<BulkOptions>,
+    Json(request): Json<MigrateInstances>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<BulkResult<MigratedInstance>>> {
+    let id = id.into_inner();
+    let result = block(move || {
+        let mut conn = pool.get()?;
+        WorkflowInstance::migrate(&mut conn, id, claims.sub, request, options)
+    })
+    .await??;
+
+    Ok(Json(result))
+}
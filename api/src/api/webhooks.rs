@@ -0,0 +1,146 @@
+use actix_web::web::{block, Data, Json, Path, Query};
+
+use crate::database::PoolManager;
+use crate::middleware::bearer::UserClaims;
+use crate::result::{AppError, JsonResult};
+use crate::webhooks::{
+    CreateWebhookSubscription, DeliveryQuery, UpdateWebhookSubscription, WebhookDelivery,
+    WebhookQuery, WebhookSubscription,
+};
+
+use super::Paginated;
+
+pub async fn list(
+    _: UserClaims,
+    Query(query): Query<WebhookQuery>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Paginated<WebhookSubscription>>> {
+    let subscriptions = block(move || -> Result<_, AppError> {
+        let mut conn = pool.get()?;
+        let total = WebhookSubscription::count(&mut conn, query.clone())?;
+        let data = WebhookSubscription::list(&mut conn, query.clone())?;
+
+        Ok(Paginated {
+            total: Some(total),
+            page: query.page,
+            page_size: query.page_size,
+            next_cursor: None,
+            data,
+        })
+    })
+    .await??;
+
+    Ok(Json(subscriptions))
+}
+
+pub async fn create(
+    _: UserClaims,
+    Json(request): Json<CreateWebhookSubscription>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WebhookSubscription>> {
+    let subscription = block(move || {
+        let mut conn = pool.get()?;
+        WebhookSubscription::create(&mut conn, request)
+    })
+    .await??;
+
+    Ok(Json(subscription))
+}
+
+pub async fn find(
+    _: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WebhookSubscription>> {
+    let id = id.into_inner();
+    let subscription = block(move || {
+        let mut conn = pool.get()?;
+        WebhookSubscription::find(&mut conn, id)
+    })
+    .await??;
+
+    match subscription {
+        Some(subscription) => Ok(Json(subscription)),
+        None => Err(AppError::not_found("WebhookSubscription", &id.to_string()).into()),
+    }
+}
+
+pub async fn update(
+    _: UserClaims,
+    id: Path<i64>,
+    Json(request): Json<UpdateWebhookSubscription>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WebhookSubscription>> {
+    let id = id.into_inner();
+    let subscription = block(move || {
+        let mut conn = pool.get()?;
+        WebhookSubscription::update(&mut conn, id, request)
+    })
+    .await??;
+
+    Ok(Json(subscription))
+}
+
+pub async fn delete(
+    _: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WebhookSubscription>> {
+    let id = id.into_inner();
+    let subscription = block(move || {
+        let mut conn = pool.get()?;
+        WebhookSubscription::delete(&mut conn, id)
+    })
+    .await??;
+
+    Ok(Json(subscription))
+}
+
+pub async fn deliveries(
+    _: UserClaims,
+    id: Path<i64>,
+    Query(query): Query<DeliveryQuery>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Paginated<WebhookDelivery>>> {
+    let id = id.into_inner();
+    let deliveries = block(move || -> Result<_, AppError> {
+        let mut conn = pool.get()?;
+        let total = WebhookDelivery::count(&mut conn, id, query.clone())?;
+        let data = WebhookDelivery::list(&mut conn, id, query.clone())?;
+
+        Ok(Paginated {
+            total: Some(total),
+            page: query.page,
+            page_size: query.page_size,
+            next_cursor: None,
+            data,
+        })
+    })
+    .await??;
+
+    Ok(Json(deliveries))
+}
+
+pub async fn replay(
+    _: UserClaims,
+    path: Path<(i64, i64)>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WebhookDelivery>> {
+    let (id, delivery_id) = path.into_inner();
+    let delivery = block(move || -> Result<_, AppError> {
+        let mut conn = pool.get()?;
+
+        match WebhookDelivery::find(&mut conn, delivery_id)? {
+            Some(delivery) if delivery.subscription_id == id => {
+                WebhookDelivery::replay(&mut conn, delivery.id)
+            }
+            _ => Err(AppError::not_found(
+                "WebhookDelivery",
+                &delivery_id.to_string(),
+            )),
+        }
+    })
+    .await??;
+
+    Ok(Json(delivery))
+}
@@ -1,11 +1,19 @@
-use actix_web::web::{block, Data, Json, Path, Query};
+use actix_web::http::header::IfMatch;
+use actix_web::web::{block, Bytes, Data, Header, Json, Path, Query};
+use actix_web::{CustomizeResponder, HttpMessage, HttpRequest, HttpResponse};
+use diesel::Connection;
 
 use crate::database::PoolManager;
+use crate::instances::{SimulateWorkflow, Simulation};
 use crate::middleware::bearer::UserClaims;
+use crate::pagination::Page;
 use crate::result::{AppError, JsonResult};
-use crate::workflows::{NewWorkflow, UpdateWorkflow, Workflow, WorkflowQuery};
+use crate::workflows::{
+    CloneWorkflow, ExportFormat, ExportQuery, ImportQuery, LintWarning, NewWorkflow, RenderQuery,
+    UpdateWorkflow, Workflow, WorkflowExport, WorkflowImport, WorkflowQuery,
+};
 
-use super::Paginated;
+use super::{checked::Checked, etag, Paginated};
 
 pub async fn list(
     _: UserClaims,
@@ -14,13 +22,17 @@ pub async fn list(
 ) -> JsonResult<Json<Paginated<Workflow>>> {
     let workflows = block(move || -> Result<_, AppError> {
         let mut conn = pool.get()?;
-        let total = Workflow::count(&mut conn, filter.clone())?;
-        let data = Workflow::list(&mut conn, filter.clone())?;
+        let total = match filter.count {
+            true => Some(Workflow::count(&mut conn, filter.clone())?),
+            false => None,
+        };
+        let Page { data, next_cursor } = Workflow::list(&mut conn, filter.clone())?;
 
         Ok(Paginated {
             total,
             page: filter.page,
-            per_page: filter.per_page,
+            page_size: filter.page_size,
+            next_cursor,
             data,
         })
     })
@@ -31,12 +43,12 @@ pub async fn list(
 
 pub async fn create(
     _: UserClaims,
-    Json(request): Json<NewWorkflow>,
+    Checked(request): Checked<NewWorkflow>,
     pool: Data<PoolManager>,
 ) -> JsonResult<Json<Workflow>> {
     let new_workflow = block(move || {
         let mut conn = pool.get()?;
-        Workflow::create(&mut conn, request.into())
+        Workflow::create(&mut conn, request)
     })
     .await??;
 
@@ -47,7 +59,7 @@ pub async fn find(
     _: UserClaims,
     id: Path<i64>,
     pool: Data<PoolManager>,
-) -> JsonResult<Json<Workflow>> {
+) -> JsonResult<CustomizeResponder<Json<Workflow>>> {
     let id = id.into_inner();
     let workflow = block(move || {
         let mut conn = pool.get()?;
@@ -56,7 +68,10 @@ pub async fn find(
     .await??;
 
     match workflow {
-        Some(w) => Ok(Json(w)),
+        Some(w) => {
+            let updated_at = w.updated_at;
+            Ok(etag::tagged(w, updated_at))
+        }
         None => Err(AppError::NotFound {
             entity: "Workflow".to_string(),
             id: id.to_string(),
@@ -68,15 +83,150 @@ pub async fn find(
 pub async fn update(
     _: UserClaims,
     id: Path<i64>,
-    Json(request): Json<UpdateWorkflow>,
+    if_match: Option<Header<IfMatch>>,
+    Checked(request): Checked<UpdateWorkflow>,
+    pool: Data<PoolManager>,
+) -> JsonResult<CustomizeResponder<Json<Workflow>>> {
+    let id = id.into_inner();
+    let workflow = block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            let workflow = Workflow::lock(conn, id)?
+                .ok_or_else(|| AppError::not_found("Workflow", &id.to_string()))?;
+            etag::check(if_match.as_deref(), workflow.updated_at)?;
+
+            Workflow::update(conn, id, request)
+        })
+    })
+    .await??;
+
+    let updated_at = workflow.updated_at;
+    Ok(etag::tagged(workflow, updated_at))
+}
+
+/// Creates a copy of the workflow, with new ids for its states, transitions
+/// and other parts.
+pub async fn clone(
+    _: UserClaims,
+    id: Path<i64>,
+    Json(request): Json<CloneWorkflow>,
     pool: Data<PoolManager>,
 ) -> JsonResult<Json<Workflow>> {
     let id = id.into_inner();
     let workflow = block(move || {
         let mut conn = pool.get()?;
-        Workflow::update(&mut conn, id, request)
+        Workflow::duplicate(&mut conn, id, request)
     })
     .await??;
 
     Ok(Json(workflow))
 }
+
+/// Responds with the portable document of the workflow, as JSON or YAML.
+pub async fn export(
+    _: UserClaims,
+    id: Path<i64>,
+    Query(query): Query<ExportQuery>,
+    pool: Data<PoolManager>,
+) -> JsonResult<HttpResponse> {
+    let id = id.into_inner();
+    let document = block(move || {
+        let mut conn = pool.get()?;
+        Workflow::export(&mut conn, id)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .body(document.to_bytes(query.format)?))
+}
+
+/// Responds with the graph of the workflow's states, as DOT, Mermaid or SVG.
+pub async fn render(
+    _: UserClaims,
+    id: Path<i64>,
+    Query(query): Query<RenderQuery>,
+    pool: Data<PoolManager>,
+) -> JsonResult<HttpResponse> {
+    let id = id.into_inner();
+    let workflow = block(move || {
+        let mut conn = pool.get()?;
+        Workflow::find(&mut conn, id)?
+            .ok_or_else(|| AppError::not_found("Workflow", &id.to_string()))
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .body(workflow.definition.render(&workflow.name, query.format)))
+}
+
+/// Responds with warnings about the workflow's definition, such as states
+/// that can't be left or approvers who can't approve.
+pub async fn lint(
+    _: UserClaims,
+    id: Path<i64>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Vec<LintWarning>>> {
+    let id = id.into_inner();
+    let warnings = block(move || {
+        let mut conn = pool.get()?;
+        let workflow = Workflow::find(&mut conn, id)?
+            .ok_or_else(|| AppError::not_found("Workflow", &id.to_string()))?;
+        workflow.lint(&mut conn)
+    })
+    .await??;
+
+    Ok(Json(warnings))
+}
+
+/// Runs the workflow, or the definition sent along, against a sequence of
+/// choices without creating an instance.
+pub async fn simulate(
+    _: UserClaims,
+    id: Path<i64>,
+    Checked(request): Checked<SimulateWorkflow>,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<Simulation>> {
+    let id = id.into_inner();
+    let workflow = block(move || {
+        let mut conn = pool.get()?;
+        Workflow::find(&mut conn, id)?
+            .filter(|w| w.deleted_at.is_none())
+            .ok_or_else(|| AppError::not_found("Workflow", &id.to_string()))
+    })
+    .await??;
+
+    let SimulateWorkflow {
+        definition,
+        data,
+        steps,
+        http_responses,
+    } = request;
+    let definition = definition.unwrap_or(workflow.definition);
+    definition.validate()?;
+
+    Ok(Json(definition.simulate(data, &steps, &http_responses)))
+}
+
+/// Creates a workflow from a portable document, sent as JSON or YAML.
+pub async fn import(
+    _: UserClaims,
+    req: HttpRequest,
+    Query(query): Query<ImportQuery>,
+    body: Bytes,
+    pool: Data<PoolManager>,
+) -> JsonResult<Json<WorkflowImport>> {
+    let format = ExportFormat::from_content_type(req.content_type());
+    let Checked(document) =
+        Checked::<WorkflowExport>::from_value(WorkflowExport::parse(&body, format)?)?;
+    document.check_version()?;
+
+    let result = block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| Workflow::import(conn, document, query))
+    })
+    .await??;
+
+    Ok(Json(result))
+}
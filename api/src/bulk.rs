@@ -0,0 +1,129 @@
+//! Applies a batch of operations, such as creating many users at once, and
+//! reports the outcome of each one.
+//!
+//! Every operation runs in a savepoint inside one transaction, so a failed
+//! operation never leaves partial changes behind. An atomic batch is rolled
+//! back as a whole when any operation fails, while a non-atomic one keeps the
+//! operations that succeeded. A dry run reports what would happen and is
+//! always rolled back.
+
+use diesel::Connection;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tsync::tsync;
+
+use crate::database::DbConnection;
+use crate::defaults::default_bool;
+use crate::result::{AppError, Result};
+
+/// The most operations a batch may have.
+pub const MAX_ROWS: usize = 1000;
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct BulkOptions {
+    /// Whether to roll back the whole batch when any operation fails. The
+    /// default is `true`.
+    #[serde(default = "default_bool::<true>")]
+    pub atomic: bool,
+    /// Whether to only report what the batch would do.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// The outcome of one operation of a batch, at its position in the batch.
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct BulkRowResult<T> {
+    pub index: usize,
+    pub result: Option<T>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct BulkResult<T> {
+    pub dry_run: bool,
+    /// Whether the changes were kept. Dry runs and atomic batches with a
+    /// failed operation are rolled back.
+    pub committed: bool,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub rows: Vec<BulkRowResult<T>>,
+}
+
+/// Applies each operation in turn and reports the outcomes.
+pub fn run<T, R>(
+    conn: &mut DbConnection,
+    operations: Vec<T>,
+    options: BulkOptions,
+    mut apply: impl FnMut(&mut DbConnection, T) -> Result<R>,
+) -> Result<BulkResult<R>> {
+    if operations.is_empty() || operations.len() > MAX_ROWS {
+        return Err(AppError::validation_error(format!(
+            "A batch must have between 1 and {} operations",
+            MAX_ROWS
+        )));
+    }
+
+    let mut report = None;
+    let outcome = conn.transaction(|conn| {
+        let rows: Vec<_> = operations
+            .into_iter()
+            .enumerate()
+            .map(
+                |(index, operation)| match conn.transaction(|conn| apply(conn, operation)) {
+                    Ok(result) => BulkRowResult {
+                        index,
+                        result: Some(result),
+                        error: None,
+                    },
+                    Err(e) => BulkRowResult {
+                        index,
+                        result: None,
+                        error: Some(e.to_string()),
+                    },
+                },
+            )
+            .collect();
+
+        let failed = rows.iter().filter(|row| row.error.is_some()).count();
+        let committed = !options.dry_run && (failed == 0 || !options.atomic);
+        report = Some(BulkResult {
+            dry_run: options.dry_run,
+            committed,
+            succeeded: rows.len() - failed,
+            failed,
+            rows,
+        });
+
+        match committed {
+            true => Ok(()),
+            false => Err(AppError::from(diesel::result::Error::RollbackTransaction)),
+        }
+    });
+
+    match (outcome, report) {
+        (Ok(()), Some(report)) => Ok(report),
+        (Err(_), Some(report)) if !report.committed => Ok(report),
+        (Err(e), _) => Err(e),
+        (Ok(()), None) => Err(AppError::server_error("Batch finished without a report")),
+    }
+}
+
+/// Parses the rows of a CSV document with a header row. Empty cells are read
+/// as missing values.
+pub fn from_csv<T: DeserializeOwned>(body: &[u8]) -> Result<Vec<T>> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body)
+        .deserialize()
+        .enumerate()
+        .map(|(index, row)| {
+            row.map_err(|e| {
+                AppError::validation_error(format!("Invalid CSV row {}: {}", index + 1, e))
+            })
+        })
+        .collect()
+}
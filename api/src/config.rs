@@ -32,6 +32,15 @@ pub struct AppSettings {
 
     /// The settings for the server.
     pub server: ServerSettings,
+
+    /// The settings for background timers.
+    pub timers: TimerSettings,
+
+    /// The settings for outbound webhooks.
+    pub webhooks: WebhookSettings,
+
+    /// The settings for idempotent requests.
+    pub idempotency: IdempotencySettings,
 }
 
 #[serde_as]
@@ -71,6 +80,48 @@ pub struct ServerSettings {
     pub workers: usize,
 }
 
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct TimerSettings {
+    /// How often overdue instances are checked for escalations.
+    /// The default is 60 seconds.
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub interval: Duration,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct WebhookSettings {
+    /// How often the outbox is checked for deliveries that are due.
+    /// The default is 10 seconds.
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub interval: Duration,
+
+    /// The maximum time to wait for a subscriber to respond.
+    /// The default is 10 seconds.
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub timeout: Duration,
+
+    /// The number of attempts made before a delivery is dead-lettered.
+    /// The default is 8.
+    pub max_attempts: i32,
+
+    /// The delay before the first retry, doubled after every failed attempt.
+    /// The default is 30 seconds.
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub retry_delay: Duration,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct IdempotencySettings {
+    /// How long the response to a request with an `Idempotency-Key` header is
+    /// kept for retries.
+    /// The default is 86400 seconds.
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub window: Duration,
+}
+
 // region: JWT settings
 
 #[serde_as]
@@ -210,6 +261,12 @@ const LOG_LEVEL: &'static str = "log.level";
 const LOG_FORMAT: &'static str = "log.format";
 const SERVER_PORT: &'static str = "server.port";
 const SERVER_WORKERS: &'static str = "server.workers";
+const TIMERS_INTERVAL: &str = "timers.interval";
+const WEBHOOKS_INTERVAL: &str = "webhooks.interval";
+const WEBHOOKS_TIMEOUT: &str = "webhooks.timeout";
+const WEBHOOKS_MAX_ATTEMPTS: &str = "webhooks.max_attempts";
+const WEBHOOKS_RETRY_DELAY: &str = "webhooks.retry_delay";
+const IDEMPOTENCY_WINDOW: &str = "idempotency.window";
 
 #[derive(Debug)]
 pub struct ConfigBuilder {
@@ -307,6 +364,46 @@ impl ConfigBuilder {
         self
     }
 
+    pub fn set_timers_interval(mut self, timers_interval: Option<u64>) -> Self {
+        self.overrides
+            .insert(TIMERS_INTERVAL.into(), Value::from(timers_interval));
+        self
+    }
+
+    pub fn set_webhooks_interval(mut self, webhooks_interval: Option<u64>) -> Self {
+        self.overrides
+            .insert(WEBHOOKS_INTERVAL.into(), Value::from(webhooks_interval));
+        self
+    }
+
+    pub fn set_webhooks_timeout(mut self, webhooks_timeout: Option<u64>) -> Self {
+        self.overrides
+            .insert(WEBHOOKS_TIMEOUT.into(), Value::from(webhooks_timeout));
+        self
+    }
+
+    pub fn set_webhooks_max_attempts(mut self, webhooks_max_attempts: Option<i32>) -> Self {
+        self.overrides.insert(
+            WEBHOOKS_MAX_ATTEMPTS.into(),
+            Value::from(webhooks_max_attempts),
+        );
+        self
+    }
+
+    pub fn set_webhooks_retry_delay(mut self, webhooks_retry_delay: Option<u64>) -> Self {
+        self.overrides.insert(
+            WEBHOOKS_RETRY_DELAY.into(),
+            Value::from(webhooks_retry_delay),
+        );
+        self
+    }
+
+    pub fn set_idempotency_window(mut self, idempotency_window: Option<u64>) -> Self {
+        self.overrides
+            .insert(IDEMPOTENCY_WINDOW.into(), Value::from(idempotency_window));
+        self
+    }
+
     pub fn parse(self) -> Result<AppSettings> {
         // Initialize with defaults
         let mut fig = Figment::new()
@@ -322,7 +419,13 @@ impl ConfigBuilder {
             .merge(Serialized::default(LOG_LEVEL, LogLevel::default()))
             .merge(Serialized::default(LOG_FORMAT, LogFormat::default()))
             .merge(Serialized::default(SERVER_PORT, 8080))
-            .merge(Serialized::default(SERVER_WORKERS, 4));
+            .merge(Serialized::default(SERVER_WORKERS, 4))
+            .merge(Serialized::default(TIMERS_INTERVAL, 60))
+            .merge(Serialized::default(WEBHOOKS_INTERVAL, 10))
+            .merge(Serialized::default(WEBHOOKS_TIMEOUT, 10))
+            .merge(Serialized::default(WEBHOOKS_MAX_ATTEMPTS, 8))
+            .merge(Serialized::default(WEBHOOKS_RETRY_DELAY, 30))
+            .merge(Serialized::default(IDEMPOTENCY_WINDOW, 86400));
 
         // Add the config file source
         fig = fig.merge(Toml::file(self.config_file.clone()));
@@ -335,13 +438,29 @@ impl ConfigBuilder {
             fig = fig.merge(Serialized::default(&key, value));
         }
 
-        fig.extract::<AppSettings>()
+        let settings = fig
+            .extract::<AppSettings>()
             .map_err(|e| match e.kind.clone() {
                 figment::error::Kind::MissingField(k) => {
                     AppError::server_error(format!("Missing Field: {}.{}", e.path.join("."), k))
                 }
                 _ => AppError::server_error(format!("Error: {}", e)),
-            })
+            })?;
+
+        // Timers tick at these intervals, which cannot be zero
+        for (key, interval) in [
+            (TIMERS_INTERVAL, settings.timers.interval),
+            (WEBHOOKS_INTERVAL, settings.webhooks.interval),
+        ] {
+            if interval.is_zero() {
+                return Err(AppError::server_error(format!(
+                    "Invalid Field: {} must be greater than 0",
+                    key
+                )));
+            }
+        }
+
+        Ok(settings)
     }
 }
 
@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, NaiveDate};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tsync::tsync;
+
+use crate::result::{AppError, Result};
+
+/// Declares the custom fields stored in the data of a workflow's instances.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct DataSchema {
+    pub fields: Vec<DataField>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct DataField {
+    /// The key of the field in the instance data.
+    pub name: String,
+    pub label: String,
+    pub description: Option<String>,
+    pub field_type: DataFieldType,
+    #[serde(default)]
+    pub required: bool,
+    /// The allowed values of an `Enum` field.
+    #[serde(default)]
+    pub options: Vec<String>,
+    /// The value used when the field is missing from the data.
+    pub default: Option<Value>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[tsync]
+pub enum DataFieldType {
+    Text,
+    Number,
+    Integer,
+    Boolean,
+    /// A calendar date (`2024-03-20`) or an RFC 3339 timestamp.
+    Date,
+    /// The id of a user. Only the shape of the id is checked, the user may
+    /// not exist or may be deleted later.
+    UserId,
+    Enum,
+}
+
+impl DataSchema {
+    /// Checks that the schema itself is well formed.
+    pub fn validate(&self) -> Result<()> {
+        let mut names = HashSet::new();
+        let mut errors = Vec::new();
+
+        for field in &self.fields {
+            if field.name.trim().is_empty() {
+                errors.push("field names cannot be empty".to_string());
+            } else if !names.insert(field.name.as_str()) {
+                errors.push(format!("field `{}` is declared more than once", field.name));
+            }
+
+            if field.field_type == DataFieldType::Enum && field.options.is_empty() {
+                errors.push(format!("enum field `{}` has no options", field.name));
+            }
+
+            if let Some(default) = &field.default {
+                if let Err(e) = field.check(default) {
+                    errors.push(format!("default of `{}` {}", field.name, e));
+                }
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(AppError::validation_error(format!(
+                "Invalid data schema: {}",
+                errors.join("; ")
+            ))),
+        }
+    }
+
+    /// Fills in defaults for missing fields and checks the data against the
+    /// schema. Keys that are not declared in the schema are left untouched.
+    pub fn apply(&self, data: &mut Value) -> Result<()> {
+        let Some(data) = data.as_object_mut() else {
+            return Err(AppError::validation_error(
+                "Instance data must be a JSON object",
+            ));
+        };
+
+        let mut errors = Vec::new();
+
+        for field in &self.fields {
+            let missing = matches!(data.get(&field.name), None | Some(Value::Null));
+
+            if missing {
+                match &field.default {
+                    Some(default) => {
+                        data.insert(field.name.clone(), default.clone());
+                    }
+                    None if field.required => {
+                        errors.push(format!("`{}` is required", field.name));
+                    }
+                    None => {}
+                }
+                continue;
+            }
+
+            if let Err(e) = field.check(&data[&field.name]) {
+                errors.push(format!("`{}` {}", field.name, e));
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(AppError::validation_error(format!(
+                "Invalid instance data: {}",
+                errors.join("; ")
+            ))),
+        }
+    }
+}
+
+impl DataField {
+    fn check(&self, value: &Value) -> std::result::Result<(), String> {
+        let valid = match self.field_type {
+            DataFieldType::Text => value.is_string(),
+            DataFieldType::Number => value.is_number(),
+            DataFieldType::Integer => value.is_i64() || value.is_u64(),
+            DataFieldType::Boolean => value.is_boolean(),
+            DataFieldType::UserId => value.is_i64(),
+            DataFieldType::Date => value.as_str().is_some_and(|s| {
+                NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok()
+                    || DateTime::parse_from_rfc3339(s).is_ok()
+            }),
+            DataFieldType::Enum => {
+                return match value.as_str() {
+                    Some(s) if self.options.iter().any(|o| o == s) => Ok(()),
+                    _ => Err(format!("must be one of {}", self.options.join(", "))),
+                }
+            }
+        };
+
+        match valid {
+            true => Ok(()),
+            false => Err(format!("must be of type {:?}", self.field_type)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn schema(fields: Value) -> DataSchema {
+        serde_json::from_value(json!({ "fields": fields })).unwrap()
+    }
+
+    fn field(name: &str, field_type: &str, extra: Value) -> Value {
+        let mut field = json!({
+            "name": name,
+            "label": name,
+            "description": null,
+            "field_type": field_type,
+            "default": null,
+        });
+        if let (Some(field), Value::Object(extra)) = (field.as_object_mut(), extra) {
+            field.extend(extra);
+        }
+        field
+    }
+
+    fn error(schema: &DataSchema, mut data: Value) -> String {
+        match schema.apply(&mut data) {
+            Ok(()) => panic!("{} was accepted", data),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn fills_in_defaults_for_missing_and_null_fields() {
+        let schema = schema(json!([
+            field("priority", "Integer", json!({ "default": 3 })),
+            field("status", "Text", json!({ "default": "new" })),
+            field("notes", "Text", json!({})),
+        ]));
+        let mut data = json!({ "status": null, "extra": true });
+
+        schema.apply(&mut data).unwrap();
+
+        assert_eq!(
+            data,
+            json!({ "priority": 3, "status": "new", "extra": true })
+        );
+    }
+
+    #[test]
+    fn keeps_values_that_are_set() {
+        let schema = schema(json!([field(
+            "priority",
+            "Integer",
+            json!({ "default": 3 })
+        )]));
+        let mut data = json!({ "priority": 1 });
+
+        schema.apply(&mut data).unwrap();
+
+        assert_eq!(data, json!({ "priority": 1 }));
+    }
+
+    #[test]
+    fn requires_required_fields_without_defaults() {
+        let schema = schema(json!([
+            field("amount", "Number", json!({ "required": true })),
+            field("owner", "UserId", json!({ "required": true, "default": 1 })),
+        ]));
+
+        assert!(error(&schema, json!({ "amount": null })).contains("`amount` is required"));
+        assert!(schema.apply(&mut json!({ "amount": 1.5 })).is_ok());
+    }
+
+    #[test]
+    fn checks_enums_against_their_options() {
+        let schema = schema(json!([field(
+            "region",
+            "Enum",
+            json!({ "options": ["east", "west"] })
+        )]));
+
+        assert!(schema.apply(&mut json!({ "region": "west" })).is_ok());
+        assert!(error(&schema, json!({ "region": "north" })).contains("must be one of east, west"));
+        assert!(error(&schema, json!({ "region": 1 })).contains("must be one of east, west"));
+    }
+
+    #[test]
+    fn accepts_calendar_dates_and_timestamps() {
+        let schema = schema(json!([field("due", "Date", json!({}))]));
+
+        assert!(schema.apply(&mut json!({ "due": "2024-03-20" })).is_ok());
+        assert!(schema
+            .apply(&mut json!({ "due": "2024-03-20T09:30:00+01:00" }))
+            .is_ok());
+        assert!(error(&schema, json!({ "due": "20/03/2024" })).contains("must be of type Date"));
+        assert!(
+            error(&schema, json!({ "due": "2024-03-20 09:30" })).contains("must be of type Date")
+        );
+    }
+
+    #[test]
+    fn takes_any_integer_as_a_user_id() {
+        let schema = schema(json!([field("owner", "UserId", json!({}))]));
+
+        assert!(schema.apply(&mut json!({ "owner": 999_999 })).is_ok());
+        assert!(error(&schema, json!({ "owner": "7" })).contains("must be of type UserId"));
+    }
+
+    #[test]
+    fn rejects_malformed_schemas() {
+        let schema = schema(json!([
+            field("kind", "Enum", json!({})),
+            field("kind", "Text", json!({})),
+            field("count", "Integer", json!({ "default": "many" })),
+        ]));
+
+        let error = schema.validate().unwrap_err().to_string();
+
+        assert!(error.contains("enum field `kind` has no options"));
+        assert!(error.contains("field `kind` is declared more than once"));
+        assert!(error.contains("default of `count` must be of type Integer"));
+    }
+}
@@ -1,5 +1,79 @@
 // @generated automatically by Diesel CLI.
 
+pub mod sql_types {
+    /// The `pg_catalog.tsvector` SQL type
+    ///
+    /// (Automatically generated by Diesel.)
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "tsvector", schema = "pg_catalog"))]
+    pub struct Tsvector;
+}
+
+diesel::table! {
+    /// Representation of the `idempotency_keys` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    idempotency_keys (id) {
+        /// The `id` column of the `idempotency_keys` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `scope` column of the `idempotency_keys` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        #[max_length = 255]
+        scope -> Varchar,
+        /// The `key` column of the `idempotency_keys` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        #[max_length = 255]
+        key -> Varchar,
+        /// The `fingerprint` column of the `idempotency_keys` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        #[max_length = 64]
+        fingerprint -> Varchar,
+        /// The `response_status` column of the `idempotency_keys` table.
+        ///
+        /// Its SQL type is `Nullable<Int4>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        response_status -> Nullable<Int4>,
+        /// The `response_body` column of the `idempotency_keys` table.
+        ///
+        /// Its SQL type is `Nullable<Jsonb>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        response_body -> Nullable<Jsonb>,
+        /// The `created_at` column of the `idempotency_keys` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamptz,
+        /// The `locked_until` column of the `idempotency_keys` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamptz>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        locked_until -> Nullable<Timestamptz>,
+        /// The `response_headers` column of the `idempotency_keys` table.
+        ///
+        /// Its SQL type is `Nullable<Jsonb>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        response_headers -> Nullable<Jsonb>,
+    }
+}
+
 diesel::table! {
     /// Representation of the `tenants` table.
     ///
@@ -36,10 +110,19 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         deleted_at -> Nullable<Timestamptz>,
+        /// The `allowed_hosts` column of the `tenants` table.
+        ///
+        /// Its SQL type is `Array<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        allowed_hosts -> Array<Text>,
     }
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
+
     /// Representation of the `users` table.
     ///
     /// (Automatically generated by Diesel.)
@@ -95,71 +178,854 @@ diesel::table! {
         /// (Automatically generated by Diesel.)
         #[max_length = 255]
         name -> Nullable<Varchar>,
+        /// The `search_vector` column of the `users` table.
+        ///
+        /// Its SQL type is `Tsvector`.
+        ///
+        /// (Automatically generated by Diesel.)
+        search_vector -> Tsvector,
     }
 }
 
 diesel::table! {
-    /// Representation of the `workflows` table.
+    /// Representation of the `webhook_deliveries` table.
     ///
     /// (Automatically generated by Diesel.)
-    workflows (id) {
-        /// The `id` column of the `workflows` table.
+    webhook_deliveries (id) {
+        /// The `id` column of the `webhook_deliveries` table.
         ///
         /// Its SQL type is `Int8`.
         ///
         /// (Automatically generated by Diesel.)
         id -> Int8,
-        /// The `tenant_id` column of the `workflows` table.
+        /// The `subscription_id` column of the `webhook_deliveries` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        subscription_id -> Int8,
+        /// The `event` column of the `webhook_deliveries` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        #[max_length = 100]
+        event -> Varchar,
+        /// The `payload` column of the `webhook_deliveries` table.
+        ///
+        /// Its SQL type is `Jsonb`.
+        ///
+        /// (Automatically generated by Diesel.)
+        payload -> Jsonb,
+        /// The `status` column of the `webhook_deliveries` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        #[max_length = 20]
+        status -> Varchar,
+        /// The `attempts` column of the `webhook_deliveries` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        attempts -> Int4,
+        /// The `next_attempt_at` column of the `webhook_deliveries` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        next_attempt_at -> Timestamptz,
+        /// The `response_status` column of the `webhook_deliveries` table.
+        ///
+        /// Its SQL type is `Nullable<Int4>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        response_status -> Nullable<Int4>,
+        /// The `error` column of the `webhook_deliveries` table.
+        ///
+        /// Its SQL type is `Nullable<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        error -> Nullable<Text>,
+        /// The `created_at` column of the `webhook_deliveries` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamptz,
+        /// The `updated_at` column of the `webhook_deliveries` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        updated_at -> Timestamptz,
+        /// The `delivered_at` column of the `webhook_deliveries` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamptz>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        delivered_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `webhook_subscriptions` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    webhook_subscriptions (id) {
+        /// The `id` column of the `webhook_subscriptions` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `tenant_id` column of the `webhook_subscriptions` table.
         ///
         /// Its SQL type is `Int4`.
         ///
         /// (Automatically generated by Diesel.)
         tenant_id -> Int4,
-        /// The `name` column of the `workflows` table.
+        /// The `url` column of the `webhook_subscriptions` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        url -> Text,
+        /// The `events` column of the `webhook_subscriptions` table.
+        ///
+        /// Its SQL type is `Array<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        events -> Array<Text>,
+        /// The `secret` column of the `webhook_subscriptions` table.
         ///
         /// Its SQL type is `Varchar`.
         ///
         /// (Automatically generated by Diesel.)
         #[max_length = 255]
-        name -> Varchar,
-        /// The `description` column of the `workflows` table.
+        secret -> Varchar,
+        /// The `created_at` column of the `webhook_subscriptions` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamptz,
+        /// The `updated_at` column of the `webhook_subscriptions` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        updated_at -> Timestamptz,
+        /// The `deleted_at` column of the `webhook_subscriptions` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamptz>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        deleted_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `workflow_instance_calls` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    workflow_instance_calls (id) {
+        /// The `id` column of the `workflow_instance_calls` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `instance_id` column of the `workflow_instance_calls` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        instance_id -> Int8,
+        /// The `token_id` column of the `workflow_instance_calls` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        token_id -> Int8,
+        /// The `state_id` column of the `workflow_instance_calls` table.
+        ///
+        /// Its SQL type is `Uuid`.
+        ///
+        /// (Automatically generated by Diesel.)
+        state_id -> Uuid,
+        /// The `action_id` column of the `workflow_instance_calls` table.
+        ///
+        /// Its SQL type is `Uuid`.
+        ///
+        /// (Automatically generated by Diesel.)
+        action_id -> Uuid,
+        /// The `exiting` column of the `workflow_instance_calls` table.
+        ///
+        /// Its SQL type is `Bool`.
+        ///
+        /// (Automatically generated by Diesel.)
+        exiting -> Bool,
+        /// The `target_state_id` column of the `workflow_instance_calls` table.
+        ///
+        /// Its SQL type is `Nullable<Uuid>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        target_state_id -> Nullable<Uuid>,
+        /// The `completed_by` column of the `workflow_instance_calls` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        completed_by -> Nullable<Int8>,
+        /// The `option_id` column of the `workflow_instance_calls` table.
+        ///
+        /// Its SQL type is `Nullable<Uuid>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        option_id -> Nullable<Uuid>,
+        /// The `comment` column of the `workflow_instance_calls` table.
         ///
         /// Its SQL type is `Nullable<Text>`.
         ///
         /// (Automatically generated by Diesel.)
-        description -> Nullable<Text>,
-        /// The `definition` column of the `workflows` table.
+        comment -> Nullable<Text>,
+        /// The `status` column of the `workflow_instance_calls` table.
         ///
-        /// Its SQL type is `Jsonb`.
+        /// Its SQL type is `Varchar`.
         ///
         /// (Automatically generated by Diesel.)
-        definition -> Jsonb,
-        /// The `created_at` column of the `workflows` table.
+        status -> Varchar,
+        /// The `error` column of the `workflow_instance_calls` table.
+        ///
+        /// Its SQL type is `Nullable<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        error -> Nullable<Text>,
+        /// The `locked_until` column of the `workflow_instance_calls` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        locked_until -> Timestamptz,
+        /// The `created_at` column of the `workflow_instance_calls` table.
         ///
         /// Its SQL type is `Timestamptz`.
         ///
         /// (Automatically generated by Diesel.)
         created_at -> Timestamptz,
-        /// The `updated_at` column of the `workflows` table.
+        /// The `updated_at` column of the `workflow_instance_calls` table.
         ///
         /// Its SQL type is `Timestamptz`.
         ///
         /// (Automatically generated by Diesel.)
         updated_at -> Timestamptz,
-        /// The `deleted_at` column of the `workflows` table.
+        /// The `completed_at` column of the `workflow_instance_calls` table.
         ///
         /// Its SQL type is `Nullable<Timestamptz>`.
         ///
         /// (Automatically generated by Diesel.)
-        deleted_at -> Nullable<Timestamptz>,
+        completed_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `workflow_instance_escalations` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    workflow_instance_escalations (id) {
+        /// The `id` column of the `workflow_instance_escalations` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `task_id` column of the `workflow_instance_escalations` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        task_id -> Int8,
+        /// The `escalation_id` column of the `workflow_instance_escalations` table.
+        ///
+        /// Its SQL type is `Uuid`.
+        ///
+        /// (Automatically generated by Diesel.)
+        escalation_id -> Uuid,
+        /// The `created_at` column of the `workflow_instance_escalations` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `workflow_instance_migrations` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    workflow_instance_migrations (id) {
+        /// The `id` column of the `workflow_instance_migrations` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `instance_id` column of the `workflow_instance_migrations` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        instance_id -> Int8,
+        /// The `token_id` column of the `workflow_instance_migrations` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        token_id -> Int8,
+        /// The `from_version` column of the `workflow_instance_migrations` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        from_version -> Int4,
+        /// The `to_version` column of the `workflow_instance_migrations` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        to_version -> Int4,
+        /// The `from_state_id` column of the `workflow_instance_migrations` table.
+        ///
+        /// Its SQL type is `Uuid`.
+        ///
+        /// (Automatically generated by Diesel.)
+        from_state_id -> Uuid,
+        /// The `to_state_id` column of the `workflow_instance_migrations` table.
+        ///
+        /// Its SQL type is `Uuid`.
+        ///
+        /// (Automatically generated by Diesel.)
+        to_state_id -> Uuid,
+        /// The `migrated_by` column of the `workflow_instance_migrations` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        migrated_by -> Nullable<Int8>,
+        /// The `created_at` column of the `workflow_instance_migrations` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `workflow_instance_tasks` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    workflow_instance_tasks (id) {
+        /// The `id` column of the `workflow_instance_tasks` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `instance_id` column of the `workflow_instance_tasks` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        instance_id -> Int8,
+        /// The `state_id` column of the `workflow_instance_tasks` table.
+        ///
+        /// Its SQL type is `Uuid`.
+        ///
+        /// (Automatically generated by Diesel.)
+        state_id -> Uuid,
+        /// The `assignee_id` column of the `workflow_instance_tasks` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        assignee_id -> Nullable<Int8>,
+        /// The `completed_by` column of the `workflow_instance_tasks` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        completed_by -> Nullable<Int8>,
+        /// The `option_id` column of the `workflow_instance_tasks` table.
+        ///
+        /// Its SQL type is `Nullable<Uuid>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        option_id -> Nullable<Uuid>,
+        /// The `comment` column of the `workflow_instance_tasks` table.
+        ///
+        /// Its SQL type is `Nullable<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        comment -> Nullable<Text>,
+        /// The `created_at` column of the `workflow_instance_tasks` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamptz,
+        /// The `updated_at` column of the `workflow_instance_tasks` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        updated_at -> Timestamptz,
+        /// The `completed_at` column of the `workflow_instance_tasks` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamptz>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        completed_at -> Nullable<Timestamptz>,
+        /// The `due_at` column of the `workflow_instance_tasks` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamptz>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        due_at -> Nullable<Timestamptz>,
+        /// The `next_escalation_at` column of the `workflow_instance_tasks` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamptz>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        next_escalation_at -> Nullable<Timestamptz>,
+        /// The `token_id` column of the `workflow_instance_tasks` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        token_id -> Int8,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `workflow_instance_tokens` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    workflow_instance_tokens (id) {
+        /// The `id` column of the `workflow_instance_tokens` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `instance_id` column of the `workflow_instance_tokens` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        instance_id -> Int8,
+        /// The `parent_id` column of the `workflow_instance_tokens` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        parent_id -> Nullable<Int8>,
+        /// The `state_id` column of the `workflow_instance_tokens` table.
+        ///
+        /// Its SQL type is `Uuid`.
+        ///
+        /// (Automatically generated by Diesel.)
+        state_id -> Uuid,
+        /// The `created_at` column of the `workflow_instance_tokens` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamptz,
+        /// The `updated_at` column of the `workflow_instance_tokens` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        updated_at -> Timestamptz,
+        /// The `completed_at` column of the `workflow_instance_tokens` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamptz>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        completed_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
+
+    /// Representation of the `workflow_instances` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    workflow_instances (id) {
+        /// The `id` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `tenant_id` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        tenant_id -> Int4,
+        /// The `workflow_id` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        workflow_id -> Int8,
+        /// The `current_state_id` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Uuid`.
+        ///
+        /// (Automatically generated by Diesel.)
+        current_state_id -> Uuid,
+        /// The `data` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Jsonb`.
+        ///
+        /// (Automatically generated by Diesel.)
+        data -> Jsonb,
+        /// The `created_by` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_by -> Nullable<Int8>,
+        /// The `assignee_id` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        assignee_id -> Nullable<Int8>,
+        /// The `created_at` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamptz,
+        /// The `updated_at` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        updated_at -> Timestamptz,
+        /// The `completed_at` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamptz>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        completed_at -> Nullable<Timestamptz>,
+        /// The `deleted_at` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamptz>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        deleted_at -> Nullable<Timestamptz>,
+        /// The `parent_id` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        parent_id -> Nullable<Int8>,
+        /// The `parent_token_id` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        parent_token_id -> Nullable<Int8>,
+        /// The `search_vector` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Tsvector`.
+        ///
+        /// (Automatically generated by Diesel.)
+        search_vector -> Tsvector,
+        /// The `workflow_version` column of the `workflow_instances` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        workflow_version -> Int4,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `workflow_templates` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    workflow_templates (id) {
+        /// The `id` column of the `workflow_templates` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `name` column of the `workflow_templates` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        name -> Varchar,
+        /// The `description` column of the `workflow_templates` table.
+        ///
+        /// Its SQL type is `Nullable<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        description -> Nullable<Text>,
+        /// The `definition` column of the `workflow_templates` table.
+        ///
+        /// Its SQL type is `Jsonb`.
+        ///
+        /// (Automatically generated by Diesel.)
+        definition -> Jsonb,
+        /// The `created_at` column of the `workflow_templates` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamptz,
+        /// The `updated_at` column of the `workflow_templates` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        updated_at -> Timestamptz,
+        /// The `deleted_at` column of the `workflow_templates` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamptz>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        deleted_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `workflow_triggers` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    workflow_triggers (id) {
+        /// The `id` column of the `workflow_triggers` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `workflow_id` column of the `workflow_triggers` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        workflow_id -> Int8,
+        /// The `name` column of the `workflow_triggers` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        #[max_length = 255]
+        name -> Varchar,
+        /// The `state_id` column of the `workflow_triggers` table.
+        ///
+        /// Its SQL type is `Nullable<Uuid>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        state_id -> Nullable<Uuid>,
+        /// The `transition_id` column of the `workflow_triggers` table.
+        ///
+        /// Its SQL type is `Nullable<Uuid>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        transition_id -> Nullable<Uuid>,
+        /// The `token` column of the `workflow_triggers` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        #[max_length = 64]
+        token -> Varchar,
+        /// The `secret` column of the `workflow_triggers` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        #[max_length = 255]
+        secret -> Varchar,
+        /// The `auth` column of the `workflow_triggers` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        #[max_length = 20]
+        auth -> Varchar,
+        /// The `created_at` column of the `workflow_triggers` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamptz,
+        /// The `updated_at` column of the `workflow_triggers` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        updated_at -> Timestamptz,
+        /// The `deleted_at` column of the `workflow_triggers` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamptz>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        deleted_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `workflow_versions` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    workflow_versions (id) {
+        /// The `id` column of the `workflow_versions` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `workflow_id` column of the `workflow_versions` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        workflow_id -> Int8,
+        /// The `version` column of the `workflow_versions` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        version -> Int4,
+        /// The `definition` column of the `workflow_versions` table.
+        ///
+        /// Its SQL type is `Jsonb`.
+        ///
+        /// (Automatically generated by Diesel.)
+        definition -> Jsonb,
+        /// The `created_at` column of the `workflow_versions` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
+
+    /// Representation of the `workflows` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    workflows (id) {
+        /// The `id` column of the `workflows` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `tenant_id` column of the `workflows` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        tenant_id -> Int4,
+        /// The `name` column of the `workflows` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        #[max_length = 255]
+        name -> Varchar,
+        /// The `description` column of the `workflows` table.
+        ///
+        /// Its SQL type is `Nullable<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        description -> Nullable<Text>,
+        /// The `definition` column of the `workflows` table.
+        ///
+        /// Its SQL type is `Jsonb`.
+        ///
+        /// (Automatically generated by Diesel.)
+        definition -> Jsonb,
+        /// The `created_at` column of the `workflows` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamptz,
+        /// The `updated_at` column of the `workflows` table.
+        ///
+        /// Its SQL type is `Timestamptz`.
+        ///
+        /// (Automatically generated by Diesel.)
+        updated_at -> Timestamptz,
+        /// The `deleted_at` column of the `workflows` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamptz>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        deleted_at -> Nullable<Timestamptz>,
+        /// The `search_vector` column of the `workflows` table.
+        ///
+        /// Its SQL type is `Tsvector`.
+        ///
+        /// (Automatically generated by Diesel.)
+        search_vector -> Tsvector,
     }
 }
 
 diesel::joinable!(users -> tenants (tenant_id));
+diesel::joinable!(webhook_deliveries -> webhook_subscriptions (subscription_id));
+diesel::joinable!(webhook_subscriptions -> tenants (tenant_id));
+diesel::joinable!(workflow_instance_calls -> users (completed_by));
+diesel::joinable!(workflow_instance_calls -> workflow_instance_tokens (token_id));
+diesel::joinable!(workflow_instance_calls -> workflow_instances (instance_id));
+diesel::joinable!(workflow_instance_escalations -> workflow_instance_tasks (task_id));
+diesel::joinable!(workflow_instance_migrations -> users (migrated_by));
+diesel::joinable!(workflow_instance_migrations -> workflow_instance_tokens (token_id));
+diesel::joinable!(workflow_instance_migrations -> workflow_instances (instance_id));
+diesel::joinable!(workflow_instance_tasks -> workflow_instance_tokens (token_id));
+diesel::joinable!(workflow_instance_tasks -> workflow_instances (instance_id));
+diesel::joinable!(workflow_instance_tokens -> workflow_instances (instance_id));
+diesel::joinable!(workflow_instances -> tenants (tenant_id));
+diesel::joinable!(workflow_instances -> workflows (workflow_id));
+diesel::joinable!(workflow_triggers -> workflows (workflow_id));
+diesel::joinable!(workflow_versions -> workflows (workflow_id));
 diesel::joinable!(workflows -> tenants (tenant_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    idempotency_keys,
     tenants,
     users,
+    webhook_deliveries,
+    webhook_subscriptions,
+    workflow_instance_calls,
+    workflow_instance_escalations,
+    workflow_instance_migrations,
+    workflow_instance_tasks,
+    workflow_instance_tokens,
+    workflow_instances,
+    workflow_templates,
+    workflow_triggers,
+    workflow_versions,
     workflows,
 );
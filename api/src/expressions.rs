@@ -0,0 +1,833 @@
+//! A small, sandboxed expression language used to guard workflow transitions.
+//!
+//! Expressions are evaluated against the JSON data of an instance. They can
+//! read data through dotted paths (`claim.amount`, `items[0].sku`), compare
+//! values, do basic arithmetic and combine conditions, but they cannot call
+//! functions or have side effects:
+//!
+//! ```text
+//! claim.amount > 500 && status in ["open", "pending"]
+//! ```
+//!
+//! Paths that do not exist resolve to `null`, and ordering comparisons
+//! involving values of different types are `false`.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+use serde_json::{Number, Value};
+
+use crate::result::{AppError, Result};
+
+/// The longest expression source that will be parsed.
+const MAX_SOURCE_LEN: usize = 1024;
+
+/// The deepest nesting of sub-expressions that will be parsed.
+const MAX_DEPTH: usize = 32;
+
+/// The most expressions kept parsed by [`Expression::cached`].
+const CACHE_SIZE: usize = 1024;
+
+/// Parsed expressions, by their source.
+static CACHE: OnceLock<Mutex<HashMap<String, Arc<Expression>>>> = OnceLock::new();
+
+#[derive(Clone, Debug)]
+pub struct Expression {
+    source: String,
+    root: Expr,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Expression> {
+        if source.len() > MAX_SOURCE_LEN {
+            return Err(AppError::validation_error(format!(
+                "Expression is longer than {} characters",
+                MAX_SOURCE_LEN
+            )));
+        }
+
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+        };
+        let root = parser.parse_expr()?;
+
+        if let Some(token) = parser.peek().cloned() {
+            return Err(parser.error(format!("Unexpected {}", token)));
+        }
+
+        Ok(Expression {
+            source: source.to_string(),
+            root,
+        })
+    }
+
+    /// Parses the expression, or returns it parsed already. Guards and
+    /// templates are evaluated far more often than their definitions change,
+    /// so they are only parsed again once the cache is full and cleared.
+    pub fn cached(source: &str) -> Result<Arc<Expression>> {
+        let cache = CACHE.get_or_init(Default::default);
+
+        if let Some(expression) = cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(source)
+        {
+            return Ok(expression.clone());
+        }
+
+        let expression = Arc::new(Self::parse(source)?);
+        let mut cache = cache.lock().unwrap_or_else(PoisonError::into_inner);
+        if cache.len() >= CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(source.to_string(), expression.clone());
+
+        Ok(expression)
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Evaluates the expression and requires the result to be a boolean.
+    pub fn evaluate(&self, data: &Value) -> Result<bool> {
+        match self.evaluate_value(data)? {
+            Value::Bool(b) => Ok(b),
+            other => Err(AppError::validation_error(format!(
+                "Expression `{}` evaluated to {} instead of a boolean",
+                self.source, other
+            ))),
+        }
+    }
+
+    pub fn evaluate_value(&self, data: &Value) -> Result<Value> {
+        self.root.evaluate(data).map_err(|cause| {
+            AppError::validation_error(format!("Failed to evaluate `{}`: {}", self.source, cause))
+        })
+    }
+}
+
+// region: evaluation
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Literal(Value),
+    Path(Vec<PathSegment>),
+    List(Vec<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl Expr {
+    fn evaluate(&self, data: &Value) -> std::result::Result<Value, String> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Path(segments) => Ok(resolve_path(data, segments)),
+            Expr::List(items) => Ok(Value::Array(
+                items
+                    .iter()
+                    .map(|item| item.evaluate(data))
+                    .collect::<std::result::Result<_, _>>()?,
+            )),
+            Expr::Not(inner) => match inner.evaluate(data)? {
+                Value::Bool(b) => Ok(Value::Bool(!b)),
+                other => Err(format!("cannot negate {}", other)),
+            },
+            Expr::Negate(inner) => match inner.evaluate(data)?.as_f64() {
+                Some(n) => Ok(number(-n)),
+                None => Err("cannot negate a non-numeric value".to_string()),
+            },
+            Expr::Binary(BinaryOp::And, lhs, rhs) => {
+                if !as_bool(lhs.evaluate(data)?)? {
+                    return Ok(Value::Bool(false));
+                }
+                Ok(Value::Bool(as_bool(rhs.evaluate(data)?)?))
+            }
+            Expr::Binary(BinaryOp::Or, lhs, rhs) => {
+                if as_bool(lhs.evaluate(data)?)? {
+                    return Ok(Value::Bool(true));
+                }
+                Ok(Value::Bool(as_bool(rhs.evaluate(data)?)?))
+            }
+            Expr::Binary(op, lhs, rhs) => binary(*op, lhs.evaluate(data)?, rhs.evaluate(data)?),
+        }
+    }
+}
+
+fn resolve_path(data: &Value, segments: &[PathSegment]) -> Value {
+    let mut current = data;
+
+    for segment in segments {
+        let next = match segment {
+            PathSegment::Key(key) => current.get(key),
+            PathSegment::Index(index) => current.get(index),
+        };
+
+        match next {
+            Some(value) => current = value,
+            None => return Value::Null,
+        }
+    }
+
+    current.clone()
+}
+
+fn as_bool(value: Value) -> std::result::Result<bool, String> {
+    match value {
+        Value::Bool(b) => Ok(b),
+        other => Err(format!("expected a boolean but found {}", other)),
+    }
+}
+
+fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        return Value::from(n as i64);
+    }
+
+    Number::from_f64(n)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+fn equals(lhs: &Value, rhs: &Value) -> bool {
+    match (lhs.as_f64(), rhs.as_f64()) {
+        (Some(l), Some(r)) => l == r,
+        _ => lhs == rhs,
+    }
+}
+
+fn compare(lhs: &Value, rhs: &Value) -> Option<std::cmp::Ordering> {
+    match (lhs, rhs) {
+        (Value::Number(l), Value::Number(r)) => l.as_f64()?.partial_cmp(&r.as_f64()?),
+        (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+        _ => None,
+    }
+}
+
+fn binary(op: BinaryOp, lhs: Value, rhs: Value) -> std::result::Result<Value, String> {
+    use std::cmp::Ordering;
+
+    let result = match op {
+        BinaryOp::Eq => Value::Bool(equals(&lhs, &rhs)),
+        BinaryOp::Ne => Value::Bool(!equals(&lhs, &rhs)),
+        BinaryOp::Lt => Value::Bool(compare(&lhs, &rhs) == Some(Ordering::Less)),
+        BinaryOp::Le => Value::Bool(matches!(
+            compare(&lhs, &rhs),
+            Some(Ordering::Less | Ordering::Equal)
+        )),
+        BinaryOp::Gt => Value::Bool(compare(&lhs, &rhs) == Some(Ordering::Greater)),
+        BinaryOp::Ge => Value::Bool(matches!(
+            compare(&lhs, &rhs),
+            Some(Ordering::Greater | Ordering::Equal)
+        )),
+        BinaryOp::In => match (&lhs, &rhs) {
+            (_, Value::Array(items)) => Value::Bool(items.iter().any(|i| equals(&lhs, i))),
+            (Value::String(needle), Value::String(haystack)) => {
+                Value::Bool(haystack.contains(needle.as_str()))
+            }
+            (_, Value::Null) => Value::Bool(false),
+            _ => return Err(format!("cannot search for {} in {}", lhs, rhs)),
+        },
+        BinaryOp::Add => match (&lhs, &rhs) {
+            (Value::String(l), Value::String(r)) => Value::String(format!("{l}{r}")),
+            _ => arithmetic(op, &lhs, &rhs)?,
+        },
+        BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+            arithmetic(op, &lhs, &rhs)?
+        }
+        BinaryOp::And | BinaryOp::Or => unreachable!("logical operators short circuit"),
+    };
+
+    Ok(result)
+}
+
+fn arithmetic(op: BinaryOp, lhs: &Value, rhs: &Value) -> std::result::Result<Value, String> {
+    let (Some(l), Some(r)) = (lhs.as_f64(), rhs.as_f64()) else {
+        return Err(format!("cannot apply {} to {} and {}", op, lhs, rhs));
+    };
+
+    match op {
+        BinaryOp::Add => Ok(number(l + r)),
+        BinaryOp::Sub => Ok(number(l - r)),
+        BinaryOp::Mul => Ok(number(l * r)),
+        BinaryOp::Div | BinaryOp::Rem if r == 0.0 => Err("division by zero".to_string()),
+        BinaryOp::Div => Ok(number(l / r)),
+        BinaryOp::Rem => Ok(number(l % r)),
+        _ => Err(format!("{} is not an arithmetic operator", op)),
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = match self {
+            BinaryOp::Or => "||",
+            BinaryOp::And => "&&",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::In => "in",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+        };
+
+        write!(f, "{}", value)
+    }
+}
+
+// endregion
+
+// region: tokenizer
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Number(f64),
+    String(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    offset: usize,
+}
+
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::Number(n) => write!(f, "number {}", n),
+            TokenKind::String(s) => write!(f, "string \"{}\"", s),
+            TokenKind::Ident(i) => write!(f, "`{}`", i),
+            TokenKind::Op(op) => write!(f, "`{}`", op),
+        }
+    }
+}
+
+const OPERATORS: [&str; 20] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "+", "-", "*", "/", "%", "(", ")", "[", "]",
+    ",", ".",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (offset, c) = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].1.is_ascii_digit() || chars[i].1 == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().map(|(_, c)| c).collect();
+            let value = text.parse::<f64>().map_err(|_| {
+                AppError::validation_error(format!("Invalid number `{}` at {}", text, offset))
+            })?;
+            tokens.push(Token {
+                kind: TokenKind::Number(value),
+                offset,
+            });
+            continue;
+        }
+
+        if c == '"' || c == '\'' {
+            let quote = c;
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => {
+                        return Err(AppError::validation_error(format!(
+                            "Unterminated string starting at {}",
+                            offset
+                        )))
+                    }
+                    Some((_, '\\')) => {
+                        if let Some((_, escaped)) = chars.get(i + 1) {
+                            value.push(*escaped);
+                        }
+                        i += 2;
+                    }
+                    Some((_, ch)) if *ch == quote => {
+                        i += 1;
+                        break;
+                    }
+                    Some((_, ch)) => {
+                        value.push(*ch);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(Token {
+                kind: TokenKind::String(value),
+                offset,
+            });
+            continue;
+        }
+
+        if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].1.is_alphanumeric() || chars[i].1 == '_') {
+                i += 1;
+            }
+            tokens.push(Token {
+                kind: TokenKind::Ident(chars[start..i].iter().map(|(_, c)| c).collect()),
+                offset,
+            });
+            continue;
+        }
+
+        let rest = &source[offset..];
+        match OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            Some(op) => {
+                tokens.push(Token {
+                    kind: TokenKind::Op(op),
+                    offset,
+                });
+                i += op.len();
+            }
+            None => {
+                return Err(AppError::validation_error(format!(
+                    "Unexpected character `{}` at {}",
+                    c, offset
+                )))
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+// endregion
+
+// region: parser
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|t| &t.kind)
+    }
+
+    fn advance(&mut self) -> Option<TokenKind> {
+        let token = self.tokens.get(self.pos).map(|t| t.kind.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn error(&self, message: String) -> AppError {
+        match self.tokens.get(self.pos) {
+            Some(token) => AppError::validation_error(format!("{} at {}", message, token.offset)),
+            None => AppError::validation_error(format!("{} at end of expression", message)),
+        }
+    }
+
+    /// Consumes the next token if it is one of the given operators or
+    /// keywords.
+    fn eat(&mut self, ops: &[&str]) -> Option<&'static str> {
+        let matched = match self.peek() {
+            Some(TokenKind::Op(op)) => ops.iter().find(|o| *o == op).map(|_| *op),
+            Some(TokenKind::Ident(word)) => match word.as_str() {
+                "and" if ops.contains(&"&&") => Some("&&"),
+                "or" if ops.contains(&"||") => Some("||"),
+                "not" if ops.contains(&"!") => Some("!"),
+                "in" if ops.contains(&"in") => Some("in"),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        if matched.is_some() {
+            self.pos += 1;
+        }
+
+        matched
+    }
+
+    fn expect(&mut self, op: &str) -> Result<()> {
+        match self.eat(&[op]) {
+            Some(_) => Ok(()),
+            None => Err(self.error(format!("Expected `{}`", op))),
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.error("Expression is nested too deeply".to_string()));
+        }
+
+        let expr = self.parse_or();
+        self.depth -= 1;
+        expr
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_and()?;
+        while self.eat(&["||"]).is_some() {
+            let rhs = self.parse_and()?;
+            lhs = Expr::Binary(BinaryOp::Or, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_not()?;
+        while self.eat(&["&&"]).is_some() {
+            let rhs = self.parse_not()?;
+            lhs = Expr::Binary(BinaryOp::And, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr> {
+        if self.eat(&["!"]).is_some() {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr> {
+        let lhs = self.parse_sum()?;
+        let op = match self.eat(&["==", "!=", "<=", ">=", "<", ">", "in"]) {
+            Some("==") => BinaryOp::Eq,
+            Some("!=") => BinaryOp::Ne,
+            Some("<=") => BinaryOp::Le,
+            Some(">=") => BinaryOp::Ge,
+            Some("<") => BinaryOp::Lt,
+            Some(">") => BinaryOp::Gt,
+            Some("in") => BinaryOp::In,
+            _ => return Ok(lhs),
+        };
+        let rhs = self.parse_sum()?;
+        Ok(Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+    }
+
+    fn parse_sum(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_term()?;
+        while let Some(op) = self.eat(&["+", "-"]) {
+            let op = if op == "+" {
+                BinaryOp::Add
+            } else {
+                BinaryOp::Sub
+            };
+            let rhs = self.parse_term()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_term(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_unary()?;
+        while let Some(op) = self.eat(&["*", "/", "%"]) {
+            let op = match op {
+                "*" => BinaryOp::Mul,
+                "/" => BinaryOp::Div,
+                _ => BinaryOp::Rem,
+            };
+            let rhs = self.parse_unary()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.eat(&["-"]).is_some() {
+            return Ok(Expr::Negate(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        match self.advance() {
+            Some(TokenKind::Number(n)) => Ok(Expr::Literal(number(n))),
+            Some(TokenKind::String(s)) => Ok(Expr::Literal(Value::String(s))),
+            Some(TokenKind::Op("(")) => {
+                let expr = self.parse_expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(TokenKind::Op("[")) => {
+                let mut items = Vec::new();
+                if self.eat(&["]"]).is_none() {
+                    loop {
+                        items.push(self.parse_expr()?);
+                        if self.eat(&[","]).is_none() {
+                            break;
+                        }
+                    }
+                    self.expect("]")?;
+                }
+                Ok(Expr::List(items))
+            }
+            Some(TokenKind::Ident(word)) => match word.as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "null" => Ok(Expr::Literal(Value::Null)),
+                "and" | "or" | "not" | "in" => {
+                    self.pos -= 1;
+                    Err(self.error(format!("Unexpected keyword `{}`", word)))
+                }
+                _ => self.parse_path(word),
+            },
+            Some(token) => {
+                self.pos -= 1;
+                Err(self.error(format!("Unexpected {}", token)))
+            }
+            None => Err(self.error("Expected a value".to_string())),
+        }
+    }
+
+    fn parse_path(&mut self, first: String) -> Result<Expr> {
+        let mut segments = vec![PathSegment::Key(first)];
+
+        loop {
+            if self.eat(&["."]).is_some() {
+                match self.advance() {
+                    Some(TokenKind::Ident(key)) => segments.push(PathSegment::Key(key)),
+                    _ => {
+                        self.pos -= 1;
+                        return Err(self.error("Expected a field name".to_string()));
+                    }
+                }
+            } else if self.eat(&["["]).is_some() {
+                match self.advance() {
+                    Some(TokenKind::Number(n)) if n >= 0.0 && n.fract() == 0.0 => {
+                        segments.push(PathSegment::Index(n as usize))
+                    }
+                    Some(TokenKind::String(key)) => segments.push(PathSegment::Key(key)),
+                    _ => {
+                        self.pos -= 1;
+                        return Err(self.error("Expected an index or key".to_string()));
+                    }
+                }
+                self.expect("]")?;
+            } else {
+                return Ok(Expr::Path(segments));
+            }
+        }
+    }
+}
+
+// endregion
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn data() -> Value {
+        json!({
+            "claim": { "amount": 750, "status": "open", "odd key": true },
+            "items": [{ "sku": "A-1" }, { "sku": "B-2" }],
+            "flag": false,
+        })
+    }
+
+    fn eval(source: &str) -> Value {
+        Expression::parse(source)
+            .and_then(|e| e.evaluate_value(&data()))
+            .unwrap_or_else(|e| panic!("`{}` failed: {}", source, e))
+    }
+
+    fn error(source: &str) -> String {
+        match Expression::parse(source).and_then(|e| e.evaluate_value(&data())) {
+            Ok(value) => panic!("`{}` evaluated to {}", source, value),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn arithmetic_binds_tighter_than_comparison() {
+        assert_eq!(eval("1 + 2 * 3"), json!(7));
+        assert_eq!(eval("(1 + 2) * 3"), json!(9));
+        assert_eq!(eval("10 - 4 - 3"), json!(3));
+        assert_eq!(eval("-2 * 3"), json!(-6));
+        assert_eq!(eval("7 % 4 + 1"), json!(4));
+        assert_eq!(eval("1 / 4"), json!(0.25));
+        assert_eq!(eval("1 + 2 * 3 == 7"), json!(true));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(eval("true || false && false"), json!(true));
+        assert_eq!(eval("(true || false) && false"), json!(false));
+        assert_eq!(eval("!false && false"), json!(false));
+        assert_eq!(eval("not flag and claim.amount > 500"), json!(true));
+        assert_eq!(
+            eval("flag or claim.status in ['open', 'pending']"),
+            json!(true)
+        );
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        assert_eq!(eval("false && 1 / 0 == 1"), json!(false));
+        assert_eq!(eval("true || 1 / 0 == 1"), json!(true));
+        assert_eq!(eval("flag && missing"), json!(false));
+        assert_eq!(
+            error("true && 1 / 0 == 1"),
+            "Failed to evaluate `true && 1 / 0 == 1`: division by zero"
+        );
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_are_errors() {
+        assert_eq!(error("!1"), "Failed to evaluate `!1`: cannot negate 1");
+        assert_eq!(
+            error("-claim.status"),
+            "Failed to evaluate `-claim.status`: cannot negate a non-numeric value"
+        );
+        assert_eq!(
+            error("'a' - 1"),
+            "Failed to evaluate `'a' - 1`: cannot apply - to \"a\" and 1"
+        );
+        assert_eq!(
+            error("true && 1"),
+            "Failed to evaluate `true && 1`: expected a boolean but found 1"
+        );
+        assert_eq!(
+            error("1 in 2"),
+            "Failed to evaluate `1 in 2`: cannot search for 1 in 2"
+        );
+
+        let result = Expression::parse("1 + 1").unwrap().evaluate(&data());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Expression `1 + 1` evaluated to 2 instead of a boolean"
+        );
+    }
+
+    #[test]
+    fn values_of_different_types_are_not_ordered() {
+        assert_eq!(eval("1 < 'a'"), json!(false));
+        assert_eq!(eval("1 >= 'a'"), json!(false));
+        assert_eq!(eval("1 == '1'"), json!(false));
+        assert_eq!(eval("1 == 1.0"), json!(true));
+        assert_eq!(eval("'abc' < 'abd'"), json!(true));
+        assert_eq!(eval("'A' + '-' + '1' == 'A-1'"), json!(true));
+    }
+
+    #[test]
+    fn missing_paths_are_null() {
+        assert_eq!(eval("missing"), json!(null));
+        assert_eq!(eval("missing == null"), json!(true));
+        assert_eq!(eval("claim.missing.deeper"), json!(null));
+        assert_eq!(eval("items[5].sku"), json!(null));
+        assert_eq!(eval("missing > 1"), json!(false));
+        assert_eq!(eval("'x' in missing"), json!(false));
+    }
+
+    #[test]
+    fn paths_read_fields_and_items() {
+        assert_eq!(eval("claim.amount"), json!(750));
+        assert_eq!(eval("items[1].sku"), json!("B-2"));
+        assert_eq!(eval("claim['odd key']"), json!(true));
+        assert_eq!(eval("'A' in items[0].sku"), json!(true));
+        assert_eq!(eval("[claim.amount, 1]"), json!([750, 1]));
+    }
+
+    #[test]
+    fn strings_take_either_quote_and_escapes() {
+        assert_eq!(eval(r#""it's""#), json!("it's"));
+        assert_eq!(eval(r#"'it\'s'"#), json!("it's"));
+        assert_eq!(eval(r#""say \"hi\"""#), json!("say \"hi\""));
+        assert_eq!(eval(r#"'a\\b'"#), json!("a\\b"));
+    }
+
+    #[test]
+    fn syntax_errors_point_at_their_offset() {
+        assert_eq!(error("1 +"), "Expected a value at end of expression");
+        assert_eq!(error("1 2"), "Unexpected number 2 at 2");
+        assert_eq!(error("(1 + 2"), "Expected `)` at end of expression");
+        assert_eq!(
+            error("claim."),
+            "Expected a field name at end of expression"
+        );
+        assert_eq!(error("items[x]"), "Expected an index or key at 6");
+        assert_eq!(error("a # b"), "Unexpected character `#` at 2");
+        assert_eq!(error("'open"), "Unterminated string starting at 0");
+        assert_eq!(error("and"), "Unexpected keyword `and` at 0");
+        assert_eq!(error("1.2.3"), "Invalid number `1.2.3` at 0");
+    }
+
+    #[test]
+    fn long_sources_are_rejected() {
+        let longest = format!("'{}'", "a".repeat(MAX_SOURCE_LEN - 2));
+        assert_eq!(eval(&longest), json!("a".repeat(MAX_SOURCE_LEN - 2)));
+
+        let source = format!("'{}'", "a".repeat(MAX_SOURCE_LEN - 1));
+        assert_eq!(error(&source), "Expression is longer than 1024 characters");
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        let nested = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+
+        assert_eq!(eval(&nested(MAX_DEPTH - 1)), json!(1));
+        assert_eq!(
+            error(&nested(MAX_DEPTH)),
+            "Expression is nested too deeply at 32"
+        );
+
+        let lists = format!("{}1{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(error(&lists).starts_with("Expression is nested too deeply"));
+    }
+
+    #[test]
+    fn cached_expressions_are_parsed_once() {
+        let first = Expression::cached("claim.amount > 500").unwrap();
+        let second = Expression::cached("claim.amount > 500").unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(second.evaluate(&data()).unwrap());
+        assert!(Expression::cached("claim.amount >").is_err());
+    }
+}
@@ -0,0 +1,655 @@
+//! Filters for list queries, sent in the `filter` query string parameter.
+//!
+//! A filter is one or more conditions on a field, combined with `and` and
+//! `or` and grouped with parentheses. `and` binds tighter than `or`:
+//!
+//! ```text
+//! name like 'Acme*' and (created_at gt 2026-01-01 or id in (1, 2, 3))
+//! ```
+//!
+//! The operators are `eq`, `ne`, `in`, `like`, `gt` and `lt`. `like` matches
+//! text case-insensitively, with `*` standing for any run of characters, and
+//! `gt` and `lt` compare numbers and dates. Dates are either RFC 3339
+//! timestamps or plain dates, which stand for midnight UTC. Values containing
+//! spaces, commas or parentheses are quoted with `'`, doubling any `'` inside
+//! them. Rows where an optional field is not set never match a condition on it.
+//!
+//! A filter is at most [`MAX_FILTER_LEN`] characters long, with at most
+//! [`MAX_CONDITIONS`] conditions in at most [`MAX_DEPTH`] nested parentheses.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use diesel::expression::BoxableExpression;
+use diesel::sql_types::Bool;
+use diesel::BoolExpressionMethods;
+
+use crate::database::DB;
+use crate::result::{AppError, Result};
+
+/// The longest filter accepted, in bytes.
+const MAX_FILTER_LEN: usize = 2048;
+
+/// The most conditions a filter may have.
+const MAX_CONDITIONS: usize = 20;
+
+/// How deeply parentheses may be nested, which bounds the recursion of the
+/// parser.
+const MAX_DEPTH: usize = 16;
+
+/// A condition, boxed so it can be applied to a boxed query on the table.
+pub type BoxedCondition<T> = Box<dyn BoxableExpression<T, DB, SqlType = Bool>>;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    In,
+    Like,
+    Gt,
+    Lt,
+}
+
+impl Operator {
+    fn parse(name: &str) -> Option<Operator> {
+        match name.to_ascii_lowercase().as_str() {
+            "eq" => Some(Operator::Eq),
+            "ne" => Some(Operator::Ne),
+            "in" => Some(Operator::In),
+            "like" => Some(Operator::Like),
+            "gt" => Some(Operator::Gt),
+            "lt" => Some(Operator::Lt),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Operator::Eq => "eq",
+            Operator::Ne => "ne",
+            Operator::In => "in",
+            Operator::Like => "like",
+            Operator::Gt => "gt",
+            Operator::Lt => "lt",
+        }
+    }
+}
+
+/// A comparison of a field with one value, or with a list of them for `in`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Condition {
+    pub field: String,
+    pub operator: Operator,
+    pub values: Vec<String>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Filter {
+    Condition(Condition),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+}
+
+/// A type that values of a field are parsed as.
+pub trait FilterValue: Sized {
+    fn parse(value: &str) -> Option<Self>;
+}
+
+impl FilterValue for String {
+    fn parse(value: &str) -> Option<Self> {
+        Some(value.to_string())
+    }
+}
+
+impl FilterValue for i32 {
+    fn parse(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+}
+
+impl FilterValue for i64 {
+    fn parse(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+}
+
+impl FilterValue for DateTime<Utc> {
+    fn parse(value: &str) -> Option<Self> {
+        DateTime::parse_from_rfc3339(value)
+            .map(|date| date.with_timezone(&Utc))
+            .ok()
+            .or_else(|| {
+                let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+                Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+            })
+    }
+}
+
+/// A table whose rows can be filtered. The implementation maps each
+/// filterable field to a condition on its column, usually with
+/// [`filter_condition!`](crate::filter_condition).
+pub trait Filterable: Sized {
+    fn condition(condition: &Condition) -> Result<BoxedCondition<Self>>;
+}
+
+/// Escapes the characters `like` gives a meaning to, so that the text only
+/// matches itself.
+pub fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+impl Condition {
+    /// The single value compared with.
+    pub fn value<V: FilterValue>(&self) -> Result<V> {
+        match self.values.as_slice() {
+            [value] => self.parse(value),
+            _ => Err(self.error("expects a single value")),
+        }
+    }
+
+    /// The values compared with by `in`.
+    pub fn values<V: FilterValue>(&self) -> Result<Vec<V>> {
+        self.values.iter().map(|value| self.parse(value)).collect()
+    }
+
+    /// The SQL pattern matched by `like`.
+    pub fn pattern(&self) -> Result<String> {
+        let value: String = self.value()?;
+        Ok(escape_like(&value).replace('*', "%"))
+    }
+
+    /// The error for an operator the field does not support.
+    pub fn unsupported(&self) -> AppError {
+        self.error("is not supported for this field")
+    }
+
+    /// The error for a field that cannot be filtered on.
+    pub fn unknown_field(&self) -> AppError {
+        AppError::validation_error(format!("Invalid filter: unknown field {}", self.field))
+    }
+
+    fn parse<V: FilterValue>(&self, value: &str) -> Result<V> {
+        V::parse(value).ok_or_else(|| self.error(&format!("has an invalid value {}", value)))
+    }
+
+    fn error(&self, message: &str) -> AppError {
+        AppError::validation_error(format!(
+            "Invalid filter: {} {} {}",
+            self.field,
+            self.operator.name(),
+            message
+        ))
+    }
+}
+
+impl Filter {
+    pub fn parse(filter: &str) -> Result<Filter> {
+        if filter.len() > MAX_FILTER_LEN {
+            return Err(invalid(format!(
+                "longer than {} characters",
+                MAX_FILTER_LEN
+            )));
+        }
+
+        let mut parser = Parser {
+            tokens: tokenize(filter)?,
+            position: 0,
+            conditions: 0,
+            depth: 0,
+        };
+
+        let filter = parser.or()?;
+        match parser.next() {
+            None => Ok(filter),
+            Some(token) => Err(invalid(format!("unexpected {}", token))),
+        }
+    }
+
+    /// Builds the condition on the table that the filter stands for.
+    pub fn expression<T: Filterable + 'static>(&self) -> Result<BoxedCondition<T>> {
+        match self {
+            Filter::Condition(condition) => T::condition(condition),
+            Filter::And(filters) => combine(filters, |a, b| Box::new(a.and(b))),
+            Filter::Or(filters) => combine(filters, |a, b| Box::new(a.or(b))),
+        }
+    }
+}
+
+/// Parses the filter sent with a list query, and builds its condition.
+pub fn condition<T: Filterable + 'static>(
+    filter: Option<&str>,
+) -> Result<Option<BoxedCondition<T>>> {
+    filter
+        .filter(|filter| !filter.trim().is_empty())
+        .map(|filter| Filter::parse(filter)?.expression())
+        .transpose()
+}
+
+/// Boxes a condition on the table.
+pub fn boxed<T, E>(expression: E) -> BoxedCondition<T>
+where
+    E: BoxableExpression<T, DB, SqlType = Bool> + 'static,
+{
+    Box::new(expression)
+}
+
+fn combine<T: Filterable + 'static>(
+    filters: &[Filter],
+    op: impl Fn(BoxedCondition<T>, BoxedCondition<T>) -> BoxedCondition<T>,
+) -> Result<BoxedCondition<T>> {
+    let mut filters = filters.iter();
+    let first = filters
+        .next()
+        .ok_or_else(|| invalid("empty group".to_string()))?
+        .expression()?;
+
+    filters.try_fold(first, |expression, filter| {
+        Ok(op(expression, filter.expression()?))
+    })
+}
+
+/// Builds a condition on a column, for a field of [`Filterable::condition`].
+/// Text fields are given as `like`, and support `eq`, `ne`, `in` and `like`.
+/// Other fields are given as the type their values are parsed as, and support
+/// `eq`, `ne`, `in`, `gt` and `lt`.
+///
+/// ```ignore
+/// "name" => filter_condition!(condition, tenants::name, like),
+/// "created_at" => filter_condition!(condition, tenants::created_at, DateTime<Utc>),
+/// ```
+#[macro_export]
+macro_rules! filter_condition {
+    ($condition:expr, $column:expr, like) => {{
+        use $crate::filters::{boxed, Operator};
+
+        let condition = $condition;
+        let column = $column;
+        match condition.operator {
+            Operator::Eq => Ok(boxed(column.eq(condition.value::<String>()?))),
+            Operator::Ne => Ok(boxed(column.ne(condition.value::<String>()?))),
+            Operator::In => Ok(boxed(column.eq_any(condition.values::<String>()?))),
+            Operator::Like => Ok(boxed(column.ilike(condition.pattern()?))),
+            Operator::Gt | Operator::Lt => Err(condition.unsupported()),
+        }
+    }};
+    ($condition:expr, $column:expr, $ty:ty) => {{
+        use $crate::filters::{boxed, Operator};
+
+        let condition = $condition;
+        let column = $column;
+        match condition.operator {
+            Operator::Eq => Ok(boxed(column.eq(condition.value::<$ty>()?))),
+            Operator::Ne => Ok(boxed(column.ne(condition.value::<$ty>()?))),
+            Operator::In => Ok(boxed(column.eq_any(condition.values::<$ty>()?))),
+            Operator::Gt => Ok(boxed(column.gt(condition.value::<$ty>()?))),
+            Operator::Lt => Ok(boxed(column.lt(condition.value::<$ty>()?))),
+            Operator::Like => Err(condition.unsupported()),
+        }
+    }};
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Comma,
+    Word(String),
+    Quoted(String),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Quoted(value) => write!(f, "'{}'", value),
+        }
+    }
+}
+
+fn invalid(message: String) -> AppError {
+    AppError::validation_error(format!("Invalid filter: {}", message))
+}
+
+fn tokenize(filter: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = filter.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            ',' => tokens.push(Token::Comma),
+            '\'' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\'') if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                            value.push('\'');
+                        }
+                        Some('\'') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(invalid("unterminated quote".to_string())),
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | ',' | '\'') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    conditions: usize,
+    /// How many parentheses the parser is in.
+    depth: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(invalid(format!("expected {}, found {}", expected, token))),
+            None => Err(invalid(format!("expected {}", expected))),
+        }
+    }
+
+    fn or(&mut self) -> Result<Filter> {
+        let mut filters = vec![self.and()?];
+        while self.keyword("or") {
+            filters.push(self.and()?);
+        }
+
+        Ok(match filters.len() {
+            1 => filters.remove(0),
+            _ => Filter::Or(filters),
+        })
+    }
+
+    fn and(&mut self) -> Result<Filter> {
+        let mut filters = vec![self.primary()?];
+        while self.keyword("and") {
+            filters.push(self.primary()?);
+        }
+
+        Ok(match filters.len() {
+            1 => filters.remove(0),
+            _ => Filter::And(filters),
+        })
+    }
+
+    fn primary(&mut self) -> Result<Filter> {
+        if self.peek() == Some(&Token::Open) {
+            self.position += 1;
+            self.depth += 1;
+            if self.depth > MAX_DEPTH {
+                return Err(invalid(format!(
+                    "parentheses may be nested at most {} deep",
+                    MAX_DEPTH
+                )));
+            }
+
+            let filter = self.or()?;
+            self.expect(Token::Close)?;
+            self.depth -= 1;
+            return Ok(filter);
+        }
+
+        let field = match self.next() {
+            Some(Token::Word(field)) => field,
+            Some(token) => return Err(invalid(format!("expected a field, found {}", token))),
+            None => return Err(invalid("expected a field".to_string())),
+        };
+
+        let operator = match self.next() {
+            Some(Token::Word(name)) => Operator::parse(&name)
+                .ok_or_else(|| invalid(format!("unknown operator {}", name)))?,
+            Some(token) => {
+                return Err(invalid(format!(
+                    "expected an operator after {}, found {}",
+                    field, token
+                )))
+            }
+            None => return Err(invalid(format!("expected an operator after {}", field))),
+        };
+
+        let values = match operator {
+            Operator::In => {
+                self.expect(Token::Open)?;
+                let mut values = vec![self.value()?];
+                while self.peek() == Some(&Token::Comma) {
+                    self.position += 1;
+                    values.push(self.value()?);
+                }
+                self.expect(Token::Close)?;
+                values
+            }
+            _ => vec![self.value()?],
+        };
+
+        self.conditions += 1;
+        if self.conditions > MAX_CONDITIONS {
+            return Err(invalid(format!(
+                "at most {} conditions are allowed",
+                MAX_CONDITIONS
+            )));
+        }
+
+        Ok(Filter::Condition(Condition {
+            field,
+            operator,
+            values,
+        }))
+    }
+
+    fn value(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => Ok(value),
+            Some(token) => Err(invalid(format!("expected a value, found {}", token))),
+            None => Err(invalid("expected a value".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(filter: &str) -> Filter {
+        Filter::parse(filter).unwrap_or_else(|e| panic!("`{}` failed: {}", filter, e))
+    }
+
+    fn error(filter: &str) -> String {
+        match Filter::parse(filter) {
+            Ok(parsed) => panic!("`{}` parsed as {:?}", filter, parsed),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn condition(field: &str, operator: Operator, values: &[&str]) -> Filter {
+        Filter::Condition(Condition {
+            field: field.to_string(),
+            operator,
+            values: values.iter().map(|v| v.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn parses_each_operator() {
+        assert_eq!(
+            parse("name eq Acme"),
+            condition("name", Operator::Eq, &["Acme"])
+        );
+        assert_eq!(parse("id ne 4"), condition("id", Operator::Ne, &["4"]));
+        assert_eq!(
+            parse("name like 'Ac*'"),
+            condition("name", Operator::Like, &["Ac*"])
+        );
+        assert_eq!(parse("id gt 1"), condition("id", Operator::Gt, &["1"]));
+        assert_eq!(parse("id lt 9"), condition("id", Operator::Lt, &["9"]));
+        assert_eq!(
+            parse("id in (1, 2,3)"),
+            condition("id", Operator::In, &["1", "2", "3"])
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("id eq 1 or id eq 2 and name eq b"),
+            Filter::Or(vec![
+                condition("id", Operator::Eq, &["1"]),
+                Filter::And(vec![
+                    condition("id", Operator::Eq, &["2"]),
+                    condition("name", Operator::Eq, &["b"]),
+                ]),
+            ])
+        );
+        assert_eq!(
+            parse("(id eq 1 OR id eq 2) AND name eq b"),
+            Filter::And(vec![
+                Filter::Or(vec![
+                    condition("id", Operator::Eq, &["1"]),
+                    condition("id", Operator::Eq, &["2"]),
+                ]),
+                condition("name", Operator::Eq, &["b"]),
+            ])
+        );
+    }
+
+    #[test]
+    fn quoted_values_keep_spaces_commas_and_parentheses() {
+        assert_eq!(
+            parse("name eq 'Acme, Inc. (East)'"),
+            condition("name", Operator::Eq, &["Acme, Inc. (East)"])
+        );
+        assert_eq!(
+            parse("name in ('O''Brien', '')"),
+            condition("name", Operator::In, &["O'Brien", ""])
+        );
+        assert_eq!(
+            parse("name eq 'and'"),
+            condition("name", Operator::Eq, &["and"])
+        );
+        assert_eq!(error("name eq 'Acme"), "Invalid filter: unterminated quote");
+    }
+
+    #[test]
+    fn reports_where_a_filter_is_malformed() {
+        assert_eq!(error(""), "Invalid filter: expected a field");
+        assert_eq!(
+            error("name"),
+            "Invalid filter: expected an operator after name"
+        );
+        assert_eq!(error("name is Acme"), "Invalid filter: unknown operator is");
+        assert_eq!(
+            error("name , Acme"),
+            "Invalid filter: expected an operator after name, found ,"
+        );
+        assert_eq!(error("name eq"), "Invalid filter: expected a value");
+        assert_eq!(error("id in 1"), "Invalid filter: expected (, found 1");
+        assert_eq!(error("id in (1, 2"), "Invalid filter: expected )");
+        assert_eq!(error("(id eq 1"), "Invalid filter: expected )");
+        assert_eq!(error("id eq 1)"), "Invalid filter: unexpected )");
+        assert_eq!(error("id eq 1 id eq 2"), "Invalid filter: unexpected id");
+        assert_eq!(error("id eq 1 and"), "Invalid filter: expected a field");
+        assert_eq!(
+            error("(, eq 1)"),
+            "Invalid filter: expected a field, found ,"
+        );
+    }
+
+    #[test]
+    fn limits_the_number_of_conditions() {
+        let conditions = |n| vec!["id eq 1"; n].join(" or ");
+
+        assert!(Filter::parse(&conditions(MAX_CONDITIONS)).is_ok());
+        assert_eq!(
+            error(&conditions(MAX_CONDITIONS + 1)),
+            format!(
+                "Invalid filter: at most {} conditions are allowed",
+                MAX_CONDITIONS
+            )
+        );
+    }
+
+    #[test]
+    fn limits_how_deeply_parentheses_nest() {
+        let nested = |depth| format!("{}id eq 1{}", "(".repeat(depth), ")".repeat(depth));
+
+        assert_eq!(
+            parse(&nested(MAX_DEPTH)),
+            condition("id", Operator::Eq, &["1"])
+        );
+        assert_eq!(
+            error(&nested(MAX_DEPTH + 1)),
+            format!(
+                "Invalid filter: parentheses may be nested at most {} deep",
+                MAX_DEPTH
+            )
+        );
+
+        // Deep enough to overflow the stack if the parser recursed into it
+        assert!(error(&"(".repeat(MAX_FILTER_LEN)).contains("nested at most"));
+    }
+
+    #[test]
+    fn limits_the_length_of_a_filter() {
+        let padded = |len| format!("name eq '{}'", "a".repeat(len - "name eq ''".len()));
+
+        assert!(Filter::parse(&padded(MAX_FILTER_LEN)).is_ok());
+        assert_eq!(
+            error(&padded(MAX_FILTER_LEN + 1)),
+            format!("Invalid filter: longer than {} characters", MAX_FILTER_LEN)
+        );
+        assert!(error(&"(".repeat(5000)).contains("longer than"));
+    }
+
+    #[test]
+    fn like_patterns_match_their_text_literally() {
+        assert_eq!(escape_like(r"50%_off\"), r"50\%\_off\\");
+
+        let Filter::Condition(like) = parse("name like '10%*'") else {
+            panic!("not a condition");
+        };
+        assert_eq!(like.pattern().unwrap(), r"10\%%");
+    }
+}
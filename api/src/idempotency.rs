@@ -0,0 +1,219 @@
+//! Records the responses to requests sent with an `Idempotency-Key` header, so
+//! that a retried request is answered with the original response instead of
+//! repeating its effects.
+//!
+//! A key is reserved before the request is handled and completed with the
+//! response afterwards. When the reservation is made in the transaction that
+//! handles the request, a concurrent retry waits on it and then sees the
+//! stored response. Failed requests release the key so it can be used again,
+//! and a request that never completes only holds it for [`LEASE_SECONDS`].
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::database::{schema::idempotency_keys, DbConnection};
+use crate::result::{AppError, Result};
+
+/// The header clients send the key of a request in.
+pub const HEADER: &str = "Idempotency-Key";
+
+/// The longest key accepted.
+const MAX_KEY_LEN: usize = 255;
+
+/// How long a reserved key is held by its request. A request that has not
+/// completed by then, such as one whose server went away, no longer holds it.
+const LEASE_SECONDS: i64 = 5 * 60;
+
+#[derive(Clone, Debug, Identifiable, Queryable, Selectable)]
+#[diesel(table_name = idempotency_keys)]
+pub struct IdempotencyKey {
+    pub id: i64,
+    /// What the key is unique within, such as a trigger.
+    pub scope: String,
+    pub key: String,
+    /// A hash of the request the key was first used with.
+    pub fingerprint: String,
+    pub response_status: Option<i32>,
+    pub response_body: Option<Value>,
+    pub created_at: DateTime<Utc>,
+    /// Until when the request the key was reserved for holds it, `None` once
+    /// its response is stored.
+    pub locked_until: Option<DateTime<Utc>>,
+    /// The headers of the response that are sent again along with it.
+    pub response_headers: Option<Value>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = idempotency_keys)]
+struct NewIdempotencyKey<'a> {
+    scope: &'a str,
+    key: &'a str,
+    fingerprint: &'a str,
+    locked_until: DateTime<Utc>,
+}
+
+/// The outcome of reserving a key.
+#[derive(Clone, Debug)]
+pub enum Reservation {
+    /// The key is new, the request should be handled and its response stored
+    /// with [`complete`].
+    Reserved(i64),
+    /// The key was already used for the same request, which responded with
+    /// this status, headers and body.
+    Replay {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: Value,
+    },
+}
+
+/// Hashes the parts of a request that must match for a key to be reused.
+pub fn fingerprint(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+
+    hex::encode(hasher.finalize())
+}
+
+/// Reserves the key within the scope. Keys older than `window` are forgotten,
+/// and reusing a key for a different request is a conflict, as is reusing it
+/// while the first request is still being handled within its lease.
+pub fn reserve(
+    conn: &mut DbConnection,
+    scope: &str,
+    key: &str,
+    fingerprint: &str,
+    window: Duration,
+) -> Result<Reservation> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Err(AppError::validation_error(format!(
+            "{} must be between 1 and {} characters",
+            HEADER, MAX_KEY_LEN
+        )));
+    }
+
+    let now = Utc::now();
+    let locked_until = now + Duration::try_seconds(LEASE_SECONDS).unwrap_or_default();
+
+    diesel::delete(idempotency_keys::table)
+        .filter(idempotency_keys::created_at.lt(now - window))
+        .execute(conn)?;
+
+    let reserved: Option<i64> = diesel::insert_into(idempotency_keys::table)
+        .values(&NewIdempotencyKey {
+            scope,
+            key,
+            fingerprint,
+            locked_until,
+        })
+        .on_conflict_do_nothing()
+        .returning(idempotency_keys::id)
+        .get_result(conn)
+        .optional()?;
+
+    if let Some(id) = reserved {
+        return Ok(Reservation::Reserved(id));
+    }
+
+    // The request holding the key may have gone away without completing or
+    // releasing it, in which case the key is free once its lease runs out
+    let taken_over: Option<i64> = diesel::update(idempotency_keys::table)
+        .filter(idempotency_keys::scope.eq(scope))
+        .filter(idempotency_keys::key.eq(key))
+        .filter(idempotency_keys::response_status.is_null())
+        .filter(idempotency_keys::locked_until.lt(now))
+        .set((
+            idempotency_keys::fingerprint.eq(fingerprint),
+            idempotency_keys::created_at.eq(now),
+            idempotency_keys::locked_until.eq(locked_until),
+        ))
+        .returning(idempotency_keys::id)
+        .get_result(conn)
+        .optional()?;
+
+    if let Some(id) = taken_over {
+        return Ok(Reservation::Reserved(id));
+    }
+
+    let in_progress = || {
+        AppError::conflict(format!(
+            "A request with {} {} is still being processed",
+            HEADER, key
+        ))
+    };
+
+    // The key may have been released since the insert conflicted with it
+    let existing = idempotency_keys::table
+        .select(IdempotencyKey::as_select())
+        .filter(idempotency_keys::scope.eq(scope))
+        .filter(idempotency_keys::key.eq(key))
+        .get_result(conn)
+        .optional()?
+        .ok_or_else(in_progress)?;
+
+    if existing.fingerprint != fingerprint {
+        return Err(AppError::conflict(format!(
+            "{} {} was already used for a different request",
+            HEADER, key
+        )));
+    }
+
+    let headers = existing
+        .response_headers
+        .as_ref()
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+        .collect();
+
+    match (existing.response_status, existing.response_body) {
+        (Some(status), Some(body)) => Ok(Reservation::Replay {
+            status: status as u16,
+            headers,
+            body,
+        }),
+        _ => Err(in_progress()),
+    }
+}
+
+/// Stores the response to the request the key was reserved for, which
+/// releases its lease.
+pub fn complete(
+    conn: &mut DbConnection,
+    id: i64,
+    status: u16,
+    headers: &[(String, String)],
+    body: &Value,
+) -> Result<()> {
+    let headers: Map<String, Value> = headers
+        .iter()
+        .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+        .collect();
+
+    diesel::update(idempotency_keys::table)
+        .filter(idempotency_keys::id.eq(id))
+        .set((
+            idempotency_keys::response_status.eq(status as i32),
+            idempotency_keys::response_headers.eq(Value::Object(headers)),
+            idempotency_keys::response_body.eq(body),
+            idempotency_keys::locked_until.eq(None::<DateTime<Utc>>),
+        ))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Forgets the key, so that a request that failed can be retried with it.
+pub fn release(conn: &mut DbConnection, id: i64) -> Result<()> {
+    diesel::delete(idempotency_keys::table)
+        .filter(idempotency_keys::id.eq(id))
+        .execute(conn)?;
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+use crate::database::schema::{users, workflow_instance_tasks, workflow_instances, workflows};
+use crate::database::DbConnection;
+use crate::result::Result;
+use crate::workflows::AutoAssignStrategy;
+
+use super::WorkflowInstance;
+
+/// Picks an assignee for an instance out of a set of candidate users.
+pub trait AssignmentStrategy {
+    fn select(
+        &self,
+        conn: &mut DbConnection,
+        instance: &WorkflowInstance,
+        candidates: &[i64],
+    ) -> Result<Option<i64>>;
+}
+
+impl AutoAssignStrategy {
+    pub fn strategy(&self) -> Box<dyn AssignmentStrategy> {
+        match self {
+            AutoAssignStrategy::RoundRobin => Box::new(RoundRobin),
+            AutoAssignStrategy::LeastLoaded => Box::new(LeastLoaded),
+        }
+    }
+}
+
+/// Returns the active users of the tenant that may be auto-assigned, ordered
+/// by id. When `user_ids` is not empty only those users are considered.
+pub fn candidates(conn: &mut DbConnection, tenant_id: i32, user_ids: &[i64]) -> Result<Vec<i64>> {
+    let mut query = users::table
+        .select(users::id)
+        .filter(users::tenant_id.eq(tenant_id))
+        .filter(users::deleted_at.is_null())
+        .into_boxed();
+
+    if !user_ids.is_empty() {
+        query = query.filter(users::id.eq_any(user_ids.to_vec()));
+    }
+
+    Ok(query.order(users::id.asc()).get_results(conn)?)
+}
+
+/// Hands out work to whichever candidate has waited the longest since their
+/// last task in the same workflow. Picks within a workflow take turns, so
+/// that instances started together are not all handed to the same user.
+pub struct RoundRobin;
+
+impl AssignmentStrategy for RoundRobin {
+    fn select(
+        &self,
+        conn: &mut DbConnection,
+        instance: &WorkflowInstance,
+        candidates: &[i64],
+    ) -> Result<Option<i64>> {
+        // Held until the transaction commits, so that the next pick sees the
+        // task this one assigns
+        workflows::table
+            .select(workflows::id)
+            .filter(workflows::id.eq(instance.workflow_id))
+            .for_no_key_update()
+            .execute(conn)?;
+
+        let last_assigned: HashMap<Option<i64>, Option<DateTime<Utc>>> =
+            workflow_instance_tasks::table
+                .inner_join(workflow_instances::table)
+                .filter(workflow_instances::workflow_id.eq(instance.workflow_id))
+                .filter(workflow_instance_tasks::assignee_id.eq_any(candidates.to_vec()))
+                .group_by(workflow_instance_tasks::assignee_id)
+                .select((
+                    workflow_instance_tasks::assignee_id,
+                    diesel::dsl::max(workflow_instance_tasks::created_at),
+                ))
+                .load::<(Option<i64>, Option<DateTime<Utc>>)>(conn)?
+                .into_iter()
+                .collect();
+
+        Ok(candidates
+            .iter()
+            .min_by_key(|id| last_assigned.get(&Some(**id)).copied().flatten())
+            .copied())
+    }
+}
+
+/// Hands out work to the candidate with the fewest open instances in the
+/// tenant.
+pub struct LeastLoaded;
+
+impl AssignmentStrategy for LeastLoaded {
+    fn select(
+        &self,
+        conn: &mut DbConnection,
+        instance: &WorkflowInstance,
+        candidates: &[i64],
+    ) -> Result<Option<i64>> {
+        let loads: HashMap<Option<i64>, i64> = workflow_instances::table
+            .filter(workflow_instances::tenant_id.eq(instance.tenant_id))
+            .filter(workflow_instances::id.ne(instance.id))
+            .filter(workflow_instances::completed_at.is_null())
+            .filter(workflow_instances::deleted_at.is_null())
+            .filter(workflow_instances::assignee_id.eq_any(candidates.to_vec()))
+            .group_by(workflow_instances::assignee_id)
+            .select((
+                workflow_instances::assignee_id,
+                diesel::dsl::count(workflow_instances::id),
+            ))
+            .load::<(Option<i64>, i64)>(conn)?
+            .into_iter()
+            .collect();
+
+        Ok(candidates
+            .iter()
+            .min_by_key(|id| loads.get(&Some(**id)).copied().unwrap_or(0))
+            .copied())
+    }
+}
@@ -0,0 +1,72 @@
+use crate::bulk::{self, BulkOptions, BulkResult};
+use crate::database::DbConnection;
+use crate::result::{AppError, Result};
+
+use super::{
+    BulkAssign, BulkTransition, BulkTransitionResult, TransitionInstance, WorkflowInstance,
+};
+
+/// The most instances a batch may move through a transition. Each of them may
+/// follow a long run of automatic transitions within the batch's transaction.
+const MAX_TRANSITIONS: usize = 100;
+
+impl WorkflowInstance {
+    /// Assigns each of the instances to the user, or leaves them unassigned.
+    pub fn bulk_assign(
+        conn: &mut DbConnection,
+        BulkAssign {
+            instance_ids,
+            user_id,
+        }: BulkAssign,
+        options: BulkOptions,
+    ) -> Result<BulkResult<WorkflowInstance>> {
+        bulk::run(conn, instance_ids, options, |conn, id| {
+            Self::assign(conn, id, user_id)
+        })
+    }
+
+    /// Moves each of the instances through the same transition. Taking a
+    /// transition may run actions that call other systems, so a dry run only
+    /// checks that each instance can take it. The HTTP actions the instances
+    /// reach are not called within the batch, the timer calls them once it
+    /// has committed.
+    pub fn bulk_transition(
+        conn: &mut DbConnection,
+        user_id: i64,
+        BulkTransition {
+            instance_ids,
+            transition_id,
+            option_id,
+            comment,
+            data,
+        }: BulkTransition,
+        options: BulkOptions,
+    ) -> Result<BulkResult<BulkTransitionResult>> {
+        if instance_ids.len() > MAX_TRANSITIONS {
+            return Err(AppError::validation_error(format!(
+                "A batch may move at most {} instances through a transition",
+                MAX_TRANSITIONS
+            )));
+        }
+
+        let request = TransitionInstance {
+            transition_id,
+            option_id,
+            comment,
+            data,
+        };
+
+        bulk::run(conn, instance_ids, options, |conn, id| {
+            let target_state_id = Self::check_transition(conn, id, Some(user_id), &request)?;
+            let instance = match options.dry_run {
+                true => None,
+                false => Some(Self::transition(conn, id, Some(user_id), request.clone())?),
+            };
+
+            Ok(BulkTransitionResult {
+                target_state_id,
+                instance,
+            })
+        })
+    }
+}
@@ -0,0 +1,237 @@
+use std::io::Write;
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::deserialize::{FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::PgValue;
+use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::{Bool, Text};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tsync::tsync;
+use uuid::Uuid;
+
+use crate::database::{schema::workflow_instance_calls, DbConnection, DB};
+use crate::result::Result;
+
+use super::walk::Departure;
+
+#[derive(
+    AsExpression, Clone, Copy, Debug, Deserialize, Eq, FromSqlRow, JsonSchema, PartialEq, Serialize,
+)]
+#[tsync]
+#[diesel(sql_type = Text)]
+pub enum CallStatus {
+    /// Waiting to be made, or being made.
+    Pending,
+    Succeeded,
+    /// The call failed and the token moved to the action's failure state.
+    Failed,
+    /// The call failed with no failure state to move to, or the token could
+    /// not be moved on after it. The token stays where it was.
+    Halted,
+}
+
+impl FromSql<Text, DB> for CallStatus {
+    fn from_sql(bytes: PgValue) -> diesel::deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"Pending" => Ok(CallStatus::Pending),
+            b"Succeeded" => Ok(CallStatus::Succeeded),
+            b"Failed" => Ok(CallStatus::Failed),
+            b"Halted" => Ok(CallStatus::Halted),
+            _ => Err("Unknown call status".into()),
+        }
+    }
+}
+
+impl ToSql<Text, DB> for CallStatus {
+    fn to_sql(&self, out: &mut Output<DB>) -> serialize::Result {
+        let status = match self {
+            CallStatus::Pending => "Pending",
+            CallStatus::Succeeded => "Succeeded",
+            CallStatus::Failed => "Failed",
+            CallStatus::Halted => "Halted",
+        };
+        out.write_all(status.as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+/// An HTTP action a token is waiting on. Calls are made outside of the
+/// transaction that reached the action, and the token is moved on in another
+/// once the endpoint has responded.
+#[derive(Clone, Debug, Deserialize, Identifiable, JsonSchema, Queryable, Selectable, Serialize)]
+#[tsync]
+#[diesel(table_name = workflow_instance_calls)]
+pub struct InstanceCall {
+    pub id: i64,
+    pub instance_id: i64,
+    pub token_id: i64,
+    pub state_id: Uuid,
+    pub action_id: Uuid,
+    /// Whether the action is an exit action of the state rather than an
+    /// entry action.
+    pub exiting: bool,
+    /// The state an exiting token is leaving for, `None` when the state is a
+    /// fork and the token is leaving down its branches.
+    pub target_state_id: Option<Uuid>,
+    pub completed_by: Option<i64>,
+    pub option_id: Option<Uuid>,
+    pub comment: Option<String>,
+    pub status: CallStatus,
+    pub error: Option<String>,
+    /// Until when the call is being made by a worker. Pending calls whose
+    /// lease has run out are made again.
+    pub locked_until: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = workflow_instance_calls)]
+struct NewInstanceCall {
+    instance_id: i64,
+    token_id: i64,
+    state_id: Uuid,
+    action_id: Uuid,
+    exiting: bool,
+    target_state_id: Option<Uuid>,
+    completed_by: Option<i64>,
+    option_id: Option<Uuid>,
+    comment: Option<String>,
+}
+
+impl InstanceCall {
+    /// Queues a call of the action for the token, along with where the token
+    /// is going when the action is one of its state's exit actions.
+    pub(super) fn queue(
+        conn: &mut DbConnection,
+        instance_id: i64,
+        token_id: i64,
+        state_id: Uuid,
+        action_id: Uuid,
+        departure: Option<&Departure>,
+    ) -> Result<InstanceCall> {
+        let Departure {
+            target_state_id,
+            completed_by,
+            option_id,
+            comment,
+            ..
+        } = departure.cloned().unwrap_or_default();
+
+        Ok(diesel::insert_into(workflow_instance_calls::table)
+            .values(&NewInstanceCall {
+                instance_id,
+                token_id,
+                state_id,
+                action_id,
+                exiting: departure.is_some(),
+                target_state_id,
+                completed_by,
+                option_id,
+                comment,
+            })
+            .returning(InstanceCall::as_returning())
+            .get_result(conn)?)
+    }
+
+    pub fn list(conn: &mut DbConnection, instance_id: i64) -> Result<Vec<InstanceCall>> {
+        Ok(workflow_instance_calls::table
+            .select(InstanceCall::as_select())
+            .filter(workflow_instance_calls::instance_id.eq(instance_id))
+            .order(workflow_instance_calls::id.asc())
+            .get_results(conn)?)
+    }
+
+    /// Finds the pending call the token is waiting on, if any.
+    pub fn find_pending(conn: &mut DbConnection, token_id: i64) -> Result<Option<InstanceCall>> {
+        Ok(workflow_instance_calls::table
+            .select(InstanceCall::as_select())
+            .filter(workflow_instance_calls::token_id.eq(token_id))
+            .filter(workflow_instance_calls::status.eq(CallStatus::Pending))
+            .first(conn)
+            .optional()?)
+    }
+
+    /// Counts the calls of the instance that have not ended yet.
+    pub fn count_pending(conn: &mut DbConnection, instance_id: i64) -> Result<i64> {
+        Ok(workflow_instance_calls::table
+            .filter(workflow_instance_calls::instance_id.eq(instance_id))
+            .filter(workflow_instance_calls::status.eq(CallStatus::Pending))
+            .count()
+            .get_result(conn)?)
+    }
+
+    /// Takes a lease on the oldest pending call that is not being made, of
+    /// the instance or of any instance.
+    pub fn claim_next(
+        conn: &mut DbConnection,
+        instance_id: Option<i64>,
+        lease: Duration,
+    ) -> Result<Option<InstanceCall>> {
+        conn.transaction(|conn| {
+            let now = Utc::now();
+            let Some(id) = workflow_instance_calls::table
+                .select(workflow_instance_calls::id)
+                .filter(workflow_instance_calls::status.eq(CallStatus::Pending))
+                .filter(workflow_instance_calls::locked_until.le(now))
+                .filter(
+                    instance_id.is_none().into_sql::<Bool>().or(
+                        workflow_instance_calls::instance_id
+                            .nullable()
+                            .eq(instance_id),
+                    ),
+                )
+                .order(workflow_instance_calls::id.asc())
+                .for_update()
+                .skip_locked()
+                .first::<i64>(conn)
+                .optional()?
+            else {
+                return Ok(None);
+            };
+
+            Ok(Some(
+                diesel::update(workflow_instance_calls::table)
+                    .filter(workflow_instance_calls::id.eq(id))
+                    .set(workflow_instance_calls::locked_until.eq(now + lease))
+                    .returning(InstanceCall::as_returning())
+                    .get_result(conn)?,
+            ))
+        })
+    }
+
+    /// Records how the call ended, unless it has ended already.
+    pub fn finish(
+        conn: &mut DbConnection,
+        id: i64,
+        status: CallStatus,
+        error: Option<String>,
+    ) -> Result<Option<InstanceCall>> {
+        Ok(diesel::update(workflow_instance_calls::table)
+            .filter(workflow_instance_calls::id.eq(id))
+            .filter(workflow_instance_calls::status.eq(CallStatus::Pending))
+            .set((
+                workflow_instance_calls::status.eq(status),
+                workflow_instance_calls::error.eq(error),
+                workflow_instance_calls::completed_at.eq(Utc::now()),
+            ))
+            .returning(InstanceCall::as_returning())
+            .get_result(conn)
+            .optional()?)
+    }
+
+    /// Where the token was going when it ran into the call.
+    pub(super) fn departure(&self) -> Departure {
+        Departure {
+            target_state_id: self.target_state_id,
+            transition_id: None,
+            completed_by: self.completed_by,
+            option_id: self.option_id,
+            comment: self.comment.clone(),
+        }
+    }
+}
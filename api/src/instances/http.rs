@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use diesel::prelude::*;
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::database::{schema::workflow_instances, DbConnection};
+use crate::result::{AppError, Result};
+use crate::templating;
+use crate::tenants::Tenant;
+use crate::workflows::{ActionDefinition, HttpMethod};
+
+use super::{CallStatus, InstanceCall, InstanceToken, WorkflowInstance};
+
+/// How long an HTTP action waits for the endpoint to respond.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+impl WorkflowInstance {
+    /// Makes the calls the instance's tokens are waiting on after it was
+    /// started or moved on, and returns the instance as they left it. Fails
+    /// when a call failed and its action has no failure state to move to.
+    /// Calls of its sub-workflow instances are left to the timer.
+    pub fn settle(conn: &mut DbConnection, instance: WorkflowInstance) -> Result<WorkflowInstance> {
+        let calls = Self::run_calls(conn, Some(instance.id))?;
+
+        if let Some(call) = calls.iter().find(|c| c.status == CallStatus::Halted) {
+            return Err(AppError::bad_request(
+                call.error.clone().unwrap_or_default(),
+            ));
+        }
+
+        Ok(Self::find(conn, instance.id)?.unwrap_or(instance))
+    }
+
+    /// Makes the HTTP calls the instance's tokens are waiting on, or those of
+    /// every instance when none is given, and moves each token on in a
+    /// transaction of its own once its call has returned. The calls are made
+    /// outside of any transaction, so this must not be called within one.
+    /// Returns the calls made.
+    pub fn run_calls(
+        conn: &mut DbConnection,
+        instance_id: Option<i64>,
+    ) -> Result<Vec<InstanceCall>> {
+        // Leave enough time for the call to time out and the token to move on
+        let lease = chrono::Duration::from_std(TIMEOUT * 2).unwrap_or_default();
+        let mut calls = Vec::new();
+
+        while let Some(call) = InstanceCall::claim_next(conn, instance_id, lease)? {
+            let finished = match Self::make_call(conn, &call) {
+                Ok(finished) => finished,
+                Err(e) => {
+                    tracing::warn!(
+                        instance_id = call.instance_id,
+                        call_id = call.id,
+                        "HTTP action halted: {e}"
+                    );
+                    InstanceCall::finish(conn, call.id, CallStatus::Halted, Some(e.to_string()))?
+                }
+            };
+            calls.extend(finished);
+        }
+
+        Ok(calls)
+    }
+
+    /// Makes the call, stores its result and moves the waiting token on.
+    /// Returns `None` when the call was finished elsewhere in the meantime.
+    fn make_call(conn: &mut DbConnection, call: &InstanceCall) -> Result<Option<InstanceCall>> {
+        let instance = Self::find(conn, call.instance_id)?.ok_or_else(|| {
+            AppError::not_found("WorkflowInstance", &call.instance_id.to_string())
+        })?;
+        let workflow = instance.workflow(conn)?;
+        let action = workflow
+            .definition
+            .state(call.state_id)
+            .map(|state| match call.exiting {
+                true => &state.exit_actions,
+                false => &state.entry_actions,
+            })
+            .and_then(|actions| actions.iter().find(|a| a.id == call.action_id))
+            .ok_or_else(|| {
+                AppError::server_error(format!(
+                    "State {} has no action {}",
+                    call.state_id, call.action_id
+                ))
+            })?;
+        let ActionDefinition::Http {
+            method,
+            url,
+            headers,
+            body,
+            result_key,
+            failure_state_id,
+        } = &action.definition
+        else {
+            return Err(AppError::server_error(format!(
+                "Action {} is not an HTTP action",
+                action.name
+            )));
+        };
+
+        Self::waiting_token(conn, call)?;
+
+        let (result, error) = match Self::send_http(conn, &instance, *method, url, headers, body) {
+            Ok((status, body)) if (200..300).contains(&status) => {
+                (json!({ "status": status, "body": body }), None)
+            }
+            Ok((status, body)) => (
+                json!({ "status": status, "body": body }),
+                Some(format!("endpoint responded with status {}", status)),
+            ),
+            Err(e) => (json!({ "error": e.to_string() }), Some(e.to_string())),
+        };
+
+        conn.transaction(|conn| {
+            let instance = Self::lock(conn, call.instance_id)?.ok_or_else(|| {
+                AppError::not_found("WorkflowInstance", &call.instance_id.to_string())
+            })?;
+            let token = Self::waiting_token(conn, call)?;
+
+            let (status, failure_state_id, error) = match (error, failure_state_id) {
+                (None, _) => (CallStatus::Succeeded, None, None),
+                (Some(error), Some(failure_state_id)) => {
+                    tracing::warn!(
+                        instance_id = instance.id,
+                        action_id = %action.id,
+                        "HTTP action failed, moving to its failure state: {error}"
+                    );
+                    (CallStatus::Failed, Some(*failure_state_id), Some(error))
+                }
+                (Some(error), None) => {
+                    let error = format!("HTTP action {} failed: {}", action.name, error);
+                    tracing::warn!(instance_id = instance.id, action_id = %action.id, "{error}");
+                    (CallStatus::Halted, None, Some(error))
+                }
+            };
+
+            let Some(finished) = InstanceCall::finish(conn, call.id, status, error)? else {
+                return Ok(None);
+            };
+
+            let instance = match result_key {
+                Some(key) => Self::store_result(conn, instance, key, result)?,
+                None => instance,
+            };
+
+            if status != CallStatus::Halted {
+                Self::resume_call(
+                    conn,
+                    instance,
+                    &workflow.definition,
+                    token,
+                    call,
+                    failure_state_id,
+                )?;
+            }
+
+            Ok(Some(finished))
+        })
+    }
+
+    /// Finds the token waiting on the call, which must not have been moved
+    /// out of the call's state since.
+    fn waiting_token(conn: &mut DbConnection, call: &InstanceCall) -> Result<InstanceToken> {
+        InstanceToken::find(conn, call.token_id)?
+            .filter(|token| token.state_id == call.state_id)
+            .ok_or_else(|| AppError::bad_request("The instance moved on before the call was made"))
+    }
+
+    fn send_http(
+        conn: &mut DbConnection,
+        instance: &WorkflowInstance,
+        method: HttpMethod,
+        url: &str,
+        headers: &HashMap<String, String>,
+        body: &Option<Value>,
+    ) -> Result<(u16, Value)> {
+        let tenant = Tenant::find(conn, instance.tenant_id)?
+            .ok_or_else(|| AppError::not_found("Tenant", &instance.tenant_id.to_string()))?;
+
+        let url = templating::render(url, &instance.data)?;
+        let url = Url::parse(&url)
+            .map_err(|e| AppError::validation_error(format!("Invalid URL {}: {}", url, e)))?;
+
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(AppError::validation_error(format!(
+                "Unsupported URL scheme {}",
+                url.scheme()
+            )));
+        }
+
+        if !tenant.allows(&url) {
+            return Err(AppError::forbidden(format!(
+                "Host {}:{} is not allowed for tenant {}",
+                url.host_str().unwrap_or_default(),
+                url.port_or_known_default().unwrap_or_default(),
+                tenant.name
+            )));
+        }
+
+        // Redirects are not followed, they could lead to a host that is not
+        // allowed
+        let agent = ureq::AgentBuilder::new()
+            .timeout(TIMEOUT)
+            .redirects(0)
+            .build();
+        let mut request = agent.request_url(method.as_str(), &url);
+
+        for (name, value) in headers {
+            request = request.set(name, &templating::render(value, &instance.data)?);
+        }
+
+        let response = match body {
+            Some(body) => request.send_json(templating::render_value(body, &instance.data)?),
+            None => request.call(),
+        };
+
+        let response = match response {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => return Err(AppError::bad_request(e.to_string())),
+        };
+
+        let status = response.status();
+        let text = response.into_string()?;
+        let body = serde_json::from_str(&text).unwrap_or(Value::String(text));
+
+        Ok((status, body))
+    }
+
+    /// Stores the result under the key of the instance data. The data schema
+    /// is not applied, so the key should not be one of its fields.
+    fn store_result(
+        conn: &mut DbConnection,
+        instance: WorkflowInstance,
+        key: &str,
+        result: Value,
+    ) -> Result<WorkflowInstance> {
+        let mut data = instance.data.clone();
+        match data.as_object_mut() {
+            Some(fields) => {
+                fields.insert(key.to_string(), result);
+            }
+            None => data = json!({ key: result }),
+        }
+
+        Ok(diesel::update(workflow_instances::table)
+            .filter(workflow_instances::id.eq(instance.id))
+            .set(workflow_instances::data.eq(data))
+            .returning(WorkflowInstance::as_returning())
+            .get_result(conn)?)
+    }
+}
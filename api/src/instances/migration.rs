@@ -0,0 +1,640 @@
+//! Moves running instances to another version of their workflow.
+//!
+//! Each state an instance occupies is mapped to a state of the target version,
+//! either by the mapping given with the request or to the state with the same
+//! id. Moving runs no actions, since as far as the workflow is concerned the
+//! instance stays where it is, and open tasks keep their assignee while
+//! their due date follows the SLA of the new state. For the same reason no
+//! state may be moved to an end state, which would leave the instance
+//! running there; it has to be completed through a transition. Nor may a
+//! token move to a state that forks, starts a sub-workflow or moves on
+//! automatically unless it already waits in such a state, and instances
+//! waiting on HTTP calls or sub-workflow instances are not moved at all.
+//! Every token moved is recorded as an instance migration.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tsync::tsync;
+use uuid::Uuid;
+
+use crate::bulk::{self, BulkOptions, BulkResult};
+use crate::database::schema::{workflow_instance_migrations, workflow_instances};
+use crate::database::DbConnection;
+use crate::result::{AppError, Result};
+use crate::workflows::{
+    TransitionDefinition, Workflow, WorkflowDefinition, WorkflowState, WorkflowVersion,
+};
+
+use super::{InstanceCall, InstanceTask, InstanceToken, WorkflowInstance};
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct MigrateInstances {
+    /// The version to move the instances to, by default the latest.
+    pub to_version: Option<i32>,
+    /// Only move instances running this version.
+    pub from_version: Option<i32>,
+    /// The instances to move, by default every running instance of the
+    /// workflow on another version.
+    pub instance_ids: Option<Vec<i64>>,
+    /// The state of the target version each state is moved to. States that
+    /// are not listed move to the state with the same id. No state may move
+    /// to an end state.
+    #[serde(default)]
+    pub state_mapping: HashMap<Uuid, Uuid>,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct TokenMove {
+    pub token_id: i64,
+    pub from_state_id: Uuid,
+    pub to_state_id: Uuid,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct MigratedInstance {
+    pub instance_id: i64,
+    pub from_version: i32,
+    pub to_version: i32,
+    /// The moves of the instance's active tokens, including those that stay
+    /// in a state with the same id.
+    pub moves: Vec<TokenMove>,
+}
+
+/// A record of a token moved from one version of the workflow to another.
+#[derive(Clone, Debug, Deserialize, Identifiable, JsonSchema, Queryable, Selectable, Serialize)]
+#[tsync]
+#[diesel(table_name = workflow_instance_migrations)]
+pub struct InstanceMigration {
+    pub id: i64,
+    pub instance_id: i64,
+    pub token_id: i64,
+    pub from_version: i32,
+    pub to_version: i32,
+    pub from_state_id: Uuid,
+    pub to_state_id: Uuid,
+    pub migrated_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = workflow_instance_migrations)]
+struct NewInstanceMigration {
+    instance_id: i64,
+    token_id: i64,
+    from_version: i32,
+    to_version: i32,
+    from_state_id: Uuid,
+    to_state_id: Uuid,
+    migrated_by: Option<i64>,
+}
+
+/// What every instance of one migration is moved with.
+struct Migration<'a> {
+    workflow_id: i64,
+    from_version: Option<i32>,
+    target: &'a WorkflowVersion,
+    sources: &'a HashMap<i32, WorkflowDefinition>,
+    state_mapping: &'a HashMap<Uuid, Uuid>,
+    user_id: i64,
+}
+
+impl WorkflowInstance {
+    /// Moves running instances of the workflow to another of its versions. A
+    /// dry run reports the moves without making them.
+    pub fn migrate(
+        conn: &mut DbConnection,
+        workflow_id: i64,
+        user_id: i64,
+        MigrateInstances {
+            to_version,
+            from_version,
+            instance_ids,
+            state_mapping,
+        }: MigrateInstances,
+        options: BulkOptions,
+    ) -> Result<BulkResult<MigratedInstance>> {
+        Workflow::find(conn, workflow_id)?
+            .filter(|w| w.deleted_at.is_none())
+            .ok_or_else(|| AppError::not_found("Workflow", &workflow_id.to_string()))?;
+
+        let target = match to_version {
+            Some(version) => WorkflowVersion::get(conn, workflow_id, version)?,
+            None => WorkflowVersion::latest(conn, workflow_id)?.ok_or_else(|| {
+                AppError::server_error(format!("Workflow {} has no versions", workflow_id))
+            })?,
+        };
+
+        let instance_ids = match instance_ids {
+            Some(ids) => ids,
+            None => {
+                let ids = Self::list_migratable(conn, workflow_id, from_version, target.version)?;
+                if ids.is_empty() {
+                    return Err(AppError::validation_error(format!(
+                        "No running instances of workflow {} need moving to version {}",
+                        workflow_id, target.version
+                    )));
+                }
+                ids
+            }
+        };
+
+        let mut sources = HashMap::new();
+        for version in Self::list_versions(conn, workflow_id, &instance_ids)? {
+            let definition = WorkflowVersion::get(conn, workflow_id, version)?.definition;
+            sources.insert(version, definition);
+        }
+
+        for (from, to) in &state_mapping {
+            if !sources.values().any(|d| d.state(*from).is_some()) {
+                return Err(AppError::validation_error(format!(
+                    "State {} is not in any version the instances run",
+                    from
+                )));
+            }
+            match target.definition.state(*to) {
+                None => {
+                    return Err(AppError::validation_error(format!(
+                        "State {} is mapped to {}, which is not in version {}",
+                        from, to, target.version
+                    )))
+                }
+                Some(state) if state.is_end_state => {
+                    return Err(AppError::validation_error(format!(
+                        "State {} is mapped to {}, which is an end state of version {}",
+                        from, to, target.version
+                    )))
+                }
+                Some(_) => {}
+            }
+        }
+
+        let migration = Migration {
+            workflow_id,
+            from_version,
+            target: &target,
+            sources: &sources,
+            state_mapping: &state_mapping,
+            user_id,
+        };
+
+        bulk::run(conn, instance_ids, options, |conn, id| {
+            Self::migrate_one(conn, &migration, id)
+        })
+    }
+
+    /// Lists the records of the instance's moves between versions, the
+    /// latest first.
+    pub fn migrations(conn: &mut DbConnection, id: i64) -> Result<Vec<InstanceMigration>> {
+        Ok(workflow_instance_migrations::table
+            .select(InstanceMigration::as_select())
+            .filter(workflow_instance_migrations::instance_id.eq(id))
+            .order(workflow_instance_migrations::id.desc())
+            .load(conn)?)
+    }
+
+    fn migrate_one(
+        conn: &mut DbConnection,
+        migration: &Migration,
+        id: i64,
+    ) -> Result<MigratedInstance> {
+        let target = migration.target;
+        let instance = workflow_instances::table
+            .select(WorkflowInstance::as_select())
+            .filter(workflow_instances::id.eq(id))
+            .filter(workflow_instances::deleted_at.is_null())
+            .for_update()
+            .get_result(conn)
+            .optional()?
+            .ok_or_else(|| AppError::not_found("WorkflowInstance", &id.to_string()))?;
+
+        if instance.workflow_id != migration.workflow_id {
+            return Err(AppError::validation_error(format!(
+                "Instance {} is not an instance of workflow {}",
+                id, migration.workflow_id
+            )));
+        }
+        if instance.completed_at.is_some() {
+            return Err(AppError::bad_request("Instance has already completed"));
+        }
+        if instance.workflow_version == target.version {
+            return Err(AppError::validation_error(format!(
+                "Instance {} already runs version {}",
+                id, target.version
+            )));
+        }
+        if let Some(version) = migration.from_version {
+            if instance.workflow_version != version {
+                return Err(AppError::validation_error(format!(
+                    "Instance {} runs version {}, not version {}",
+                    id, instance.workflow_version, version
+                )));
+            }
+        }
+
+        let source = migration
+            .sources
+            .get(&instance.workflow_version)
+            .ok_or_else(|| {
+                AppError::server_error(format!(
+                    "Version {} of instance {} was not loaded",
+                    instance.workflow_version, id
+                ))
+            })?;
+        let map = |state_id: Uuid| {
+            migration
+                .state_mapping
+                .get(&state_id)
+                .copied()
+                .or_else(|| target.definition.state(state_id).map(|s| s.id))
+                .ok_or_else(|| {
+                    AppError::validation_error(format!(
+                        "Instance {} is in state {} of version {}, which has no state in version {} to move to",
+                        id,
+                        source
+                            .state(state_id)
+                            .map_or(state_id.to_string(), |s| s.name.clone()),
+                        instance.workflow_version,
+                        target.version
+                    ))
+                })
+        };
+
+        check_settled(
+            id,
+            InstanceCall::count_pending(conn, id)?,
+            Self::count_running_children(conn, id)?,
+        )?;
+
+        let mut moves = Vec::new();
+        for token in InstanceToken::list_active(conn, id)? {
+            let to_state_id = map(token.state_id)?;
+            let to_state = target.definition.state(to_state_id).ok_or_else(|| {
+                AppError::server_error(format!(
+                    "State {} is not in version {}",
+                    to_state_id, target.version
+                ))
+            })?;
+            let forked = InstanceToken::count_active_children(conn, token.id)? > 0;
+            check_move(
+                id,
+                source.state(token.state_id),
+                to_state,
+                forked,
+                target.version,
+            )?;
+
+            moves.push((
+                TokenMove {
+                    token_id: token.id,
+                    from_state_id: token.state_id,
+                    to_state_id,
+                },
+                to_state,
+            ));
+        }
+        let current_state_id = map(instance.current_state_id)?;
+
+        for (token_move, to_state) in &moves {
+            if token_move.from_state_id != token_move.to_state_id {
+                InstanceToken::move_to(conn, token_move.token_id, token_move.to_state_id, false)?;
+                InstanceTask::move_open(conn, token_move.token_id, to_state)?;
+            }
+        }
+        let moves: Vec<TokenMove> = moves.into_iter().map(|(m, _)| m).collect();
+
+        diesel::insert_into(workflow_instance_migrations::table)
+            .values(
+                moves
+                    .iter()
+                    .map(|token_move| NewInstanceMigration {
+                        instance_id: id,
+                        token_id: token_move.token_id,
+                        from_version: instance.workflow_version,
+                        to_version: target.version,
+                        from_state_id: token_move.from_state_id,
+                        to_state_id: token_move.to_state_id,
+                        migrated_by: Some(migration.user_id),
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .execute(conn)?;
+
+        diesel::update(workflow_instances::table)
+            .filter(workflow_instances::id.eq(id))
+            .set((
+                workflow_instances::workflow_version.eq(target.version),
+                workflow_instances::current_state_id.eq(current_state_id),
+            ))
+            .execute(conn)?;
+
+        Ok(MigratedInstance {
+            instance_id: id,
+            from_version: instance.workflow_version,
+            to_version: target.version,
+            moves,
+        })
+    }
+
+    /// Counts the instances started by the instance's sub-workflow states
+    /// that have not completed.
+    fn count_running_children(conn: &mut DbConnection, id: i64) -> Result<i64> {
+        Ok(workflow_instances::table
+            .filter(workflow_instances::parent_id.eq(id))
+            .filter(workflow_instances::completed_at.is_null())
+            .filter(workflow_instances::deleted_at.is_null())
+            .count()
+            .get_result(conn)?)
+    }
+
+    /// Lists the running instances of the workflow that are not on the
+    /// version, optionally only those on another given version.
+    fn list_migratable(
+        conn: &mut DbConnection,
+        workflow_id: i64,
+        from_version: Option<i32>,
+        to_version: i32,
+    ) -> Result<Vec<i64>> {
+        let mut query = workflow_instances::table
+            .select(workflow_instances::id)
+            .filter(workflow_instances::workflow_id.eq(workflow_id))
+            .filter(workflow_instances::workflow_version.ne(to_version))
+            .filter(workflow_instances::completed_at.is_null())
+            .filter(workflow_instances::deleted_at.is_null())
+            .order(workflow_instances::id.asc())
+            .into_boxed();
+
+        if let Some(version) = from_version {
+            query = query.filter(workflow_instances::workflow_version.eq(version));
+        }
+
+        Ok(query.load(conn)?)
+    }
+
+    /// Lists the versions the instances of the workflow run.
+    fn list_versions(
+        conn: &mut DbConnection,
+        workflow_id: i64,
+        instance_ids: &[i64],
+    ) -> Result<Vec<i32>> {
+        Ok(workflow_instances::table
+            .select(workflow_instances::workflow_version)
+            .filter(workflow_instances::workflow_id.eq(workflow_id))
+            .filter(workflow_instances::id.eq_any(instance_ids))
+            .distinct()
+            .load(conn)?)
+    }
+}
+
+/// What entering a state does besides waiting for a transition. Moving a
+/// token there does not do it, so a token may only move to such a state from
+/// one of the same kind, where it has done it already.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Entry {
+    Fork,
+    SubWorkflow,
+    Automatic,
+}
+
+impl Entry {
+    fn of(state: &WorkflowState) -> Option<Entry> {
+        if state.fork().is_some() {
+            Some(Entry::Fork)
+        } else if state.sub_workflow().is_some() {
+            Some(Entry::SubWorkflow)
+        } else if state
+            .transitions
+            .iter()
+            .any(|t| matches!(t.definition, TransitionDefinition::Automatic { .. }))
+        {
+            Some(Entry::Automatic)
+        } else {
+            None
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Entry::Fork => "forks",
+            Entry::SubWorkflow => "starts a sub-workflow",
+            Entry::Automatic => "moves on automatically",
+        }
+    }
+}
+
+/// Checks that nothing the instance started is still running, since it
+/// would finish against a version the instance no longer runs.
+fn check_settled(id: i64, pending_calls: i64, running_children: i64) -> Result<()> {
+    if pending_calls > 0 {
+        return Err(AppError::validation_error(format!(
+            "Instance {} is waiting on {} HTTP calls",
+            id, pending_calls
+        )));
+    }
+    if running_children > 0 {
+        return Err(AppError::validation_error(format!(
+            "Instance {} is waiting on {} sub-workflow instances",
+            id, running_children
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that a token in the state of the instance's version, which has
+/// parallel branches running when `forked`, can move to the state of the
+/// target version.
+fn check_move(
+    id: i64,
+    from: Option<&WorkflowState>,
+    to: &WorkflowState,
+    forked: bool,
+    version: i32,
+) -> Result<()> {
+    let from_name = from.map_or("unknown", |s| s.name.as_str());
+
+    if to.is_end_state {
+        return Err(AppError::validation_error(format!(
+            "Instance {} is in state {}, which moves to end state {} of version {}",
+            id, from_name, to.name, version
+        )));
+    }
+
+    let entry = Entry::of(to);
+    if forked && entry != Some(Entry::Fork) {
+        return Err(AppError::validation_error(format!(
+            "Instance {} is waiting for parallel branches, so state {} must move to a fork state",
+            id, from_name
+        )));
+    }
+
+    if let Some(entry) = entry {
+        if from.and_then(Entry::of) != Some(entry) {
+            return Err(AppError::validation_error(format!(
+                "Instance {} is in state {}, which cannot move to state {} of version {}: \
+                 it {} on entry, which moving skips",
+                id,
+                from_name,
+                to.name,
+                version,
+                entry.describe()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn state(name: &str, fields: Value) -> WorkflowState {
+        let mut state = json!({
+            "id": Uuid::new_v4(),
+            "name": name,
+            "description": null,
+            "is_end_state": false,
+            "entry_actions": [],
+            "exit_actions": [],
+            "transitions": [],
+        });
+        if let (Some(state), Value::Object(fields)) = (state.as_object_mut(), fields) {
+            state.extend(fields);
+        }
+        serde_json::from_value(state).unwrap()
+    }
+
+    fn transition(definition: Value) -> Value {
+        json!({
+            "id": Uuid::new_v4(),
+            "name": "Next",
+            "description": null,
+            "definition": definition,
+        })
+    }
+
+    fn waiting() -> WorkflowState {
+        let option = json!({
+            "id": Uuid::new_v4(),
+            "label": "Done",
+            "target_state_id": Uuid::new_v4(),
+            "comment_required": false,
+            "data": [],
+        });
+        state(
+            "Review",
+            json!({ "transitions": [transition(json!({ "type": "Manual", "options": [option] }))] }),
+        )
+    }
+
+    fn fork() -> WorkflowState {
+        state(
+            "Split",
+            json!({ "transitions": [transition(json!({
+                "type": "Parallel",
+                "branch_state_ids": [Uuid::new_v4(), Uuid::new_v4()],
+                "join_state_id": Uuid::new_v4(),
+            }))] }),
+        )
+    }
+
+    fn sub_workflow() -> WorkflowState {
+        state(
+            "Delegate",
+            json!({ "transitions": [transition(json!({
+                "type": "SubWorkflow",
+                "workflow_id": 2,
+                "options": [],
+                "outcomes": [],
+            }))] }),
+        )
+    }
+
+    fn automatic() -> WorkflowState {
+        state(
+            "Route",
+            json!({ "transitions": [transition(json!({
+                "type": "Automatic",
+                "target_state_id": Uuid::new_v4(),
+            }))] }),
+        )
+    }
+
+    fn error(result: Result<()>) -> String {
+        match result {
+            Ok(()) => panic!("move is allowed"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn moves_tokens_between_waiting_states() {
+        check_move(1, Some(&waiting()), &waiting(), false, 2).unwrap();
+    }
+
+    #[test]
+    fn moves_tokens_between_states_of_the_same_kind() {
+        check_move(1, Some(&fork()), &fork(), true, 2).unwrap();
+        check_move(1, Some(&sub_workflow()), &sub_workflow(), false, 2).unwrap();
+        check_move(1, Some(&automatic()), &automatic(), false, 2).unwrap();
+    }
+
+    #[test]
+    fn rejects_moves_to_end_states() {
+        let done = state("Done", json!({ "is_end_state": true }));
+
+        assert!(error(check_move(1, Some(&waiting()), &done, false, 2)).contains("end state Done"));
+    }
+
+    #[test]
+    fn rejects_moves_to_fork_states() {
+        let message = error(check_move(1, Some(&waiting()), &fork(), false, 2));
+
+        assert!(message.contains("state Split of version 2"), "{}", message);
+        assert!(message.contains("forks"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_moves_to_sub_workflow_states() {
+        let message = error(check_move(1, Some(&automatic()), &sub_workflow(), false, 2));
+
+        assert!(message.contains("starts a sub-workflow"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_moves_to_automatic_states() {
+        let message = error(check_move(1, Some(&fork()), &automatic(), false, 2));
+
+        assert!(message.contains("moves on automatically"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_moves_of_forked_tokens_out_of_fork_states() {
+        let message = error(check_move(1, Some(&fork()), &waiting(), true, 2));
+
+        assert!(message.contains("parallel branches"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_instances_waiting_on_calls() {
+        assert!(error(check_settled(1, 1, 0)).contains("1 HTTP calls"));
+    }
+
+    #[test]
+    fn rejects_instances_with_running_children() {
+        assert!(error(check_settled(1, 0, 2)).contains("2 sub-workflow instances"));
+    }
+
+    #[test]
+    fn accepts_settled_instances() {
+        check_settled(1, 0, 0).unwrap();
+    }
+}
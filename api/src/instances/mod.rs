@@ -0,0 +1,368 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tsync::tsync;
+use uuid::Uuid;
+
+use crate::database::schema::{
+    workflow_instance_tasks, workflow_instance_tokens, workflow_instances,
+};
+use crate::database::{DbConnection, DB};
+use crate::defaults::{default_bool, default_i64};
+use crate::pagination;
+use crate::result::{AppError, Result};
+use crate::search::{self, Language};
+use crate::users::User;
+use crate::webhooks::{self, WebhookEvent};
+
+mod assignment;
+mod bulk;
+mod calls;
+mod http;
+mod migration;
+mod runtime;
+mod simulation;
+mod sla;
+mod tasks;
+mod tokens;
+mod walk;
+
+pub use assignment::{AssignmentStrategy, LeastLoaded, RoundRobin};
+pub use calls::{CallStatus, InstanceCall};
+pub use migration::{InstanceMigration, MigrateInstances, MigratedInstance, TokenMove};
+pub use simulation::{
+    ActionPhase, GuardEvaluation, SimulateWorkflow, SimulatedAction, SimulatedChoice,
+    SimulatedResponse, SimulatedVisit, SimulatedWait, Simulation, SimulationOutcome,
+    SimulationStop,
+};
+pub use tasks::InstanceTask;
+pub use tokens::InstanceToken;
+
+#[derive(Clone, Debug, Deserialize, Identifiable, JsonSchema, Queryable, Selectable, Serialize)]
+#[tsync]
+#[diesel(table_name = workflow_instances)]
+pub struct WorkflowInstance {
+    pub id: i64,
+    pub tenant_id: i32,
+    pub workflow_id: i64,
+    /// The version of the workflow the instance runs.
+    pub workflow_version: i32,
+    pub current_state_id: Uuid,
+    pub data: serde_json::Value,
+    pub created_by: Option<i64>,
+    pub assignee_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// The instance whose sub-workflow state started this instance.
+    pub parent_id: Option<i64>,
+    pub parent_token_id: Option<i64>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = workflow_instances)]
+struct NewWorkflowInstance {
+    tenant_id: i32,
+    workflow_id: i64,
+    workflow_version: i32,
+    current_state_id: Uuid,
+    data: serde_json::Value,
+    created_by: Option<i64>,
+    parent_id: Option<i64>,
+    parent_token_id: Option<i64>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct StartInstance {
+    pub workflow_id: i64,
+    pub data: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct TransitionInstance {
+    pub transition_id: Uuid,
+    pub option_id: Option<Uuid>,
+    pub comment: Option<String>,
+    pub data: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct AssignInstance {
+    pub user_id: i64,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct BulkAssign {
+    pub instance_ids: Vec<i64>,
+    /// The user to assign the instances to, or `None` to unassign them.
+    pub user_id: Option<i64>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct BulkTransition {
+    pub instance_ids: Vec<i64>,
+    pub transition_id: Uuid,
+    pub option_id: Option<Uuid>,
+    pub comment: Option<String>,
+    pub data: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct BulkTransitionResult {
+    /// The state the transition leads to, before any automatic transitions
+    /// out of it are taken.
+    pub target_state_id: Uuid,
+    /// The instance after the transition, not set for dry runs.
+    pub instance: Option<WorkflowInstance>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct InstanceQuery {
+    pub tenant_id: Option<i32>,
+    pub workflow_id: Option<i64>,
+    pub assignee_id: Option<i64>,
+    pub current_state_id: Option<Uuid>,
+    pub parent_id: Option<i64>,
+    pub completed: Option<bool>,
+    pub overdue: Option<bool>,
+    /// Search text, matched against the values in the instance data.
+    pub q: Option<String>,
+    #[serde(default = "default_bool::<true>")]
+    pub active: bool,
+    #[serde(default = "default_i64::<1>")]
+    pub page: i64,
+    #[serde(default = "default_i64::<10>")]
+    pub page_size: i64,
+}
+
+impl WorkflowInstance {
+    pub fn find(conn: &mut DbConnection, id: i64) -> Result<Option<WorkflowInstance>> {
+        Ok(workflow_instances::table
+            .select(WorkflowInstance::as_select())
+            .filter(workflow_instances::id.eq(id))
+            .filter(workflow_instances::deleted_at.is_null())
+            .get_result(conn)
+            .optional()?)
+    }
+
+    /// Finds the instance and locks it until the end of the transaction.
+    pub fn lock(conn: &mut DbConnection, id: i64) -> Result<Option<WorkflowInstance>> {
+        Ok(workflow_instances::table
+            .select(WorkflowInstance::as_select())
+            .filter(workflow_instances::id.eq(id))
+            .filter(workflow_instances::deleted_at.is_null())
+            .for_update()
+            .get_result(conn)
+            .optional()?)
+    }
+
+    pub fn list(conn: &mut DbConnection, params: InstanceQuery) -> Result<Vec<WorkflowInstance>> {
+        pagination::check(params.page, params.page_size)?;
+
+        let InstanceQuery {
+            page, page_size, ..
+        } = params;
+
+        Ok(Self::filtered(&params)
+            .select(WorkflowInstance::as_select())
+            .order(workflow_instances::id.asc())
+            .limit(page_size)
+            .offset(page_size * (page - 1))
+            .get_results(conn)?)
+    }
+
+    pub fn count(conn: &mut DbConnection, params: InstanceQuery) -> Result<i64> {
+        Ok(Self::filtered(&params).count().get_result(conn)?)
+    }
+
+    /// The instances matching the query, which both listing and counting them
+    /// start from.
+    fn filtered(params: &InstanceQuery) -> workflow_instances::BoxedQuery<'static, DB> {
+        let mut query = workflow_instances::table.into_boxed::<DB>();
+
+        if let Some(tenant_id) = params.tenant_id {
+            query = query.filter(workflow_instances::tenant_id.eq(tenant_id));
+        }
+
+        if let Some(workflow_id) = params.workflow_id {
+            query = query.filter(workflow_instances::workflow_id.eq(workflow_id));
+        }
+
+        if let Some(assignee_id) = params.assignee_id {
+            query = query.filter(workflow_instances::assignee_id.eq(assignee_id));
+        }
+
+        if let Some(current_state_id) = params.current_state_id {
+            let tokens = workflow_instance_tokens::table
+                .select(workflow_instance_tokens::instance_id)
+                .filter(workflow_instance_tokens::state_id.eq(current_state_id))
+                .filter(
+                    workflow_instance_tokens::completed_at
+                        .is_null()
+                        .or(workflow_instance_tokens::parent_id.is_null()),
+                );
+            query = query.filter(workflow_instances::id.eq_any(tokens));
+        }
+
+        if let Some(parent_id) = params.parent_id {
+            query = query.filter(workflow_instances::parent_id.eq(parent_id));
+        }
+
+        query = match params.completed {
+            Some(true) => query.filter(workflow_instances::completed_at.is_not_null()),
+            Some(false) => query.filter(workflow_instances::completed_at.is_null()),
+            None => query,
+        };
+
+        let overdue_tasks = workflow_instance_tasks::table
+            .select(workflow_instance_tasks::instance_id)
+            .filter(workflow_instance_tasks::completed_at.is_null())
+            .filter(workflow_instance_tasks::due_at.lt(Utc::now()));
+
+        query = match params.overdue {
+            Some(true) => query.filter(workflow_instances::id.eq_any(overdue_tasks)),
+            Some(false) => query.filter(workflow_instances::id.ne_all(overdue_tasks)),
+            None => query,
+        };
+
+        if let Some(text) = &params.q {
+            query = query.filter(search::matches(
+                workflow_instances::search_vector,
+                Language::Simple,
+                text,
+            ));
+        }
+
+        match params.active {
+            true => query.filter(workflow_instances::deleted_at.is_null()),
+            false => query.filter(workflow_instances::deleted_at.is_not_null()),
+        }
+    }
+
+    /// Sets the assignee of the instance and of its open task. Passing `None`
+    /// leaves the instance unassigned.
+    pub fn assign(
+        conn: &mut DbConnection,
+        id: i64,
+        assignee_id: Option<i64>,
+    ) -> Result<WorkflowInstance> {
+        conn.transaction(|conn| {
+            let instance = Self::find(conn, id)?
+                .ok_or_else(|| AppError::not_found("WorkflowInstance", &id.to_string()))?;
+
+            if let Some(user_id) = assignee_id {
+                Self::ensure_assignable(conn, &instance, user_id)?;
+            }
+
+            let instance = diesel::update(workflow_instances::table)
+                .filter(workflow_instances::id.eq(instance.id))
+                .set(workflow_instances::assignee_id.eq(assignee_id))
+                .returning(WorkflowInstance::as_returning())
+                .get_result(conn)?;
+
+            Self::assigned(conn, instance)
+        })
+    }
+
+    /// Assigns an unassigned, open instance to the given user. The instance is
+    /// only taken when no one else has claimed it in the meantime.
+    pub fn claim(conn: &mut DbConnection, id: i64, user_id: i64) -> Result<WorkflowInstance> {
+        conn.transaction(|conn| {
+            let claimed = diesel::update(workflow_instances::table)
+                .filter(workflow_instances::id.eq(id))
+                .filter(workflow_instances::deleted_at.is_null())
+                .filter(workflow_instances::completed_at.is_null())
+                .filter(workflow_instances::assignee_id.is_null())
+                .set(workflow_instances::assignee_id.eq(user_id))
+                .returning(WorkflowInstance::as_returning())
+                .get_result(conn)
+                .optional()?;
+
+            if let Some(instance) = claimed {
+                Self::ensure_assignable(conn, &instance, user_id)?;
+                return Self::assigned(conn, instance);
+            }
+
+            let instance = Self::find(conn, id)?
+                .ok_or_else(|| AppError::not_found("WorkflowInstance", &id.to_string()))?;
+
+            if instance.completed_at.is_some() {
+                return Err(AppError::forbidden("Instance is completed"));
+            }
+            match instance.assignee_id {
+                Some(assignee_id) if assignee_id == user_id => Ok(instance),
+                _ => Err(AppError::forbidden("Instance is already assigned")),
+            }
+        })
+    }
+
+    /// Releases an open instance currently assigned to the given user.
+    pub fn unclaim(conn: &mut DbConnection, id: i64, user_id: i64) -> Result<WorkflowInstance> {
+        conn.transaction(|conn| {
+            let released = diesel::update(workflow_instances::table)
+                .filter(workflow_instances::id.eq(id))
+                .filter(workflow_instances::deleted_at.is_null())
+                .filter(workflow_instances::completed_at.is_null())
+                .filter(workflow_instances::assignee_id.eq(user_id))
+                .set(workflow_instances::assignee_id.eq(None::<i64>))
+                .returning(WorkflowInstance::as_returning())
+                .get_result(conn)
+                .optional()?;
+
+            if let Some(instance) = released {
+                return Self::assigned(conn, instance);
+            }
+
+            let instance = Self::find(conn, id)?
+                .ok_or_else(|| AppError::not_found("WorkflowInstance", &id.to_string()))?;
+
+            if instance.completed_at.is_some() {
+                return Err(AppError::forbidden("Instance is completed"));
+            }
+            Err(AppError::forbidden("Instance is not assigned to you"))
+        })
+    }
+
+    /// Hands the open task of an instance whose assignee changed to the new
+    /// assignee, and announces the change.
+    fn assigned(conn: &mut DbConnection, instance: WorkflowInstance) -> Result<WorkflowInstance> {
+        InstanceTask::reassign_open(conn, instance.id, instance.assignee_id)?;
+
+        webhooks::publish(
+            conn,
+            instance.tenant_id,
+            WebhookEvent::InstanceAssigned,
+            json!({ "instance": instance }),
+        )?;
+
+        Ok(instance)
+    }
+
+    fn ensure_assignable(
+        conn: &mut DbConnection,
+        instance: &WorkflowInstance,
+        user_id: i64,
+    ) -> Result<()> {
+        let user = User::find(conn, user_id)?
+            .ok_or_else(|| AppError::not_found("User", &user_id.to_string()))?;
+
+        if user.tenant_id != instance.tenant_id || user.deleted_at.is_some() {
+            return Err(AppError::validation_error(format!(
+                "User {} cannot be assigned to instances of tenant {}",
+                user_id, instance.tenant_id
+            )));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,617 @@
+use chrono::Utc;
+use diesel::prelude::*;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::database::{schema::workflow_instances, DbConnection};
+use crate::expressions::Expression;
+use crate::result::{AppError, Result};
+use crate::webhooks::{self, WebhookEvent};
+use crate::workflows::{
+    ActionDefinition, NotifyTarget, Workflow, WorkflowAction, WorkflowDefinition, WorkflowState,
+    WorkflowVersion,
+};
+
+use super::assignment;
+use super::calls::InstanceCall;
+use super::walk::{self, ActionOutcome, Chooser, Departure, Move, Step, Walker};
+use super::WorkflowInstance;
+use super::{InstanceTask, InstanceToken, NewWorkflowInstance, StartInstance, TransitionInstance};
+
+impl WorkflowInstance {
+    /// Finds the instance's workflow, with the definition of the version the
+    /// instance runs rather than the current one.
+    pub fn workflow(&self, conn: &mut DbConnection) -> Result<Workflow> {
+        let workflow = Workflow::find(conn, self.workflow_id)?
+            .ok_or_else(|| AppError::not_found("Workflow", &self.workflow_id.to_string()))?;
+        let version = WorkflowVersion::get(conn, self.workflow_id, self.workflow_version)?;
+
+        Ok(Workflow {
+            definition: version.definition,
+            ..workflow
+        })
+    }
+
+    /// Creates an instance of the workflow and enters its initial state.
+    pub fn start(
+        conn: &mut DbConnection,
+        created_by: Option<i64>,
+        StartInstance { workflow_id, data }: StartInstance,
+    ) -> Result<WorkflowInstance> {
+        conn.transaction(|conn| Self::create(conn, workflow_id, created_by, data, None))
+    }
+
+    /// Inserts the instance, started from the parent token when the instance
+    /// runs a sub-workflow, and enters the workflow's initial state.
+    fn create(
+        conn: &mut DbConnection,
+        workflow_id: i64,
+        created_by: Option<i64>,
+        data: Option<Value>,
+        parent: Option<&InstanceToken>,
+    ) -> Result<WorkflowInstance> {
+        let workflow = Workflow::find(conn, workflow_id)?
+            .filter(|w| w.deleted_at.is_none())
+            .ok_or_else(|| AppError::not_found("Workflow", &workflow_id.to_string()))?;
+
+        let mut data = data.unwrap_or_else(|| json!({}));
+        if !data.is_object() {
+            return Err(AppError::validation_error(
+                "Instance data must be a JSON object",
+            ));
+        }
+
+        if let Some(data_schema) = &workflow.definition.data_schema {
+            data_schema.apply(&mut data)?;
+        }
+
+        let version = WorkflowVersion::latest(conn, workflow.id)?.ok_or_else(|| {
+            AppError::server_error(format!("Workflow {} has no versions", workflow.id))
+        })?;
+
+        let instance = diesel::insert_into(workflow_instances::table)
+            .values(&NewWorkflowInstance {
+                tenant_id: workflow.tenant_id,
+                workflow_id: workflow.id,
+                workflow_version: version.version,
+                current_state_id: workflow.definition.initial_state,
+                data,
+                created_by,
+                parent_id: parent.map(|token| token.instance_id),
+                parent_token_id: parent.map(|token| token.id),
+            })
+            .returning(WorkflowInstance::as_returning())
+            .get_result(conn)?;
+
+        webhooks::publish(
+            conn,
+            instance.tenant_id,
+            WebhookEvent::InstanceStarted,
+            json!({ "instance": instance }),
+        )?;
+
+        let token =
+            InstanceToken::open(conn, instance.id, None, workflow.definition.initial_state)?;
+
+        let step = Step::Enter(Move::to(workflow.definition.initial_state));
+        Self::walk(conn, instance, &workflow.definition, token, step)
+    }
+
+    /// Moves the instance out of its current state through one of the
+    /// state's transitions. Requests from inbound triggers have no user.
+    pub fn transition(
+        conn: &mut DbConnection,
+        id: i64,
+        user_id: Option<i64>,
+        request: TransitionInstance,
+    ) -> Result<WorkflowInstance> {
+        conn.transaction(|conn| {
+            let (instance, workflow, token, target) =
+                Self::prepare_transition(conn, id, user_id, &request)?;
+            let TransitionInstance {
+                option_id,
+                comment,
+                data,
+                ..
+            } = request;
+            let definition = &workflow.definition;
+
+            let instance = Self::merge_data(conn, instance, definition, data)?;
+
+            Self::move_to(
+                conn, instance, definition, token, target, user_id, option_id, comment,
+            )
+        })
+    }
+
+    /// Checks that the instance can take the transition, without running any
+    /// of the actions taking it would. Returns the state it would move to.
+    pub fn check_transition(
+        conn: &mut DbConnection,
+        id: i64,
+        user_id: Option<i64>,
+        request: &TransitionInstance,
+    ) -> Result<Uuid> {
+        let (_, _, _, target) = Self::prepare_transition(conn, id, user_id, request)?;
+
+        Ok(target)
+    }
+
+    /// Finds the instance's token that can take the transition, and the
+    /// state the transition leads to for the chosen option. The instance is
+    /// locked, so that concurrent transitions cannot both move the token.
+    fn prepare_transition(
+        conn: &mut DbConnection,
+        id: i64,
+        user_id: Option<i64>,
+        TransitionInstance {
+            transition_id,
+            option_id,
+            comment,
+            ..
+        }: &TransitionInstance,
+    ) -> Result<(WorkflowInstance, Workflow, InstanceToken, Uuid)> {
+        let instance = Self::lock(conn, id)?
+            .ok_or_else(|| AppError::not_found("WorkflowInstance", &id.to_string()))?;
+
+        if instance.completed_at.is_some() {
+            return Err(AppError::bad_request("Instance has already completed"));
+        }
+
+        let workflow = instance.workflow(conn)?;
+        let (token, transition) = InstanceToken::list_active(conn, instance.id)?
+            .into_iter()
+            .find_map(|token| {
+                let transition = workflow
+                    .definition
+                    .state(token.state_id)?
+                    .transitions
+                    .iter()
+                    .find(|t| t.id == *transition_id)?;
+                Some((token, transition))
+            })
+            .ok_or_else(|| {
+                AppError::validation_error(format!(
+                    "Transition {} is not available from the instance's current states",
+                    transition_id
+                ))
+            })?;
+
+        let target = walk::resolve_target(
+            transition,
+            *option_id,
+            comment.as_deref(),
+            Chooser::User(user_id),
+        )?;
+
+        Ok((instance, workflow, token, target))
+    }
+
+    /// Exits the token's current state and enters the target state.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn move_to(
+        conn: &mut DbConnection,
+        instance: WorkflowInstance,
+        definition: &WorkflowDefinition,
+        token: InstanceToken,
+        target_state_id: Uuid,
+        completed_by: Option<i64>,
+        option_id: Option<Uuid>,
+        comment: Option<String>,
+    ) -> Result<WorkflowInstance> {
+        let state = definition.state(token.state_id).ok_or_else(|| {
+            AppError::server_error(format!(
+                "Instance {} is in unknown state {}",
+                instance.id, token.state_id
+            ))
+        })?;
+
+        if InstanceCall::find_pending(conn, token.id)?.is_some() {
+            return Err(AppError::bad_request(format!(
+                "Instance is waiting on an HTTP action of state {}",
+                state.name
+            )));
+        }
+
+        let departure = Departure {
+            target_state_id: Some(target_state_id),
+            completed_by,
+            option_id,
+            comment,
+            ..Departure::default()
+        };
+        let step = Step::Leave(state, departure, 0);
+
+        Self::walk(conn, instance, definition, token, step)
+    }
+
+    /// Moves the token on from the HTTP action it was waiting on. A failed
+    /// call routes the token to the action's failure state, skipping the
+    /// state's remaining actions.
+    pub(super) fn resume_call(
+        conn: &mut DbConnection,
+        instance: WorkflowInstance,
+        definition: &WorkflowDefinition,
+        token: InstanceToken,
+        call: &InstanceCall,
+        failure_state_id: Option<Uuid>,
+    ) -> Result<WorkflowInstance> {
+        let state = definition.state(call.state_id).ok_or_else(|| {
+            AppError::server_error(format!(
+                "Instance {} is in unknown state {}",
+                instance.id, call.state_id
+            ))
+        })?;
+        let actions = match call.exiting {
+            true => &state.exit_actions,
+            false => &state.entry_actions,
+        };
+        let index = actions
+            .iter()
+            .position(|a| a.id == call.action_id)
+            .ok_or_else(|| {
+                AppError::server_error(format!(
+                    "State {} has no action {}",
+                    state.name, call.action_id
+                ))
+            })?;
+
+        let step = match (call.exiting, failure_state_id) {
+            (false, None) => Step::Proceed(state, index + 1),
+            (false, Some(failure_state_id)) => {
+                Step::Leave(state, Departure::to(failure_state_id), 0)
+            }
+            (true, None) => Step::Leave(state, call.departure(), index + 1),
+            (true, Some(failure_state_id)) => {
+                let departure = Departure {
+                    target_state_id: Some(failure_state_id),
+                    ..call.departure()
+                };
+                Step::Leave(state, departure, actions.len())
+            }
+        };
+
+        Self::walk(conn, instance, definition, token, step)
+    }
+
+    /// Walks the token from the step on, and returns the instance as the walk
+    /// left it.
+    fn walk(
+        conn: &mut DbConnection,
+        instance: WorkflowInstance,
+        definition: &WorkflowDefinition,
+        token: InstanceToken,
+        step: Step,
+    ) -> Result<WorkflowInstance> {
+        let mut runtime = Runtime { conn, instance };
+        walk::walk(&mut runtime, definition, token, step)?;
+
+        Ok(runtime.instance)
+    }
+
+    /// Leaves the sub-workflow state the completed instance was started from,
+    /// through the option mapped to the end state the instance reached.
+    fn resume_parent(
+        conn: &mut DbConnection,
+        child: &WorkflowInstance,
+        end_state_id: Uuid,
+    ) -> Result<()> {
+        let (Some(parent_id), Some(token_id)) = (child.parent_id, child.parent_token_id) else {
+            return Ok(());
+        };
+        // Locked before its token is read, so that the token is not moved by
+        // a transition of the parent in the meantime
+        let parent = Self::lock(conn, parent_id)?
+            .ok_or_else(|| AppError::not_found("WorkflowInstance", &parent_id.to_string()))?;
+        let Some(token) = InstanceToken::find(conn, token_id)?.filter(|t| t.completed_at.is_none())
+        else {
+            return Ok(());
+        };
+        let workflow = parent.workflow(conn)?;
+
+        // The parent may have been moved on while the child was running
+        let Some((_, options, outcomes)) = workflow
+            .definition
+            .state(token.state_id)
+            .and_then(|s| s.sub_workflow())
+            .filter(|(workflow_id, ..)| *workflow_id == child.workflow_id)
+        else {
+            tracing::info!(
+                instance_id = parent.id,
+                child_id = child.id,
+                "Instance is no longer waiting on the sub-workflow"
+            );
+            return Ok(());
+        };
+
+        let option = outcomes
+            .iter()
+            .find(|o| o.end_state_id == end_state_id)
+            .and_then(|o| options.iter().find(|option| option.id == o.option_id))
+            .ok_or_else(|| {
+                AppError::validation_error(format!(
+                    "End state {} of the sub-workflow is not mapped to an option of workflow {}",
+                    end_state_id, workflow.name
+                ))
+            })?;
+
+        Self::move_to(
+            conn,
+            parent,
+            &workflow.definition,
+            token,
+            option.target_state_id,
+            None,
+            Some(option.id),
+            None,
+        )?;
+
+        Ok(())
+    }
+
+    fn publish_state_change(
+        conn: &mut DbConnection,
+        instance: &WorkflowInstance,
+        from_state_id: Uuid,
+        state: &WorkflowState,
+    ) -> Result<()> {
+        if from_state_id != state.id {
+            webhooks::publish(
+                conn,
+                instance.tenant_id,
+                WebhookEvent::InstanceStateChanged,
+                json!({
+                    "instance": instance,
+                    "from_state_id": from_state_id,
+                    "state_id": state.id,
+                    "state_name": state.name,
+                }),
+            )?;
+        }
+
+        if state.is_end_state {
+            webhooks::publish(
+                conn,
+                instance.tenant_id,
+                WebhookEvent::InstanceCompleted,
+                json!({ "instance": instance }),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn run_action(
+        conn: &mut DbConnection,
+        instance: WorkflowInstance,
+        action: &WorkflowAction,
+    ) -> Result<WorkflowInstance> {
+        match &action.definition {
+            ActionDefinition::AutoAssign { strategy, user_ids } => {
+                let candidates = assignment::candidates(conn, instance.tenant_id, user_ids)?;
+
+                match strategy.strategy().select(conn, &instance, &candidates)? {
+                    Some(user_id) => Self::assign(conn, instance.id, Some(user_id)),
+                    None => {
+                        tracing::warn!(
+                            instance_id = instance.id,
+                            action_id = %action.id,
+                            "No candidates available for auto assignment"
+                        );
+                        Ok(instance)
+                    }
+                }
+            }
+            ActionDefinition::AssignTo { user_id } => {
+                Self::assign(conn, instance.id, Some(*user_id))
+            }
+            ActionDefinition::Email { template_id, email } => {
+                tracing::info!(
+                    instance_id = instance.id,
+                    template_id,
+                    email,
+                    "Email action has no delivery channel configured"
+                );
+                Ok(instance)
+            }
+            ActionDefinition::Notify {
+                template_id,
+                target,
+            } => {
+                Self::notify(&instance, *template_id, target);
+                Ok(instance)
+            }
+            // HTTP actions are queued as calls by the runtime walker
+            ActionDefinition::Http { .. } => Ok(instance),
+        }
+    }
+
+    pub(super) fn notify(instance: &WorkflowInstance, template_id: i64, target: &NotifyTarget) {
+        tracing::info!(
+            instance_id = instance.id,
+            template_id,
+            ?target,
+            "Notify action has no delivery channel configured"
+        );
+    }
+
+    /// Merges the data a step brings into the instance's, see
+    /// [`walk::merge_data`].
+    fn merge_data(
+        conn: &mut DbConnection,
+        instance: WorkflowInstance,
+        definition: &WorkflowDefinition,
+        data: Option<Value>,
+    ) -> Result<WorkflowInstance> {
+        let merged = walk::merge_data(&instance.data, definition, data)?;
+
+        if merged == instance.data {
+            return Ok(instance);
+        }
+
+        Ok(diesel::update(workflow_instances::table)
+            .filter(workflow_instances::id.eq(instance.id))
+            .set(workflow_instances::data.eq(merged))
+            .returning(WorkflowInstance::as_returning())
+            .get_result(conn)?)
+    }
+}
+
+/// Walks the tokens of an instance in the database. HTTP actions are queued
+/// as calls for the token to wait on, and made once the walk has committed.
+struct Runtime<'a> {
+    conn: &'a mut DbConnection,
+    instance: WorkflowInstance,
+}
+
+impl Walker for Runtime<'_> {
+    type Token = InstanceToken;
+
+    fn state_id(&self, token: &InstanceToken) -> Uuid {
+        token.state_id
+    }
+
+    fn parent(&mut self, token: &InstanceToken) -> Result<Option<InstanceToken>> {
+        let Some(parent_id) = token.parent_id else {
+            return Ok(None);
+        };
+
+        InstanceToken::find(self.conn, parent_id)?
+            .map(Some)
+            .ok_or_else(|| {
+                AppError::server_error(format!("Token {} has no parent {}", token.id, parent_id))
+            })
+    }
+
+    fn join(
+        &mut self,
+        token: InstanceToken,
+        parent: &InstanceToken,
+        state: &WorkflowState,
+        _: &Move,
+    ) -> Result<bool> {
+        // Branches reaching the join in concurrent transactions take turns,
+        // otherwise each could see the other still running and neither would
+        // resume the parent
+        InstanceToken::lock(self.conn, parent.id)?;
+        InstanceToken::move_to(self.conn, token.id, state.id, true)?;
+
+        Ok(InstanceToken::count_active_children(self.conn, parent.id)? == 0)
+    }
+
+    fn enter(
+        &mut self,
+        token: InstanceToken,
+        state: &WorkflowState,
+        _: &Move,
+    ) -> Result<InstanceToken> {
+        let token = InstanceToken::move_to(self.conn, token.id, state.id, state.is_end_state)?;
+
+        if token.parent_id.is_none() {
+            let from_state_id = self.instance.current_state_id;
+
+            self.instance = diesel::update(workflow_instances::table)
+                .filter(workflow_instances::id.eq(self.instance.id))
+                .set((
+                    workflow_instances::current_state_id.eq(state.id),
+                    workflow_instances::completed_at.eq(state.is_end_state.then(Utc::now)),
+                ))
+                .returning(WorkflowInstance::as_returning())
+                .get_result(self.conn)?;
+
+            WorkflowInstance::publish_state_change(
+                self.conn,
+                &self.instance,
+                from_state_id,
+                state,
+            )?;
+        }
+
+        if !state.is_end_state {
+            let (due_at, next_escalation_at) = state
+                .sla
+                .as_ref()
+                .map(|sla| sla.deadlines(Utc::now()))
+                .map_or((None, None), |(due_at, next)| (Some(due_at), next));
+            InstanceTask::open(
+                self.conn,
+                self.instance.id,
+                token.id,
+                state.id,
+                self.instance.assignee_id,
+                due_at,
+                next_escalation_at,
+            )?;
+        }
+
+        Ok(token)
+    }
+
+    fn run_action(
+        &mut self,
+        token: &InstanceToken,
+        state: &WorkflowState,
+        action: &WorkflowAction,
+        departure: Option<&Departure>,
+    ) -> Result<ActionOutcome> {
+        if let ActionDefinition::Http { .. } = &action.definition {
+            InstanceCall::queue(
+                self.conn,
+                self.instance.id,
+                token.id,
+                state.id,
+                action.id,
+                departure,
+            )?;
+            return Ok(ActionOutcome::Waiting);
+        }
+
+        self.instance = WorkflowInstance::run_action(self.conn, self.instance.clone(), action)?;
+        Ok(ActionOutcome::Done)
+    }
+
+    fn guard(&mut self, _: &WorkflowState, _: Uuid, guard: &str) -> Result<bool> {
+        Expression::cached(guard)?.evaluate(&self.instance.data)
+    }
+
+    fn complete(&mut self, token: &InstanceToken, departure: &Departure) -> Result<()> {
+        InstanceTask::complete_open(
+            self.conn,
+            token.id,
+            departure.completed_by,
+            departure.option_id,
+            departure.comment.clone(),
+        )?;
+
+        Ok(())
+    }
+
+    fn spawn(&mut self, token: &InstanceToken, branches: &[Uuid]) -> Result<Vec<InstanceToken>> {
+        branches
+            .iter()
+            .map(|branch| InstanceToken::open(self.conn, self.instance.id, Some(token.id), *branch))
+            .collect()
+    }
+
+    fn end(&mut self, token: &InstanceToken, state: &WorkflowState) -> Result<()> {
+        if token.parent_id.is_none() {
+            WorkflowInstance::resume_parent(self.conn, &self.instance, state.id)?;
+        }
+
+        Ok(())
+    }
+
+    fn start_child(&mut self, token: &InstanceToken, workflow_id: i64) -> Result<()> {
+        let data = Some(self.instance.data.clone());
+        let created_by = self.instance.created_by;
+        WorkflowInstance::create(self.conn, workflow_id, created_by, data, Some(token))?;
+
+        // The child may have completed straight away and moved this instance
+        // on already
+        self.instance = WorkflowInstance::find(self.conn, self.instance.id)?.ok_or_else(|| {
+            AppError::not_found("WorkflowInstance", &self.instance.id.to_string())
+        })?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,836 @@
+//! Runs a definition against a sequence of choices without creating an
+//! instance, so designers can try a workflow before it has real data.
+//!
+//! The simulation walks the definition with the same rules as the runtime:
+//! entry and exit actions, automatic transitions and their guards, forks and
+//! joins. Nothing is written and no action has an effect. HTTP actions
+//! respond with the responses given in the request, or with an empty success,
+//! and sub-workflow states wait for a step choosing the option the child
+//! would finish with.
+
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tsync::tsync;
+use uuid::Uuid;
+
+use crate::defaults::default_u16;
+use crate::expressions::Expression;
+use crate::result::{AppError, Result};
+use crate::workflows::{
+    ActionDefinition, TransitionDefinition, WorkflowAction, WorkflowDefinition, WorkflowState,
+    WorkflowTransition,
+};
+
+use super::walk::{
+    merge_data, resolve_target, walk, ActionOutcome, Chooser, Departure, Move, Step, Walker,
+};
+use super::TransitionInstance;
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct SimulateWorkflow {
+    /// A definition to simulate instead of the stored one, such as unsaved
+    /// changes from the editor.
+    pub definition: Option<WorkflowDefinition>,
+    /// The data the instance starts with.
+    pub data: Option<Value>,
+    /// The transitions to take in order, with the option chosen for each.
+    #[serde(default)]
+    pub steps: Vec<TransitionInstance>,
+    /// The responses HTTP actions receive, by action id.
+    #[serde(default)]
+    pub http_responses: HashMap<Uuid, SimulatedResponse>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct SimulatedResponse {
+    #[serde(default = "default_u16::<200>")]
+    pub status: u16,
+    #[serde(default)]
+    pub body: Value,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[tsync]
+#[serde(rename_all = "snake_case")]
+pub enum SimulationOutcome {
+    /// The instance reached an end state.
+    Completed,
+    /// Every step was taken and the instance waits for another choice.
+    Waiting,
+    /// A step or an automatic transition failed.
+    Stuck,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+#[serde(rename_all = "snake_case")]
+pub enum ActionPhase {
+    Entry,
+    Exit,
+}
+
+/// A state the instance entered. The step is the index of the step that led
+/// there, and is not set for the states entered when the instance starts.
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct SimulatedVisit {
+    pub step: Option<usize>,
+    /// The branch of the instance, 0 for the instance itself and a higher
+    /// number for each branch of a fork.
+    pub token: usize,
+    pub state_id: Uuid,
+    pub state_name: String,
+    /// The transition the state was entered through.
+    pub transition_id: Option<Uuid>,
+    pub option_id: Option<Uuid>,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct SimulatedAction {
+    pub step: Option<usize>,
+    pub state_id: Uuid,
+    pub action_id: Uuid,
+    pub phase: ActionPhase,
+    /// What the action would do.
+    pub effect: String,
+    /// The state the instance is sent to when the action fails.
+    pub failure_state_id: Option<Uuid>,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct GuardEvaluation {
+    pub step: Option<usize>,
+    pub state_id: Uuid,
+    pub transition_id: Uuid,
+    pub guard: String,
+    pub result: Option<bool>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct SimulationStop {
+    /// The step that failed, not set when starting the instance failed.
+    pub step: Option<usize>,
+    pub state_id: Option<Uuid>,
+    pub reason: String,
+}
+
+/// A state the instance rests in, with the choices that move it on.
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct SimulatedWait {
+    pub token: usize,
+    pub state_id: Uuid,
+    pub state_name: String,
+    pub choices: Vec<SimulatedChoice>,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct SimulatedChoice {
+    pub transition_id: Uuid,
+    pub option_id: Option<Uuid>,
+    pub label: String,
+    pub target_state_id: Uuid,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct Simulation {
+    pub outcome: SimulationOutcome,
+    pub stuck: Option<SimulationStop>,
+    pub path: Vec<SimulatedVisit>,
+    pub actions: Vec<SimulatedAction>,
+    pub guards: Vec<GuardEvaluation>,
+    pub waiting: Vec<SimulatedWait>,
+    /// The instance data after the last step.
+    pub data: Value,
+}
+
+struct Token {
+    parent: Option<usize>,
+    state_id: Uuid,
+    active: bool,
+}
+
+struct Simulator<'a> {
+    definition: &'a WorkflowDefinition,
+    responses: &'a HashMap<Uuid, SimulatedResponse>,
+    step: Option<usize>,
+    tokens: Vec<Token>,
+    data: Value,
+    path: Vec<SimulatedVisit>,
+    actions: Vec<SimulatedAction>,
+    guards: Vec<GuardEvaluation>,
+}
+
+impl WorkflowDefinition {
+    /// Starts an instance of the definition in memory and takes the steps in
+    /// order, stopping at the first one that fails.
+    pub fn simulate(
+        &self,
+        data: Option<Value>,
+        steps: &[TransitionInstance],
+        responses: &HashMap<Uuid, SimulatedResponse>,
+    ) -> Simulation {
+        let mut simulator = Simulator {
+            definition: self,
+            responses,
+            step: None,
+            tokens: vec![],
+            data: json!({}),
+            path: vec![],
+            actions: vec![],
+            guards: vec![],
+        };
+
+        let mut stuck = simulator.start(data).err().map(|e| simulator.stop(e));
+        if stuck.is_none() {
+            for (index, step) in steps.iter().enumerate() {
+                simulator.step = Some(index);
+                if let Err(e) = simulator.take(step) {
+                    stuck = Some(simulator.stop(e));
+                    break;
+                }
+            }
+        }
+
+        let completed = simulator.tokens.first().is_some_and(|root| {
+            !root.active && self.state(root.state_id).is_some_and(|s| s.is_end_state)
+        });
+        let outcome = match (&stuck, completed) {
+            (Some(_), _) => SimulationOutcome::Stuck,
+            (None, true) => SimulationOutcome::Completed,
+            (None, false) => SimulationOutcome::Waiting,
+        };
+
+        Simulation {
+            outcome,
+            stuck,
+            waiting: simulator.waiting(),
+            path: simulator.path,
+            actions: simulator.actions,
+            guards: simulator.guards,
+            data: simulator.data,
+        }
+    }
+}
+
+impl<'a> Simulator<'a> {
+    fn start(&mut self, data: Option<Value>) -> Result<()> {
+        self.data = data.unwrap_or_else(|| json!({}));
+        if !self.data.is_object() {
+            return Err(AppError::validation_error(
+                "Instance data must be a JSON object",
+            ));
+        }
+        if let Some(data_schema) = &self.definition.data_schema {
+            data_schema.apply(&mut self.data)?;
+        }
+
+        let initial_state = self.definition.initial_state;
+        self.tokens.push(Token {
+            parent: None,
+            state_id: initial_state,
+            active: true,
+        });
+
+        walk(
+            self,
+            self.definition,
+            0,
+            Step::Enter(Move::to(initial_state)),
+        )
+    }
+
+    /// Takes the transition from whichever of the instance's current states
+    /// offers it.
+    fn take(&mut self, request: &TransitionInstance) -> Result<()> {
+        let definition = self.definition;
+        let (token, transition) = self
+            .resting()
+            .into_iter()
+            .find_map(|token| {
+                let transition = definition
+                    .state(self.tokens[token].state_id)?
+                    .transitions
+                    .iter()
+                    .find(|t| t.id == request.transition_id)?;
+                Some((token, transition))
+            })
+            .ok_or_else(|| {
+                AppError::validation_error(format!(
+                    "Transition {} is not available from the instance's current states",
+                    request.transition_id
+                ))
+            })?;
+
+        let target = resolve_target(
+            transition,
+            request.option_id,
+            request.comment.as_deref(),
+            Chooser::Simulation,
+        )?;
+        self.data = merge_data(&self.data, definition, request.data.clone())?;
+
+        let state = self.state(self.tokens[token].state_id)?;
+        let departure = Departure {
+            transition_id: Some(transition.id),
+            option_id: request.option_id,
+            ..Departure::to(target)
+        };
+
+        walk(self, definition, token, Step::Leave(state, departure, 0))
+    }
+
+    fn state(&self, id: Uuid) -> Result<&'a WorkflowState> {
+        self.definition
+            .state(id)
+            .ok_or_else(|| AppError::validation_error(format!("State {} does not exist", id)))
+    }
+
+    fn visit(&mut self, token: usize, state: &WorkflowState, via: &Move) {
+        self.path.push(SimulatedVisit {
+            step: self.step,
+            token,
+            state_id: state.id,
+            state_name: state.name.clone(),
+            transition_id: via.transition_id,
+            option_id: via.option_id,
+        });
+    }
+
+    /// The tokens resting in a state, which are the active ones without any
+    /// active branches of their own.
+    fn resting(&self) -> Vec<usize> {
+        (0..self.tokens.len())
+            .filter(|&token| {
+                self.tokens[token].active
+                    && !self
+                        .tokens
+                        .iter()
+                        .any(|t| t.parent == Some(token) && t.active)
+            })
+            .collect()
+    }
+
+    fn waiting(&self) -> Vec<SimulatedWait> {
+        self.resting()
+            .into_iter()
+            .filter_map(|token| {
+                let state = self.definition.state(self.tokens[token].state_id)?;
+                Some(SimulatedWait {
+                    token,
+                    state_id: state.id,
+                    state_name: state.name.clone(),
+                    choices: state.transitions.iter().flat_map(choices).collect(),
+                })
+            })
+            .collect()
+    }
+
+    fn stop(&self, e: AppError) -> SimulationStop {
+        let state_id = match self.resting().as_slice() {
+            [token] => Some(self.tokens[*token].state_id),
+            _ => self.path.last().map(|visit| visit.state_id),
+        };
+
+        SimulationStop {
+            step: self.step,
+            state_id,
+            reason: e.to_string(),
+        }
+    }
+}
+
+/// Walks the tokens in memory. HTTP actions respond straight away with the
+/// simulated responses, and every other action is only recorded.
+impl Walker for Simulator<'_> {
+    type Token = usize;
+
+    fn state_id(&self, token: &usize) -> Uuid {
+        self.tokens[*token].state_id
+    }
+
+    fn parent(&mut self, token: &usize) -> Result<Option<usize>> {
+        Ok(self.tokens[*token].parent)
+    }
+
+    fn join(
+        &mut self,
+        token: usize,
+        parent: &usize,
+        state: &WorkflowState,
+        via: &Move,
+    ) -> Result<bool> {
+        self.tokens[token].state_id = state.id;
+        self.tokens[token].active = false;
+        self.visit(token, state, via);
+
+        Ok(!self
+            .tokens
+            .iter()
+            .any(|t| t.parent == Some(*parent) && t.active))
+    }
+
+    fn enter(&mut self, token: usize, state: &WorkflowState, via: &Move) -> Result<usize> {
+        self.tokens[token].state_id = state.id;
+        self.tokens[token].active = !state.is_end_state;
+        self.visit(token, state, via);
+
+        Ok(token)
+    }
+
+    fn run_action(
+        &mut self,
+        _: &usize,
+        state: &WorkflowState,
+        action: &WorkflowAction,
+        departure: Option<&Departure>,
+    ) -> Result<ActionOutcome> {
+        let failure_state_id = match &action.definition {
+            ActionDefinition::Http {
+                failure_state_id, ..
+            } => *failure_state_id,
+            _ => None,
+        };
+        self.actions.push(SimulatedAction {
+            step: self.step,
+            state_id: state.id,
+            action_id: action.id,
+            phase: match departure {
+                Some(_) => ActionPhase::Exit,
+                None => ActionPhase::Entry,
+            },
+            effect: effect(&action.definition),
+            failure_state_id,
+        });
+
+        let ActionDefinition::Http { result_key, .. } = &action.definition else {
+            return Ok(ActionOutcome::Done);
+        };
+
+        let response = self.responses.get(&action.id);
+        let status = response.map_or(200, |r| r.status);
+        let body = response.map_or(Value::Null, |r| r.body.clone());
+
+        if let (Some(key), Some(data)) = (result_key, self.data.as_object_mut()) {
+            data.insert(key.clone(), json!({ "status": status, "body": body }));
+        }
+
+        if (200..300).contains(&status) {
+            return Ok(ActionOutcome::Done);
+        }
+
+        match failure_state_id {
+            Some(failure_state_id) => Ok(ActionOutcome::Failed(failure_state_id)),
+            None => Err(AppError::validation_error(format!(
+                "HTTP action {} failed: endpoint responded with status {}",
+                action.id, status
+            ))),
+        }
+    }
+
+    /// Evaluates the guard and records the evaluation.
+    fn guard(&mut self, state: &WorkflowState, transition_id: Uuid, guard: &str) -> Result<bool> {
+        let result = Expression::cached(guard).and_then(|e| e.evaluate(&self.data));
+        self.guards.push(GuardEvaluation {
+            step: self.step,
+            state_id: state.id,
+            transition_id,
+            guard: guard.to_string(),
+            result: result.as_ref().ok().copied(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+
+        result
+    }
+
+    fn complete(&mut self, _: &usize, _: &Departure) -> Result<()> {
+        Ok(())
+    }
+
+    fn spawn(&mut self, token: &usize, branches: &[Uuid]) -> Result<Vec<usize>> {
+        Ok(branches
+            .iter()
+            .map(|branch| {
+                self.tokens.push(Token {
+                    parent: Some(*token),
+                    state_id: *branch,
+                    active: true,
+                });
+                self.tokens.len() - 1
+            })
+            .collect())
+    }
+
+    fn end(&mut self, _: &usize, _: &WorkflowState) -> Result<()> {
+        Ok(())
+    }
+
+    /// Sub-workflow states wait for a step choosing the option the child
+    /// would finish with.
+    fn start_child(&mut self, _: &usize, _: i64) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The choices a step can make through the transition.
+fn choices(transition: &WorkflowTransition) -> Vec<SimulatedChoice> {
+    let choice = |option_id, label: &str, target_state_id| SimulatedChoice {
+        transition_id: transition.id,
+        option_id,
+        label: label.to_string(),
+        target_state_id,
+    };
+
+    match &transition.definition {
+        TransitionDefinition::Automatic { .. } | TransitionDefinition::Parallel { .. } => vec![],
+        TransitionDefinition::VendorConfirmation { target_state_id } => {
+            vec![choice(None, &transition.name, *target_state_id)]
+        }
+        TransitionDefinition::Manual { options }
+        | TransitionDefinition::SubWorkflow { options, .. } => options
+            .iter()
+            .map(|o| choice(Some(o.id), &o.label, o.target_state_id))
+            .collect(),
+        TransitionDefinition::Approval {
+            approval_option,
+            rejection_option,
+            ..
+        } => [approval_option, rejection_option]
+            .into_iter()
+            .map(|o| choice(Some(o.id), &o.label, o.target_state_id))
+            .collect(),
+    }
+}
+
+fn effect(action: &ActionDefinition) -> String {
+    match action {
+        ActionDefinition::AutoAssign { strategy, user_ids } => match user_ids.is_empty() {
+            true => format!(
+                "Assigns the instance to a user of the tenant ({:?})",
+                strategy
+            ),
+            false => format!(
+                "Assigns the instance to one of users {:?} ({:?})",
+                user_ids, strategy
+            ),
+        },
+        ActionDefinition::AssignTo { user_id } => {
+            format!("Assigns the instance to user {}", user_id)
+        }
+        ActionDefinition::Email { template_id, email } => {
+            format!("Emails {} with template {}", email, template_id)
+        }
+        ActionDefinition::Notify {
+            template_id,
+            target,
+        } => format!("Notifies {:?} with template {}", target, template_id),
+        ActionDefinition::Http { method, url, .. } => format!("Calls {:?} {}", method, url),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::workflows::fixtures::{
+        action, automatic, definition, end_state, id, manual, option, parallel, state, transition,
+    };
+
+    use super::*;
+
+    fn step(transition: u128, option: Option<u128>) -> TransitionInstance {
+        TransitionInstance {
+            transition_id: id(transition),
+            option_id: option.map(id),
+            comment: None,
+            data: None,
+        }
+    }
+
+    fn simulate(definition: &WorkflowDefinition, steps: &[TransitionInstance]) -> Simulation {
+        definition.simulate(None, steps, &HashMap::new())
+    }
+
+    fn path(simulation: &Simulation) -> Vec<(Option<usize>, usize, Uuid)> {
+        simulation
+            .path
+            .iter()
+            .map(|visit| (visit.step, visit.token, visit.state_id))
+            .collect()
+    }
+
+    /// Draft, submitted to Review, which moves on to Done by itself.
+    fn review() -> WorkflowDefinition {
+        definition(vec![
+            state(
+                1,
+                "Draft",
+                json!({
+                    "entry_actions": [action(20, json!({ "type": "AssignTo", "user_id": 7 }))],
+                    "exit_actions": [action(21, json!({
+                        "type": "Email",
+                        "template_id": 1,
+                        "email": "reviewers@example.com",
+                    }))],
+                    "transitions": [manual(10, &[
+                        option(11, "Submit", 2),
+                        json!({
+                            "id": id(12),
+                            "label": "Withdraw",
+                            "target_state_id": id(3),
+                            "comment_required": true,
+                            "data": [],
+                        }),
+                    ])],
+                }),
+            ),
+            state(2, "Review", json!({ "transitions": [automatic(30, 3)] })),
+            end_state(3, "Done"),
+        ])
+    }
+
+    #[test]
+    fn completes_along_the_chosen_options() {
+        let simulation = simulate(&review(), &[step(10, Some(11))]);
+
+        assert_eq!(simulation.outcome, SimulationOutcome::Completed);
+        assert!(simulation.stuck.is_none());
+        assert!(simulation.waiting.is_empty());
+        assert_eq!(
+            path(&simulation),
+            vec![(None, 0, id(1)), (Some(0), 0, id(2)), (Some(0), 0, id(3))]
+        );
+        assert_eq!(simulation.path[1].transition_id, Some(id(10)));
+        assert_eq!(simulation.path[1].option_id, Some(id(11)));
+    }
+
+    #[test]
+    fn records_entry_and_exit_actions() {
+        let simulation = simulate(&review(), &[step(10, Some(11))]);
+
+        let actions: Vec<(Option<usize>, Uuid, &str)> = simulation
+            .actions
+            .iter()
+            .map(|a| {
+                let phase = match a.phase {
+                    ActionPhase::Entry => "entry",
+                    ActionPhase::Exit => "exit",
+                };
+                (a.step, a.action_id, phase)
+            })
+            .collect();
+        assert_eq!(
+            actions,
+            vec![(None, id(20), "entry"), (Some(0), id(21), "exit")]
+        );
+        assert_eq!(
+            simulation.actions[0].effect,
+            "Assigns the instance to user 7"
+        );
+    }
+
+    #[test]
+    fn waits_with_the_choices_left() {
+        let simulation = simulate(&review(), &[]);
+
+        assert_eq!(simulation.outcome, SimulationOutcome::Waiting);
+        assert_eq!(simulation.waiting.len(), 1);
+        let wait = &simulation.waiting[0];
+        assert_eq!(wait.state_id, id(1));
+        let labels: Vec<&str> = wait.choices.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(labels, vec!["Submit", "Withdraw"]);
+    }
+
+    #[test]
+    fn gets_stuck_on_unknown_options() {
+        let simulation = simulate(&review(), &[step(10, Some(99))]);
+
+        assert_eq!(simulation.outcome, SimulationOutcome::Stuck);
+        let stuck = simulation.stuck.unwrap();
+        assert_eq!(stuck.step, Some(0));
+        assert_eq!(stuck.state_id, Some(id(1)));
+        assert!(stuck.reason.contains("Unknown transition option"));
+    }
+
+    #[test]
+    fn gets_stuck_without_a_required_comment() {
+        let simulation = simulate(&review(), &[step(10, Some(12))]);
+        assert_eq!(simulation.outcome, SimulationOutcome::Stuck);
+        assert!(simulation
+            .stuck
+            .unwrap()
+            .reason
+            .contains("A comment is required for option Withdraw"));
+
+        let commented = TransitionInstance {
+            comment: Some("No longer needed".to_string()),
+            ..step(10, Some(12))
+        };
+        assert_eq!(
+            simulate(&review(), &[commented]).outcome,
+            SimulationOutcome::Completed
+        );
+    }
+
+    #[test]
+    fn records_guard_evaluations() {
+        let definition = definition(vec![
+            state(
+                1,
+                "Route",
+                json!({ "transitions": [
+                    transition(10, json!({
+                        "type": "Automatic",
+                        "target_state_id": id(2),
+                        "guard": "amount > 100",
+                    })),
+                    automatic(11, 3),
+                ] }),
+            ),
+            end_state(2, "Large"),
+            end_state(3, "Small"),
+        ]);
+
+        let simulation = definition.simulate(Some(json!({ "amount": 50 })), &[], &HashMap::new());
+
+        assert_eq!(simulation.outcome, SimulationOutcome::Completed);
+        assert_eq!(simulation.path.last().map(|v| v.state_id), Some(id(3)));
+        assert_eq!(simulation.guards.len(), 1);
+        assert_eq!(simulation.guards[0].transition_id, id(10));
+        assert_eq!(simulation.guards[0].result, Some(false));
+        assert_eq!(simulation.guards[0].error, None);
+    }
+
+    #[test]
+    fn forks_and_joins() {
+        let definition = definition(vec![
+            state(
+                1,
+                "Split",
+                json!({ "transitions": [parallel(10, &[2, 3], 4)] }),
+            ),
+            state(
+                2,
+                "Legal",
+                json!({ "transitions": [manual(20, &[option(21, "Cleared", 4)])] }),
+            ),
+            state(
+                3,
+                "Finance",
+                json!({ "transitions": [manual(30, &[option(31, "Cleared", 4)])] }),
+            ),
+            state(4, "Joined", json!({ "transitions": [automatic(40, 5)] })),
+            end_state(5, "Done"),
+        ]);
+
+        let halfway = simulate(&definition, &[step(20, Some(21))]);
+        assert_eq!(halfway.outcome, SimulationOutcome::Waiting);
+        let waiting: Vec<(usize, Uuid)> = halfway
+            .waiting
+            .iter()
+            .map(|w| (w.token, w.state_id))
+            .collect();
+        assert_eq!(waiting, vec![(2, id(3))]);
+
+        let simulation = simulate(&definition, &[step(20, Some(21)), step(30, Some(31))]);
+        assert_eq!(simulation.outcome, SimulationOutcome::Completed);
+        assert_eq!(
+            path(&simulation),
+            vec![
+                (None, 0, id(1)),
+                (None, 1, id(2)),
+                (None, 2, id(3)),
+                (Some(0), 1, id(4)),
+                (Some(1), 2, id(4)),
+                (Some(1), 0, id(4)),
+                (Some(1), 0, id(5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn answers_http_actions_with_the_given_responses() {
+        let definition = definition(vec![
+            state(
+                1,
+                "Check",
+                json!({
+                    "entry_actions": [action(20, json!({
+                        "type": "Http",
+                        "url": "https://example.com/check",
+                        "result_key": "check",
+                        "failure_state_id": id(3),
+                    }))],
+                    "transitions": [automatic(10, 2)],
+                }),
+            ),
+            end_state(2, "Passed"),
+            end_state(3, "Failed"),
+        ]);
+
+        let passed = simulate(&definition, &[]);
+        assert_eq!(passed.path.last().map(|v| v.state_id), Some(id(2)));
+        assert_eq!(passed.data["check"], json!({ "status": 200, "body": null }));
+
+        let responses = HashMap::from([(
+            id(20),
+            SimulatedResponse {
+                status: 503,
+                body: json!({ "error": "down" }),
+            },
+        )]);
+        let failed = definition.simulate(None, &[], &responses);
+        assert_eq!(failed.outcome, SimulationOutcome::Completed);
+        assert_eq!(failed.path.last().map(|v| v.state_id), Some(id(3)));
+        assert_eq!(failed.data["check"]["status"], json!(503));
+        assert_eq!(failed.actions[0].failure_state_id, Some(id(3)));
+    }
+
+    #[test]
+    fn leaves_sub_workflow_states_through_the_chosen_option() {
+        let definition = definition(vec![
+            state(
+                1,
+                "Delegate",
+                json!({ "transitions": [transition(10, json!({
+                    "type": "SubWorkflow",
+                    "workflow_id": 2,
+                    "options": [option(11, "Approved", 2)],
+                    "outcomes": [],
+                }))] }),
+            ),
+            end_state(2, "Done"),
+        ]);
+
+        let waiting = simulate(&definition, &[]);
+        assert_eq!(waiting.outcome, SimulationOutcome::Waiting);
+        assert_eq!(waiting.waiting[0].choices[0].option_id, Some(id(11)));
+
+        let simulation = simulate(&definition, &[step(10, Some(11))]);
+        assert_eq!(simulation.outcome, SimulationOutcome::Completed);
+    }
+
+    #[test]
+    fn merges_step_data() {
+        let submit = TransitionInstance {
+            data: Some(json!({ "reason": "ready" })),
+            ..step(10, Some(11))
+        };
+
+        let simulation =
+            review().simulate(Some(json!({ "amount": 5 })), &[submit], &HashMap::new());
+
+        assert_eq!(simulation.data, json!({ "amount": 5, "reason": "ready" }));
+    }
+}
@@ -0,0 +1,153 @@
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+
+use crate::database::DbConnection;
+use crate::result::{AppError, Result};
+use crate::workflows::{EscalationAction, StateSla};
+
+use super::{InstanceTask, InstanceToken, WorkflowInstance};
+
+impl StateSla {
+    /// Returns when the escalations of a task due at the given time fire.
+    pub fn escalation_times(
+        &self,
+        due_at: DateTime<Utc>,
+    ) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        self.escalations
+            .iter()
+            .map(move |e| due_at + Duration::try_seconds(e.after).unwrap_or_default())
+    }
+
+    /// Returns when a task of the state opened at the given time is due, and
+    /// when its first escalation fires.
+    pub fn deadlines(&self, opened_at: DateTime<Utc>) -> (DateTime<Utc>, Option<DateTime<Utc>>) {
+        let due_at = opened_at + Duration::try_seconds(self.due_in).unwrap_or_default();
+        (due_at, self.escalation_times(due_at).min())
+    }
+}
+
+impl WorkflowInstance {
+    /// Fires the escalations of every overdue task whose delay has elapsed.
+    /// Returns the number of escalations fired.
+    pub fn escalate_overdue(conn: &mut DbConnection) -> Result<usize> {
+        let now = Utc::now();
+        let mut fired = 0;
+
+        for task in InstanceTask::list_escalating(conn, now)? {
+            match Self::escalate_task(conn, &task, now) {
+                Ok(count) => fired += count,
+                Err(e) => tracing::error!(task_id = task.id, "Failed to escalate task: {e}"),
+            }
+        }
+
+        Ok(fired)
+    }
+
+    fn escalate_task(
+        conn: &mut DbConnection,
+        task: &InstanceTask,
+        now: DateTime<Utc>,
+    ) -> Result<usize> {
+        conn.transaction(|conn| {
+            // The instance is locked so that no transition moves the task's
+            // token meanwhile, and the task read again in case one just did
+            let Some(mut instance) = Self::lock(conn, task.instance_id)? else {
+                InstanceTask::schedule_escalation(conn, task.id, None)?;
+                return Ok(0);
+            };
+            let Some(task) =
+                InstanceTask::find(conn, task.id)?.filter(|t| t.completed_at.is_none())
+            else {
+                return Ok(0);
+            };
+            let workflow = instance.workflow(conn)?;
+            let (Some(sla), Some(due_at)) = (
+                workflow
+                    .definition
+                    .state(task.state_id)
+                    .and_then(|s| s.sla.as_ref()),
+                task.due_at,
+            ) else {
+                InstanceTask::schedule_escalation(conn, task.id, None)?;
+                return Ok(0);
+            };
+
+            // Escalations still to come are looked at again when the first
+            // of them is due
+            let next = sla.escalation_times(due_at).filter(|at| *at > now).min();
+            InstanceTask::schedule_escalation(conn, task.id, next)?;
+
+            let mut fired = 0;
+            for escalation in &sla.escalations {
+                if due_at + Duration::try_seconds(escalation.after).unwrap_or_default() > now {
+                    continue;
+                }
+
+                if !InstanceTask::record_escalation(conn, task.id, escalation.id)? {
+                    continue;
+                }
+
+                fired += 1;
+                tracing::info!(
+                    instance_id = instance.id,
+                    task_id = task.id,
+                    escalation_id = %escalation.id,
+                    "Firing escalation"
+                );
+
+                match &escalation.action {
+                    EscalationAction::Notify {
+                        template_id,
+                        target,
+                    } => Self::notify(&instance, *template_id, target),
+                    EscalationAction::Reassign { user_id } => {
+                        // The user may have left the tenant since the
+                        // definition was saved, which leaves the escalation
+                        // recorded and the ones after it free to fire
+                        let assigned = conn
+                            .transaction(|conn| Self::assign(conn, instance.id, Some(*user_id)));
+                        match assigned {
+                            Ok(assigned) => instance = assigned,
+                            Err(e) => tracing::warn!(
+                                task_id = task.id,
+                                escalation_id = %escalation.id,
+                                "Skipping reassign escalation: {e}"
+                            ),
+                        }
+                    }
+                    EscalationAction::Transition { target_state_id } => {
+                        let token = InstanceToken::find(conn, task.token_id)?.ok_or_else(|| {
+                            AppError::not_found("InstanceToken", &task.token_id.to_string())
+                        })?;
+
+                        // The escalation stays recorded when the token cannot
+                        // be moved, such as while it waits on an HTTP action,
+                        // so that it is not tried again on every tick
+                        let moved = conn.transaction(|conn| {
+                            Self::move_to(
+                                conn,
+                                instance,
+                                &workflow.definition,
+                                token,
+                                *target_state_id,
+                                None,
+                                None,
+                                None,
+                            )
+                        });
+                        if let Err(e) = moved {
+                            tracing::warn!(
+                                task_id = task.id,
+                                escalation_id = %escalation.id,
+                                "Skipping transition escalation: {e}"
+                            );
+                        }
+                        return Ok(fired);
+                    }
+                }
+            }
+
+            Ok(fired)
+        })
+    }
+}
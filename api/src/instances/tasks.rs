@@ -0,0 +1,199 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tsync::tsync;
+use uuid::Uuid;
+
+use crate::database::schema::{workflow_instance_escalations, workflow_instance_tasks};
+use crate::database::DbConnection;
+use crate::result::Result;
+use crate::workflows::WorkflowState;
+
+/// A record of an instance's stay in a single state. A task is opened when
+/// the instance enters a state and completed when it leaves.
+#[derive(Clone, Debug, Deserialize, Identifiable, JsonSchema, Queryable, Selectable, Serialize)]
+#[tsync]
+#[diesel(table_name = workflow_instance_tasks)]
+pub struct InstanceTask {
+    pub id: i64,
+    pub instance_id: i64,
+    pub state_id: Uuid,
+    pub assignee_id: Option<i64>,
+    pub completed_by: Option<i64>,
+    pub option_id: Option<Uuid>,
+    pub comment: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub token_id: i64,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = workflow_instance_tasks)]
+struct NewInstanceTask {
+    instance_id: i64,
+    token_id: i64,
+    state_id: Uuid,
+    assignee_id: Option<i64>,
+    due_at: Option<DateTime<Utc>>,
+    next_escalation_at: Option<DateTime<Utc>>,
+}
+
+impl InstanceTask {
+    pub fn open(
+        conn: &mut DbConnection,
+        instance_id: i64,
+        token_id: i64,
+        state_id: Uuid,
+        assignee_id: Option<i64>,
+        due_at: Option<DateTime<Utc>>,
+        next_escalation_at: Option<DateTime<Utc>>,
+    ) -> Result<InstanceTask> {
+        Ok(diesel::insert_into(workflow_instance_tasks::table)
+            .values(&NewInstanceTask {
+                instance_id,
+                token_id,
+                state_id,
+                assignee_id,
+                due_at,
+                next_escalation_at,
+            })
+            .returning(InstanceTask::as_returning())
+            .get_result(conn)?)
+    }
+
+    pub fn find(conn: &mut DbConnection, id: i64) -> Result<Option<InstanceTask>> {
+        Ok(workflow_instance_tasks::table
+            .select(InstanceTask::as_select())
+            .filter(workflow_instance_tasks::id.eq(id))
+            .get_result(conn)
+            .optional()?)
+    }
+
+    /// Completes the open task of the token.
+    pub fn complete_open(
+        conn: &mut DbConnection,
+        token_id: i64,
+        completed_by: Option<i64>,
+        option_id: Option<Uuid>,
+        comment: Option<String>,
+    ) -> Result<usize> {
+        Ok(diesel::update(workflow_instance_tasks::table)
+            .filter(workflow_instance_tasks::token_id.eq(token_id))
+            .filter(workflow_instance_tasks::completed_at.is_null())
+            .set((
+                workflow_instance_tasks::completed_by.eq(completed_by),
+                workflow_instance_tasks::option_id.eq(option_id),
+                workflow_instance_tasks::comment.eq(comment),
+                workflow_instance_tasks::completed_at.eq(Utc::now()),
+            ))
+            .execute(conn)?)
+    }
+
+    /// Moves the open task of the token to another state, keeping its
+    /// assignee. Its due date and escalations follow the SLA of the new
+    /// state, counted from when the task was opened.
+    pub fn move_open(
+        conn: &mut DbConnection,
+        token_id: i64,
+        state: &WorkflowState,
+    ) -> Result<usize> {
+        let tasks: Vec<(i64, DateTime<Utc>)> = workflow_instance_tasks::table
+            .select((
+                workflow_instance_tasks::id,
+                workflow_instance_tasks::created_at,
+            ))
+            .filter(workflow_instance_tasks::token_id.eq(token_id))
+            .filter(workflow_instance_tasks::completed_at.is_null())
+            .load(conn)?;
+
+        for (id, created_at) in &tasks {
+            let (due_at, next_escalation_at) = state
+                .sla
+                .as_ref()
+                .map(|sla| sla.deadlines(*created_at))
+                .map_or((None, None), |(due_at, next)| (Some(due_at), next));
+
+            diesel::update(workflow_instance_tasks::table)
+                .filter(workflow_instance_tasks::id.eq(id))
+                .set((
+                    workflow_instance_tasks::state_id.eq(state.id),
+                    workflow_instance_tasks::due_at.eq(due_at),
+                    workflow_instance_tasks::next_escalation_at.eq(next_escalation_at),
+                ))
+                .execute(conn)?;
+        }
+
+        Ok(tasks.len())
+    }
+
+    pub fn reassign_open(
+        conn: &mut DbConnection,
+        instance_id: i64,
+        assignee_id: Option<i64>,
+    ) -> Result<usize> {
+        Ok(diesel::update(workflow_instance_tasks::table)
+            .filter(workflow_instance_tasks::instance_id.eq(instance_id))
+            .filter(workflow_instance_tasks::completed_at.is_null())
+            .set(workflow_instance_tasks::assignee_id.eq(assignee_id))
+            .execute(conn)?)
+    }
+
+    pub fn list_for_instance(
+        conn: &mut DbConnection,
+        instance_id: i64,
+    ) -> Result<Vec<InstanceTask>> {
+        Ok(workflow_instance_tasks::table
+            .select(InstanceTask::as_select())
+            .filter(workflow_instance_tasks::instance_id.eq(instance_id))
+            .order(workflow_instance_tasks::id.asc())
+            .get_results(conn)?)
+    }
+
+    /// Lists the open tasks with an escalation due at or before the given
+    /// time.
+    pub fn list_escalating(
+        conn: &mut DbConnection,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<InstanceTask>> {
+        Ok(workflow_instance_tasks::table
+            .select(InstanceTask::as_select())
+            .filter(workflow_instance_tasks::completed_at.is_null())
+            .filter(workflow_instance_tasks::next_escalation_at.le(now))
+            .order(workflow_instance_tasks::next_escalation_at.asc())
+            .get_results(conn)?)
+    }
+
+    /// Sets when the next escalation of the task is due, `None` once every
+    /// escalation has fired.
+    pub fn schedule_escalation(
+        conn: &mut DbConnection,
+        task_id: i64,
+        next_escalation_at: Option<DateTime<Utc>>,
+    ) -> Result<usize> {
+        Ok(diesel::update(workflow_instance_tasks::table)
+            .filter(workflow_instance_tasks::id.eq(task_id))
+            .set(workflow_instance_tasks::next_escalation_at.eq(next_escalation_at))
+            .execute(conn)?)
+    }
+
+    /// Records that the escalation fired for the task. Returns `false` when it
+    /// had already been recorded.
+    pub fn record_escalation(
+        conn: &mut DbConnection,
+        task_id: i64,
+        escalation_id: Uuid,
+    ) -> Result<bool> {
+        let inserted = diesel::insert_into(workflow_instance_escalations::table)
+            .values((
+                workflow_instance_escalations::task_id.eq(task_id),
+                workflow_instance_escalations::escalation_id.eq(escalation_id),
+            ))
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+
+        Ok(inserted > 0)
+    }
+}
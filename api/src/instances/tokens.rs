@@ -0,0 +1,117 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tsync::tsync;
+use uuid::Uuid;
+
+use crate::database::schema::workflow_instance_tokens;
+use crate::database::DbConnection;
+use crate::result::Result;
+
+/// A position of an instance in its workflow. Every instance has a root token,
+/// and a fork parks its token at the fork state while one child token runs
+/// each branch. The parent resumes at the join once every child has arrived.
+#[derive(Clone, Debug, Deserialize, Identifiable, JsonSchema, Queryable, Selectable, Serialize)]
+#[tsync]
+#[diesel(table_name = workflow_instance_tokens)]
+pub struct InstanceToken {
+    pub id: i64,
+    pub instance_id: i64,
+    pub parent_id: Option<i64>,
+    pub state_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = workflow_instance_tokens)]
+struct NewInstanceToken {
+    instance_id: i64,
+    parent_id: Option<i64>,
+    state_id: Uuid,
+}
+
+impl InstanceToken {
+    pub fn open(
+        conn: &mut DbConnection,
+        instance_id: i64,
+        parent_id: Option<i64>,
+        state_id: Uuid,
+    ) -> Result<InstanceToken> {
+        Ok(diesel::insert_into(workflow_instance_tokens::table)
+            .values(&NewInstanceToken {
+                instance_id,
+                parent_id,
+                state_id,
+            })
+            .returning(InstanceToken::as_returning())
+            .get_result(conn)?)
+    }
+
+    pub fn find(conn: &mut DbConnection, id: i64) -> Result<Option<InstanceToken>> {
+        Ok(workflow_instance_tokens::table
+            .select(InstanceToken::as_select())
+            .find(id)
+            .first(conn)
+            .optional()?)
+    }
+
+    /// Finds the token and locks it until the end of the transaction.
+    pub fn lock(conn: &mut DbConnection, id: i64) -> Result<Option<InstanceToken>> {
+        Ok(workflow_instance_tokens::table
+            .select(InstanceToken::as_select())
+            .filter(workflow_instance_tokens::id.eq(id))
+            .for_update()
+            .first(conn)
+            .optional()?)
+    }
+
+    /// Moves the token to the state, completing it when `completed` is set.
+    pub fn move_to(
+        conn: &mut DbConnection,
+        id: i64,
+        state_id: Uuid,
+        completed: bool,
+    ) -> Result<InstanceToken> {
+        Ok(diesel::update(workflow_instance_tokens::table)
+            .filter(workflow_instance_tokens::id.eq(id))
+            .set((
+                workflow_instance_tokens::state_id.eq(state_id),
+                workflow_instance_tokens::completed_at.eq(completed.then(Utc::now)),
+            ))
+            .returning(InstanceToken::as_returning())
+            .get_result(conn)?)
+    }
+
+    pub fn list_for_instance(
+        conn: &mut DbConnection,
+        instance_id: i64,
+    ) -> Result<Vec<InstanceToken>> {
+        Ok(workflow_instance_tokens::table
+            .select(InstanceToken::as_select())
+            .filter(workflow_instance_tokens::instance_id.eq(instance_id))
+            .order(workflow_instance_tokens::id.asc())
+            .get_results(conn)?)
+    }
+
+    /// Lists the tokens of the instance that have not completed.
+    pub fn list_active(conn: &mut DbConnection, instance_id: i64) -> Result<Vec<InstanceToken>> {
+        Ok(workflow_instance_tokens::table
+            .select(InstanceToken::as_select())
+            .filter(workflow_instance_tokens::instance_id.eq(instance_id))
+            .filter(workflow_instance_tokens::completed_at.is_null())
+            .order(workflow_instance_tokens::id.asc())
+            .get_results(conn)?)
+    }
+
+    /// Counts the children of the token that have not reached the join yet.
+    pub fn count_active_children(conn: &mut DbConnection, parent_id: i64) -> Result<i64> {
+        Ok(workflow_instance_tokens::table
+            .filter(workflow_instance_tokens::parent_id.eq(parent_id))
+            .filter(workflow_instance_tokens::completed_at.is_null())
+            .count()
+            .get_result(conn)?)
+    }
+}
@@ -0,0 +1,457 @@
+//! The rules that move a token through a definition: the option a step
+//! chooses, the data it brings, entry and exit actions, end states, forks and
+//! joins, sub-workflow states and automatic transitions. The runtime and the
+//! simulation both walk with them, each through a [`Walker`] doing what
+//! moving a token means to it.
+
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::result::{AppError, Result};
+use crate::workflows::{
+    TransitionDefinition, WorkflowAction, WorkflowDefinition, WorkflowState, WorkflowTransition,
+};
+
+/// The number of states that may be entered in response to a single start or
+/// transition before the definition is assumed to loop.
+const MAX_AUTOMATIC_TRANSITIONS: usize = 64;
+
+/// Where a token leaving its state is going, and how its task is completed.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Departure {
+    /// The state the token is going to, `None` when it leaves a fork down
+    /// its branches.
+    pub target_state_id: Option<Uuid>,
+    /// The transition the token leaves through. Calls do not keep it, only
+    /// simulations report it and they make no calls.
+    pub transition_id: Option<Uuid>,
+    pub completed_by: Option<i64>,
+    pub option_id: Option<Uuid>,
+    pub comment: Option<String>,
+}
+
+impl Departure {
+    pub fn to(target_state_id: Uuid) -> Departure {
+        Departure {
+            target_state_id: Some(target_state_id),
+            ..Departure::default()
+        }
+    }
+}
+
+/// A state a token moves into, and the transition and option it moves
+/// through.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Move {
+    pub state_id: Uuid,
+    pub transition_id: Option<Uuid>,
+    pub option_id: Option<Uuid>,
+}
+
+impl Move {
+    pub fn to(state_id: Uuid) -> Move {
+        Move {
+            state_id,
+            transition_id: None,
+            option_id: None,
+        }
+    }
+}
+
+/// Where a walk starts.
+pub(super) enum Step<'a> {
+    /// The token enters the state.
+    Enter(Move),
+    /// The token runs the entry actions of its state from the given one on.
+    Proceed(&'a WorkflowState, usize),
+    /// The token leaves its state, running its exit actions from the given
+    /// one on.
+    Leave(&'a WorkflowState, Departure, usize),
+}
+
+pub(super) enum ActionOutcome {
+    Done,
+    /// The token waits in its state until the action has been carried out.
+    Waiting,
+    /// The action failed and sends the token to the given state.
+    Failed(Uuid),
+}
+
+/// What moving a token through a definition does.
+pub(super) trait Walker {
+    type Token;
+
+    fn state_id(&self, token: &Self::Token) -> Uuid;
+
+    /// The token the token was spawned by, if any.
+    fn parent(&mut self, token: &Self::Token) -> Result<Option<Self::Token>>;
+
+    /// Ends the branch token in the join state of its parent's fork. Returns
+    /// whether every other branch has joined as well.
+    fn join(
+        &mut self,
+        token: Self::Token,
+        parent: &Self::Token,
+        state: &WorkflowState,
+        via: &Move,
+    ) -> Result<bool>;
+
+    /// Moves the token into the state, ending it when the state is an end
+    /// state.
+    fn enter(
+        &mut self,
+        token: Self::Token,
+        state: &WorkflowState,
+        via: &Move,
+    ) -> Result<Self::Token>;
+
+    /// Runs one of the entry actions of the state, or one of its exit actions
+    /// when the token is departing.
+    fn run_action(
+        &mut self,
+        token: &Self::Token,
+        state: &WorkflowState,
+        action: &WorkflowAction,
+        departure: Option<&Departure>,
+    ) -> Result<ActionOutcome>;
+
+    /// Evaluates the guard of an automatic transition of the state.
+    fn guard(&mut self, state: &WorkflowState, transition_id: Uuid, guard: &str) -> Result<bool>;
+
+    /// Completes the token's task in the state it is leaving.
+    fn complete(&mut self, token: &Self::Token, departure: &Departure) -> Result<()>;
+
+    /// Starts a child token on each branch of the fork the token is leaving.
+    fn spawn(&mut self, token: &Self::Token, branches: &[Uuid]) -> Result<Vec<Self::Token>>;
+
+    /// Handles the token coming to rest in an end state.
+    fn end(&mut self, token: &Self::Token, state: &WorkflowState) -> Result<()>;
+
+    /// Starts the sub-workflow of the state the token has entered.
+    fn start_child(&mut self, token: &Self::Token, workflow_id: i64) -> Result<()>;
+}
+
+/// Moves the token from the step on, following automatic transitions and
+/// forks until every token it leads to comes to rest. Fork states park the
+/// token and run a child token down each branch; the last child to reach the
+/// join resumes the parent from there. A token whose action waits stays in
+/// its state.
+pub(super) fn walk<W: Walker>(
+    walker: &mut W,
+    definition: &WorkflowDefinition,
+    token: W::Token,
+    step: Step,
+) -> Result<()> {
+    let mut steps = MAX_AUTOMATIC_TRANSITIONS;
+
+    let next = match step {
+        Step::Enter(via) => Some(via),
+        Step::Proceed(state, from) => proceed(walker, definition, &token, state, from, &mut steps)?,
+        Step::Leave(state, departure, from) => leave(
+            walker, definition, &token, state, departure, from, &mut steps,
+        )?,
+    };
+
+    match next {
+        Some(via) => advance(walker, definition, token, via, &mut steps),
+        None => Ok(()),
+    }
+}
+
+fn advance<W: Walker>(
+    walker: &mut W,
+    definition: &WorkflowDefinition,
+    mut token: W::Token,
+    via: Move,
+    steps: &mut usize,
+) -> Result<()> {
+    let mut via = via;
+
+    loop {
+        if *steps == 0 {
+            return Err(AppError::validation_error(
+                "Too many automatic transitions, the workflow may contain a loop",
+            ));
+        }
+        *steps -= 1;
+
+        let state = definition.state(via.state_id).ok_or_else(|| {
+            AppError::validation_error(format!("State {} does not exist", via.state_id))
+        })?;
+
+        if let Some(parent) = joining_parent(walker, definition, &token, state.id)? {
+            if !walker.join(token, &parent, state, &via)? {
+                return Ok(());
+            }
+
+            token = parent;
+        }
+
+        token = walker.enter(token, state, &via)?;
+
+        match proceed(walker, definition, &token, state, 0, steps)? {
+            Some(next) => via = next,
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Runs the entry actions of the state the token has entered, from the given
+/// one on, and then does what the state does once entered. Returns where the
+/// token moves next, or `None` once it comes to rest or waits.
+fn proceed<W: Walker>(
+    walker: &mut W,
+    definition: &WorkflowDefinition,
+    token: &W::Token,
+    state: &WorkflowState,
+    from: usize,
+    steps: &mut usize,
+) -> Result<Option<Move>> {
+    let actions = state.entry_actions.get(from..).unwrap_or_default();
+    match run_actions(walker, token, state, actions, None)? {
+        ActionOutcome::Done => {}
+        ActionOutcome::Waiting => return Ok(None),
+        ActionOutcome::Failed(failure_state_id) => {
+            let departure = Departure::to(failure_state_id);
+            return leave(walker, definition, token, state, departure, 0, steps);
+        }
+    }
+
+    if state.is_end_state {
+        walker.end(token, state)?;
+        return Ok(None);
+    }
+
+    if state.fork().is_some() {
+        let departure = Departure {
+            transition_id: state.transitions.iter().find_map(|t| match t.definition {
+                TransitionDefinition::Parallel { .. } => Some(t.id),
+                _ => None,
+            }),
+            ..Departure::default()
+        };
+        return leave(walker, definition, token, state, departure, 0, steps);
+    }
+
+    if let Some((workflow_id, ..)) = state.sub_workflow() {
+        walker.start_child(token, workflow_id)?;
+        return Ok(None);
+    }
+
+    for transition in &state.transitions {
+        let TransitionDefinition::Automatic {
+            target_state_id,
+            guard,
+        } = &transition.definition
+        else {
+            continue;
+        };
+
+        let passes = match guard {
+            Some(guard) => walker.guard(state, transition.id, guard)?,
+            None => true,
+        };
+
+        if passes {
+            let departure = Departure {
+                transition_id: Some(transition.id),
+                ..Departure::to(*target_state_id)
+            };
+            return leave(walker, definition, token, state, departure, 0, steps);
+        }
+    }
+
+    Ok(None)
+}
+
+/// Runs the exit actions of the token's state, from the given one on, and
+/// completes the token's task. Returns where the token moves next. Leaving a
+/// fork runs a child token down each of its branches instead.
+fn leave<W: Walker>(
+    walker: &mut W,
+    definition: &WorkflowDefinition,
+    token: &W::Token,
+    state: &WorkflowState,
+    mut departure: Departure,
+    from: usize,
+    steps: &mut usize,
+) -> Result<Option<Move>> {
+    let actions = state.exit_actions.get(from..).unwrap_or_default();
+    match run_actions(walker, token, state, actions, Some(&departure))? {
+        ActionOutcome::Done => {}
+        ActionOutcome::Waiting => return Ok(None),
+        // The remaining exit actions are skipped
+        ActionOutcome::Failed(failure_state_id) => {
+            departure.target_state_id = Some(failure_state_id)
+        }
+    }
+
+    walker.complete(token, &departure)?;
+
+    if let Some(state_id) = departure.target_state_id {
+        return Ok(Some(Move {
+            state_id,
+            transition_id: departure.transition_id,
+            option_id: departure.option_id,
+        }));
+    }
+
+    let (branches, _) = state
+        .fork()
+        .ok_or_else(|| AppError::server_error(format!("State {} is not a fork", state.name)))?;
+
+    // Every child must exist before any of them runs, otherwise the first
+    // to reach the join would find no siblings
+    for child in walker.spawn(token, branches)? {
+        let via = Move {
+            transition_id: departure.transition_id,
+            ..Move::to(walker.state_id(&child))
+        };
+        advance(walker, definition, child, via, steps)?;
+    }
+
+    Ok(None)
+}
+
+/// Runs the actions in order until one of them does not complete.
+fn run_actions<W: Walker>(
+    walker: &mut W,
+    token: &W::Token,
+    state: &WorkflowState,
+    actions: &[WorkflowAction],
+    departure: Option<&Departure>,
+) -> Result<ActionOutcome> {
+    for action in actions {
+        match walker.run_action(token, state, action, departure)? {
+            ActionOutcome::Done => {}
+            outcome => return Ok(outcome),
+        }
+    }
+
+    Ok(ActionOutcome::Done)
+}
+
+/// Returns the parent of the token when the state is the join of the fork
+/// the token was spawned by.
+fn joining_parent<W: Walker>(
+    walker: &mut W,
+    definition: &WorkflowDefinition,
+    token: &W::Token,
+    state_id: Uuid,
+) -> Result<Option<W::Token>> {
+    let Some(parent) = walker.parent(token)? else {
+        return Ok(None);
+    };
+
+    let joins = definition
+        .state(walker.state_id(&parent))
+        .and_then(|s| s.fork())
+        .is_some_and(|(_, join_state_id)| join_state_id == state_id);
+
+    Ok(joins.then_some(parent))
+}
+
+/// Who chooses the option of a transition.
+#[derive(Clone, Copy, Debug)]
+pub(super) enum Chooser {
+    /// A user of the instance, or an inbound trigger when `None`. Only the
+    /// approver may decide an approval, and sub-workflow states are left when
+    /// the child instance completes.
+    User(Option<i64>),
+    /// A simulation, which decides approvals as anyone and leaves a
+    /// sub-workflow state through the option chosen as if the child had
+    /// completed.
+    Simulation,
+}
+
+/// Returns the state the chosen option of the transition leads to.
+pub(super) fn resolve_target(
+    transition: &WorkflowTransition,
+    option_id: Option<Uuid>,
+    comment: Option<&str>,
+    chooser: Chooser,
+) -> Result<Uuid> {
+    let option = match &transition.definition {
+        TransitionDefinition::Automatic { .. } => {
+            return Err(AppError::bad_request(
+                "Automatic transitions cannot be triggered manually",
+            ))
+        }
+        TransitionDefinition::Parallel { .. } => {
+            return Err(AppError::bad_request(
+                "Parallel transitions are taken when the fork state is entered",
+            ))
+        }
+        TransitionDefinition::SubWorkflow { options, .. } => match chooser {
+            Chooser::User(_) => {
+                return Err(AppError::bad_request(
+                    "Sub-workflow transitions are taken when the child instance completes",
+                ))
+            }
+            Chooser::Simulation => options.iter().find(|o| Some(o.id) == option_id),
+        },
+        TransitionDefinition::VendorConfirmation { target_state_id } => {
+            return Ok(*target_state_id)
+        }
+        TransitionDefinition::Manual { options } => {
+            options.iter().find(|o| Some(o.id) == option_id)
+        }
+        TransitionDefinition::Approval {
+            approver_id,
+            approval_option,
+            rejection_option,
+        } => {
+            if let Chooser::User(user_id) = chooser {
+                if Some(*approver_id) != user_id {
+                    return Err(AppError::forbidden(
+                        "Only the approver may complete this transition",
+                    ));
+                }
+            }
+
+            [approval_option, rejection_option]
+                .into_iter()
+                .find(|o| Some(o.id) == option_id)
+        }
+    }
+    .ok_or_else(|| AppError::validation_error("Unknown transition option"))?;
+
+    if option.comment_required && comment.is_none_or(|c| c.trim().is_empty()) {
+        return Err(AppError::validation_error(format!(
+            "A comment is required for option {}",
+            option.label
+        )));
+    }
+
+    Ok(option.target_state_id)
+}
+
+/// Shallow merges the object a step brings into the instance data, and
+/// returns the result checked against the definition's data schema.
+pub(super) fn merge_data(
+    current: &Value,
+    definition: &WorkflowDefinition,
+    data: Option<Value>,
+) -> Result<Value> {
+    let patch = match data {
+        None => Map::new(),
+        Some(Value::Object(patch)) => patch,
+        Some(_) => {
+            return Err(AppError::validation_error(
+                "Instance data must be a JSON object",
+            ))
+        }
+    };
+
+    let mut merged = current.clone();
+    match merged.as_object_mut() {
+        Some(current) => current.extend(patch),
+        None => merged = Value::Object(patch),
+    }
+
+    if let Some(data_schema) = &definition.data_schema {
+        data_schema.apply(&mut merged)?;
+    }
+
+    Ok(merged)
+}
@@ -0,0 +1,237 @@
+//! JSON Schemas of the bodies the API reads and writes, generated from the
+//! same types as the TypeScript declarations. They are published at
+//! `/api/schemas` for clients that don't use TypeScript, and request bodies
+//! holding workflow definitions are checked against them before they are
+//! read, so that errors point at the value that is wrong.
+
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use jsonschema::JSONSchema;
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+
+use crate::instances::{
+    AssignInstance, BulkAssign, BulkTransition, BulkTransitionResult, InstanceCall,
+    InstanceMigration, InstanceTask, InstanceToken, MigrateInstances, MigratedInstance,
+    SimulateWorkflow, Simulation, StartInstance, TransitionInstance, WorkflowInstance,
+};
+use crate::result::{AppError, Result};
+use crate::search::SearchHit;
+use crate::tenants::{CreateTenant, Tenant, UpdateTenant};
+use crate::triggers::{CreateWorkflowTrigger, ResumeInstance, TriggerCredentials, WorkflowTrigger};
+use crate::users::{CreateUser, UpdateUser, User, UserCredentials, UserOperation};
+use crate::webhooks::{
+    CreateWebhookSubscription, UpdateWebhookSubscription, WebhookDelivery, WebhookSubscription,
+};
+use crate::workflows::{
+    CloneWorkflow, DefinitionDiff, InstantiateTemplate, LintWarning, NewWorkflow,
+    NewWorkflowTemplate, UpdateWorkflow, UpdateWorkflowTemplate, Workflow, WorkflowDefinition,
+    WorkflowExport, WorkflowImport, WorkflowTemplate, WorkflowVersion,
+};
+
+/// A published schema and its compiled form, see [`conditional`].
+struct Published {
+    schema: Value,
+    validator: JSONSchema,
+}
+
+/// The schemas, by the name of their type.
+static CATALOGUE: OnceLock<BTreeMap<String, Published>> = OnceLock::new();
+
+/// Generates the schema of each type, named after it.
+macro_rules! catalogue {
+    ($($ty:ty),* $(,)?) => {{
+        let mut catalogue = BTreeMap::new();
+        $(catalogue.insert(<$ty as JsonSchema>::schema_name(), publish::<$ty>());)*
+        catalogue
+    }};
+}
+
+fn catalogue() -> &'static BTreeMap<String, Published> {
+    CATALOGUE.get_or_init(|| {
+        catalogue![
+            AssignInstance,
+            BulkAssign,
+            BulkTransition,
+            BulkTransitionResult,
+            CloneWorkflow,
+            CreateTenant,
+            CreateUser,
+            CreateWebhookSubscription,
+            CreateWorkflowTrigger,
+            DefinitionDiff,
+            InstanceCall,
+            InstanceMigration,
+            InstanceTask,
+            InstanceToken,
+            InstantiateTemplate,
+            LintWarning,
+            MigrateInstances,
+            MigratedInstance,
+            NewWorkflow,
+            NewWorkflowTemplate,
+            ResumeInstance,
+            SearchHit,
+            SimulateWorkflow,
+            Simulation,
+            StartInstance,
+            Tenant,
+            TransitionInstance,
+            TriggerCredentials,
+            UpdateTenant,
+            UpdateUser,
+            UpdateWebhookSubscription,
+            UpdateWorkflow,
+            UpdateWorkflowTemplate,
+            User,
+            UserCredentials,
+            UserOperation,
+            WebhookDelivery,
+            WebhookSubscription,
+            Workflow,
+            WorkflowDefinition,
+            WorkflowExport,
+            WorkflowImport,
+            WorkflowInstance,
+            WorkflowTemplate,
+            WorkflowTrigger,
+            WorkflowVersion,
+        ]
+    })
+}
+
+/// Generates the schema of the type in draft 2019-09, the first draft with a
+/// format for UUIDs. Formats are only annotations in that draft, so checking
+/// them is turned on.
+fn publish<T: JsonSchema>() -> Published {
+    let schema = SchemaSettings::draft2019_09()
+        .into_generator()
+        .into_root_schema_for::<T>();
+    let schema = serde_json::to_value(schema).expect("schemas serialize to JSON");
+    let mut checked = schema.clone();
+    conditional(&mut checked);
+    let validator = JSONSchema::options()
+        .should_validate_formats(true)
+        .compile(&checked)
+        .expect("generated schemas compile");
+
+    Published { schema, validator }
+}
+
+/// Rewrites the unions of the schema as conditions. Validators can only
+/// report that a value matches none of the branches of a union, while the
+/// branch of a nullable value or of a variant of a tagged enum is known from
+/// the value itself, so errors can be reported from within it.
+fn conditional(schema: &mut Value) {
+    match schema {
+        Value::Object(map) => {
+            map.values_mut().for_each(conditional);
+
+            if let Some(Value::Array(branches)) = map.get("anyOf") {
+                if let [a, b] = branches.as_slice() {
+                    let null = json!({ "type": "null" });
+                    let other = match (a == &null, b == &null) {
+                        (true, false) => Some(b.clone()),
+                        (false, true) => Some(a.clone()),
+                        _ => None,
+                    };
+                    if let Some(other) = other {
+                        map.remove("anyOf");
+                        map.insert("if".to_string(), null);
+                        map.insert("else".to_string(), other);
+                    }
+                }
+            }
+
+            if let Some(Value::Array(branches)) = map.get("oneOf") {
+                if let Some(conditions) = tagged(branches) {
+                    map.remove("oneOf");
+                    match map.get_mut("allOf") {
+                        Some(Value::Array(all)) => all.extend(conditions),
+                        _ => {
+                            map.insert("allOf".to_string(), Value::Array(conditions));
+                        }
+                    }
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(conditional),
+        _ => {}
+    }
+}
+
+/// Returns the conditions choosing the branch of a tagged enum by its tag:
+/// one requiring a known tag, then one per variant. Returns `None` when the
+/// branches are not objects with a common property naming their variant.
+fn tagged(branches: &[Value]) -> Option<Vec<Value>> {
+    let variant = |branch: &Value, tag: &str| -> Option<Value> {
+        match branch
+            .pointer(&format!("/properties/{}/enum", tag))?
+            .as_array()?
+            .as_slice()
+        {
+            [variant] => Some(variant.clone()),
+            _ => None,
+        }
+    };
+
+    let properties: &Map<String, Value> = branches.first()?.get("properties")?.as_object()?;
+    let tag = properties
+        .keys()
+        .find(|tag| branches.iter().all(|b| variant(b, tag).is_some()))?;
+    let variants = branches
+        .iter()
+        .map(|b| variant(b, tag))
+        .collect::<Option<Vec<_>>>()?;
+
+    let mut conditions = vec![json!({
+        "properties": { tag: { "enum": variants } },
+        "required": [tag],
+    })];
+    for (branch, variant) in branches.iter().zip(variants) {
+        conditions.push(json!({
+            "if": { "properties": { tag: { "const": variant } }, "required": [tag] },
+            "then": branch,
+        }));
+    }
+
+    Some(conditions)
+}
+
+/// Lists the names of the published schemas.
+pub fn names() -> Vec<&'static str> {
+    catalogue().keys().map(String::as_str).collect()
+}
+
+/// Finds the schema of the type with the name.
+pub fn find(name: &str) -> Option<&'static Value> {
+    catalogue().get(name).map(|published| &published.schema)
+}
+
+/// Checks the JSON against the schema of the type, with each error prefixed
+/// by the JSON pointer of the value it is about.
+pub fn check<T: JsonSchema>(value: &Value) -> Result<()> {
+    let name = T::schema_name();
+    let published = catalogue()
+        .get(&name)
+        .ok_or_else(|| AppError::server_error(format!("No schema is published for {}", name)))?;
+
+    if let Err(errors) = published.validator.validate(value) {
+        let errors = errors
+            .map(|e| match e.instance_path.to_string() {
+                path if path.is_empty() => e.to_string(),
+                path => format!("{}: {}", path, e),
+            })
+            .collect::<Vec<_>>();
+
+        return Err(AppError::validation_error(format!(
+            "Invalid {}: {}",
+            name,
+            errors.join("; ")
+        )));
+    }
+
+    Ok(())
+}
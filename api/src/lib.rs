@@ -1,12 +1,25 @@
 #![recursion_limit = "256"]
 
 pub mod api;
+pub mod bulk;
 pub mod config;
+pub mod data_schema;
 pub mod database;
 pub mod defaults;
+pub mod expressions;
+pub mod filters;
+pub mod idempotency;
+pub mod instances;
+pub mod json_schema;
 pub mod middleware;
+pub mod pagination;
 pub mod result;
+pub mod search;
 pub mod server;
+pub mod templating;
 pub mod tenants;
+pub mod timers;
+pub mod triggers;
 pub mod users;
+pub mod webhooks;
 pub mod workflows;
@@ -1,23 +1,51 @@
+use std::path::{Path, PathBuf};
+
 use clap::Parser;
 use console::style;
 use tracing_subscriber::{self, filter::Targets, layer::SubscriberExt, util::SubscriberInitExt};
 
 use daedalus::config::{AppSettings, ConfigBuilder, LogFormat, LogLevel};
-use daedalus::result::Result;
+use daedalus::database::{PoolManager, PooledConnection};
+use daedalus::result::{AppError, Result};
 use daedalus::server;
+use daedalus::workflows::{
+    upgrade_stored, ExportFormat, GraphFormat, LintWarning, Workflow, WorkflowDefinition,
+    WorkflowExport, WorkflowVersion, SCHEMA_VERSION,
+};
 
 #[actix_web::main]
 async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let app_settings = match parse_args() {
-        Ok(settings) => settings,
-        Err(e) => {
-            eprintln!(
-                "{}\n\n\t{}\n",
-                style("Failed to parse configuration:").bold().red(),
-                e
-            );
-            std::process::exit(1);
+    let cli = Cli::parse();
+    let serve_cmd = match cli.command {
+        None => cli.serve,
+        Some(Command::Serve(serve_cmd)) => serve_cmd,
+        Some(Command::Render(render_cmd)) => {
+            if let Err(e) = render(render_cmd) {
+                exit_with("Failed to render the workflow:", e);
+            }
+            return Ok(());
+        }
+        Some(Command::Lint(lint_cmd)) => match lint(lint_cmd) {
+            Ok(warnings) if warnings.is_empty() => return Ok(()),
+            Ok(_) => std::process::exit(1),
+            Err(e) => exit_with("Failed to lint the workflow:", e),
+        },
+        Some(Command::Diff(diff_cmd)) => {
+            if let Err(e) = diff(diff_cmd) {
+                exit_with("Failed to compare the workflows:", e);
+            }
+            return Ok(());
         }
+        Some(Command::UpgradeDefinitions(upgrade_cmd)) => match upgrade_definitions(upgrade_cmd) {
+            Ok(0) => return Ok(()),
+            Ok(_) => std::process::exit(1),
+            Err(e) => exit_with("Failed to upgrade the workflow definitions:", e),
+        },
+    };
+
+    let app_settings = match parse_args(serve_cmd) {
+        Ok(settings) => settings,
+        Err(e) => exit_with("Failed to parse configuration:", e),
     };
 
     tracing_init(&app_settings);
@@ -25,6 +53,11 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     server::start(app_settings).await
 }
 
+fn exit_with(message: &str, e: AppError) -> ! {
+    eprintln!("{}\n\n\t{}\n", style(message).bold().red(), e);
+    std::process::exit(1);
+}
+
 fn tracing_init(settings: &AppSettings) {
     let log_level: tracing::Level = settings.log.level.clone().into();
     let base_level = match settings.debug {
@@ -51,10 +84,9 @@ fn tracing_init(settings: &AppSettings) {
     }
 }
 
-fn parse_args() -> Result<AppSettings> {
+fn parse_args(serve_cmd: Serve) -> Result<AppSettings> {
     let version = env!("BUILD_ID");
 
-    let serve_cmd = Serve::parse();
     ConfigBuilder::new(version.into(), serve_cmd.config)
         .set_debug(serve_cmd.debug)
         .set_db_url(serve_cmd.database_url)
@@ -69,9 +101,139 @@ fn parse_args() -> Result<AppSettings> {
         .set_jwt_lifetime(serve_cmd.jwt_lifetime)
         .set_server_port(serve_cmd.server_port)
         .set_server_workers(serve_cmd.server_workers)
+        .set_timers_interval(serve_cmd.timers_interval)
+        .set_webhooks_interval(serve_cmd.webhooks_interval)
+        .set_webhooks_timeout(serve_cmd.webhooks_timeout)
+        .set_webhooks_max_attempts(serve_cmd.webhooks_max_attempts)
+        .set_webhooks_retry_delay(serve_cmd.webhooks_retry_delay)
+        .set_idempotency_window(serve_cmd.idempotency_window)
         .parse()
 }
 
+fn render(render_cmd: Render) -> Result<()> {
+    let (name, definition) = render_cmd.source.load()?;
+    let graph = definition.render(&name, render_cmd.format);
+
+    match render_cmd.output {
+        Some(path) => std::fs::write(path, graph).map_err(AppError::server_error),
+        None => {
+            print!("{}", graph);
+            Ok(())
+        }
+    }
+}
+
+fn find_workflow(conn: &mut PooledConnection, id: i64) -> Result<Workflow> {
+    Workflow::find(conn, id)?.ok_or_else(|| AppError::not_found("Workflow", &id.to_string()))
+}
+
+/// Prints the warnings about the workflow and returns them.
+fn lint(lint_cmd: Lint) -> Result<Vec<LintWarning>> {
+    let source = lint_cmd.source;
+    let warnings = match source.workflow_id {
+        Some(id) => {
+            let mut conn = source.database.connect()?;
+            find_workflow(&mut conn, id)?.lint(&mut conn)?
+        }
+        None => {
+            eprintln!("Users are only checked for stored workflows");
+            source.load()?.1.lint()
+        }
+    };
+
+    if lint_cmd.json {
+        let json = serde_json::to_string_pretty(&warnings).map_err(AppError::server_error)?;
+        println!("{}", json);
+        return Ok(warnings);
+    }
+
+    for warning in &warnings {
+        let rule = serde_json::to_value(warning.rule).map_err(AppError::server_error)?;
+        println!(
+            "{}[{}]: {}",
+            style("warning").bold().yellow(),
+            rule.as_str().unwrap_or_default(),
+            warning.message
+        );
+    }
+    println!("{} warning(s)", warnings.len());
+
+    Ok(warnings)
+}
+
+fn diff(diff_cmd: Diff) -> Result<()> {
+    let (old, new) = match (diff_cmd.old, diff_cmd.new, diff_cmd.workflow_id) {
+        (Some(old), Some(new), _) => (
+            read_document(&old)?.definition,
+            read_document(&new)?.definition,
+        ),
+        (_, _, Some(id)) => {
+            let mut conn = diff_cmd.database.connect()?;
+            let to = match diff_cmd.to {
+                Some(version) => WorkflowVersion::get(&mut conn, id, version)?,
+                None => WorkflowVersion::latest(&mut conn, id)?
+                    .ok_or_else(|| AppError::not_found("Workflow", &id.to_string()))?,
+            };
+            let from = match diff_cmd.from {
+                Some(from) if from < 1 => {
+                    return Err(AppError::bad_request("Versions are numbered from 1"))
+                }
+                Some(from) => from,
+                None if to.version <= 1 => {
+                    return Err(AppError::bad_request(format!(
+                        "Version {} has no version before it to compare with",
+                        to.version
+                    )))
+                }
+                None => to.version - 1,
+            };
+            (
+                WorkflowVersion::get(&mut conn, id, from)?.definition,
+                to.definition,
+            )
+        }
+        _ => {
+            return Err(AppError::bad_request(
+                "Two documents or a workflow id are required",
+            ))
+        }
+    };
+
+    let json = serde_json::to_string_pretty(&old.diff(&new)).map_err(AppError::server_error)?;
+    println!("{}", json);
+
+    Ok(())
+}
+
+/// Rewrites the stored definitions in the current schema version and returns
+/// the number that could not be upgraded.
+fn upgrade_definitions(upgrade_cmd: UpgradeDefinitions) -> Result<usize> {
+    let mut conn = upgrade_cmd.database.connect()?;
+    let tables = upgrade_stored(&mut conn, upgrade_cmd.dry_run)?;
+
+    let mut failures = 0;
+    for table in &tables {
+        println!(
+            "{}: {} {}, {} current, {} failed",
+            style(table.table).bold(),
+            table.upgraded,
+            match upgrade_cmd.dry_run {
+                true => "to upgrade",
+                false => "upgraded",
+            },
+            table.current,
+            table.failed.len()
+        );
+        for (id, error) in &table.failed {
+            println!("\t{} {}: {}", style("error").bold().red(), id, error);
+        }
+        failures += table.failed.len();
+    }
+    println!("Schema version {}", SCHEMA_VERSION);
+
+    Ok(failures)
+}
+
 const ABOUT: &str = r#"
 ______               _       _
 |  _  \             | |     | |
@@ -84,7 +246,32 @@ Daedalus application server
 "#;
 
 #[derive(clap::Parser)]
-#[clap(name = "daedalus", about = ABOUT)]
+#[clap(name = "daedalus", about = ABOUT, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+    #[clap(flatten)]
+    pub serve: Serve,
+}
+
+#[derive(clap::Subcommand)]
+pub enum Command {
+    /// Start the application server. This is the default command.
+    Serve(Serve),
+    /// Render a workflow as a Graphviz DOT, Mermaid or SVG graph.
+    Render(Render),
+    /// Check a workflow for states that can't be left, invalid approvers and
+    /// other likely mistakes. Exits with status 1 when there are warnings.
+    Lint(Lint),
+    /// Compare two versions of a stored workflow, or two exported workflow
+    /// documents, and print the changes as JSON.
+    Diff(Diff),
+    /// Rewrite the stored workflow definitions in the current schema version.
+    /// Exits with status 1 when a definition can't be upgraded.
+    UpgradeDefinitions(UpgradeDefinitions),
+}
+
+#[derive(clap::Args)]
 pub struct Serve {
     #[clap(short, long)]
     pub config: Option<String>,
@@ -114,4 +301,130 @@ pub struct Serve {
     pub server_port: Option<u16>,
     #[clap(short = 'w', long)]
     pub server_workers: Option<usize>,
+    #[clap(long)]
+    pub timers_interval: Option<u64>,
+    #[clap(long)]
+    pub webhooks_interval: Option<u64>,
+    #[clap(long)]
+    pub webhooks_timeout: Option<u64>,
+    #[clap(long)]
+    pub webhooks_max_attempts: Option<i32>,
+    #[clap(long)]
+    pub webhooks_retry_delay: Option<u64>,
+    #[clap(long)]
+    pub idempotency_window: Option<u64>,
+}
+
+#[derive(clap::Args)]
+pub struct Render {
+    #[clap(flatten)]
+    pub source: WorkflowSource,
+    #[clap(long, value_enum, default_value = "svg")]
+    pub format: GraphFormat,
+    /// The file to write the graph to, instead of standard output.
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+pub struct Lint {
+    #[clap(flatten)]
+    pub source: WorkflowSource,
+    /// Print the warnings as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(clap::Args)]
+pub struct Diff {
+    /// The older exported workflow document.
+    #[clap(requires = "new", conflicts_with = "workflow_id")]
+    pub old: Option<PathBuf>,
+    /// The newer exported workflow document.
+    pub new: Option<PathBuf>,
+    /// The stored workflow to compare versions of.
+    #[clap(short, long, required_unless_present = "old")]
+    pub workflow_id: Option<i64>,
+    /// The older version, by default the one before the newer version.
+    #[clap(long)]
+    pub from: Option<i32>,
+    /// The newer version, by default the latest.
+    #[clap(long)]
+    pub to: Option<i32>,
+    #[clap(flatten)]
+    pub database: Database,
+}
+
+#[derive(clap::Args)]
+pub struct UpgradeDefinitions {
+    /// Report the definitions that would be rewritten without writing them.
+    #[clap(long)]
+    pub dry_run: bool,
+    #[clap(flatten)]
+    pub database: Database,
+}
+
+/// Where a command reads a workflow from: a stored workflow, or a file with
+/// an exported workflow document.
+#[derive(clap::Args)]
+pub struct WorkflowSource {
+    /// The id of a stored workflow.
+    #[clap(required_unless_present = "file", conflicts_with = "file")]
+    pub workflow_id: Option<i64>,
+    /// An exported workflow document, as JSON or as YAML with a .yaml or .yml
+    /// extension.
+    #[clap(short, long)]
+    pub file: Option<PathBuf>,
+    #[clap(flatten)]
+    pub database: Database,
+}
+
+impl WorkflowSource {
+    /// Returns the name and definition of the workflow.
+    pub fn load(self) -> Result<(String, WorkflowDefinition)> {
+        if let Some(path) = self.file {
+            let document = read_document(&path)?;
+            return Ok((document.name, document.definition));
+        }
+
+        let id = self
+            .workflow_id
+            .ok_or_else(|| AppError::bad_request("A workflow id or file is required"))?;
+        let workflow = find_workflow(&mut self.database.connect()?, id)?;
+
+        Ok((workflow.name, workflow.definition))
+    }
+}
+
+/// The database of a command that reads stored workflows.
+#[derive(clap::Args)]
+pub struct Database {
+    #[clap(short, long)]
+    pub config: Option<String>,
+    #[clap(short = 'D', long)]
+    pub database_url: Option<String>,
+}
+
+impl Database {
+    /// Connects to the database of the configuration.
+    pub fn connect(&self) -> Result<PooledConnection> {
+        let settings = ConfigBuilder::new(env!("BUILD_ID").into(), self.config.clone())
+            .set_db_url(self.database_url.clone())
+            .parse()?;
+
+        PoolManager::new(&settings.database).get()
+    }
+}
+
+/// Reads an exported workflow document, as YAML when the file has a .yaml or
+/// .yml extension and as JSON otherwise.
+fn read_document(path: &Path) -> Result<WorkflowExport> {
+    let format = match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml" | "yml") => ExportFormat::Yaml,
+        _ => ExportFormat::Json,
+    };
+    let body = std::fs::read(path)
+        .map_err(|e| AppError::bad_request(format!("Can't read {}: {}", path.display(), e)))?;
+
+    WorkflowExport::from_bytes(&body, format)
 }
@@ -0,0 +1,204 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::Duration;
+
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, CONTENT_TYPE, ETAG, LAST_MODIFIED, LOCATION};
+use actix_web::http::{Method, StatusCode};
+use actix_web::web::{block, Bytes, Data};
+use actix_web::{Error, HttpMessage, HttpResponse, ResponseError};
+use serde_json::Value;
+
+use crate::database::PoolManager;
+use crate::idempotency::{self, Reservation};
+use crate::middleware::bearer::UserClaims;
+use crate::result::AppError;
+
+/// The headers of a stored response that are sent again when it is replayed.
+const REPLAYED_HEADERS: [HeaderName; 4] = [CONTENT_TYPE, ETAG, LAST_MODIFIED, LOCATION];
+
+/// Makes mutating requests from signed in users idempotent. When a request
+/// has an `Idempotency-Key` header, its successful response is stored and
+/// replayed, along with headers such as its ETag, for retries of the same
+/// request within the window, while reusing the key for a different request is
+/// rejected with 409 Conflict.
+///
+/// Keys are scoped to the user, so it must run after [`super::bearer::JwtAuth`].
+pub struct Idempotency {
+    window: Duration,
+}
+
+impl Idempotency {
+    pub fn new(window: Duration) -> Self {
+        Idempotency { window }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Idempotency
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = IdempotencyMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IdempotencyMiddleware {
+            service: Rc::new(service),
+            window: chrono::Duration::from_std(self.window).unwrap_or_default(),
+        }))
+    }
+}
+
+pub struct IdempotencyMiddleware<S> {
+    service: Rc<S>,
+    window: chrono::Duration,
+}
+
+impl<S, B> Service<ServiceRequest> for IdempotencyMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let window = self.window;
+
+        Box::pin(async move {
+            let mutating = matches!(
+                *req.method(),
+                Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+            );
+            let key = req
+                .headers()
+                .get(idempotency::HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            let user_id = req.extensions().get::<UserClaims>().map(|c| c.sub);
+            let pool = req.app_data::<Data<PoolManager>>().cloned();
+
+            let (true, Some(key), Some(user_id), Some(pool)) = (mutating, key, user_id, pool)
+            else {
+                return Ok(service.call(req).await?.map_into_boxed_body());
+            };
+
+            // Read with the app's payload limit, the one the handlers use,
+            // rather than the smaller default
+            let body = req.extract::<Bytes>().await?;
+            let fingerprint = idempotency::fingerprint(&[
+                req.method().as_str().as_bytes(),
+                req.uri().to_string().as_bytes(),
+                &body,
+            ]);
+
+            // Put the body back for the handler
+            let (_, mut payload) = actix_http::h1::Payload::create(true);
+            payload.unread_data(body);
+            req.set_payload(payload.into());
+
+            let scope = format!("user:{}", user_id);
+            let reservation = {
+                let pool = pool.clone();
+                let key = key.clone();
+                block(move || -> Result<_, AppError> {
+                    let mut conn = pool.get()?;
+                    idempotency::reserve(&mut conn, &scope, &key, &fingerprint, window)
+                })
+                .await
+                .map_err(AppError::from)
+                .and_then(|reservation| reservation)
+            };
+
+            let id = match reservation {
+                Ok(Reservation::Reserved(id)) => id,
+                Ok(Reservation::Replay {
+                    status,
+                    headers,
+                    body,
+                }) => {
+                    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::OK);
+                    let mut builder = HttpResponse::build(status);
+                    for header in headers {
+                        builder.insert_header(header);
+                    }
+                    let response = builder
+                        .insert_header(("Idempotent-Replayed", "true"))
+                        .json(body);
+                    return Ok(req.into_response(response));
+                }
+                Err(e) => return Ok(req.into_response(e.error_response())),
+            };
+
+            let res = match service.call(req).await {
+                Ok(res) if res.status().is_success() => res,
+                result => {
+                    // Only successful responses are kept, so the request can
+                    // be retried with the same key after an error
+                    release(pool, id).await;
+                    return result.map(ServiceResponse::map_into_boxed_body);
+                }
+            };
+
+            let (req, res) = res.into_parts();
+            let (res, body) = res.into_parts();
+            let body = match to_bytes(body).await {
+                Ok(body) => body,
+                Err(e) => {
+                    release(pool, id).await;
+                    let e: Box<dyn std::error::Error> = e.into();
+                    return Err(AppError::server_error(e).into());
+                }
+            };
+
+            let status = res.status().as_u16();
+            let headers: Vec<_> = REPLAYED_HEADERS
+                .iter()
+                .filter_map(|name| {
+                    let value = res.headers().get(name)?.to_str().ok()?;
+                    Some((name.to_string(), value.to_string()))
+                })
+                .collect();
+            let stored = serde_json::from_slice(&body)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()));
+            let result = block(move || -> Result<_, AppError> {
+                let mut conn = pool.get()?;
+                idempotency::complete(&mut conn, id, status, &headers, &stored)
+            })
+            .await;
+
+            if let Err(e) | Ok(Err(e)) = result.map_err(AppError::from) {
+                tracing::error!(
+                    "Failed to store response for {} {key}: {e}",
+                    idempotency::HEADER
+                );
+            }
+
+            Ok(ServiceResponse::new(req, res.set_body(BoxBody::new(body))))
+        })
+    }
+}
+
+async fn release(pool: Data<PoolManager>, id: i64) {
+    let result = block(move || -> Result<_, AppError> {
+        let mut conn = pool.get()?;
+        idempotency::release(&mut conn, id)
+    })
+    .await;
+
+    if let Err(e) | Ok(Err(e)) = result.map_err(AppError::from) {
+        tracing::error!("Failed to release idempotency key {id}: {e}");
+    }
+}
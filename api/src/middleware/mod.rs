@@ -1 +1,2 @@
 pub mod bearer;
+pub mod idempotency;
@@ -0,0 +1,306 @@
+//! Sorting and keyset pagination for list queries.
+//!
+//! Lists are always ordered by the selected sort field and then by id, so
+//! every row has a stable position. A page can be requested by number, or by
+//! the opaque cursor returned with the previous page, which resumes right
+//! after its last row without scanning the rows before it.
+
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tsync::tsync;
+
+use crate::result::{AppError, Result};
+
+/// The largest page that may be requested.
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[tsync]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// A page of rows, along with the cursor of the page after it when there is
+/// one.
+#[derive(Clone, Debug)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+/// Where a page starts, decoded from a cursor or from the page number.
+#[derive(Clone, Debug)]
+pub enum Start<K> {
+    /// Skip this many rows.
+    Offset(i64),
+    /// Start after the row with this sort key and id.
+    After(K, i64),
+}
+
+#[derive(Deserialize, Serialize)]
+struct CursorData {
+    sort: Value,
+    direction: SortDirection,
+    key: Value,
+    id: i64,
+}
+
+/// Checks the page number and size.
+pub fn check(page: i64, page_size: i64) -> Result<()> {
+    if page < 1 {
+        return Err(AppError::validation_error("page must be at least 1"));
+    }
+
+    match (1..=MAX_PAGE_SIZE).contains(&page_size) {
+        true => Ok(()),
+        false => Err(AppError::validation_error(format!(
+            "page_size must be between 1 and {}",
+            MAX_PAGE_SIZE
+        ))),
+    }
+}
+
+/// Decodes where the page starts. A cursor must have been issued for the
+/// same sort field and direction.
+pub fn start<K: DeserializeOwned>(
+    cursor: Option<&str>,
+    sort: impl Serialize,
+    direction: SortDirection,
+    page: i64,
+    page_size: i64,
+) -> Result<Start<K>> {
+    check(page, page_size)?;
+
+    let Some(cursor) = cursor else {
+        return Ok(Start::Offset(page_size * (page - 1)));
+    };
+
+    let invalid = || AppError::validation_error("Invalid cursor");
+    let data: CursorData = hex::decode(cursor)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or_else(invalid)?;
+
+    if data.sort != json!(sort) || data.direction != direction {
+        return Err(AppError::validation_error(
+            "Cursor was issued for a different sort order",
+        ));
+    }
+
+    let key = serde_json::from_value(data.key).map_err(|_| invalid())?;
+    Ok(Start::After(key, data.id))
+}
+
+/// Builds the page from rows fetched with a limit of one more than the page
+/// size, so that the extra row tells whether another page follows.
+pub fn page<T>(
+    mut rows: Vec<T>,
+    page_size: i64,
+    sort: impl Serialize,
+    direction: SortDirection,
+    key: impl Fn(&T) -> (Value, i64),
+) -> Page<T> {
+    if rows.len() as i64 <= page_size {
+        return Page {
+            data: rows,
+            next_cursor: None,
+        };
+    }
+
+    rows.truncate(page_size as usize);
+    let next_cursor = rows.last().map(|last| {
+        let (key, id) = key(last);
+        let data = CursorData {
+            sort: json!(sort),
+            direction,
+            key,
+            id,
+        };
+        hex::encode(json!(data).to_string())
+    });
+
+    Page {
+        data: rows,
+        next_cursor,
+    }
+}
+
+/// Orders the boxed query by the column and then by the id column, whose Rust
+/// type is given after `as`. When the page starts after a row, only the rows
+/// that follow it in that order are kept.
+///
+/// ```ignore
+/// sort_by!(query, tenants::name, tenants::id as i32, direction, start)
+/// ```
+#[macro_export]
+macro_rules! sort_by {
+    ($query:expr, $column:path, $id:path as $id_ty:ty, $direction:expr, $start:expr) => {{
+        use $crate::pagination::{SortDirection, Start};
+
+        let query = $query;
+        let (query, offset) = match $start {
+            Start::Offset(offset) => (query, offset),
+            Start::After(key, id) => {
+                let query = match $direction {
+                    SortDirection::Asc => query.filter(
+                        $column
+                            .gt(key.clone())
+                            .or($column.eq(key).and($id.gt(id as $id_ty))),
+                    ),
+                    SortDirection::Desc => query.filter(
+                        $column
+                            .lt(key.clone())
+                            .or($column.eq(key).and($id.lt(id as $id_ty))),
+                    ),
+                };
+                (query, 0)
+            }
+        };
+
+        let query = match $direction {
+            SortDirection::Asc => query.order(($column.asc(), $id.asc())),
+            SortDirection::Desc => query.order(($column.desc(), $id.desc())),
+        };
+
+        query.offset(offset)
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    use super::*;
+
+    #[derive(Clone, Copy, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Sort {
+        Id,
+        Name,
+        CreatedAt,
+    }
+
+    fn error<K: DeserializeOwned>(result: Result<Start<K>>) -> String {
+        match result {
+            Ok(_) => panic!("start was decoded"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    /// Pages the rows, each a key and an id, and decodes the cursor of the
+    /// page after.
+    fn round_trip<K>(rows: Vec<(K, i64)>, sort: Sort, direction: SortDirection) -> (K, i64)
+    where
+        K: Clone + DeserializeOwned + Serialize,
+    {
+        let page = page(rows, 2, sort, direction, |(key, id)| (json!(key), *id));
+        let cursor = page.next_cursor.expect("another page follows");
+
+        match start(Some(&cursor), sort, direction, 1, 2).unwrap() {
+            Start::After(key, id) => (key, id),
+            Start::Offset(_) => panic!("cursor decoded to an offset"),
+        }
+    }
+
+    #[test]
+    fn cursors_resume_after_the_last_row_for_every_key_type() {
+        let ids = vec![(1_i64, 1), (2, 2), (3, 3)];
+        assert_eq!(round_trip(ids, Sort::Id, SortDirection::Asc), (2, 2));
+
+        let names = vec![
+            ("b".to_string(), 7),
+            ("a".to_string(), 3),
+            ("a".to_string(), 1),
+        ];
+        assert_eq!(
+            round_trip(names, Sort::Name, SortDirection::Desc),
+            ("a".to_string(), 3)
+        );
+
+        let at = |s| Utc.timestamp_opt(s, 123_456_000).unwrap();
+        let times: Vec<(DateTime<Utc>, i64)> = vec![(at(10), 4), (at(20), 5), (at(30), 6)];
+        assert_eq!(
+            round_trip(times, Sort::CreatedAt, SortDirection::Asc),
+            (at(20), 5)
+        );
+    }
+
+    #[test]
+    fn pages_without_a_cursor_start_at_an_offset() {
+        assert!(matches!(
+            start::<i64>(None, Sort::Id, SortDirection::Asc, 3, 20),
+            Ok(Start::Offset(40))
+        ));
+    }
+
+    #[test]
+    fn rejects_cursors_of_another_sort_order() {
+        let rows = vec![(1_i64, 1), (2, 2), (3, 3)];
+        let cursor = page(rows, 2, Sort::Id, SortDirection::Asc, |(key, id)| {
+            (json!(key), *id)
+        })
+        .next_cursor
+        .unwrap();
+
+        for (sort, direction) in [
+            (Sort::Name, SortDirection::Asc),
+            (Sort::Id, SortDirection::Desc),
+        ] {
+            let message = error(start::<i64>(Some(&cursor), sort, direction, 1, 2));
+            assert!(message.contains("different sort order"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn rejects_garbage_cursors() {
+        let not_json = hex::encode("not json");
+        let wrong_key = hex::encode(
+            json!({ "sort": "id", "direction": "asc", "key": "one", "id": 1 }).to_string(),
+        );
+
+        for cursor in ["zz", not_json.as_str(), wrong_key.as_str()] {
+            let message = error(start::<i64>(
+                Some(cursor),
+                Sort::Id,
+                SortDirection::Asc,
+                1,
+                2,
+            ));
+            assert!(message.contains("Invalid cursor"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn checks_page_numbers_and_sizes() {
+        assert!(check(1, 1).is_ok());
+        assert!(check(1, MAX_PAGE_SIZE).is_ok());
+        assert!(check(0, 10).is_err());
+        assert!(check(1, 0).is_err());
+        assert!(check(1, MAX_PAGE_SIZE + 1).is_err());
+        assert!(
+            error(start::<i64>(None, Sort::Id, SortDirection::Asc, 1, 0)).contains("page_size")
+        );
+    }
+
+    #[test]
+    fn only_an_extra_row_means_another_page() {
+        let key = |row: &i64| (json!(row), *row);
+
+        let full = page(vec![1, 2], 2, Sort::Id, SortDirection::Asc, key);
+        assert_eq!(full.data, vec![1, 2]);
+        assert!(full.next_cursor.is_none());
+
+        let more = page(vec![1, 2, 3], 2, Sort::Id, SortDirection::Asc, key);
+        assert_eq!(more.data, vec![1, 2]);
+        assert!(more.next_cursor.is_some());
+
+        let empty = page(Vec::<i64>::new(), 2, Sort::Id, SortDirection::Asc, key);
+        assert!(empty.data.is_empty());
+        assert!(empty.next_cursor.is_none());
+    }
+}
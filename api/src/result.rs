@@ -4,6 +4,8 @@ use actix_web::error::{self, BlockingError};
 use actix_web::http::StatusCode;
 use actix_web::HttpResponse;
 use derive_more::{Display, Error};
+use schemars::JsonSchema;
+use serde::Serialize;
 use serde_json::json;
 
 pub type Result<T> = std::result::Result<T, AppError>;
@@ -22,6 +24,12 @@ pub enum AppError {
     #[display(fmt = "{} not found with id: {}", entity, id)]
     NotFound { entity: String, id: String },
 
+    #[display(fmt = "{}", cause)]
+    Conflict { cause: String },
+
+    #[display(fmt = "{}", cause)]
+    PreconditionFailed { cause: String },
+
     #[display(fmt = "{}", cause)]
     ValidationError { cause: String },
 
@@ -61,6 +69,18 @@ impl AppError {
         }
     }
 
+    pub fn conflict<E: ToString>(cause: E) -> AppError {
+        AppError::Conflict {
+            cause: cause.to_string(),
+        }
+    }
+
+    pub fn precondition_failed<E: ToString>(cause: E) -> AppError {
+        AppError::PreconditionFailed {
+            cause: cause.to_string(),
+        }
+    }
+
     pub fn unauthorized() -> AppError {
         AppError::Unauthorized
     }
@@ -71,26 +91,57 @@ impl AppError {
             AppError::ServerError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::NotFound { .. } => StatusCode::NOT_FOUND,
             AppError::Forbidden { .. } => StatusCode::FORBIDDEN,
+            AppError::Conflict { .. } => StatusCode::CONFLICT,
+            AppError::PreconditionFailed { .. } => StatusCode::PRECONDITION_FAILED,
             AppError::BadRequest { .. } => StatusCode::BAD_REQUEST,
             AppError::Unauthorized => StatusCode::UNAUTHORIZED,
         }
     }
 
     pub fn to_json(&self) -> serde_json::Value {
-        json!({
-            "type": match *self {
-                AppError::ValidationError { .. } => "ValidationError",
-                AppError::ServerError { .. } => "ServerError",
-                AppError::NotFound { .. } => "NotFound",
-                AppError::Forbidden { .. } => "Forbidden",
-                AppError::BadRequest { .. } => "BadRequest",
-                AppError::Unauthorized => "Unauthorized",
+        json!(ErrorBody {
+            kind: match *self {
+                AppError::ValidationError { .. } => ErrorKind::ValidationError,
+                AppError::ServerError { .. } => ErrorKind::ServerError,
+                AppError::NotFound { .. } => ErrorKind::NotFound,
+                AppError::Forbidden { .. } => ErrorKind::Forbidden,
+                AppError::Conflict { .. } => ErrorKind::Conflict,
+                AppError::PreconditionFailed { .. } => ErrorKind::PreconditionFailed,
+                AppError::BadRequest { .. } => ErrorKind::BadRequest,
+                AppError::Unauthorized => ErrorKind::Unauthorized,
             },
-            "error": self.to_string()
+            error: self.to_string(),
         })
     }
 }
 
+/// The body of error responses.
+#[derive(JsonSchema, Serialize)]
+pub struct ErrorBody {
+    #[serde(rename = "type")]
+    pub kind: ErrorKind,
+    pub error: String,
+}
+
+#[derive(JsonSchema, Serialize)]
+pub enum ErrorKind {
+    ValidationError,
+    ServerError,
+    NotFound,
+    Forbidden,
+    Conflict,
+    PreconditionFailed,
+    BadRequest,
+    Unauthorized,
+}
+
+/// The body of responses to requests whose JSON body could not be parsed.
+#[derive(JsonSchema, Serialize)]
+pub struct ParseErrorBody {
+    pub error: String,
+    pub detail: String,
+}
+
 impl error::ResponseError for AppError {
     fn error_response(&self) -> HttpResponse {
         HttpResponse::build(self.status_code()).json(self.to_json())
@@ -0,0 +1,266 @@
+//! Full-text search over workflows, instances and users.
+//!
+//! Each of those tables has a generated `search_vector` column with a GIN
+//! index. Search text is parsed like a web search engine query, so it may use
+//! quoted phrases, `or` and `-` to exclude words. The text search
+//! configuration used for a table must match the one its column is generated
+//! with, or the index cannot be used.
+
+use diesel::dsl::{sql, SqlTypeOf};
+use diesel::expression::{AsExpression, SqlLiteral};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_builder::QueryId;
+use diesel::sql_types::{SqlType, Text};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tsync::tsync;
+
+use crate::database::schema::sql_types::Tsvector;
+use crate::database::schema::{users, workflow_instances, workflows};
+use crate::database::DbConnection;
+use crate::defaults::default_i64;
+use crate::instances::WorkflowInstance;
+use crate::result::{AppError, Result};
+use crate::users::User;
+use crate::workflows::Workflow;
+
+/// The most hits returned by one search.
+pub const MAX_LIMIT: i64 = 100;
+
+/// The `pg_catalog.tsquery` SQL type.
+#[derive(QueryId, SqlType)]
+#[diesel(postgres_type(name = "tsquery", schema = "pg_catalog"))]
+pub struct Tsquery;
+
+/// The `pg_catalog.regconfig` SQL type.
+#[derive(QueryId, SqlType)]
+#[diesel(postgres_type(name = "regconfig", schema = "pg_catalog"))]
+pub struct Regconfig;
+
+diesel::infix_operator!(Matches, " @@ ", backend: Pg);
+
+diesel::sql_function! {
+    fn websearch_to_tsquery(config: Regconfig, query: Text) -> Tsquery;
+}
+
+diesel::sql_function! {
+    fn ts_rank(vector: Tsvector, query: Tsquery) -> Float4;
+}
+
+/// A text search configuration.
+#[derive(Clone, Copy, Debug)]
+pub enum Language {
+    /// Stems English words and drops stop words. Used for workflows.
+    English,
+    /// Matches words as written. Used for instance data and users, which are
+    /// mostly names, numbers and addresses.
+    Simple,
+}
+
+impl Language {
+    fn config(self) -> SqlLiteral<Regconfig> {
+        match self {
+            Language::English => sql("'english'"),
+            Language::Simple => sql("'simple'"),
+        }
+    }
+}
+
+pub type Query = websearch_to_tsquery::HelperType<SqlLiteral<Regconfig>, String>;
+
+/// Parses the search text.
+pub fn query(language: Language, text: &str) -> Query {
+    websearch_to_tsquery(language.config(), text.to_string())
+}
+
+/// Whether the search vector matches the search text.
+pub fn matches<V>(vector: V, language: Language, text: &str) -> Matches<V, Query>
+where
+    V: Expression<SqlType = Tsvector>,
+{
+    Matches::new(vector, query(language, text))
+}
+
+/// How well the search vector matches the search text.
+pub fn rank<V>(vector: V, language: Language, text: &str) -> ts_rank::HelperType<V, Query>
+where
+    V: AsExpression<Tsvector>,
+    SqlTypeOf<V::Expression>: SqlType,
+{
+    ts_rank(vector, query(language, text))
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[tsync]
+#[serde(rename_all = "snake_case")]
+pub enum SearchType {
+    Workflow,
+    Instance,
+    User,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct SearchQuery {
+    /// The search text.
+    pub q: String,
+    /// A comma separated list of the types to search, all of them when not
+    /// set.
+    pub types: Option<String>,
+    pub tenant_id: Option<i32>,
+    #[serde(default = "default_i64::<20>")]
+    pub limit: i64,
+}
+
+impl SearchQuery {
+    fn types(&self) -> Result<Vec<SearchType>> {
+        let Some(types) = &self.types else {
+            return Ok(vec![
+                SearchType::Workflow,
+                SearchType::Instance,
+                SearchType::User,
+            ]);
+        };
+
+        types
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                serde_json::from_value(serde_json::Value::String(name.to_string())).map_err(|_| {
+                    AppError::validation_error(format!("Unknown search type {}", name))
+                })
+            })
+            .collect()
+    }
+}
+
+/// A search result, along with its rank. Higher ranks are better matches.
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SearchHit {
+    Workflow {
+        rank: f32,
+        workflow: Workflow,
+    },
+    Instance {
+        rank: f32,
+        instance: WorkflowInstance,
+    },
+    User {
+        rank: f32,
+        user: User,
+    },
+}
+
+impl SearchHit {
+    fn rank(&self) -> f32 {
+        match self {
+            SearchHit::Workflow { rank, .. }
+            | SearchHit::Instance { rank, .. }
+            | SearchHit::User { rank, .. } => *rank,
+        }
+    }
+}
+
+/// Searches the requested types and merges the hits, best first. Deleted
+/// rows are never returned.
+pub fn search(conn: &mut DbConnection, query: SearchQuery) -> Result<Vec<SearchHit>> {
+    let text = query.q.trim();
+    if text.is_empty() {
+        return Err(AppError::validation_error("q must not be empty"));
+    }
+
+    if !(1..=MAX_LIMIT).contains(&query.limit) {
+        return Err(AppError::validation_error(format!(
+            "limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+
+    let mut hits = Vec::new();
+    for search_type in query.types()? {
+        match search_type {
+            SearchType::Workflow => {
+                let mut q = workflows::table
+                    .select((
+                        Workflow::as_select(),
+                        rank(workflows::search_vector, Language::English, text),
+                    ))
+                    .filter(matches(workflows::search_vector, Language::English, text))
+                    .filter(workflows::deleted_at.is_null())
+                    .into_boxed();
+
+                if let Some(tenant_id) = query.tenant_id {
+                    q = q.filter(workflows::tenant_id.eq(tenant_id));
+                }
+
+                let rows: Vec<(Workflow, f32)> = q
+                    .order(rank(workflows::search_vector, Language::English, text).desc())
+                    .limit(query.limit)
+                    .get_results(conn)?;
+                hits.extend(
+                    rows.into_iter()
+                        .map(|(workflow, rank)| SearchHit::Workflow { rank, workflow }),
+                );
+            }
+            SearchType::Instance => {
+                let mut q = workflow_instances::table
+                    .select((
+                        WorkflowInstance::as_select(),
+                        rank(workflow_instances::search_vector, Language::Simple, text),
+                    ))
+                    .filter(matches(
+                        workflow_instances::search_vector,
+                        Language::Simple,
+                        text,
+                    ))
+                    .filter(workflow_instances::deleted_at.is_null())
+                    .into_boxed();
+
+                if let Some(tenant_id) = query.tenant_id {
+                    q = q.filter(workflow_instances::tenant_id.eq(tenant_id));
+                }
+
+                let rows: Vec<(WorkflowInstance, f32)> = q
+                    .order(rank(workflow_instances::search_vector, Language::Simple, text).desc())
+                    .limit(query.limit)
+                    .get_results(conn)?;
+                hits.extend(
+                    rows.into_iter()
+                        .map(|(instance, rank)| SearchHit::Instance { rank, instance }),
+                );
+            }
+            SearchType::User => {
+                let mut q = users::table
+                    .select((
+                        User::as_select(),
+                        rank(users::search_vector, Language::Simple, text),
+                    ))
+                    .filter(matches(users::search_vector, Language::Simple, text))
+                    .filter(users::deleted_at.is_null())
+                    .into_boxed();
+
+                if let Some(tenant_id) = query.tenant_id {
+                    q = q.filter(users::tenant_id.eq(tenant_id));
+                }
+
+                let rows: Vec<(User, f32)> = q
+                    .order(rank(users::search_vector, Language::Simple, text).desc())
+                    .limit(query.limit)
+                    .get_results(conn)?;
+                hits.extend(
+                    rows.into_iter()
+                        .map(|(user, rank)| SearchHit::User { rank, user }),
+                );
+            }
+        }
+    }
+
+    hits.sort_by(|a, b| b.rank().total_cmp(&a.rank()));
+    hits.truncate(query.limit as usize);
+
+    Ok(hits)
+}
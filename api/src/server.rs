@@ -1,11 +1,18 @@
 use actix_web::middleware::{Compress, NormalizePath};
-use actix_web::web::{Data, JsonConfig};
+use actix_web::web::{Data, JsonConfig, PayloadConfig};
 use actix_web::{App, HttpServer};
 use tracing_actix_web::TracingLogger;
 
 use crate::api::api_routes;
 use crate::config::{AppSettings, ServerSettings};
 use crate::database::PoolManager;
+use crate::result::ParseErrorBody;
+use crate::timers;
+use crate::webhooks::dispatcher;
+
+/// The largest request body the API reads, large enough for workflow imports
+/// and CSV uploads. Handlers and middleware reading the body share the limit.
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
 
 pub async fn start(settings: AppSettings) -> Result<(), Box<dyn std::error::Error>> {
     let mut pool_manager = PoolManager::new(&settings.database);
@@ -13,21 +20,29 @@ pub async fn start(settings: AppSettings) -> Result<(), Box<dyn std::error::Erro
 
     pool_manager.migrate()?;
 
+    timers::start(settings.timers.clone(), pool_manager.clone());
+    dispatcher::start(settings.webhooks.clone(), pool_manager.clone());
+
     tracing::info!("Starting server at: http://localhost:{}", port);
 
     HttpServer::new(move || {
         App::new()
-            .app_data(JsonConfig::default().error_handler(|err, _req| {
-                let json = serde_json::json!({
-                    "error": "Failed to parse JSON",
-                    "detail": err.to_string()
-                });
-                actix_web::error::InternalError::from_response(
-                    err,
-                    actix_web::HttpResponse::UnprocessableEntity().json(json),
-                )
-                .into()
-            }))
+            .app_data(PayloadConfig::new(MAX_BODY_SIZE))
+            .app_data(
+                JsonConfig::default()
+                    .limit(MAX_BODY_SIZE)
+                    .error_handler(|err, _req| {
+                        let body = ParseErrorBody {
+                            error: "Failed to parse JSON".to_string(),
+                            detail: err.to_string(),
+                        };
+                        actix_web::error::InternalError::from_response(
+                            err,
+                            actix_web::HttpResponse::UnprocessableEntity().json(body),
+                        )
+                        .into()
+                    }),
+            )
             .app_data(Data::new(settings.clone()))
             .app_data(Data::new(pool_manager.clone()))
             .wrap(NormalizePath::trim())
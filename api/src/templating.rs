@@ -0,0 +1,181 @@
+//! Renders `{{ expression }}` placeholders in strings and JSON documents.
+//!
+//! Placeholders hold an [`Expression`] evaluated against the data of an
+//! instance. Inside a longer string the value is interpolated as text, while a
+//! string made of a single placeholder is replaced by the value itself, so
+//! numbers, booleans and objects keep their JSON type:
+//!
+//! ```text
+//! { "claim": "{{ claim }}", "summary": "Claim {{ claim.id }} for {{ claim.amount }}" }
+//! ```
+
+use serde_json::Value;
+
+use crate::expressions::Expression;
+use crate::result::{AppError, Result};
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+
+/// Checks that every placeholder in the template parses.
+pub fn check(template: &str) -> Result<()> {
+    for part in split(template)? {
+        if let Part::Placeholder(source) = part {
+            Expression::parse(source)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks every string in the JSON template.
+pub fn check_value(template: &Value) -> Result<()> {
+    match template {
+        Value::String(s) => check(s),
+        Value::Array(items) => items.iter().try_for_each(check_value),
+        Value::Object(fields) => fields.values().try_for_each(check_value),
+        _ => Ok(()),
+    }
+}
+
+/// Renders the template to a string.
+pub fn render(template: &str, data: &Value) -> Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+
+    for part in split(template)? {
+        match part {
+            Part::Text(text) => rendered.push_str(text),
+            Part::Placeholder(source) => match Expression::cached(source)?.evaluate_value(data)? {
+                Value::Null => {}
+                Value::String(s) => rendered.push_str(&s),
+                value => rendered.push_str(&value.to_string()),
+            },
+        }
+    }
+
+    Ok(rendered)
+}
+
+/// Renders every string in the JSON template.
+pub fn render_value(template: &Value, data: &Value) -> Result<Value> {
+    match template {
+        Value::String(s) => match split(s)?.as_slice() {
+            [Part::Placeholder(source)] => Expression::cached(source)?.evaluate_value(data),
+            _ => render(s, data).map(Value::String),
+        },
+        Value::Array(items) => items
+            .iter()
+            .map(|item| render_value(item, data))
+            .collect::<Result<_>>()
+            .map(Value::Array),
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), render_value(value, data)?)))
+            .collect::<Result<_>>()
+            .map(Value::Object),
+        value => Ok(value.clone()),
+    }
+}
+
+enum Part<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+fn split(template: &str) -> Result<Vec<Part<'_>>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find(OPEN) {
+        if start > 0 {
+            parts.push(Part::Text(&rest[..start]));
+        }
+
+        let after = &rest[start + OPEN.len()..];
+        let end = after.find(CLOSE).ok_or_else(|| {
+            AppError::validation_error(format!("Unclosed placeholder in template `{}`", template))
+        })?;
+
+        parts.push(Part::Placeholder(after[..end].trim()));
+        rest = &after[end + CLOSE.len()..];
+    }
+
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn data() -> Value {
+        json!({
+            "claim": { "id": 42, "amount": 12.5, "approved": true, "note": null },
+            "name": "Ada",
+        })
+    }
+
+    #[test]
+    fn interpolates_values_as_text() {
+        assert_eq!(
+            render(
+                "Claim {{ claim.id }} by {{name}}: {{ claim.approved }}",
+                &data()
+            )
+            .unwrap(),
+            "Claim 42 by Ada: true"
+        );
+        assert_eq!(
+            render("no placeholders", &data()).unwrap(),
+            "no placeholders"
+        );
+    }
+
+    #[test]
+    fn interpolates_null_as_nothing() {
+        assert_eq!(render("[{{ claim.note }}]", &data()).unwrap(), "[]");
+    }
+
+    #[test]
+    fn whole_value_placeholders_keep_their_type() {
+        let template = json!({
+            "claim": "{{ claim }}",
+            "amount": "{{ claim.amount }}",
+            "approved": [" {{ claim.approved }} "],
+            "summary": "Claim {{ claim.id }}",
+            "count": 3,
+        });
+
+        assert_eq!(
+            render_value(&template, &data()).unwrap(),
+            json!({
+                "claim": data()["claim"],
+                "amount": 12.5,
+                "approved": [" true "],
+                "summary": "Claim 42",
+                "count": 3,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_unclosed_placeholders() {
+        let error = render("Claim {{ claim.id", &data())
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("Unclosed placeholder"), "{}", error);
+        assert!(check("{{ claim.id }} and {{").is_err());
+    }
+
+    #[test]
+    fn checks_every_placeholder_parses() {
+        assert!(check_value(&json!({ "a": ["{{ claim.id }}", 1], "b": "{{ name }}" })).is_ok());
+        assert!(check_value(&json!({ "a": ["{{ claim.id + }}"] })).is_err());
+    }
+}
@@ -1,13 +1,19 @@
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use tsync::tsync;
+use url::Url;
 
 use crate::database::{schema::tenants, DbConnection, DB};
 use crate::defaults::{default_bool, default_i64};
+use crate::filters::{self, BoxedCondition, Condition, Filterable};
+use crate::pagination::{self, Page, SortDirection};
 use crate::result::Result;
+use crate::{filter_condition, sort_by};
 
-#[derive(Clone, Debug, Deserialize, Queryable, Selectable, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Queryable, Selectable, Serialize)]
 #[tsync]
 #[diesel(table_name = tenants)]
 pub struct Tenant {
@@ -16,32 +22,62 @@ pub struct Tenant {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub deleted_at: Option<DateTime<Utc>>,
+    /// The hosts the HTTP actions and webhooks of the tenant may call. A
+    /// leading `*.` allows every subdomain of the host, and a trailing
+    /// `:port` that port rather than the default one of the URL's scheme.
+    pub allowed_hosts: Vec<String>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+#[serde(rename_all = "snake_case")]
+pub enum TenantSort {
+    #[default]
+    Id,
+    Name,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 #[tsync]
 pub struct TenantQuery {
     pub name: Option<String>,
+    /// Conditions on the fields, see [`crate::filters`].
+    pub filter: Option<String>,
     #[serde(default = "default_bool::<true>")]
     pub active: bool,
+    #[serde(default)]
+    pub sort: TenantSort,
+    #[serde(default)]
+    pub direction: SortDirection,
+    /// The cursor of the page to fetch, returned with the page before it.
+    /// When set, `page` is ignored.
+    pub cursor: Option<String>,
+    /// Whether to count the matching tenants.
+    #[serde(default = "default_bool::<true>")]
+    pub count: bool,
     #[serde(default = "default_i64::<1>")]
     pub page: i64,
     #[serde(default = "default_i64::<10>")]
     pub page_size: i64,
 }
 
-#[derive(Clone, Debug, Deserialize, Insertable, Serialize)]
+#[derive(Clone, Debug, Deserialize, Insertable, JsonSchema, Serialize)]
 #[tsync]
 #[diesel(table_name = tenants)]
 pub struct CreateTenant {
     pub name: String,
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
 }
 
-#[derive(AsChangeset, Clone, Debug, Deserialize, Serialize)]
+#[derive(AsChangeset, Clone, Debug, Deserialize, JsonSchema, Serialize)]
 #[tsync]
 #[diesel(table_name = tenants)]
 pub struct UpdateTenant {
     pub name: Option<String>,
+    pub allowed_hosts: Option<Vec<String>>,
 }
 
 impl Tenant {
@@ -53,6 +89,16 @@ impl Tenant {
             .optional()?)
     }
 
+    /// Finds the tenant and locks it until the end of the transaction.
+    pub fn lock(conn: &mut DbConnection, id: i32) -> Result<Option<Tenant>> {
+        Ok(tenants::table
+            .select(Tenant::as_select())
+            .filter(tenants::id.eq(id))
+            .for_update()
+            .get_result(conn)
+            .optional()?)
+    }
+
     pub fn find_by_name(conn: &mut DbConnection, name: String) -> Result<Option<Tenant>> {
         Ok(tenants::table
             .select(Tenant::as_select())
@@ -76,6 +122,40 @@ impl Tenant {
             .get_result(conn)?)
     }
 
+    /// Checks whether the tenant allows HTTP actions and webhooks to call the
+    /// URL. An allowed host without a port only allows the default port of the
+    /// URL's scheme.
+    pub fn allows(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let host = host.to_ascii_lowercase();
+
+        self.allowed_hosts.iter().any(|allowed| {
+            let allowed = allowed.to_ascii_lowercase();
+            let (pattern, port) = match allowed
+                .rsplit_once(':')
+                .filter(|(host, _)| !host.starts_with('[') || host.ends_with(']'))
+            {
+                Some((pattern, port)) => match port.parse::<u16>() {
+                    Ok(port) => (pattern, Some(port)),
+                    Err(_) => return false,
+                },
+                None => (allowed.as_str(), None),
+            };
+
+            let host_matches = match pattern.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|sub| sub.ends_with('.')),
+                None => host == pattern,
+            };
+
+            // The URL has no port when it uses the default one
+            host_matches && url.port() == port
+        })
+    }
+
     pub fn delete(conn: &mut DbConnection, id: i32) -> Result<Tenant> {
         Ok(diesel::update(tenants::table)
             .filter(tenants::id.eq(id))
@@ -84,49 +164,182 @@ impl Tenant {
             .get_result(conn)?)
     }
 
-    pub fn list(
-        conn: &mut DbConnection,
-        TenantQuery {
-            name,
-            active,
+    pub fn list(conn: &mut DbConnection, params: TenantQuery) -> Result<Page<Tenant>> {
+        let mut query = Self::filtered(&params)?;
+        let TenantQuery {
+            sort,
+            direction,
+            cursor,
             page,
             page_size,
-        }: TenantQuery,
-    ) -> Result<Vec<Tenant>> {
-        let mut query = tenants::table.into_boxed::<DB>();
+            ..
+        } = params;
 
-        if let Some(name) = name {
-            query = query.filter(tenants::name.eq(name));
-        }
+        let cursor = cursor.as_deref();
+        query = match sort {
+            TenantSort::Id => sort_by!(
+                query,
+                tenants::id,
+                tenants::id as i32,
+                direction,
+                pagination::start::<i32>(cursor, sort, direction, page, page_size)?
+            ),
+            TenantSort::Name => sort_by!(
+                query,
+                tenants::name,
+                tenants::id as i32,
+                direction,
+                pagination::start::<String>(cursor, sort, direction, page, page_size)?
+            ),
+            TenantSort::CreatedAt => sort_by!(
+                query,
+                tenants::created_at,
+                tenants::id as i32,
+                direction,
+                pagination::start::<DateTime<Utc>>(cursor, sort, direction, page, page_size)?
+            ),
+            TenantSort::UpdatedAt => sort_by!(
+                query,
+                tenants::updated_at,
+                tenants::id as i32,
+                direction,
+                pagination::start::<DateTime<Utc>>(cursor, sort, direction, page, page_size)?
+            ),
+        };
+
+        let rows = query
+            .select(Tenant::as_select())
+            .limit(page_size + 1)
+            .get_results(conn)?;
 
-        if active {
-            query = query.filter(tenants::deleted_at.is_null());
-        } else {
-            query = query.filter(tenants::deleted_at.is_not_null());
+        Ok(pagination::page(rows, page_size, sort, direction, |t| {
+            (t.sort_key(sort), t.id.into())
+        }))
+    }
+
+    /// The value of the sort field, stored in cursors.
+    fn sort_key(&self, sort: TenantSort) -> Value {
+        match sort {
+            TenantSort::Id => json!(self.id),
+            TenantSort::Name => json!(self.name),
+            TenantSort::CreatedAt => json!(self.created_at),
+            TenantSort::UpdatedAt => json!(self.updated_at),
         }
+    }
 
-        Ok(query
-            .limit(page_size)
-            .offset(page_size * (page - 1))
-            .load::<Tenant>(conn)?)
+    pub fn count(conn: &mut DbConnection, params: TenantQuery) -> Result<i64> {
+        let query = Self::filtered(&params)?;
+
+        Ok(query.count().get_result(conn)?)
     }
 
-    pub fn count(
-        conn: &mut DbConnection,
-        TenantQuery { name, active, .. }: TenantQuery,
-    ) -> Result<i64> {
+    /// The tenants matching the query, which both listing and counting them
+    /// start from.
+    fn filtered(params: &TenantQuery) -> Result<tenants::BoxedQuery<'static, DB>> {
         let mut query = tenants::table.into_boxed::<DB>();
 
-        if let Some(name) = name {
-            query = query.filter(tenants::name.eq(name));
+        if let Some(name) = &params.name {
+            query = query.filter(tenants::name.eq(name.clone()));
         }
 
-        if active {
-            query = query.filter(tenants::deleted_at.is_null());
-        } else {
-            query = query.filter(tenants::deleted_at.is_not_null());
+        if let Some(condition) = filters::condition(params.filter.as_deref())? {
+            query = query.filter(condition);
         }
 
-        Ok(query.count().get_result(conn)?)
+        query = match params.active {
+            true => query.filter(tenants::deleted_at.is_null()),
+            false => query.filter(tenants::deleted_at.is_not_null()),
+        };
+
+        Ok(query)
+    }
+}
+
+impl Filterable for tenants::table {
+    fn condition(condition: &Condition) -> Result<BoxedCondition<Self>> {
+        match condition.field.as_str() {
+            "id" => filter_condition!(condition, tenants::id, i32),
+            "name" => filter_condition!(condition, tenants::name, like),
+            "created_at" => filter_condition!(condition, tenants::created_at, DateTime<Utc>),
+            "updated_at" => filter_condition!(condition, tenants::updated_at, DateTime<Utc>),
+            _ => Err(condition.unknown_field()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tenant(allowed_hosts: &[&str]) -> Tenant {
+        let now = Utc::now();
+        Tenant {
+            id: 1,
+            name: "Tenant".to_string(),
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            allowed_hosts: allowed_hosts.iter().map(|h| h.to_string()).collect(),
+        }
+    }
+
+    fn allows(allowed_hosts: &[&str], url: &str) -> bool {
+        tenant(allowed_hosts).allows(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn allows_listed_hosts_only() {
+        assert!(allows(
+            &["api.example.com"],
+            "https://api.example.com/hooks"
+        ));
+        assert!(allows(&["API.example.com"], "https://api.EXAMPLE.com/"));
+        assert!(!allows(&["api.example.com"], "https://example.com/"));
+        assert!(!allows(
+            &["api.example.com"],
+            "https://api.example.com.evil.test/"
+        ));
+        assert!(!allows(&[], "https://api.example.com/"));
+    }
+
+    #[test]
+    fn wildcards_allow_subdomains_but_not_the_domain() {
+        let hosts = ["*.example.com"];
+
+        assert!(allows(&hosts, "https://api.example.com/"));
+        assert!(allows(&hosts, "https://a.b.example.com/"));
+        assert!(!allows(&hosts, "https://example.com/"));
+        assert!(!allows(&hosts, "https://badexample.com/"));
+    }
+
+    #[test]
+    fn hosts_without_a_port_allow_only_the_default_port() {
+        let hosts = ["api.example.com"];
+
+        assert!(allows(&hosts, "http://api.example.com/"));
+        assert!(allows(&hosts, "https://api.example.com:443/"));
+        assert!(!allows(&hosts, "https://api.example.com:8443/"));
+    }
+
+    #[test]
+    fn hosts_with_a_port_allow_only_that_port() {
+        let hosts = ["api.example.com:8443", "*.internal.test:9000"];
+
+        assert!(allows(&hosts, "https://api.example.com:8443/"));
+        assert!(!allows(&hosts, "https://api.example.com/"));
+        assert!(allows(&hosts, "http://queue.internal.test:9000/"));
+        assert!(!allows(&hosts, "http://queue.internal.test:9001/"));
+        assert!(!allows(
+            &["api.example.com:https"],
+            "https://api.example.com/"
+        ));
+    }
+
+    #[test]
+    fn matches_bracketed_ipv6_hosts() {
+        assert!(allows(&["[::1]"], "http://[::1]/"));
+        assert!(!allows(&["[::1]"], "http://[::1]:8080/"));
+        assert!(allows(&["[::1]:8080"], "http://[::1]:8080/"));
+        assert!(!allows(&["[::1]:8080"], "http://[::2]:8080/"));
     }
 }
@@ -0,0 +1,47 @@
+use actix_web::rt::{spawn, time::interval};
+use actix_web::web::block;
+
+use crate::config::TimerSettings;
+use crate::database::PoolManager;
+use crate::instances::WorkflowInstance;
+use crate::result::AppError;
+
+/// Spawns the background timer that escalates overdue instances and makes
+/// the HTTP calls instances are waiting on.
+pub fn start(settings: TimerSettings, pool: PoolManager) {
+    spawn(async move {
+        let mut ticker = interval(settings.interval);
+
+        loop {
+            ticker.tick().await;
+
+            let escalations = pool.clone();
+            let result = block(move || -> Result<_, AppError> {
+                let mut conn = escalations.get()?;
+                WorkflowInstance::escalate_overdue(&mut conn)
+            })
+            .await;
+
+            match result {
+                Ok(Ok(0)) => {}
+                Ok(Ok(fired)) => tracing::info!("Fired {} escalations", fired),
+                Ok(Err(e)) => tracing::error!("Failed to escalate overdue instances: {e}"),
+                Err(e) => tracing::error!("Failed to escalate overdue instances: {e}"),
+            }
+
+            let calls = pool.clone();
+            let result = block(move || -> Result<_, AppError> {
+                let mut conn = calls.get()?;
+                WorkflowInstance::run_calls(&mut conn, None)
+            })
+            .await;
+
+            match result {
+                Ok(Ok(calls)) if calls.is_empty() => {}
+                Ok(Ok(calls)) => tracing::info!("Made {} HTTP calls", calls.len()),
+                Ok(Err(e)) => tracing::error!("Failed to make HTTP calls: {e}"),
+                Err(e) => tracing::error!("Failed to make HTTP calls: {e}"),
+            }
+        }
+    });
+}
@@ -0,0 +1,550 @@
+//! Inbound triggers let external systems start instances of a workflow, or
+//! resume instances waiting in one of its states, without a user session.
+//!
+//! Each trigger is reached at `/api/triggers/{token}`, where the token is
+//! random and unguessable, and requests are authenticated with the trigger's
+//! secret: either an `X-Daedalus-Signature` header signed the same way as
+//! outgoing webhooks, or the secret itself in an `X-Api-Key` header.
+
+use std::io::Write;
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::deserialize::{FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::PgValue;
+use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use tsync::tsync;
+use uuid::Uuid;
+
+use crate::database::{schema::workflow_triggers, DbConnection, DB};
+use crate::idempotency::{self, Reservation};
+use crate::instances::{StartInstance, TransitionInstance, WorkflowInstance};
+use crate::result::{AppError, Result};
+use crate::workflows::{TransitionDefinition, Workflow};
+
+/// How far the timestamp of a signature may be from the current time.
+const SIGNATURE_TOLERANCE: i64 = 300;
+
+/// How a trigger authenticates requests.
+#[derive(
+    AsExpression,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    Eq,
+    FromSqlRow,
+    JsonSchema,
+    PartialEq,
+    Serialize,
+)]
+#[tsync]
+#[diesel(sql_type = Text)]
+pub enum TriggerAuth {
+    /// An `X-Daedalus-Signature` header holding the timestamp and an
+    /// HMAC-SHA256 of the timestamp and body, as `t=<timestamp>,v1=<hex>`.
+    #[default]
+    Hmac,
+    /// An `X-Api-Key` header holding the secret.
+    ApiKey,
+}
+
+impl FromSql<Text, DB> for TriggerAuth {
+    fn from_sql(bytes: PgValue) -> diesel::deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"Hmac" => Ok(TriggerAuth::Hmac),
+            b"ApiKey" => Ok(TriggerAuth::ApiKey),
+            _ => Err("Unknown trigger authentication".into()),
+        }
+    }
+}
+
+impl ToSql<Text, DB> for TriggerAuth {
+    fn to_sql(&self, out: &mut Output<DB>) -> serialize::Result {
+        let auth = match self {
+            TriggerAuth::Hmac => "Hmac",
+            TriggerAuth::ApiKey => "ApiKey",
+        };
+        out.write_all(auth.as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Identifiable, JsonSchema, Queryable, Selectable, Serialize)]
+#[tsync]
+#[diesel(table_name = workflow_triggers)]
+pub struct WorkflowTrigger {
+    pub id: i64,
+    pub workflow_id: i64,
+    pub name: String,
+    /// The state whose waiting instances the trigger resumes. Triggers
+    /// without a state start new instances.
+    pub state_id: Option<Uuid>,
+    /// The transition taken out of the state when an instance is resumed.
+    pub transition_id: Option<Uuid>,
+    /// Identifies the trigger in its URL.
+    pub token: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub auth: TriggerAuth,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = workflow_triggers)]
+struct NewWorkflowTrigger {
+    workflow_id: i64,
+    name: String,
+    state_id: Option<Uuid>,
+    transition_id: Option<Uuid>,
+    token: String,
+    secret: String,
+    auth: TriggerAuth,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct CreateWorkflowTrigger {
+    pub name: String,
+    #[serde(default)]
+    pub auth: TriggerAuth,
+    /// The transition resumed instances leave their state through. When
+    /// omitted, the trigger starts new instances.
+    pub transition_id: Option<Uuid>,
+}
+
+/// A newly created trigger along with its secret, which is only ever returned
+/// here.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct TriggerCredentials {
+    pub trigger: WorkflowTrigger,
+    pub secret: String,
+}
+
+/// The body of a request to a trigger that resumes instances.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct ResumeInstance {
+    pub instance_id: i64,
+    pub option_id: Option<Uuid>,
+    pub comment: Option<String>,
+    pub data: Option<Value>,
+}
+
+/// The response to a trigger request, and whether it was replayed from an
+/// earlier request with the same idempotency key.
+#[derive(Clone, Debug)]
+pub struct TriggerResponse {
+    pub status: u16,
+    pub body: Value,
+    pub replayed: bool,
+}
+
+impl WorkflowTrigger {
+    pub fn find(conn: &mut DbConnection, id: i64) -> Result<Option<WorkflowTrigger>> {
+        Ok(workflow_triggers::table
+            .select(WorkflowTrigger::as_select())
+            .filter(workflow_triggers::id.eq(id))
+            .get_result(conn)
+            .optional()?)
+    }
+
+    /// Finds the active trigger with the token.
+    pub fn find_by_token(conn: &mut DbConnection, token: &str) -> Result<Option<WorkflowTrigger>> {
+        Ok(workflow_triggers::table
+            .select(WorkflowTrigger::as_select())
+            .filter(workflow_triggers::token.eq(token))
+            .filter(workflow_triggers::deleted_at.is_null())
+            .get_result(conn)
+            .optional()?)
+    }
+
+    pub fn list(conn: &mut DbConnection, workflow_id: i64) -> Result<Vec<WorkflowTrigger>> {
+        Ok(workflow_triggers::table
+            .select(WorkflowTrigger::as_select())
+            .filter(workflow_triggers::workflow_id.eq(workflow_id))
+            .filter(workflow_triggers::deleted_at.is_null())
+            .order(workflow_triggers::id.asc())
+            .get_results(conn)?)
+    }
+
+    /// Creates a trigger with a random token and secret.
+    pub fn create(
+        conn: &mut DbConnection,
+        workflow_id: i64,
+        CreateWorkflowTrigger {
+            name,
+            auth,
+            transition_id,
+        }: CreateWorkflowTrigger,
+    ) -> Result<TriggerCredentials> {
+        if name.trim().is_empty() {
+            return Err(AppError::validation_error("Trigger name is required"));
+        }
+
+        let workflow = Workflow::find(conn, workflow_id)?
+            .filter(|w| w.deleted_at.is_none())
+            .ok_or_else(|| AppError::not_found("Workflow", &workflow_id.to_string()))?;
+
+        let state_id = match transition_id {
+            Some(transition_id) => Some(resumable_state(&workflow, transition_id)?),
+            None => None,
+        };
+
+        let secret = random_hex();
+        let trigger = diesel::insert_into(workflow_triggers::table)
+            .values(&NewWorkflowTrigger {
+                workflow_id,
+                name,
+                state_id,
+                transition_id,
+                token: random_hex(),
+                secret: secret.clone(),
+                auth,
+            })
+            .returning(WorkflowTrigger::as_returning())
+            .get_result(conn)?;
+
+        Ok(TriggerCredentials { trigger, secret })
+    }
+
+    /// Disables the trigger, so requests to its URL are rejected.
+    pub fn delete(conn: &mut DbConnection, id: i64) -> Result<WorkflowTrigger> {
+        Ok(diesel::update(workflow_triggers::table)
+            .filter(workflow_triggers::id.eq(id))
+            .set(workflow_triggers::deleted_at.eq(Utc::now()))
+            .returning(WorkflowTrigger::as_returning())
+            .get_result(conn)?)
+    }
+
+    /// Checks the request's signature or API key, whichever the trigger uses.
+    pub fn authenticate(
+        &self,
+        signature: Option<&str>,
+        api_key: Option<&str>,
+        body: &[u8],
+    ) -> Result<()> {
+        let authenticated = match self.auth {
+            TriggerAuth::Hmac => signature.is_some_and(|s| self.verify_signature(s, body)),
+            TriggerAuth::ApiKey => api_key.is_some_and(|key| constant_time_eq(key, &self.secret)),
+        };
+
+        match authenticated {
+            true => Ok(()),
+            false => Err(AppError::unauthorized()),
+        }
+    }
+
+    fn verify_signature(&self, signature: &str, body: &[u8]) -> bool {
+        let mut timestamp = None;
+        let mut digest = None;
+        for part in signature.split(',') {
+            match part.trim().split_once('=') {
+                Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+                Some(("v1", value)) => digest = hex::decode(value).ok(),
+                _ => {}
+            }
+        }
+
+        let (Some(timestamp), Some(digest)) = (timestamp, digest) else {
+            return false;
+        };
+
+        if (Utc::now().timestamp() - timestamp).abs() > SIGNATURE_TOLERANCE {
+            return false;
+        }
+
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes()) else {
+            return false;
+        };
+        mac.update(format!("{}.", timestamp).as_bytes());
+        mac.update(body);
+        mac.verify_slice(&digest).is_ok()
+    }
+
+    /// Starts or resumes an instance with the request body. When the request
+    /// has an idempotency key, the response is stored for `window` so that
+    /// retries of the request are answered with it rather than handled again.
+    /// The response is the instance as it was committed, before any HTTP
+    /// action it reached was called.
+    pub fn fire(
+        &self,
+        conn: &mut DbConnection,
+        body: &[u8],
+        idempotency_key: Option<&str>,
+        window: Duration,
+    ) -> Result<TriggerResponse> {
+        let (response, instance) = conn.transaction(|conn| -> Result<_> {
+            let reservation = match idempotency_key {
+                Some(key) => Some(idempotency::reserve(
+                    conn,
+                    &format!("trigger:{}", self.id),
+                    key,
+                    &idempotency::fingerprint(&[body]),
+                    window,
+                )?),
+                None => None,
+            };
+
+            let id = match reservation {
+                Some(Reservation::Replay { status, body, .. }) => {
+                    let response = TriggerResponse {
+                        status,
+                        body,
+                        replayed: true,
+                    };
+                    return Ok((response, None));
+                }
+                Some(Reservation::Reserved(id)) => Some(id),
+                None => None,
+            };
+
+            let instance = match self.transition_id {
+                Some(transition_id) => self.resume(conn, transition_id, body)?,
+                None => WorkflowInstance::start(
+                    conn,
+                    None,
+                    StartInstance {
+                        workflow_id: self.workflow_id,
+                        data: parse_body(body)?,
+                    },
+                )?,
+            };
+
+            let body = serde_json::to_value(&instance).map_err(AppError::server_error)?;
+            if let Some(id) = id {
+                idempotency::complete(conn, id, 200, &[], &body)?;
+            }
+
+            let response = TriggerResponse {
+                status: 200,
+                body,
+                replayed: false,
+            };
+            Ok((response, Some(instance)))
+        })?;
+
+        if let Some(instance) = instance {
+            if let Err(e) = WorkflowInstance::settle(conn, instance) {
+                tracing::warn!(trigger_id = self.id, "Failed to settle the instance: {e}");
+            }
+        }
+
+        Ok(response)
+    }
+
+    fn resume(
+        &self,
+        conn: &mut DbConnection,
+        transition_id: Uuid,
+        body: &[u8],
+    ) -> Result<WorkflowInstance> {
+        let ResumeInstance {
+            instance_id,
+            option_id,
+            comment,
+            data,
+        } = serde_json::from_slice(body)
+            .map_err(|e| AppError::validation_error(format!("Invalid request body: {}", e)))?;
+
+        let instance = WorkflowInstance::find(conn, instance_id)?;
+        self.check_resumable(instance_id, instance.as_ref())?;
+
+        WorkflowInstance::transition(
+            conn,
+            instance_id,
+            None,
+            TransitionInstance {
+                transition_id,
+                option_id,
+                comment,
+                data,
+            },
+        )
+    }
+
+    /// Checks that the instance belongs to the trigger's workflow. Instances
+    /// of other workflows are reported as not found, as if they didn't exist.
+    fn check_resumable(&self, instance_id: i64, instance: Option<&WorkflowInstance>) -> Result<()> {
+        match instance {
+            Some(instance) if instance.workflow_id == self.workflow_id => Ok(()),
+            _ => Err(AppError::not_found(
+                "WorkflowInstance",
+                &instance_id.to_string(),
+            )),
+        }
+    }
+}
+
+/// Finds the state the transition leaves from, checking that it waits on
+/// something a trigger can provide.
+fn resumable_state(workflow: &Workflow, transition_id: Uuid) -> Result<Uuid> {
+    let (state, transition) = workflow
+        .definition
+        .states
+        .iter()
+        .find_map(|state| {
+            let transition = state.transitions.iter().find(|t| t.id == transition_id)?;
+            Some((state, transition))
+        })
+        .ok_or_else(|| AppError::not_found("WorkflowTransition", &transition_id.to_string()))?;
+
+    match transition.definition {
+        TransitionDefinition::Manual { .. } | TransitionDefinition::VendorConfirmation { .. } => {
+            Ok(state.id)
+        }
+        _ => Err(AppError::validation_error(format!(
+            "Transition {} cannot be taken by a trigger, only manual and vendor confirmation \
+             transitions can",
+            transition.name
+        ))),
+    }
+}
+
+fn parse_body(body: &[u8]) -> Result<Option<Value>> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+
+    serde_json::from_slice(body)
+        .map(Some)
+        .map_err(|e| AppError::validation_error(format!("Invalid request body: {}", e)))
+}
+
+/// Compares the strings in time that only depends on their lengths.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+fn random_hex() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::webhooks::dispatcher::sign;
+
+    const SECRET: &str = "a secret of the trigger";
+    const BODY: &[u8] = br#"{"instance_id":1}"#;
+
+    fn trigger(auth: TriggerAuth) -> WorkflowTrigger {
+        WorkflowTrigger {
+            id: 1,
+            workflow_id: 10,
+            name: "Vendor portal".to_string(),
+            state_id: None,
+            transition_id: None,
+            token: random_hex(),
+            secret: SECRET.to_string(),
+            auth,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            deleted_at: None,
+        }
+    }
+
+    fn signature(timestamp: i64, body: &[u8]) -> String {
+        sign(SECRET, timestamp, std::str::from_utf8(body).unwrap()).unwrap()
+    }
+
+    fn instance(workflow_id: i64) -> WorkflowInstance {
+        WorkflowInstance {
+            id: 1,
+            tenant_id: 1,
+            workflow_id,
+            workflow_version: 1,
+            current_state_id: Uuid::nil(),
+            data: json!({}),
+            created_by: None,
+            assignee_id: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            completed_at: None,
+            deleted_at: None,
+            parent_id: None,
+            parent_token_id: None,
+        }
+    }
+
+    #[test]
+    fn accepts_a_valid_signature() {
+        let trigger = trigger(TriggerAuth::Hmac);
+        let signature = signature(Utc::now().timestamp(), BODY);
+
+        assert!(trigger.authenticate(Some(&signature), None, BODY).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_stale_signature() {
+        let trigger = trigger(TriggerAuth::Hmac);
+        let signature = signature(Utc::now().timestamp() - SIGNATURE_TOLERANCE - 1, BODY);
+
+        assert!(trigger.authenticate(Some(&signature), None, BODY).is_err());
+    }
+
+    #[test]
+    fn rejects_a_tampered_body() {
+        let trigger = trigger(TriggerAuth::Hmac);
+        let signature = signature(Utc::now().timestamp(), BODY);
+
+        assert!(trigger
+            .authenticate(Some(&signature), None, br#"{"instance_id":2}"#)
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_a_missing_signature_or_an_api_key_instead() {
+        let trigger = trigger(TriggerAuth::Hmac);
+
+        assert!(trigger.authenticate(None, None, BODY).is_err());
+        assert!(trigger.authenticate(None, Some(SECRET), BODY).is_err());
+    }
+
+    #[test]
+    fn accepts_the_api_key_and_rejects_a_wrong_one() {
+        let trigger = trigger(TriggerAuth::ApiKey);
+
+        assert!(trigger.authenticate(None, Some(SECRET), BODY).is_ok());
+        assert!(trigger
+            .authenticate(None, Some("a secret of another trigger"), BODY)
+            .is_err());
+        assert!(trigger.authenticate(None, Some("a secret"), BODY).is_err());
+        assert!(trigger.authenticate(None, None, BODY).is_err());
+    }
+
+    #[test]
+    fn resumes_only_instances_of_its_workflow() {
+        let trigger = trigger(TriggerAuth::ApiKey);
+
+        assert!(trigger.check_resumable(1, Some(&instance(10))).is_ok());
+        assert!(matches!(
+            trigger.check_resumable(1, Some(&instance(11))),
+            Err(AppError::NotFound { .. })
+        ));
+        assert!(matches!(
+            trigger.check_resumable(1, None),
+            Err(AppError::NotFound { .. })
+        ));
+    }
+}
@@ -1,15 +1,23 @@
 use argon2::password_hash::{rand_core::OsRng, SaltString};
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use tsync::tsync;
 
+use crate::bulk;
 use crate::database::{schema::users, DbConnection, DB};
 use crate::defaults::{default_bool, default_i64};
+use crate::filters::{self, BoxedCondition, Condition, Filterable};
+use crate::pagination::{self, Page, SortDirection};
 use crate::result::AppError;
+use crate::search::{self, Language};
+use crate::{filter_condition, sort_by};
 
 #[tsync]
-#[derive(Clone, Debug, Deserialize, Insertable, Serialize)]
+#[derive(Clone, Debug, Deserialize, Insertable, JsonSchema, Serialize)]
 #[diesel(table_name = users)]
 pub struct CreateUser {
     pub tenant_id: i32,
@@ -19,7 +27,7 @@ pub struct CreateUser {
 }
 
 #[tsync]
-#[derive(AsChangeset, Clone, Debug, Deserialize, Serialize)]
+#[derive(AsChangeset, Clone, Debug, Deserialize, JsonSchema, Serialize)]
 #[diesel(table_name = users)]
 pub struct UpdateUser {
     pub email: Option<String>,
@@ -28,7 +36,7 @@ pub struct UpdateUser {
 }
 
 #[tsync]
-#[derive(Clone, Debug, Deserialize, Queryable, Selectable, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Queryable, Selectable, Serialize)]
 #[diesel(table_name = users)]
 pub struct User {
     pub id: i64,
@@ -43,21 +51,119 @@ pub struct User {
 }
 
 #[tsync]
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserSort {
+    #[default]
+    Id,
+    Email,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[tsync]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 pub struct UserQuery {
     pub tenant_id: Option<i32>,
     pub email: Option<String>,
     pub name: Option<String>,
+    /// Search text, matched against the name and email.
+    pub q: Option<String>,
+    /// Conditions on the fields, see [`crate::filters`].
+    pub filter: Option<String>,
     #[serde(default = "default_bool::<true>")]
     pub active: bool,
+    #[serde(default)]
+    pub sort: UserSort,
+    #[serde(default)]
+    pub direction: SortDirection,
+    /// The cursor of the page to fetch, returned with the page before it.
+    /// When set, `page` is ignored.
+    pub cursor: Option<String>,
+    /// Whether to count the matching users.
+    #[serde(default = "default_bool::<true>")]
+    pub count: bool,
     #[serde(default = "default_i64::<1>")]
     pub page: i64,
     #[serde(default = "default_i64::<10>")]
     pub page_size: i64,
 }
 
+/// An operation of a bulk request on users.
 #[tsync]
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum UserOperation {
+    Create {
+        tenant_id: i32,
+        email: String,
+        password: String,
+        name: Option<String>,
+    },
+    Update {
+        id: i64,
+        email: Option<String>,
+        password: Option<String>,
+        name: Option<String>,
+    },
+    /// Deactivates the user, who can then no longer sign in.
+    Deactivate { id: i64 },
+}
+
+/// A row of a bulk request on users sent as CSV.
+#[derive(Clone, Debug, Deserialize)]
+struct UserRow {
+    op: Option<String>,
+    id: Option<i64>,
+    tenant_id: Option<i32>,
+    email: Option<String>,
+    password: Option<String>,
+    name: Option<String>,
+}
+
+impl UserRow {
+    fn operation(self) -> Result<UserOperation, String> {
+        let required = |column: &str| format!("{} is required", column);
+
+        match self.op.as_deref().unwrap_or("create") {
+            "create" => Ok(UserOperation::Create {
+                tenant_id: self.tenant_id.ok_or_else(|| required("tenant_id"))?,
+                email: self.email.ok_or_else(|| required("email"))?,
+                password: self.password.ok_or_else(|| required("password"))?,
+                name: self.name,
+            }),
+            "update" => Ok(UserOperation::Update {
+                id: self.id.ok_or_else(|| required("id"))?,
+                email: self.email,
+                password: self.password,
+                name: self.name,
+            }),
+            "deactivate" => Ok(UserOperation::Deactivate {
+                id: self.id.ok_or_else(|| required("id"))?,
+            }),
+            op => Err(format!("unknown op {}", op)),
+        }
+    }
+}
+
+impl UserOperation {
+    /// Reads operations from CSV with the columns `op`, `id`, `tenant_id`,
+    /// `email`, `password` and `name`. Rows without an `op` create a user.
+    pub fn from_csv(body: &[u8]) -> Result<Vec<UserOperation>, AppError> {
+        bulk::from_csv::<UserRow>(body)?
+            .into_iter()
+            .enumerate()
+            .map(|(index, row)| {
+                row.operation().map_err(|e| {
+                    AppError::validation_error(format!("Invalid CSV row {}: {}", index + 1, e))
+                })
+            })
+            .collect()
+    }
+}
+
+#[tsync]
+#[derive(JsonSchema, Serialize, Deserialize)]
 pub struct UserCredentials {
     pub tenant_id: i32,
     pub email: String,
@@ -73,6 +179,16 @@ impl User {
             .optional()?)
     }
 
+    /// Finds the user and locks it until the end of the transaction.
+    pub fn lock(conn: &mut DbConnection, id: i64) -> Result<Option<User>, AppError> {
+        Ok(users::table
+            .select(User::as_select())
+            .filter(users::id.eq(id))
+            .for_update()
+            .get_result(conn)
+            .optional()?)
+    }
+
     pub fn find_by_email_and_tenant(
         conn: &mut DbConnection,
         email: String,
@@ -143,73 +259,161 @@ impl User {
             .get_result(conn)?)
     }
 
-    pub fn list(
-        conn: &mut DbConnection,
-        UserQuery {
-            tenant_id,
-            email,
-            name,
-            active,
+    /// Marks the user as deleted, which keeps them from signing in.
+    pub fn deactivate(conn: &mut DbConnection, id: i64) -> Result<User, AppError> {
+        diesel::update(users::table)
+            .filter(users::id.eq(id))
+            .filter(users::deleted_at.is_null())
+            .set(users::deleted_at.eq(Utc::now()))
+            .returning(User::as_returning())
+            .get_result(conn)
+            .optional()?
+            .ok_or_else(|| AppError::not_found("User", &id.to_string()))
+    }
+
+    /// Applies an operation of a bulk request.
+    pub fn apply(conn: &mut DbConnection, operation: UserOperation) -> Result<User, AppError> {
+        match operation {
+            UserOperation::Create {
+                tenant_id,
+                email,
+                password,
+                name,
+            } => Self::create(
+                conn,
+                CreateUser {
+                    tenant_id,
+                    email,
+                    password,
+                    name,
+                },
+            ),
+            UserOperation::Update {
+                id,
+                email,
+                password,
+                name,
+            } => {
+                Self::find(conn, id)?
+                    .ok_or_else(|| AppError::not_found("User", &id.to_string()))?;
+                Self::update(
+                    conn,
+                    id,
+                    UpdateUser {
+                        email,
+                        password,
+                        name,
+                    },
+                )
+            }
+            UserOperation::Deactivate { id } => Self::deactivate(conn, id),
+        }
+    }
+
+    pub fn list(conn: &mut DbConnection, params: UserQuery) -> Result<Page<User>, AppError> {
+        let mut query = Self::filtered(&params)?;
+        let UserQuery {
+            sort,
+            direction,
+            cursor,
             page,
             page_size,
-        }: UserQuery,
-    ) -> Result<Vec<User>, AppError> {
-        let mut query = users::table.into_boxed::<DB>();
+            ..
+        } = params;
 
-        if let Some(tenant_id) = tenant_id {
-            query = query.filter(users::tenant_id.eq(tenant_id));
-        }
+        let cursor = cursor.as_deref();
+        query = match sort {
+            UserSort::Id => sort_by!(
+                query,
+                users::id,
+                users::id as i64,
+                direction,
+                pagination::start::<i64>(cursor, sort, direction, page, page_size)?
+            ),
+            UserSort::Email => sort_by!(
+                query,
+                users::email,
+                users::id as i64,
+                direction,
+                pagination::start::<String>(cursor, sort, direction, page, page_size)?
+            ),
+            UserSort::CreatedAt => sort_by!(
+                query,
+                users::created_at,
+                users::id as i64,
+                direction,
+                pagination::start::<DateTime<Utc>>(cursor, sort, direction, page, page_size)?
+            ),
+            UserSort::UpdatedAt => sort_by!(
+                query,
+                users::updated_at,
+                users::id as i64,
+                direction,
+                pagination::start::<DateTime<Utc>>(cursor, sort, direction, page, page_size)?
+            ),
+        };
 
-        if let Some(email) = email {
-            query = query.filter(users::email.eq(email));
-        }
+        let rows = query
+            .select(User::as_select())
+            .limit(page_size + 1)
+            .get_results(conn)?;
 
-        if let Some(name) = name {
-            query = query.filter(users::name.ilike(format!("%{name}%")));
+        Ok(pagination::page(rows, page_size, sort, direction, |u| {
+            (u.sort_key(sort), u.id)
+        }))
+    }
+
+    /// The value of the sort field, stored in cursors.
+    fn sort_key(&self, sort: UserSort) -> Value {
+        match sort {
+            UserSort::Id => json!(self.id),
+            UserSort::Email => json!(self.email),
+            UserSort::CreatedAt => json!(self.created_at),
+            UserSort::UpdatedAt => json!(self.updated_at),
         }
+    }
 
-        query = match active {
-            true => query.filter(users::deleted_at.is_null()),
-            false => query.filter(users::deleted_at.is_not_null()),
-        };
+    pub fn count(conn: &mut DbConnection, params: UserQuery) -> Result<i64, AppError> {
+        let query = Self::filtered(&params)?;
 
-        Ok(query
-            .select(User::as_select())
-            .limit(page_size)
-            .offset(page_size * (page - 1))
-            .get_results(conn)?)
+        Ok(query.count().get_result(conn)?)
     }
 
-    pub fn count(
-        conn: &mut DbConnection,
-        UserQuery {
-            tenant_id,
-            email,
-            name,
-            active,
-            ..
-        }: UserQuery,
-    ) -> Result<i64, AppError> {
+    /// The users matching the query, which both listing and counting them
+    /// start from.
+    fn filtered(params: &UserQuery) -> Result<users::BoxedQuery<'static, DB>, AppError> {
         let mut query = users::table.into_boxed::<DB>();
 
-        if let Some(tenant_id) = tenant_id {
+        if let Some(tenant_id) = params.tenant_id {
             query = query.filter(users::tenant_id.eq(tenant_id));
         }
 
-        if let Some(email) = email {
-            query = query.filter(users::email.eq(email));
+        if let Some(email) = &params.email {
+            query = query.filter(users::email.eq(email.clone()));
         }
 
-        if let Some(name) = name {
+        if let Some(name) = &params.name {
             query = query.filter(users::name.ilike(format!("%{name}%")));
         }
 
-        query = match active {
+        if let Some(text) = &params.q {
+            query = query.filter(search::matches(
+                users::search_vector,
+                Language::Simple,
+                text,
+            ));
+        }
+
+        if let Some(condition) = filters::condition(params.filter.as_deref())? {
+            query = query.filter(condition);
+        }
+
+        query = match params.active {
             true => query.filter(users::deleted_at.is_null()),
             false => query.filter(users::deleted_at.is_not_null()),
         };
 
-        Ok(query.count().get_result(conn)?)
+        Ok(query)
     }
 
     pub fn authenticate(
@@ -233,7 +437,7 @@ impl User {
             .verify_password(password.as_bytes(), &parsed_hash)
             .is_ok();
 
-        match password_match {
+        match password_match && user.deleted_at.is_none() {
             true => Ok(user),
             false => Err(AppError::Forbidden {
                 cause: "Invalid email or password".to_string(),
@@ -241,3 +445,17 @@ impl User {
         }
     }
 }
+
+impl Filterable for users::table {
+    fn condition(condition: &Condition) -> Result<BoxedCondition<Self>, AppError> {
+        match condition.field.as_str() {
+            "id" => filter_condition!(condition, users::id, i64),
+            "tenant_id" => filter_condition!(condition, users::tenant_id, i32),
+            "email" => filter_condition!(condition, users::email, like),
+            "name" => filter_condition!(condition, users::name.assume_not_null(), like),
+            "created_at" => filter_condition!(condition, users::created_at, DateTime<Utc>),
+            "updated_at" => filter_condition!(condition, users::updated_at, DateTime<Utc>),
+            _ => Err(condition.unknown_field()),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::io::Write;
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::deserialize::{FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::PgValue;
+use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tsync::tsync;
+
+use crate::database::{schema::webhook_deliveries, DbConnection, DB};
+use crate::defaults::default_i64;
+use crate::pagination;
+use crate::result::{AppError, Result};
+
+use super::WebhookEvent;
+
+/// The most retry delays are doubled before they stop growing.
+const MAX_BACKOFF_DOUBLINGS: u32 = 10;
+
+#[derive(
+    AsExpression, Clone, Copy, Debug, Deserialize, Eq, FromSqlRow, JsonSchema, PartialEq, Serialize,
+)]
+#[tsync]
+#[diesel(sql_type = Text)]
+pub enum DeliveryStatus {
+    /// Waiting for its first attempt or for a retry.
+    Pending,
+    Delivered,
+    /// Every attempt failed and no more will be made until it is replayed.
+    Dead,
+}
+
+impl FromSql<Text, DB> for DeliveryStatus {
+    fn from_sql(bytes: PgValue) -> diesel::deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"Pending" => Ok(DeliveryStatus::Pending),
+            b"Delivered" => Ok(DeliveryStatus::Delivered),
+            b"Dead" => Ok(DeliveryStatus::Dead),
+            _ => Err("Unknown delivery status".into()),
+        }
+    }
+}
+
+impl ToSql<Text, DB> for DeliveryStatus {
+    fn to_sql(&self, out: &mut Output<DB>) -> serialize::Result {
+        let status = match self {
+            DeliveryStatus::Pending => "Pending",
+            DeliveryStatus::Delivered => "Delivered",
+            DeliveryStatus::Dead => "Dead",
+        };
+        out.write_all(status.as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+/// A single event queued for a subscription, along with the outcome of its
+/// latest attempt.
+#[derive(Clone, Debug, Deserialize, Identifiable, JsonSchema, Queryable, Selectable, Serialize)]
+#[tsync]
+#[diesel(table_name = webhook_deliveries)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub subscription_id: i64,
+    pub event: String,
+    pub payload: Value,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    /// The HTTP status the subscriber responded with on the latest attempt.
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = webhook_deliveries)]
+struct NewWebhookDelivery {
+    subscription_id: i64,
+    event: String,
+    payload: Value,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct DeliveryQuery {
+    pub status: Option<DeliveryStatus>,
+    #[serde(default = "default_i64::<1>")]
+    pub page: i64,
+    #[serde(default = "default_i64::<10>")]
+    pub page_size: i64,
+}
+
+/// The result of sending a delivery to its subscriber.
+#[derive(Clone, Debug)]
+pub struct DeliveryAttempt {
+    pub response_status: Option<i32>,
+    /// Why the attempt failed, `None` when it succeeded.
+    pub error: Option<String>,
+}
+
+impl WebhookDelivery {
+    pub fn find(conn: &mut DbConnection, id: i64) -> Result<Option<WebhookDelivery>> {
+        Ok(webhook_deliveries::table
+            .select(WebhookDelivery::as_select())
+            .filter(webhook_deliveries::id.eq(id))
+            .get_result(conn)
+            .optional()?)
+    }
+
+    pub(super) fn enqueue(
+        conn: &mut DbConnection,
+        subscription_ids: &[i64],
+        event: WebhookEvent,
+        payload: Value,
+    ) -> Result<usize> {
+        let deliveries: Vec<_> = subscription_ids
+            .iter()
+            .map(|subscription_id| NewWebhookDelivery {
+                subscription_id: *subscription_id,
+                event: event.name().to_string(),
+                payload: payload.clone(),
+            })
+            .collect();
+
+        Ok(diesel::insert_into(webhook_deliveries::table)
+            .values(&deliveries)
+            .execute(conn)?)
+    }
+
+    pub fn list(
+        conn: &mut DbConnection,
+        subscription_id: i64,
+        DeliveryQuery {
+            status,
+            page,
+            page_size,
+        }: DeliveryQuery,
+    ) -> Result<Vec<WebhookDelivery>> {
+        pagination::check(page, page_size)?;
+
+        let mut query = webhook_deliveries::table
+            .filter(webhook_deliveries::subscription_id.eq(subscription_id))
+            .into_boxed::<DB>();
+
+        if let Some(status) = status {
+            query = query.filter(webhook_deliveries::status.eq(status));
+        }
+
+        Ok(query
+            .select(WebhookDelivery::as_select())
+            .order(webhook_deliveries::id.desc())
+            .limit(page_size)
+            .offset(page_size * (page - 1))
+            .get_results(conn)?)
+    }
+
+    pub fn count(
+        conn: &mut DbConnection,
+        subscription_id: i64,
+        DeliveryQuery { status, .. }: DeliveryQuery,
+    ) -> Result<i64> {
+        let mut query = webhook_deliveries::table
+            .filter(webhook_deliveries::subscription_id.eq(subscription_id))
+            .into_boxed::<DB>();
+
+        if let Some(status) = status {
+            query = query.filter(webhook_deliveries::status.eq(status));
+        }
+
+        Ok(query.count().get_result(conn)?)
+    }
+
+    /// Queues the delivery's payload to be sent again as a new delivery,
+    /// leaving the original in the log.
+    pub fn replay(conn: &mut DbConnection, id: i64) -> Result<WebhookDelivery> {
+        let delivery = Self::find(conn, id)?
+            .ok_or_else(|| AppError::not_found("WebhookDelivery", &id.to_string()))?;
+
+        Ok(diesel::insert_into(webhook_deliveries::table)
+            .values(&NewWebhookDelivery {
+                subscription_id: delivery.subscription_id,
+                event: delivery.event,
+                payload: delivery.payload,
+            })
+            .returning(WebhookDelivery::as_returning())
+            .get_result(conn)?)
+    }
+
+    /// Claims up to `limit` pending deliveries that are due by pushing their
+    /// next attempt back by `lease`, so that other dispatchers skip them while
+    /// they are being sent.
+    pub fn claim_due(
+        conn: &mut DbConnection,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<WebhookDelivery>> {
+        conn.transaction(|conn| {
+            let now = Utc::now();
+            let ids: Vec<i64> = webhook_deliveries::table
+                .select(webhook_deliveries::id)
+                .filter(webhook_deliveries::status.eq(DeliveryStatus::Pending))
+                .filter(webhook_deliveries::next_attempt_at.le(now))
+                .order(webhook_deliveries::next_attempt_at.asc())
+                .limit(limit)
+                .for_update()
+                .skip_locked()
+                .get_results(conn)?;
+
+            Ok(diesel::update(webhook_deliveries::table)
+                .filter(webhook_deliveries::id.eq_any(ids))
+                .set(webhook_deliveries::next_attempt_at.eq(now + lease))
+                .returning(WebhookDelivery::as_returning())
+                .get_results(conn)?)
+        })
+    }
+
+    /// Records the outcome of an attempt. Failed deliveries are retried with
+    /// exponential backoff until `max_attempts` is reached, after which they
+    /// are dead-lettered.
+    pub fn record_attempt(
+        conn: &mut DbConnection,
+        id: i64,
+        attempt: DeliveryAttempt,
+        max_attempts: i32,
+        retry_delay: Duration,
+    ) -> Result<WebhookDelivery> {
+        let delivery = Self::find(conn, id)?
+            .ok_or_else(|| AppError::not_found("WebhookDelivery", &id.to_string()))?;
+        let attempts = delivery.attempts + 1;
+        let now = Utc::now();
+
+        let (status, next_attempt_at, delivered_at) = match &attempt.error {
+            None => (DeliveryStatus::Delivered, now, Some(now)),
+            Some(_) if attempts >= max_attempts => (DeliveryStatus::Dead, now, None),
+            Some(_) => {
+                let doublings = (attempts - 1).clamp(0, MAX_BACKOFF_DOUBLINGS as i32) as u32;
+                (
+                    DeliveryStatus::Pending,
+                    now + retry_delay * 2i32.pow(doublings),
+                    None,
+                )
+            }
+        };
+
+        Ok(diesel::update(webhook_deliveries::table)
+            .filter(webhook_deliveries::id.eq(id))
+            .set((
+                webhook_deliveries::status.eq(status),
+                webhook_deliveries::attempts.eq(attempts),
+                webhook_deliveries::next_attempt_at.eq(next_attempt_at),
+                webhook_deliveries::response_status.eq(attempt.response_status),
+                webhook_deliveries::error.eq(attempt.error),
+                webhook_deliveries::delivered_at.eq(delivered_at),
+            ))
+            .returning(WebhookDelivery::as_returning())
+            .get_result(conn)?)
+    }
+}
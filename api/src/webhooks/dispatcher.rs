@@ -0,0 +1,165 @@
+use actix_web::rt::{spawn, time::interval};
+use actix_web::web::block;
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::config::WebhookSettings;
+use crate::database::{DbConnection, PoolManager};
+use crate::result::{AppError, Result};
+use crate::tenants::Tenant;
+
+use super::deliveries::DeliveryAttempt;
+use super::{validate_url, WebhookDelivery, WebhookSubscription};
+
+/// The number of deliveries sent per batch.
+const BATCH_SIZE: i64 = 50;
+
+/// Spawns the background dispatcher that sends due webhook deliveries.
+pub fn start(settings: WebhookSettings, pool: PoolManager) {
+    spawn(async move {
+        let mut ticker = interval(settings.interval);
+
+        loop {
+            ticker.tick().await;
+
+            let pool = pool.clone();
+            let settings = settings.clone();
+            let result = block(move || -> Result<_> {
+                let mut conn = pool.get()?;
+                dispatch_due(&mut conn, &settings)
+            })
+            .await;
+
+            match result {
+                Ok(Ok(0)) => {}
+                Ok(Ok(sent)) => tracing::info!("Attempted {} webhook deliveries", sent),
+                Ok(Err(e)) => tracing::error!("Failed to dispatch webhooks: {e}"),
+                Err(e) => tracing::error!("Failed to dispatch webhooks: {e}"),
+            }
+        }
+    });
+}
+
+/// Sends every delivery that is due, one batch at a time. Returns the number
+/// of attempts made.
+pub fn dispatch_due(conn: &mut DbConnection, settings: &WebhookSettings) -> Result<usize> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(settings.timeout)
+        .redirects(0)
+        .build();
+    let timeout = Duration::from_std(settings.timeout).unwrap_or_default();
+    let retry_delay = Duration::from_std(settings.retry_delay).unwrap_or_default();
+    let mut attempted = 0;
+
+    loop {
+        // Leave enough time for every request in the batch to time out
+        let lease = timeout * (BATCH_SIZE as i32 + 1);
+        let batch = WebhookDelivery::claim_due(conn, BATCH_SIZE, lease)?;
+
+        if batch.is_empty() {
+            return Ok(attempted);
+        }
+
+        for delivery in batch {
+            let attempt = match WebhookSubscription::find(conn, delivery.subscription_id)? {
+                Some(subscription) if subscription.deleted_at.is_none() => {
+                    match Tenant::find(conn, subscription.tenant_id)? {
+                        Some(tenant) => send(&agent, &tenant, &subscription, &delivery),
+                        None => DeliveryAttempt {
+                            response_status: None,
+                            error: Some("Tenant has been deleted".to_string()),
+                        },
+                    }
+                }
+                _ => DeliveryAttempt {
+                    response_status: None,
+                    error: Some("Subscription has been deleted".to_string()),
+                },
+            };
+
+            if let Some(error) = &attempt.error {
+                tracing::warn!(
+                    delivery_id = delivery.id,
+                    subscription_id = delivery.subscription_id,
+                    "Webhook delivery failed: {error}"
+                );
+            }
+
+            WebhookDelivery::record_attempt(
+                conn,
+                delivery.id,
+                attempt,
+                settings.max_attempts,
+                retry_delay,
+            )?;
+            attempted += 1;
+        }
+    }
+}
+
+fn send(
+    agent: &ureq::Agent,
+    tenant: &Tenant,
+    subscription: &WebhookSubscription,
+    delivery: &WebhookDelivery,
+) -> DeliveryAttempt {
+    if let Err(e) = validate_url(tenant, &subscription.url) {
+        return DeliveryAttempt {
+            response_status: None,
+            error: Some(e.to_string()),
+        };
+    }
+
+    let body = delivery.payload.to_string();
+    let signature = match sign(&subscription.secret, Utc::now().timestamp(), &body) {
+        Ok(signature) => signature,
+        Err(e) => {
+            return DeliveryAttempt {
+                response_status: None,
+                error: Some(e.to_string()),
+            }
+        }
+    };
+
+    let response = agent
+        .post(&subscription.url)
+        .set("Content-Type", "application/json")
+        .set("X-Daedalus-Event", &delivery.event)
+        .set("X-Daedalus-Delivery", &delivery.id.to_string())
+        .set("X-Daedalus-Signature", &signature)
+        .send_string(&body);
+
+    match response {
+        Ok(response) if (200..300).contains(&response.status()) => DeliveryAttempt {
+            response_status: Some(response.status().into()),
+            error: None,
+        },
+        Ok(response) | Err(ureq::Error::Status(_, response)) => DeliveryAttempt {
+            response_status: Some(response.status().into()),
+            error: Some(format!(
+                "Subscriber responded with status {}",
+                response.status()
+            )),
+        },
+        Err(e) => DeliveryAttempt {
+            response_status: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// Signs the body for the `X-Daedalus-Signature` header. The signature is an
+/// HMAC-SHA256 of the timestamp and the body joined by a dot, so subscribers
+/// can reject stale or replayed requests.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> Result<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|e| AppError::server_error(e.to_string()))?;
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+
+    Ok(format!(
+        "t={},v1={}",
+        timestamp,
+        hex::encode(mac.finalize().into_bytes())
+    ))
+}
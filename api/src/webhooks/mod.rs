@@ -0,0 +1,301 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tsync::tsync;
+use url::Url;
+use uuid::Uuid;
+
+use crate::database::{schema::webhook_subscriptions, DbConnection, DB};
+use crate::defaults::{default_bool, default_i64};
+use crate::pagination;
+use crate::result::{AppError, Result};
+use crate::tenants::Tenant;
+
+mod deliveries;
+pub mod dispatcher;
+
+pub use deliveries::{DeliveryQuery, DeliveryStatus, WebhookDelivery};
+
+/// The shortest secret accepted for signing payloads.
+const MIN_SECRET_LEN: usize = 16;
+
+/// The events a subscription can be filtered to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WebhookEvent {
+    WorkflowCreated,
+    WorkflowUpdated,
+    InstanceStarted,
+    InstanceStateChanged,
+    InstanceAssigned,
+    InstanceCompleted,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 6] = [
+        WebhookEvent::WorkflowCreated,
+        WebhookEvent::WorkflowUpdated,
+        WebhookEvent::InstanceStarted,
+        WebhookEvent::InstanceStateChanged,
+        WebhookEvent::InstanceAssigned,
+        WebhookEvent::InstanceCompleted,
+    ];
+
+    /// The name of the event in subscription filters and payloads.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::WorkflowCreated => "workflow.created",
+            WebhookEvent::WorkflowUpdated => "workflow.updated",
+            WebhookEvent::InstanceStarted => "instance.started",
+            WebhookEvent::InstanceStateChanged => "instance.state_changed",
+            WebhookEvent::InstanceAssigned => "instance.assigned",
+            WebhookEvent::InstanceCompleted => "instance.completed",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Identifiable, JsonSchema, Queryable, Selectable, Serialize)]
+#[tsync]
+#[diesel(table_name = webhook_subscriptions)]
+pub struct WebhookSubscription {
+    pub id: i64,
+    pub tenant_id: i32,
+    pub url: String,
+    /// The names of the events sent to the subscription. When empty, every
+    /// event of the tenant is sent.
+    pub events: Vec<String>,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Deserialize, Insertable, JsonSchema, Serialize)]
+#[tsync]
+#[diesel(table_name = webhook_subscriptions)]
+pub struct CreateWebhookSubscription {
+    pub tenant_id: i32,
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// The key payloads are signed with.
+    pub secret: String,
+}
+
+#[derive(AsChangeset, Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+#[diesel(table_name = webhook_subscriptions)]
+pub struct UpdateWebhookSubscription {
+    pub url: Option<String>,
+    pub events: Option<Vec<String>>,
+    pub secret: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct WebhookQuery {
+    pub tenant_id: Option<i32>,
+    #[serde(default = "default_bool::<true>")]
+    pub active: bool,
+    #[serde(default = "default_i64::<1>")]
+    pub page: i64,
+    #[serde(default = "default_i64::<10>")]
+    pub page_size: i64,
+}
+
+impl WebhookSubscription {
+    pub fn find(conn: &mut DbConnection, id: i64) -> Result<Option<WebhookSubscription>> {
+        Ok(webhook_subscriptions::table
+            .select(WebhookSubscription::as_select())
+            .filter(webhook_subscriptions::id.eq(id))
+            .get_result(conn)
+            .optional()?)
+    }
+
+    pub fn list(
+        conn: &mut DbConnection,
+        WebhookQuery {
+            tenant_id,
+            active,
+            page,
+            page_size,
+        }: WebhookQuery,
+    ) -> Result<Vec<WebhookSubscription>> {
+        pagination::check(page, page_size)?;
+
+        let mut query = webhook_subscriptions::table.into_boxed::<DB>();
+
+        if let Some(tenant_id) = tenant_id {
+            query = query.filter(webhook_subscriptions::tenant_id.eq(tenant_id));
+        }
+
+        query = match active {
+            true => query.filter(webhook_subscriptions::deleted_at.is_null()),
+            false => query.filter(webhook_subscriptions::deleted_at.is_not_null()),
+        };
+
+        Ok(query
+            .select(WebhookSubscription::as_select())
+            .order(webhook_subscriptions::id.asc())
+            .limit(page_size)
+            .offset(page_size * (page - 1))
+            .get_results(conn)?)
+    }
+
+    pub fn count(
+        conn: &mut DbConnection,
+        WebhookQuery {
+            tenant_id, active, ..
+        }: WebhookQuery,
+    ) -> Result<i64> {
+        let mut query = webhook_subscriptions::table.into_boxed::<DB>();
+
+        if let Some(tenant_id) = tenant_id {
+            query = query.filter(webhook_subscriptions::tenant_id.eq(tenant_id));
+        }
+
+        query = match active {
+            true => query.filter(webhook_subscriptions::deleted_at.is_null()),
+            false => query.filter(webhook_subscriptions::deleted_at.is_not_null()),
+        };
+
+        Ok(query.count().get_result(conn)?)
+    }
+
+    pub fn create(
+        conn: &mut DbConnection,
+        subscription: CreateWebhookSubscription,
+    ) -> Result<WebhookSubscription> {
+        let tenant = Tenant::find(conn, subscription.tenant_id)?
+            .ok_or_else(|| AppError::not_found("Tenant", &subscription.tenant_id.to_string()))?;
+        validate_url(&tenant, &subscription.url)?;
+        validate_events(&subscription.events)?;
+        validate_secret(&subscription.secret)?;
+
+        Ok(diesel::insert_into(webhook_subscriptions::table)
+            .values(&subscription)
+            .returning(WebhookSubscription::as_returning())
+            .get_result(conn)?)
+    }
+
+    pub fn update(
+        conn: &mut DbConnection,
+        id: i64,
+        subscription: UpdateWebhookSubscription,
+    ) -> Result<WebhookSubscription> {
+        if let Some(url) = &subscription.url {
+            let tenant_id = Self::find(conn, id)?
+                .ok_or_else(|| AppError::not_found("WebhookSubscription", &id.to_string()))?
+                .tenant_id;
+            let tenant = Tenant::find(conn, tenant_id)?
+                .ok_or_else(|| AppError::not_found("Tenant", &tenant_id.to_string()))?;
+            validate_url(&tenant, url)?;
+        }
+
+        if let Some(events) = &subscription.events {
+            validate_events(events)?;
+        }
+
+        if let Some(secret) = &subscription.secret {
+            validate_secret(secret)?;
+        }
+
+        Ok(diesel::update(webhook_subscriptions::table)
+            .filter(webhook_subscriptions::id.eq(id))
+            .set(&subscription)
+            .returning(WebhookSubscription::as_returning())
+            .get_result(conn)?)
+    }
+
+    /// Stops sending events to the subscription. Its delivery log is kept.
+    pub fn delete(conn: &mut DbConnection, id: i64) -> Result<WebhookSubscription> {
+        Ok(diesel::update(webhook_subscriptions::table)
+            .filter(webhook_subscriptions::id.eq(id))
+            .set(webhook_subscriptions::deleted_at.eq(Utc::now()))
+            .returning(WebhookSubscription::as_returning())
+            .get_result(conn)?)
+    }
+}
+
+/// Queues a delivery of the event to every active subscription of the tenant
+/// that is filtered to it. Called within the transaction making the change, so
+/// the event is only sent when the change is committed.
+pub fn publish(
+    conn: &mut DbConnection,
+    tenant_id: i32,
+    event: WebhookEvent,
+    data: Value,
+) -> Result<usize> {
+    let subscriptions: Vec<i64> = webhook_subscriptions::table
+        .select(webhook_subscriptions::id)
+        .filter(webhook_subscriptions::tenant_id.eq(tenant_id))
+        .filter(webhook_subscriptions::deleted_at.is_null())
+        .filter(
+            webhook_subscriptions::events
+                .eq(Vec::<String>::new())
+                .or(webhook_subscriptions::events.contains(vec![event.name()])),
+        )
+        .get_results(conn)?;
+
+    if subscriptions.is_empty() {
+        return Ok(0);
+    }
+
+    let payload = json!({
+        "id": Uuid::now_v7(),
+        "event": event.name(),
+        "tenant_id": tenant_id,
+        "occurred_at": Utc::now(),
+        "data": data,
+    });
+
+    WebhookDelivery::enqueue(conn, &subscriptions, event, payload)
+}
+
+/// Checks that the URL is an http or https URL on a host the tenant allows.
+/// Checked again before each delivery, since the tenant's allowed hosts may
+/// have changed since the subscription was saved.
+fn validate_url(tenant: &Tenant, url: &str) -> Result<()> {
+    let url = Url::parse(url)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .ok_or_else(|| AppError::validation_error("Webhook URL must be an http or https URL"))?;
+
+    match tenant.allows(&url) {
+        true => Ok(()),
+        false => Err(AppError::forbidden(format!(
+            "Host {}:{} is not allowed for tenant {}",
+            url.host_str().unwrap_or_default(),
+            url.port_or_known_default().unwrap_or_default(),
+            tenant.name
+        ))),
+    }
+}
+
+fn validate_events(events: &[String]) -> Result<()> {
+    let unknown: Vec<_> = events
+        .iter()
+        .filter(|e| !WebhookEvent::ALL.iter().any(|known| known.name() == *e))
+        .map(String::as_str)
+        .collect();
+
+    match unknown.is_empty() {
+        true => Ok(()),
+        false => Err(AppError::validation_error(format!(
+            "Unknown webhook events: {}",
+            unknown.join(", ")
+        ))),
+    }
+}
+
+fn validate_secret(secret: &str) -> Result<()> {
+    match secret.len() >= MIN_SECRET_LEN {
+        true => Ok(()),
+        false => Err(AppError::validation_error(format!(
+            "Webhook secret must be at least {} characters",
+            MIN_SECRET_LEN
+        ))),
+    }
+}
@@ -0,0 +1,659 @@
+//! Compares two definitions by the ids of their parts, so that a renamed
+//! state is reported as renamed rather than as one state removed and another
+//! added.
+//!
+//! Changes to the parts of a state that was added or removed are not listed
+//! separately. Positions only matter to the editor, so they are reported on
+//! their own, and the diff says when they are the only difference.
+
+use std::collections::{BTreeSet, HashMap};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use tsync::tsync;
+use uuid::Uuid;
+
+use super::{
+    TransitionDefinition, WorkflowAction, WorkflowDefinition, WorkflowPosition, WorkflowState,
+    WorkflowTransition,
+};
+
+/// The keys of a transition definition that hold the states it leads to,
+/// which are compared as a whole.
+const TARGET_KEYS: [&str; 3] = ["target_state_id", "branch_state_ids", "join_state_id"];
+
+#[derive(Clone, Debug, Default, JsonSchema, Serialize)]
+#[tsync]
+pub struct DefinitionDiff {
+    pub initial_state: Option<InitialStateChange>,
+    pub states: Vec<StateChange>,
+    pub transitions: Vec<TransitionChange>,
+    pub actions: Vec<ActionChange>,
+    pub data_schema_changed: bool,
+    pub positions: Vec<PositionChange>,
+    /// Whether the positions of states are the only difference.
+    pub layout_only: bool,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+#[serde(tag = "change")]
+pub enum StateChange {
+    Added {
+        state_id: Uuid,
+        name: String,
+    },
+    Removed {
+        state_id: Uuid,
+        name: String,
+    },
+    Renamed {
+        state_id: Uuid,
+        from: String,
+        to: String,
+    },
+    /// The fields of the state that changed other than its name.
+    Modified {
+        state_id: Uuid,
+        name: String,
+        fields: Vec<String>,
+    },
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+#[serde(tag = "change")]
+pub enum TransitionChange {
+    Added {
+        state_id: Uuid,
+        transition_id: Uuid,
+        name: String,
+    },
+    Removed {
+        state_id: Uuid,
+        transition_id: Uuid,
+        name: String,
+    },
+    Renamed {
+        state_id: Uuid,
+        transition_id: Uuid,
+        from: String,
+        to: String,
+    },
+    /// The states the transition leads to changed.
+    Retargeted {
+        state_id: Uuid,
+        transition_id: Uuid,
+        name: String,
+        from: Vec<Uuid>,
+        to: Vec<Uuid>,
+    },
+    /// The fields of the transition that changed other than its name and
+    /// targets, such as `definition.guard`.
+    Modified {
+        state_id: Uuid,
+        transition_id: Uuid,
+        name: String,
+        fields: Vec<String>,
+    },
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+#[serde(tag = "change")]
+pub enum ActionChange {
+    Added {
+        state_id: Uuid,
+        action_id: Uuid,
+        name: String,
+    },
+    Removed {
+        state_id: Uuid,
+        action_id: Uuid,
+        name: String,
+    },
+    Renamed {
+        state_id: Uuid,
+        action_id: Uuid,
+        from: String,
+        to: String,
+    },
+    /// The fields of the action that changed other than its name, such as
+    /// `definition.url`, or `phase` when it moved between the entry and exit
+    /// actions.
+    Modified {
+        state_id: Uuid,
+        action_id: Uuid,
+        name: String,
+        fields: Vec<String>,
+    },
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct InitialStateChange {
+    pub from: Uuid,
+    pub to: Uuid,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct PositionChange {
+    pub state_id: Uuid,
+    pub from: Option<WorkflowPosition>,
+    pub to: Option<WorkflowPosition>,
+}
+
+impl DefinitionDiff {
+    pub fn is_empty(&self) -> bool {
+        self.initial_state.is_none()
+            && self.states.is_empty()
+            && self.transitions.is_empty()
+            && self.actions.is_empty()
+            && !self.data_schema_changed
+            && self.positions.is_empty()
+    }
+}
+
+impl WorkflowDefinition {
+    /// Compares the definition, as the older one, with the newer one.
+    pub fn diff(&self, new: &WorkflowDefinition) -> DefinitionDiff {
+        let old = self;
+        let mut diff = DefinitionDiff {
+            initial_state: (old.initial_state != new.initial_state).then_some(InitialStateChange {
+                from: old.initial_state,
+                to: new.initial_state,
+            }),
+            data_schema_changed: to_value(&old.data_schema) != to_value(&new.data_schema),
+            ..Default::default()
+        };
+
+        for state in &new.states {
+            match old.state(state.id) {
+                None => diff.states.push(StateChange::Added {
+                    state_id: state.id,
+                    name: state.name.clone(),
+                }),
+                Some(before) => diff_state(&mut diff, before, state),
+            }
+        }
+        for state in old.states.iter().filter(|s| new.state(s.id).is_none()) {
+            diff.states.push(StateChange::Removed {
+                state_id: state.id,
+                name: state.name.clone(),
+            });
+        }
+
+        diff_transitions(&mut diff, old, new);
+        diff_actions(&mut diff, old, new);
+
+        // Checked before the positions are added to tell if they are the
+        // only difference
+        let unchanged = diff.is_empty();
+
+        let ids: BTreeSet<&Uuid> = old
+            .metadata
+            .positions
+            .keys()
+            .chain(new.metadata.positions.keys())
+            .collect();
+        for id in ids {
+            let from = old.metadata.positions.get(id);
+            let to = new.metadata.positions.get(id);
+            let moved = match (from, to) {
+                (Some(from), Some(to)) => from.x != to.x || from.y != to.y,
+                _ => true,
+            };
+            if moved {
+                diff.positions.push(PositionChange {
+                    state_id: *id,
+                    from: from.cloned(),
+                    to: to.cloned(),
+                });
+            }
+        }
+
+        diff.layout_only = unchanged && !diff.positions.is_empty();
+
+        diff
+    }
+}
+
+fn diff_state(diff: &mut DefinitionDiff, old: &WorkflowState, new: &WorkflowState) {
+    if old.name != new.name {
+        diff.states.push(StateChange::Renamed {
+            state_id: new.id,
+            from: old.name.clone(),
+            to: new.name.clone(),
+        });
+    }
+
+    let mut fields = Vec::new();
+    if old.description != new.description {
+        fields.push("description".to_string());
+    }
+    if old.is_end_state != new.is_end_state {
+        fields.push("is_end_state".to_string());
+    }
+    if to_value(&old.sla) != to_value(&new.sla) {
+        fields.push("sla".to_string());
+    }
+    if !fields.is_empty() {
+        diff.states.push(StateChange::Modified {
+            state_id: new.id,
+            name: new.name.clone(),
+            fields,
+        });
+    }
+}
+
+fn diff_transitions(diff: &mut DefinitionDiff, old: &WorkflowDefinition, new: &WorkflowDefinition) {
+    let before = transitions(old);
+    let after = transitions(new);
+
+    for (state, transition) in transitions_in_order(new) {
+        let Some((old_state, before)) = before.get(&transition.id) else {
+            if old.state(state.id).is_some() {
+                diff.transitions.push(TransitionChange::Added {
+                    state_id: state.id,
+                    transition_id: transition.id,
+                    name: transition.name.clone(),
+                });
+            }
+            continue;
+        };
+
+        if before.name != transition.name {
+            diff.transitions.push(TransitionChange::Renamed {
+                state_id: state.id,
+                transition_id: transition.id,
+                from: before.name.clone(),
+                to: transition.name.clone(),
+            });
+        }
+
+        let (from, to) = (targets(before), targets(transition));
+        if from != to {
+            diff.transitions.push(TransitionChange::Retargeted {
+                state_id: state.id,
+                transition_id: transition.id,
+                name: transition.name.clone(),
+                from,
+                to,
+            });
+        }
+
+        let mut fields = Vec::new();
+        if old_state.id != state.id {
+            fields.push("state_id".to_string());
+        }
+        let mut old_value = to_value(*before);
+        let mut new_value = to_value(transition);
+        strip_targets(&mut old_value);
+        strip_targets(&mut new_value);
+        fields.extend(changed_fields(
+            &old_value,
+            &new_value,
+            &["description", "definition"],
+        ));
+        if !fields.is_empty() {
+            diff.transitions.push(TransitionChange::Modified {
+                state_id: state.id,
+                transition_id: transition.id,
+                name: transition.name.clone(),
+                fields,
+            });
+        }
+    }
+
+    for (state, transition) in transitions_in_order(old) {
+        if !after.contains_key(&transition.id) && new.state(state.id).is_some() {
+            diff.transitions.push(TransitionChange::Removed {
+                state_id: state.id,
+                transition_id: transition.id,
+                name: transition.name.clone(),
+            });
+        }
+    }
+}
+
+fn diff_actions(diff: &mut DefinitionDiff, old: &WorkflowDefinition, new: &WorkflowDefinition) {
+    let before = actions(old);
+    let after = actions(new);
+
+    for (state, exit, action) in actions_in_order(new) {
+        let Some((old_state, old_exit, before)) = before.get(&action.id) else {
+            if old.state(state.id).is_some() {
+                diff.actions.push(ActionChange::Added {
+                    state_id: state.id,
+                    action_id: action.id,
+                    name: action.name.clone(),
+                });
+            }
+            continue;
+        };
+
+        if before.name != action.name {
+            diff.actions.push(ActionChange::Renamed {
+                state_id: state.id,
+                action_id: action.id,
+                from: before.name.clone(),
+                to: action.name.clone(),
+            });
+        }
+
+        let mut fields = Vec::new();
+        if old_state.id != state.id {
+            fields.push("state_id".to_string());
+        }
+        if *old_exit != exit {
+            fields.push("phase".to_string());
+        }
+        fields.extend(changed_fields(
+            &to_value(*before),
+            &to_value(action),
+            &["description", "definition"],
+        ));
+        if !fields.is_empty() {
+            diff.actions.push(ActionChange::Modified {
+                state_id: state.id,
+                action_id: action.id,
+                name: action.name.clone(),
+                fields,
+            });
+        }
+    }
+
+    for (state, _, action) in actions_in_order(old) {
+        if !after.contains_key(&action.id) && new.state(state.id).is_some() {
+            diff.actions.push(ActionChange::Removed {
+                state_id: state.id,
+                action_id: action.id,
+                name: action.name.clone(),
+            });
+        }
+    }
+}
+
+fn transitions_in_order(
+    definition: &WorkflowDefinition,
+) -> impl Iterator<Item = (&WorkflowState, &WorkflowTransition)> {
+    definition
+        .states
+        .iter()
+        .flat_map(|state| state.transitions.iter().map(move |t| (state, t)))
+}
+
+fn transitions(
+    definition: &WorkflowDefinition,
+) -> HashMap<Uuid, (&WorkflowState, &WorkflowTransition)> {
+    transitions_in_order(definition)
+        .map(|(state, t)| (t.id, (state, t)))
+        .collect()
+}
+
+/// The actions of every state, and whether each is an exit action.
+fn actions_in_order(
+    definition: &WorkflowDefinition,
+) -> impl Iterator<Item = (&WorkflowState, bool, &WorkflowAction)> {
+    definition.states.iter().flat_map(|state| {
+        let entry = state.entry_actions.iter().map(move |a| (state, false, a));
+        let exit = state.exit_actions.iter().map(move |a| (state, true, a));
+        entry.chain(exit)
+    })
+}
+
+fn actions(
+    definition: &WorkflowDefinition,
+) -> HashMap<Uuid, (&WorkflowState, bool, &WorkflowAction)> {
+    actions_in_order(definition)
+        .map(|(state, exit, a)| (a.id, (state, exit, a)))
+        .collect()
+}
+
+/// The states the transition leads to, including the join of a fork.
+fn targets(transition: &WorkflowTransition) -> Vec<Uuid> {
+    let mut targets = transition.definition.targets();
+    if let TransitionDefinition::Parallel { join_state_id, .. } = &transition.definition {
+        targets.push(*join_state_id);
+    }
+    targets
+}
+
+fn strip_targets(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for key in TARGET_KEYS {
+                map.remove(key);
+            }
+            map.values_mut().for_each(strip_targets);
+        }
+        Value::Array(values) => values.iter_mut().for_each(strip_targets),
+        _ => {}
+    }
+}
+
+/// Returns the keys whose values differ, descending into `definition` so the
+/// changed fields of a transition or action definition are named.
+fn changed_fields(old: &Value, new: &Value, keys: &[&str]) -> Vec<String> {
+    let mut fields = Vec::new();
+
+    for key in keys {
+        let (before, after) = (old.get(key), new.get(key));
+        if before == after {
+            continue;
+        }
+
+        match (*key, before, after) {
+            ("definition", Some(Value::Object(before)), Some(Value::Object(after))) => {
+                let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+                fields.extend(
+                    keys.into_iter()
+                        .filter(|k| before.get(*k) != after.get(*k))
+                        .map(|k| format!("definition.{}", k)),
+                );
+            }
+            _ => fields.push(key.to_string()),
+        }
+    }
+
+    fields
+}
+
+fn to_value<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::super::fixtures::{
+        action, automatic, definition, end_state, id, manual, option, state,
+    };
+    use super::*;
+
+    /// Draft, which is assigned to user 1 and submitted to Review, which
+    /// moves on to Done.
+    fn states() -> Vec<Value> {
+        vec![
+            state(
+                1,
+                "Draft",
+                json!({
+                    "entry_actions": [action(20, json!({ "type": "AssignTo", "user_id": 1 }))],
+                    "transitions": [manual(10, &[option(11, "Submit", 2)])],
+                }),
+            ),
+            state(2, "Review", json!({ "transitions": [automatic(30, 3)] })),
+            end_state(3, "Done"),
+        ]
+    }
+
+    fn diff(change: impl FnOnce(&mut Vec<Value>)) -> DefinitionDiff {
+        let mut states = states();
+        change(&mut states);
+        definition(self::states()).diff(&definition(states))
+    }
+
+    #[test]
+    fn finds_nothing_between_equal_definitions() {
+        let diff = diff(|_| {});
+
+        assert!(diff.is_empty());
+        assert!(!diff.layout_only);
+    }
+
+    #[test]
+    fn lists_added_and_removed_states_without_their_parts() {
+        let diff = diff(|states| {
+            states.remove(1);
+            states.push(state(
+                4,
+                "Archive",
+                json!({ "transitions": [automatic(40, 3)] }),
+            ));
+        });
+
+        assert_eq!(
+            to_value(&diff.states),
+            json!([
+                { "change": "Added", "state_id": id(4), "name": "Archive" },
+                { "change": "Removed", "state_id": id(2), "name": "Review" },
+            ])
+        );
+        assert!(diff.transitions.is_empty());
+    }
+
+    #[test]
+    fn lists_renamed_states_by_id() {
+        let diff = diff(|states| states[1]["name"] = json!("Approval"));
+
+        assert_eq!(
+            to_value(&diff.states),
+            json!([{ "change": "Renamed", "state_id": id(2), "from": "Review", "to": "Approval" }])
+        );
+    }
+
+    #[test]
+    fn lists_changed_slas() {
+        let diff = diff(|states| states[1]["sla"] = json!({ "due_in": 3600 }));
+
+        assert_eq!(
+            to_value(&diff.states),
+            json!([{ "change": "Modified", "state_id": id(2), "name": "Review", "fields": ["sla"] }])
+        );
+    }
+
+    #[test]
+    fn lists_added_and_removed_transitions() {
+        let diff = diff(|states| states[1]["transitions"] = json!([automatic(31, 3)]));
+
+        assert_eq!(
+            to_value(&diff.transitions),
+            json!([
+                { "change": "Added", "state_id": id(2), "transition_id": id(31), "name": "Transition 31" },
+                { "change": "Removed", "state_id": id(2), "transition_id": id(30), "name": "Transition 30" },
+            ])
+        );
+    }
+
+    #[test]
+    fn lists_renamed_transitions() {
+        let diff = diff(|states| states[1]["transitions"][0]["name"] = json!("Finish"));
+
+        assert_eq!(
+            to_value(&diff.transitions),
+            json!([{
+                "change": "Renamed",
+                "state_id": id(2),
+                "transition_id": id(30),
+                "from": "Transition 30",
+                "to": "Finish",
+            }])
+        );
+    }
+
+    #[test]
+    fn lists_retargeted_transitions_without_other_changes() {
+        let diff = diff(|states| {
+            states[0]["transitions"][0]["definition"]["options"][0]["target_state_id"] =
+                json!(id(3));
+        });
+
+        assert_eq!(
+            to_value(&diff.transitions),
+            json!([{
+                "change": "Retargeted",
+                "state_id": id(1),
+                "transition_id": id(10),
+                "name": "Transition 10",
+                "from": [id(2)],
+                "to": [id(3)],
+            }])
+        );
+    }
+
+    #[test]
+    fn lists_changed_actions() {
+        let diff = diff(|states| {
+            let mut assign = states[0]["entry_actions"][0].take();
+            assign["definition"]["user_id"] = json!(2);
+            states[0]["entry_actions"] = json!([]);
+            states[0]["exit_actions"] = json!([assign]);
+            states[1]["entry_actions"] =
+                json!([action(21, json!({ "type": "AssignTo", "user_id": 1 }))]);
+        });
+
+        assert_eq!(
+            to_value(&diff.actions),
+            json!([
+                {
+                    "change": "Modified",
+                    "state_id": id(1),
+                    "action_id": id(20),
+                    "name": "Action 20",
+                    "fields": ["phase", "definition.user_id"],
+                },
+                { "change": "Added", "state_id": id(2), "action_id": id(21), "name": "Action 21" },
+            ])
+        );
+    }
+
+    #[test]
+    fn lists_removed_actions() {
+        let diff = diff(|states| states[0]["entry_actions"] = json!([]));
+
+        assert_eq!(
+            to_value(&diff.actions),
+            json!([{ "change": "Removed", "state_id": id(1), "action_id": id(20), "name": "Action 20" }])
+        );
+    }
+
+    #[test]
+    fn notices_changed_data_schemas() {
+        let old = definition(states());
+        let mut new = old.clone();
+        new.data_schema = serde_json::from_value(json!({ "fields": [] })).unwrap();
+
+        let diff = old.diff(&new);
+
+        assert!(diff.data_schema_changed);
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn tells_when_only_positions_changed() {
+        let old = definition(states());
+        let mut new = old.clone();
+        new.metadata
+            .positions
+            .insert(id(1), WorkflowPosition { x: 10.0, y: 20.0 });
+
+        let diff = old.diff(&new);
+
+        assert_eq!(diff.positions.len(), 1);
+        assert!(diff.layout_only);
+    }
+}
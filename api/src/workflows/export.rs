@@ -0,0 +1,668 @@
+//! A portable document of a workflow, for moving it between tenants or
+//! installations.
+//!
+//! Users, templates and sub-workflows are stored outside the definition, so
+//! the document describes the ones it refers to. On import, users are matched
+//! by email and sub-workflows by name in the target tenant, unless a mapping
+//! to ids in the target is given, while templates always need a mapping. The
+//! ids of the states, transitions and other parts of the definition are
+//! replaced with new ones.
+
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tsync::tsync;
+use uuid::Uuid;
+
+use crate::database::{schema::workflows, DbConnection};
+use crate::json_schema;
+use crate::result::{AppError, Result};
+use crate::tenants::Tenant;
+use crate::users::User;
+
+use super::{upgrade, NewWorkflow, Reference, Workflow, WorkflowDefinition};
+
+/// The version of the document format written by this release.
+pub const EXPORT_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct WorkflowExport {
+    /// The version of the document format.
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub name: String,
+    pub description: Option<String>,
+    pub definition: WorkflowDefinition,
+    /// The users the definition refers to.
+    #[serde(default)]
+    pub users: Vec<ExportedUser>,
+    /// The ids of the templates the definition's emails and notifications
+    /// use.
+    #[serde(default)]
+    pub templates: Vec<i64>,
+    /// The workflows the definition invokes as sub-workflows.
+    #[serde(default)]
+    pub workflows: Vec<ExportedWorkflow>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct ExportedUser {
+    pub id: i64,
+    pub email: String,
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct ExportedWorkflow {
+    pub id: i64,
+    pub name: String,
+    /// The end states of the workflow, which sub-workflow outcomes refer to.
+    pub end_states: Vec<ExportedState>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct ExportedState {
+    pub id: Uuid,
+    pub name: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[tsync]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Yaml,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct ImportQuery {
+    /// The tenant to create the workflow in.
+    pub tenant_id: i32,
+    /// The name of the new workflow, the exported name when not set.
+    pub name: Option<String>,
+    /// Whether to only report how the references would be resolved.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Whether to create the workflow even when some references cannot be
+    /// resolved, in which case they keep their exported ids.
+    #[serde(default)]
+    pub allow_unresolved: bool,
+    /// Users of the target tenant to use in place of exported ones, as
+    /// comma separated `exported:target` pairs of ids.
+    pub user_ids: Option<String>,
+    /// Templates to use in place of exported ones, as `exported:target` pairs.
+    pub template_ids: Option<String>,
+    /// Workflows of the target tenant to invoke in place of exported ones, as
+    /// `exported:target` pairs.
+    pub workflow_ids: Option<String>,
+}
+
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, JsonSchema, Ord, PartialEq, PartialOrd, Serialize,
+)]
+#[tsync]
+#[serde(rename_all = "snake_case")]
+pub enum ReferenceKind {
+    User,
+    Template,
+    Workflow,
+    State,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct UnresolvedReference {
+    pub kind: ReferenceKind,
+    /// The id of the reference in the document.
+    pub id: String,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct WorkflowImport {
+    /// The new workflow, not set for dry runs or when references could not be
+    /// resolved.
+    pub workflow: Option<Workflow>,
+    /// The definition with its ids replaced and references resolved.
+    pub definition: WorkflowDefinition,
+    pub unresolved: Vec<UnresolvedReference>,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Yaml => "application/yaml",
+        }
+    }
+
+    /// The format of a document sent with the content type.
+    pub fn from_content_type(content_type: &str) -> ExportFormat {
+        match content_type {
+            "application/yaml" | "application/x-yaml" | "text/yaml" => ExportFormat::Yaml,
+            _ => ExportFormat::Json,
+        }
+    }
+}
+
+impl WorkflowExport {
+    pub fn to_bytes(&self, format: ExportFormat) -> Result<Vec<u8>> {
+        match format {
+            ExportFormat::Json => serde_json::to_vec_pretty(self).map_err(AppError::server_error),
+            ExportFormat::Yaml => serde_yaml::to_string(self)
+                .map(String::into_bytes)
+                .map_err(AppError::server_error),
+        }
+    }
+
+    /// Reads and checks a document, as the CLI does with files.
+    pub fn from_bytes(body: &[u8], format: ExportFormat) -> Result<WorkflowExport> {
+        let document = Self::parse(body, format)?;
+        json_schema::check::<WorkflowExport>(&document)?;
+        let document: WorkflowExport = serde_json::from_value(document)
+            .map_err(|e| AppError::validation_error(format!("Invalid workflow document: {}", e)))?;
+        document.check_version()?;
+
+        Ok(document)
+    }
+
+    /// Reads a document into JSON, to be checked against the schema of
+    /// [`WorkflowExport`]. Documents exported by older releases hold
+    /// definitions in an older shape, so the definition is upgraded first.
+    pub fn parse(body: &[u8], format: ExportFormat) -> Result<serde_json::Value> {
+        let invalid =
+            |e: String| AppError::validation_error(format!("Invalid workflow document: {}", e));
+        let mut document: serde_json::Value = match format {
+            ExportFormat::Json => serde_json::from_slice(body).map_err(|e| e.to_string()),
+            ExportFormat::Yaml => serde_yaml::from_slice(body).map_err(|e| e.to_string()),
+        }
+        .map_err(invalid)?;
+
+        if let Some(definition) = document.get_mut("definition") {
+            upgrade(definition).map_err(invalid)?;
+        }
+
+        Ok(document)
+    }
+
+    /// Checks that the document is written in the format this release reads.
+    pub fn check_version(&self) -> Result<()> {
+        match self.version {
+            EXPORT_VERSION => Ok(()),
+            version => Err(AppError::validation_error(format!(
+                "Unsupported workflow document version {}, expected {}",
+                version, EXPORT_VERSION
+            ))),
+        }
+    }
+}
+
+impl Workflow {
+    /// Builds the portable document of the workflow.
+    pub fn export(conn: &mut DbConnection, id: i64) -> Result<WorkflowExport> {
+        let workflow = Self::find(conn, id)?
+            .ok_or_else(|| AppError::not_found("Workflow", &id.to_string()))?;
+
+        let mut user_ids = BTreeSet::new();
+        let mut template_ids = BTreeSet::new();
+        let mut workflow_ids = BTreeSet::new();
+        workflow
+            .definition
+            .clone()
+            .visit_references(|reference| match reference {
+                Reference::User(id) => {
+                    user_ids.insert(*id);
+                }
+                Reference::Template(id) => {
+                    template_ids.insert(*id);
+                }
+                Reference::Workflow { workflow_id, .. } => {
+                    workflow_ids.insert(*workflow_id);
+                }
+            });
+
+        let mut users = Vec::new();
+        for id in user_ids {
+            if let Some(user) = User::find(conn, id)? {
+                users.push(ExportedUser {
+                    id,
+                    email: user.email,
+                    name: user.name,
+                });
+            }
+        }
+
+        let mut workflows = Vec::new();
+        for id in workflow_ids {
+            if let Some(child) = Self::find(conn, id)? {
+                workflows.push(ExportedWorkflow {
+                    id,
+                    name: child.name,
+                    end_states: child
+                        .definition
+                        .states
+                        .into_iter()
+                        .filter(|s| s.is_end_state)
+                        .map(|s| ExportedState {
+                            id: s.id,
+                            name: s.name,
+                        })
+                        .collect(),
+                });
+            }
+        }
+
+        Ok(WorkflowExport {
+            version: EXPORT_VERSION,
+            exported_at: Utc::now(),
+            name: workflow.name,
+            description: workflow.description,
+            definition: workflow.definition,
+            users,
+            templates: template_ids.into_iter().collect(),
+            workflows,
+        })
+    }
+
+    /// Creates a workflow in the tenant from a portable document. Nothing is
+    /// created for dry runs, or when references cannot be resolved unless
+    /// that is allowed.
+    pub fn import(
+        conn: &mut DbConnection,
+        document: WorkflowExport,
+        query: ImportQuery,
+    ) -> Result<WorkflowImport> {
+        let tenant_id = query.tenant_id;
+        Tenant::find(conn, tenant_id)?
+            .filter(|t| t.deleted_at.is_none())
+            .ok_or_else(|| AppError::not_found("Tenant", &tenant_id.to_string()))?;
+
+        let user_map = parse_ids("user_ids", query.user_ids.as_deref())?;
+        let template_map = parse_ids("template_ids", query.template_ids.as_deref())?;
+        let workflow_map = parse_ids("workflow_ids", query.workflow_ids.as_deref())?;
+
+        let WorkflowExport {
+            name,
+            description,
+            mut definition,
+            users,
+            workflows,
+            ..
+        } = document;
+
+        let mut referenced_users = BTreeSet::new();
+        let mut referenced_workflows = BTreeSet::new();
+        definition
+            .clone()
+            .visit_references(|reference| match reference {
+                Reference::User(id) => {
+                    referenced_users.insert(*id);
+                }
+                Reference::Workflow { workflow_id, .. } => {
+                    referenced_workflows.insert(*workflow_id);
+                }
+                Reference::Template(_) => {}
+            });
+
+        let mut unresolved = Vec::new();
+        let mut unresolved_ids = BTreeSet::new();
+        let mut unresolve = |kind, id: String, reason: String| {
+            if unresolved_ids.insert((kind, id.clone())) {
+                unresolved.push(UnresolvedReference { kind, id, reason });
+            }
+        };
+
+        let mut resolved_users = HashMap::new();
+        for id in referenced_users {
+            match resolve_user(conn, tenant_id, id, &user_map, &users)? {
+                Ok(target) => {
+                    resolved_users.insert(id, target);
+                }
+                Err(reason) => unresolve(ReferenceKind::User, id.to_string(), reason),
+            }
+        }
+
+        let mut resolved_workflows = HashMap::new();
+        for id in referenced_workflows {
+            match resolve_workflow(conn, tenant_id, id, &workflow_map, &workflows)? {
+                Ok(target) => {
+                    resolved_workflows.insert(id, target);
+                }
+                Err(reason) => unresolve(ReferenceKind::Workflow, id.to_string(), reason),
+            }
+        }
+
+        definition.visit_references(|reference| match reference {
+            Reference::User(id) => {
+                if let Some(target) = resolved_users.get(id) {
+                    *id = *target;
+                }
+            }
+            Reference::Template(id) => match template_map.get(id) {
+                Some(target) => *id = *target,
+                None => unresolve(
+                    ReferenceKind::Template,
+                    id.to_string(),
+                    "no template was given in its place".to_string(),
+                ),
+            },
+            Reference::Workflow {
+                workflow_id,
+                outcomes,
+            } => {
+                let Some(target) = resolved_workflows.get(workflow_id) else {
+                    return;
+                };
+                let exported = workflows.iter().find(|w| w.id == *workflow_id);
+
+                for outcome in outcomes.iter_mut() {
+                    let name = exported.and_then(|w| {
+                        w.end_states
+                            .iter()
+                            .find(|s| s.id == outcome.end_state_id)
+                            .map(|s| &s.name)
+                    });
+                    let end_state = name.and_then(|name| {
+                        target
+                            .definition
+                            .states
+                            .iter()
+                            .find(|s| s.is_end_state && &s.name == name)
+                    });
+
+                    match end_state {
+                        Some(end_state) => outcome.end_state_id = end_state.id,
+                        None => unresolve(
+                            ReferenceKind::State,
+                            outcome.end_state_id.to_string(),
+                            format!(
+                                "workflow {} has no end state named {}",
+                                target.name,
+                                name.map_or("like it", String::as_str)
+                            ),
+                        ),
+                    }
+                }
+                *workflow_id = target.id;
+            }
+        });
+
+        let definition = definition.renew_ids();
+
+        if query.dry_run || (!unresolved.is_empty() && !query.allow_unresolved) {
+            return Ok(WorkflowImport {
+                workflow: None,
+                definition,
+                unresolved,
+            });
+        }
+
+        let workflow = Self::create(
+            conn,
+            NewWorkflow {
+                tenant_id,
+                name: query.name.unwrap_or(name),
+                description,
+                definition: definition.clone(),
+            },
+        )?;
+
+        Ok(WorkflowImport {
+            workflow: Some(workflow),
+            definition,
+            unresolved,
+        })
+    }
+}
+
+/// Parses comma separated `from:to` pairs of ids.
+fn parse_ids(name: &str, value: Option<&str>) -> Result<HashMap<i64, i64>> {
+    let Some(value) = value else {
+        return Ok(HashMap::new());
+    };
+
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            pair.split_once(':')
+                .and_then(|(from, to)| Some((from.trim().parse().ok()?, to.trim().parse().ok()?)))
+                .ok_or_else(|| {
+                    AppError::validation_error(format!("Invalid {} pair {}", name, pair))
+                })
+        })
+        .collect()
+}
+
+/// Finds the user of the tenant to use in place of an exported one, or the
+/// reason there is none.
+fn resolve_user(
+    conn: &mut DbConnection,
+    tenant_id: i32,
+    id: i64,
+    user_map: &HashMap<i64, i64>,
+    users: &[ExportedUser],
+) -> Result<std::result::Result<i64, String>> {
+    let active = |user: &User| user.tenant_id == tenant_id && user.deleted_at.is_none();
+
+    if let Some(target) = user_map.get(&id) {
+        return Ok(match User::find(conn, *target)? {
+            Some(user) if active(&user) => Ok(user.id),
+            _ => Err(format!(
+                "user {} is not an active user of the tenant",
+                target
+            )),
+        });
+    }
+
+    let Some(exported) = users.iter().find(|u| u.id == id) else {
+        return Ok(Err("the user is not described in the document".to_string()));
+    };
+
+    Ok(
+        match User::find_by_email_and_tenant(conn, exported.email.clone(), tenant_id)? {
+            Some(user) if active(&user) => Ok(user.id),
+            _ => Err(format!(
+                "the tenant has no active user with email {}",
+                exported.email
+            )),
+        },
+    )
+}
+
+/// Finds the workflow of the tenant to invoke in place of an exported one, or
+/// the reason there is none.
+fn resolve_workflow(
+    conn: &mut DbConnection,
+    tenant_id: i32,
+    id: i64,
+    workflow_map: &HashMap<i64, i64>,
+    workflows: &[ExportedWorkflow],
+) -> Result<std::result::Result<Workflow, String>> {
+    if let Some(target) = workflow_map.get(&id) {
+        return Ok(match Workflow::find(conn, *target)? {
+            Some(w) if w.tenant_id == tenant_id && w.deleted_at.is_none() => Ok(w),
+            _ => Err(format!(
+                "workflow {} is not a workflow of the tenant",
+                target
+            )),
+        });
+    }
+
+    let Some(exported) = workflows.iter().find(|w| w.id == id) else {
+        return Ok(Err(
+            "the workflow is not described in the document".to_string()
+        ));
+    };
+
+    let mut matches: Vec<Workflow> = workflows::table
+        .select(Workflow::as_select())
+        .filter(workflows::tenant_id.eq(tenant_id))
+        .filter(workflows::name.eq(&exported.name))
+        .filter(workflows::deleted_at.is_null())
+        .limit(2)
+        .get_results(conn)?;
+
+    Ok(match matches.len() {
+        1 => Ok(matches.remove(0)),
+        0 => Err(format!(
+            "the tenant has no workflow named {}",
+            exported.name
+        )),
+        _ => Err(format!(
+            "the tenant has more than one workflow named {}",
+            exported.name
+        )),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use serde_json::json;
+
+    use super::super::fixtures::{
+        action, definition, end_state, id, manual, option, parallel, state, transition,
+    };
+    use super::super::WorkflowPosition;
+    use super::*;
+
+    fn document() -> WorkflowExport {
+        let mut split = option(20, "Split", 2);
+        split["data"] = json!([{ "type": "Date", "id": id(40), "label": "Due" }]);
+
+        let mut definition = definition(vec![
+            state(
+                1,
+                "Intake",
+                json!({
+                    "entry_actions": [action(30, json!({
+                        "type": "Http",
+                        "url": "https://example.com/intake",
+                        "failure_state_id": id(6),
+                    }))],
+                    "sla": {
+                        "due_in": 3600,
+                        "escalations": [{
+                            "id": id(31),
+                            "action": { "type": "Transition", "target_state_id": id(6) },
+                        }],
+                    },
+                    "transitions": [manual(10, &[split])],
+                }),
+            ),
+            state(
+                2,
+                "Fork",
+                json!({ "transitions": [parallel(11, &[3, 4], 5)] }),
+            ),
+            state(
+                3,
+                "Legal",
+                json!({ "transitions": [transition(12, json!({
+                    "type": "Approval",
+                    "approver_id": 1,
+                    "approval_option": option(21, "Approve", 5),
+                    "rejection_option": option(22, "Reject", 5),
+                }))] }),
+            ),
+            state(
+                4,
+                "Finance",
+                json!({ "transitions": [transition(13, json!({
+                    "type": "VendorConfirmation",
+                    "target_state_id": id(5),
+                }))] }),
+            ),
+            state(
+                5,
+                "Join",
+                json!({ "transitions": [transition(14, json!({
+                    "type": "SubWorkflow",
+                    "workflow_id": 7,
+                    "options": [option(23, "Done", 6)],
+                    "outcomes": [{ "end_state_id": id(99), "option_id": id(23) }],
+                }))] }),
+            ),
+            end_state(6, "Done"),
+        ]);
+        for n in 1..=6 {
+            let position = WorkflowPosition {
+                x: n as f32,
+                y: 0.0,
+            };
+            definition.metadata.positions.insert(id(n), position);
+        }
+
+        WorkflowExport {
+            version: EXPORT_VERSION,
+            exported_at: Utc::now(),
+            name: "Onboarding".to_string(),
+            description: None,
+            definition,
+            users: vec![],
+            templates: vec![],
+            workflows: vec![],
+        }
+    }
+
+    #[test]
+    fn round_trips_through_both_formats_with_every_reference_resolving() {
+        let exported = document();
+        let original = exported.definition.declared_ids();
+
+        for format in [ExportFormat::Json, ExportFormat::Yaml] {
+            let bytes = exported.to_bytes(format).unwrap();
+            let imported = WorkflowExport::from_bytes(&bytes, format).unwrap();
+            assert_eq!(imported.definition.declared_ids(), original);
+
+            let mut definition = imported.definition.renew_ids();
+            definition.validate().unwrap();
+
+            let declared = definition.declared_ids();
+            assert_eq!(declared.len(), original.len());
+            assert!(declared.is_disjoint(&original));
+
+            let mut referenced = HashSet::new();
+            definition.visit_ids(|id| {
+                referenced.insert(*id);
+            });
+            assert_eq!(referenced, declared);
+
+            // Outcomes keep the ids of the invoked workflow's end states
+            let (_, _, outcomes) = definition.states[4].sub_workflow().unwrap();
+            assert_eq!(outcomes[0].end_state_id, id(99));
+        }
+    }
+
+    #[test]
+    fn rejects_documents_of_other_versions() {
+        let mut exported = document();
+        exported.version = EXPORT_VERSION + 1;
+        let bytes = exported.to_bytes(ExportFormat::Json).unwrap();
+
+        assert!(WorkflowExport::from_bytes(&bytes, ExportFormat::Json)
+            .unwrap_err()
+            .to_string()
+            .contains("Unsupported workflow document version"));
+    }
+}
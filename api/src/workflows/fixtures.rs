@@ -0,0 +1,100 @@
+//! Definitions for the unit tests of the workflow modules. States and
+//! transitions are written as JSON and numbered, so that a test only spells
+//! out the parts of the definition it is about.
+
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::WorkflowDefinition;
+
+/// The id of the state, transition, action or option numbered `n`.
+pub fn id(n: u128) -> Uuid {
+    Uuid::from_u128(n)
+}
+
+/// A state without actions or transitions, with the given fields set on top.
+pub fn state(n: u128, name: &str, fields: Value) -> Value {
+    let mut state = json!({
+        "id": id(n),
+        "name": name,
+        "description": null,
+        "is_end_state": false,
+        "entry_actions": [],
+        "exit_actions": [],
+        "transitions": [],
+    });
+
+    if let (Some(state), Value::Object(fields)) = (state.as_object_mut(), fields) {
+        state.extend(fields);
+    }
+
+    state
+}
+
+pub fn end_state(n: u128, name: &str) -> Value {
+    state(n, name, json!({ "is_end_state": true }))
+}
+
+pub fn transition(n: u128, definition: Value) -> Value {
+    json!({
+        "id": id(n),
+        "name": format!("Transition {}", n),
+        "description": null,
+        "definition": definition,
+    })
+}
+
+pub fn automatic(n: u128, target: u128) -> Value {
+    transition(
+        n,
+        json!({ "type": "Automatic", "target_state_id": id(target) }),
+    )
+}
+
+pub fn option(n: u128, label: &str, target: u128) -> Value {
+    json!({
+        "id": id(n),
+        "label": label,
+        "target_state_id": id(target),
+        "comment_required": false,
+        "data": [],
+    })
+}
+
+/// A manual transition offering each of the options.
+pub fn manual(n: u128, options: &[Value]) -> Value {
+    transition(n, json!({ "type": "Manual", "options": options }))
+}
+
+pub fn parallel(n: u128, branches: &[u128], join: u128) -> Value {
+    let branches: Vec<Uuid> = branches.iter().map(|b| id(*b)).collect();
+
+    transition(
+        n,
+        json!({
+            "type": "Parallel",
+            "branch_state_ids": branches,
+            "join_state_id": id(join),
+        }),
+    )
+}
+
+pub fn action(n: u128, definition: Value) -> Value {
+    json!({
+        "id": id(n),
+        "name": format!("Action {}", n),
+        "description": null,
+        "definition": definition,
+    })
+}
+
+/// A definition of the states, starting in the first of them.
+pub fn definition(states: Vec<Value>) -> WorkflowDefinition {
+    let definition = json!({
+        "initial_state": states.first().map(|s| s["id"].clone()),
+        "states": states,
+        "metadata": { "positions": {} },
+    });
+
+    serde_json::from_value(definition).unwrap_or_else(|e| panic!("invalid definition: {}", e))
+}
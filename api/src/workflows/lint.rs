@@ -0,0 +1,586 @@
+//! Warnings about definitions that are valid but probably not what the
+//! designer meant, such as states an instance can never leave.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use diesel::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tsync::tsync;
+use uuid::Uuid;
+
+use crate::database::{schema::users, DbConnection};
+use crate::result::Result;
+use crate::users::User;
+
+use super::{
+    ActionDefinition, EscalationAction, NotifyTarget, TransitionDefinition, TransitionOption,
+    Workflow, WorkflowDefinition, WorkflowState, WorkflowTransition,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[tsync]
+#[serde(rename_all = "snake_case")]
+pub enum LintRule {
+    /// A state that is not an end state but has no transitions.
+    DeadEnd,
+    /// An end state with transitions, which are never taken.
+    EndStateTransitions,
+    /// An approval whose approver is deleted, missing or in another tenant.
+    InvalidApprover,
+    /// An assignment or notification to a user that is deleted, missing or in
+    /// another tenant.
+    MissingAssignee,
+    /// Options of the same transition with the same label.
+    DuplicateOptionLabels,
+    /// States that lead to each other but never to an end state.
+    InescapableCycle,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[tsync]
+pub struct LintWarning {
+    pub rule: LintRule,
+    pub message: String,
+    pub state_id: Option<Uuid>,
+    pub transition_id: Option<Uuid>,
+}
+
+impl LintWarning {
+    fn new(rule: LintRule, state: &WorkflowState, message: String) -> Self {
+        LintWarning {
+            rule,
+            message,
+            state_id: Some(state.id),
+            transition_id: None,
+        }
+    }
+
+    fn transition(mut self, transition: &WorkflowTransition) -> Self {
+        self.transition_id = Some(transition.id);
+        self
+    }
+}
+
+impl Workflow {
+    /// Lints the workflow's definition, checking the users it refers to
+    /// against the workflow's tenant.
+    pub fn lint(&self, conn: &mut DbConnection) -> Result<Vec<LintWarning>> {
+        let definition = &self.definition;
+        let mut warnings = definition.lint();
+
+        let mut ids = HashSet::new();
+        for (_, _, user_id) in definition.approvers() {
+            ids.insert(user_id);
+        }
+        for (_, user_id) in definition.assignees() {
+            ids.insert(user_id);
+        }
+        let users: HashMap<i64, User> = users::table
+            .select(User::as_select())
+            .filter(users::id.eq_any(ids))
+            .load(conn)?
+            .into_iter()
+            .map(|u| (u.id, u))
+            .collect();
+
+        warnings.extend(definition.lint_users(self.tenant_id, &users));
+        Ok(warnings)
+    }
+}
+
+impl WorkflowDefinition {
+    /// Lints the definition on its own, without the checks that need the
+    /// database.
+    pub fn lint(&self) -> Vec<LintWarning> {
+        let mut warnings = Vec::new();
+
+        for state in &self.states {
+            if state.is_end_state && !state.transitions.is_empty() {
+                warnings.push(LintWarning::new(
+                    LintRule::EndStateTransitions,
+                    state,
+                    format!(
+                        "end state {} has transitions, which are never taken",
+                        state.name
+                    ),
+                ));
+            }
+
+            if !state.is_end_state && state.transitions.is_empty() {
+                warnings.push(LintWarning::new(
+                    LintRule::DeadEnd,
+                    state,
+                    format!(
+                        "state {} has no transitions and is not an end state",
+                        state.name
+                    ),
+                ));
+            }
+
+            for transition in &state.transitions {
+                let options: Vec<&TransitionOption> = match &transition.definition {
+                    TransitionDefinition::Approval {
+                        approval_option,
+                        rejection_option,
+                        ..
+                    } => vec![approval_option, rejection_option],
+                    TransitionDefinition::Manual { options }
+                    | TransitionDefinition::SubWorkflow { options, .. } => options.iter().collect(),
+                    _ => vec![],
+                };
+
+                let mut labels = HashSet::new();
+                let mut duplicates = Vec::new();
+                for option in options {
+                    let label = option.label.trim();
+                    if !labels.insert(label.to_lowercase()) && !duplicates.contains(&label) {
+                        duplicates.push(label);
+                    }
+                }
+
+                for label in duplicates {
+                    warnings.push(
+                        LintWarning::new(
+                            LintRule::DuplicateOptionLabels,
+                            state,
+                            format!(
+                                "transition {} of state {} has more than one option labelled \"{}\"",
+                                transition.name, state.name, label
+                            ),
+                        )
+                        .transition(transition),
+                    );
+                }
+            }
+        }
+
+        warnings.extend(self.inescapable_cycles());
+        warnings
+    }
+
+    /// Checks the approvers and assignees against the users loaded for them,
+    /// any of which may be missing.
+    fn lint_users(&self, tenant_id: i32, users: &HashMap<i64, User>) -> Vec<LintWarning> {
+        let mut warnings = Vec::new();
+
+        let problem = |user_id: i64| match users.get(&user_id) {
+            None => Some("does not exist"),
+            Some(user) if user.deleted_at.is_some() => Some("is deleted"),
+            Some(user) if user.tenant_id != tenant_id => Some("belongs to another tenant"),
+            Some(_) => None,
+        };
+
+        for (state, transition, user_id) in self.approvers() {
+            if let Some(problem) = problem(user_id) {
+                warnings.push(
+                    LintWarning::new(
+                        LintRule::InvalidApprover,
+                        state,
+                        format!(
+                            "approver {} of transition {} of state {} {}",
+                            user_id, transition.name, state.name, problem
+                        ),
+                    )
+                    .transition(transition),
+                );
+            }
+        }
+
+        for (state, user_id) in self.assignees() {
+            if let Some(problem) = problem(user_id) {
+                warnings.push(LintWarning::new(
+                    LintRule::MissingAssignee,
+                    state,
+                    format!(
+                        "state {} assigns or notifies user {}, who {}",
+                        state.name, user_id, problem
+                    ),
+                ));
+            }
+        }
+
+        warnings
+    }
+
+    /// Returns the groups of states that can reach each other but no end
+    /// state, so an instance that enters one can never complete.
+    fn inescapable_cycles(&self) -> Vec<LintWarning> {
+        let successors: HashMap<Uuid, Vec<Uuid>> = self
+            .states
+            .iter()
+            .map(|state| (state.id, successors(state)))
+            .collect();
+        let reachable: HashMap<Uuid, HashSet<Uuid>> = self
+            .states
+            .iter()
+            .map(|state| (state.id, reachable(&successors, state.id)))
+            .collect();
+
+        let mut reported = HashSet::new();
+        let mut warnings = Vec::new();
+
+        for state in &self.states {
+            let reach = &reachable[&state.id];
+            let completes = reach
+                .iter()
+                .any(|id| self.state(*id).is_some_and(|s| s.is_end_state));
+
+            if completes || !reach.contains(&state.id) || reported.contains(&state.id) {
+                continue;
+            }
+
+            let cycle: Vec<&WorkflowState> = self
+                .states
+                .iter()
+                .filter(|s| reach.contains(&s.id) && reachable[&s.id].contains(&state.id))
+                .collect();
+            reported.extend(cycle.iter().map(|s| s.id));
+
+            warnings.push(LintWarning::new(
+                LintRule::InescapableCycle,
+                state,
+                format!(
+                    "states {} lead to each other but never to an end state",
+                    cycle
+                        .iter()
+                        .map(|s| s.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            ));
+        }
+
+        warnings
+    }
+
+    fn approvers(&self) -> Vec<(&WorkflowState, &WorkflowTransition, i64)> {
+        self.states
+            .iter()
+            .flat_map(|state| {
+                state.transitions.iter().filter_map(move |transition| {
+                    match &transition.definition {
+                        TransitionDefinition::Approval { approver_id, .. } => {
+                            Some((state, transition, *approver_id))
+                        }
+                        _ => None,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// The users the state's actions and escalations assign instances to or
+    /// notify.
+    fn assignees(&self) -> Vec<(&WorkflowState, i64)> {
+        self.states
+            .iter()
+            .flat_map(|state| {
+                let actions = state
+                    .entry_actions
+                    .iter()
+                    .chain(&state.exit_actions)
+                    .flat_map(|action| match &action.definition {
+                        ActionDefinition::AssignTo { user_id } => vec![*user_id],
+                        ActionDefinition::AutoAssign { user_ids, .. } => user_ids.clone(),
+                        ActionDefinition::Notify {
+                            target: NotifyTarget::User { id },
+                            ..
+                        } => vec![*id],
+                        _ => vec![],
+                    });
+                let escalations = state
+                    .sla
+                    .iter()
+                    .flat_map(|sla| &sla.escalations)
+                    .filter_map(|escalation| match &escalation.action {
+                        EscalationAction::Reassign { user_id } => Some(*user_id),
+                        EscalationAction::Notify {
+                            target: NotifyTarget::User { id },
+                            ..
+                        } => Some(*id),
+                        _ => None,
+                    });
+
+                actions
+                    .chain(escalations)
+                    .map(move |user_id| (state, user_id))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+/// The states an instance can move to from the state, including through the
+/// join of a fork, failing HTTP actions and escalations.
+fn successors(state: &WorkflowState) -> Vec<Uuid> {
+    let mut ids: Vec<Uuid> = state
+        .transitions
+        .iter()
+        .flat_map(|t| t.definition.targets())
+        .collect();
+
+    if let Some((_, join_state_id)) = state.fork() {
+        ids.push(join_state_id);
+    }
+
+    for action in state.entry_actions.iter().chain(&state.exit_actions) {
+        if let ActionDefinition::Http {
+            failure_state_id: Some(id),
+            ..
+        } = &action.definition
+        {
+            ids.push(*id);
+        }
+    }
+
+    if let Some(sla) = &state.sla {
+        for escalation in &sla.escalations {
+            if let EscalationAction::Transition { target_state_id } = &escalation.action {
+                ids.push(*target_state_id);
+            }
+        }
+    }
+
+    ids
+}
+
+/// The states reachable from the state in one or more moves.
+fn reachable(successors: &HashMap<Uuid, Vec<Uuid>>, from: Uuid) -> HashSet<Uuid> {
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([from]);
+
+    while let Some(id) = queue.pop_front() {
+        for next in successors.get(&id).into_iter().flatten() {
+            if seen.insert(*next) {
+                queue.push_back(*next);
+            }
+        }
+    }
+
+    seen
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::super::fixtures::{
+        action, automatic, definition, end_state, id, manual, option, state, transition,
+    };
+    use super::*;
+
+    fn rules(warnings: &[LintWarning]) -> Vec<LintRule> {
+        warnings.iter().map(|w| w.rule).collect()
+    }
+
+    fn user(id: i64, tenant_id: i32, deleted: bool) -> (i64, User) {
+        let now = chrono::Utc::now();
+        let user = User {
+            id,
+            tenant_id,
+            email: format!("user{}@example.com", id),
+            password: String::new(),
+            name: None,
+            created_at: now,
+            updated_at: now,
+            deleted_at: deleted.then_some(now),
+        };
+        (id, user)
+    }
+
+    /// Users 1 and 2 of tenant 1, a deleted user 3 and user 4 of tenant 2.
+    fn users() -> HashMap<i64, User> {
+        HashMap::from([
+            user(1, 1, false),
+            user(2, 1, false),
+            user(3, 1, true),
+            user(4, 2, false),
+        ])
+    }
+
+    fn approval(n: u128, approver_id: i64) -> Value {
+        transition(
+            n,
+            json!({
+                "type": "Approval",
+                "approver_id": approver_id,
+                "approval_option": option(n + 1, "Approve", 2),
+                "rejection_option": option(n + 2, "Reject", 2),
+            }),
+        )
+    }
+
+    fn reviewed_by(fields: Value) -> WorkflowDefinition {
+        definition(vec![state(1, "Review", fields), end_state(2, "Done")])
+    }
+
+    #[test]
+    fn warns_about_dead_ends() {
+        let warnings = definition(vec![
+            state(1, "Draft", json!({ "transitions": [automatic(10, 2)] })),
+            state(2, "Stuck", json!({})),
+        ])
+        .lint();
+
+        assert_eq!(rules(&warnings), vec![LintRule::DeadEnd]);
+        assert_eq!(warnings[0].state_id, Some(id(2)));
+    }
+
+    #[test]
+    fn accepts_states_that_lead_to_an_end_state() {
+        let warnings = definition(vec![
+            state(1, "Draft", json!({ "transitions": [automatic(10, 2)] })),
+            end_state(2, "Done"),
+        ])
+        .lint();
+
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn warns_about_end_states_with_transitions() {
+        let warnings = definition(vec![
+            state(1, "Draft", json!({ "transitions": [automatic(10, 2)] })),
+            state(
+                2,
+                "Done",
+                json!({ "is_end_state": true, "transitions": [automatic(11, 1)] }),
+            ),
+        ])
+        .lint();
+
+        assert_eq!(rules(&warnings), vec![LintRule::EndStateTransitions]);
+        assert_eq!(warnings[0].state_id, Some(id(2)));
+    }
+
+    #[test]
+    fn warns_about_approvers_that_cannot_approve() {
+        let lint = |approver_id| {
+            reviewed_by(json!({ "transitions": [approval(10, approver_id)] }))
+                .lint_users(1, &users())
+        };
+
+        for approver_id in [3, 4, 5] {
+            let warnings = lint(approver_id);
+            assert_eq!(rules(&warnings), vec![LintRule::InvalidApprover]);
+            assert_eq!(warnings[0].transition_id, Some(id(10)));
+        }
+        assert!(lint(1).is_empty());
+    }
+
+    #[test]
+    fn warns_about_missing_assignees() {
+        let warnings = reviewed_by(json!({
+            "transitions": [automatic(10, 2)],
+            "entry_actions": [
+                action(20, json!({ "type": "AssignTo", "user_id": 3 })),
+                action(21, json!({ "type": "AutoAssign", "user_ids": [1, 4] })),
+            ],
+            "exit_actions": [
+                action(22, json!({
+                    "type": "Notify",
+                    "template_id": 1,
+                    "target": { "type": "User", "id": 5 },
+                })),
+            ],
+        }))
+        .lint_users(1, &users());
+
+        assert_eq!(rules(&warnings), vec![LintRule::MissingAssignee; 3]);
+        let messages: Vec<&str> = warnings.iter().map(|w| w.message.as_str()).collect();
+        assert!(messages[0].contains("user 3, who is deleted"));
+        assert!(messages[1].contains("user 4, who belongs to another tenant"));
+        assert!(messages[2].contains("user 5, who does not exist"));
+    }
+
+    #[test]
+    fn accepts_assignees_of_the_tenant() {
+        let warnings = reviewed_by(json!({
+            "transitions": [automatic(10, 2)],
+            "entry_actions": [
+                action(20, json!({ "type": "AssignTo", "user_id": 1 })),
+                action(21, json!({ "type": "AutoAssign", "user_ids": [1, 2] })),
+                action(22, json!({
+                    "type": "Notify",
+                    "template_id": 1,
+                    "target": { "type": "User", "id": 2 },
+                })),
+            ],
+            "sla": {
+                "due_in": 3600,
+                "escalations": [
+                    { "id": id(30), "action": { "type": "Reassign", "user_id": 2 } },
+                ],
+            },
+        }))
+        .lint_users(1, &users());
+
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn warns_about_duplicate_option_labels() {
+        let warnings = reviewed_by(json!({
+            "transitions": [manual(10, &[
+                option(11, "Approve", 2),
+                option(12, " approve ", 2),
+                option(13, "Reject", 2),
+            ])],
+        }))
+        .lint();
+
+        assert_eq!(rules(&warnings), vec![LintRule::DuplicateOptionLabels]);
+        assert_eq!(warnings[0].transition_id, Some(id(10)));
+    }
+
+    #[test]
+    fn accepts_distinct_option_labels() {
+        let warnings = reviewed_by(json!({
+            "transitions": [manual(10, &[option(11, "Approve", 2), option(12, "Reject", 2)])],
+        }))
+        .lint();
+
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn warns_about_inescapable_cycles_once() {
+        let warnings = definition(vec![
+            state(
+                1,
+                "Draft",
+                json!({ "transitions": [manual(10, &[
+                option(11, "Submit", 2),
+                option(12, "Finish", 4),
+            ])] }),
+            ),
+            state(2, "Review", json!({ "transitions": [automatic(20, 3)] })),
+            state(3, "Rework", json!({ "transitions": [automatic(30, 2)] })),
+            end_state(4, "Done"),
+        ])
+        .lint();
+
+        assert_eq!(rules(&warnings), vec![LintRule::InescapableCycle]);
+        assert!(warnings[0].message.contains("Review, Rework"));
+    }
+
+    #[test]
+    fn accepts_cycles_with_a_way_out() {
+        let warnings = definition(vec![
+            state(
+                1,
+                "Review",
+                json!({ "transitions": [manual(10, &[
+                option(11, "Rework", 2),
+                option(12, "Approve", 3),
+            ])] }),
+            ),
+            state(2, "Rework", json!({ "transitions": [automatic(20, 1)] })),
+            end_state(3, "Done"),
+        ])
+        .lint();
+
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+}
@@ -0,0 +1,661 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use chrono::{DateTime, Utc};
+use diesel::deserialize::{FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::PgValue;
+use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Jsonb;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tsync::tsync;
+use uuid::Uuid;
+
+use crate::data_schema::DataSchema;
+use crate::database::{schema::workflows, DbConnection, DB};
+use crate::defaults::{default_bool, default_i64, default_u32};
+use crate::filters::{self, BoxedCondition, Condition, Filterable};
+use crate::pagination::{self, Page, SortDirection};
+use crate::result::AppError;
+use crate::search::{self, Language};
+use crate::webhooks::{self, WebhookEvent};
+use crate::{filter_condition, sort_by};
+
+mod diff;
+mod export;
+#[cfg(test)]
+pub(crate) mod fixtures;
+mod lint;
+mod references;
+mod render;
+mod templates;
+mod upgrade;
+mod validation;
+mod versions;
+
+pub use diff::{
+    ActionChange, DefinitionDiff, InitialStateChange, PositionChange, StateChange, TransitionChange,
+};
+pub use export::{
+    ExportFormat, ExportQuery, ExportedState, ExportedUser, ExportedWorkflow, ImportQuery,
+    ReferenceKind, UnresolvedReference, WorkflowExport, WorkflowImport, EXPORT_VERSION,
+};
+pub use lint::{LintRule, LintWarning};
+pub use references::Reference;
+pub use render::{GraphFormat, RenderQuery};
+pub use templates::{
+    CloneWorkflow, InstantiateTemplate, NewWorkflowTemplate, TemplateQuery, TemplateSort,
+    UpdateWorkflowTemplate, WorkflowTemplate,
+};
+pub use upgrade::{upgrade, upgrade_stored, TableUpgrade, SCHEMA_VERSION};
+pub use versions::{VersionDiffQuery, WorkflowVersion};
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct WorkflowPosition {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Clone, Deserialize, Insertable, JsonSchema, Serialize)]
+#[diesel(table_name = workflows)]
+#[tsync]
+pub struct NewWorkflow {
+    pub tenant_id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub definition: WorkflowDefinition,
+}
+
+#[derive(AsChangeset, Clone, Deserialize, JsonSchema, Serialize)]
+#[diesel(table_name = workflows)]
+#[tsync]
+pub struct UpdateWorkflow {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub definition: Option<WorkflowDefinition>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+#[serde(rename_all = "snake_case")]
+pub enum WorkflowSort {
+    #[default]
+    Id,
+    Name,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Clone, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct WorkflowQuery {
+    pub tenant_id: Option<i32>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Search text, matched against the name and description.
+    pub q: Option<String>,
+    /// Conditions on the fields, see [`crate::filters`].
+    pub filter: Option<String>,
+    #[serde(default = "default_bool::<true>")]
+    pub active: bool,
+    #[serde(default)]
+    pub sort: WorkflowSort,
+    #[serde(default)]
+    pub direction: SortDirection,
+    /// The cursor of the page to fetch, returned with the page before it.
+    /// When set, `page` is ignored.
+    pub cursor: Option<String>,
+    /// Whether to count the matching workflows.
+    #[serde(default = "default_bool::<true>")]
+    pub count: bool,
+    #[serde(default = "default_i64::<1>")]
+    pub page: i64,
+    #[serde(default = "default_i64::<10>", alias = "per_page")]
+    pub page_size: i64,
+}
+
+impl Workflow {
+    pub fn create(
+        conn: &mut DbConnection,
+        new_workflow: NewWorkflow,
+    ) -> Result<Workflow, AppError> {
+        new_workflow.definition.validate()?;
+
+        conn.transaction(|conn| {
+            new_workflow
+                .definition
+                .validate_sub_workflows(conn, new_workflow.tenant_id, None)?;
+
+            let res: Workflow = diesel::insert_into(workflows::table)
+                .values(new_workflow)
+                .returning(Workflow::as_returning())
+                .get_result(conn)?;
+            WorkflowVersion::record(conn, &res)?;
+
+            webhooks::publish(
+                conn,
+                res.tenant_id,
+                WebhookEvent::WorkflowCreated,
+                json!({ "workflow": res }),
+            )?;
+
+            Ok(res)
+        })
+    }
+
+    pub fn update(
+        conn: &mut DbConnection,
+        id: i64,
+        update_workflow: UpdateWorkflow,
+    ) -> Result<Workflow, AppError> {
+        conn.transaction(|conn| {
+            let redefined = update_workflow.definition.is_some();
+            if let Some(definition) = &update_workflow.definition {
+                definition.validate()?;
+
+                let workflow = Self::find(conn, id)?
+                    .ok_or_else(|| AppError::not_found("Workflow", &id.to_string()))?;
+                definition.validate_sub_workflows(conn, workflow.tenant_id, Some(id))?;
+            }
+
+            let res: Workflow = diesel::update(workflows::table)
+                .filter(workflows::id.eq(id))
+                .set(update_workflow)
+                .returning(Workflow::as_returning())
+                .get_result(conn)?;
+            if redefined {
+                res.validate_invokers(conn)?;
+            }
+            WorkflowVersion::record(conn, &res)?;
+
+            webhooks::publish(
+                conn,
+                res.tenant_id,
+                WebhookEvent::WorkflowUpdated,
+                json!({ "workflow": res }),
+            )?;
+
+            Ok(res)
+        })
+    }
+
+    pub fn find(conn: &mut DbConnection, id: i64) -> Result<Option<Workflow>, AppError> {
+        let res = workflows::table
+            .select(Workflow::as_select())
+            .filter(workflows::id.eq(id))
+            .get_result(conn)
+            .optional()?;
+
+        Ok(res)
+    }
+
+    /// Finds the workflow and locks it until the end of the transaction.
+    pub fn lock(conn: &mut DbConnection, id: i64) -> Result<Option<Workflow>, AppError> {
+        Ok(workflows::table
+            .select(Workflow::as_select())
+            .filter(workflows::id.eq(id))
+            .for_update()
+            .get_result(conn)
+            .optional()?)
+    }
+
+    pub fn count(conn: &mut DbConnection, query: WorkflowQuery) -> Result<i64, AppError> {
+        let res = Self::filtered(&query)?.count().get_result(conn)?;
+
+        Ok(res)
+    }
+
+    pub fn list(conn: &mut DbConnection, query: WorkflowQuery) -> Result<Page<Workflow>, AppError> {
+        let mut q = Self::filtered(&query)?.select(Workflow::as_select());
+
+        let WorkflowQuery {
+            sort,
+            direction,
+            page,
+            page_size,
+            ..
+        } = query;
+        let cursor = query.cursor.as_deref();
+        q = match sort {
+            WorkflowSort::Id => sort_by!(
+                q,
+                workflows::id,
+                workflows::id as i64,
+                direction,
+                pagination::start::<i64>(cursor, sort, direction, page, page_size)?
+            ),
+            WorkflowSort::Name => sort_by!(
+                q,
+                workflows::name,
+                workflows::id as i64,
+                direction,
+                pagination::start::<String>(cursor, sort, direction, page, page_size)?
+            ),
+            WorkflowSort::CreatedAt => sort_by!(
+                q,
+                workflows::created_at,
+                workflows::id as i64,
+                direction,
+                pagination::start::<DateTime<Utc>>(cursor, sort, direction, page, page_size)?
+            ),
+            WorkflowSort::UpdatedAt => sort_by!(
+                q,
+                workflows::updated_at,
+                workflows::id as i64,
+                direction,
+                pagination::start::<DateTime<Utc>>(cursor, sort, direction, page, page_size)?
+            ),
+        };
+
+        let rows = q.limit(page_size + 1).get_results(conn)?;
+
+        Ok(pagination::page(rows, page_size, sort, direction, |w| {
+            (w.sort_key(sort), w.id)
+        }))
+    }
+
+    /// The workflows matching the query, which both listing and counting them
+    /// start from.
+    fn filtered(query: &WorkflowQuery) -> Result<workflows::BoxedQuery<'static, DB>, AppError> {
+        let mut q = workflows::table.into_boxed::<DB>();
+
+        if let Some(tenant_id) = query.tenant_id {
+            q = q.filter(workflows::tenant_id.eq(tenant_id));
+        }
+
+        if let Some(name) = &query.name {
+            q = q.filter(workflows::name.eq(name.clone()));
+        }
+
+        if let Some(description) = &query.description {
+            q = q.filter(workflows::description.eq(description.clone()));
+        }
+
+        if let Some(text) = &query.q {
+            q = q.filter(search::matches(
+                workflows::search_vector,
+                Language::English,
+                text,
+            ));
+        }
+
+        if let Some(condition) = filters::condition(query.filter.as_deref())? {
+            q = q.filter(condition);
+        }
+
+        q = match query.active {
+            true => q.filter(workflows::deleted_at.is_null()),
+            false => q.filter(workflows::deleted_at.is_not_null()),
+        };
+
+        Ok(q)
+    }
+
+    /// The value of the sort field, stored in cursors.
+    fn sort_key(&self, sort: WorkflowSort) -> Value {
+        match sort {
+            WorkflowSort::Id => json!(self.id),
+            WorkflowSort::Name => json!(self.name),
+            WorkflowSort::CreatedAt => json!(self.created_at),
+            WorkflowSort::UpdatedAt => json!(self.updated_at),
+        }
+    }
+}
+
+impl Filterable for workflows::table {
+    fn condition(condition: &Condition) -> Result<BoxedCondition<Self>, AppError> {
+        match condition.field.as_str() {
+            "id" => filter_condition!(condition, workflows::id, i64),
+            "tenant_id" => filter_condition!(condition, workflows::tenant_id, i32),
+            "name" => filter_condition!(condition, workflows::name, like),
+            "description" => {
+                filter_condition!(condition, workflows::description.assume_not_null(), like)
+            }
+            "created_at" => filter_condition!(condition, workflows::created_at, DateTime<Utc>),
+            "updated_at" => filter_condition!(condition, workflows::updated_at, DateTime<Utc>),
+            _ => Err(condition.unknown_field()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Queryable, Identifiable, Selectable, Serialize)]
+#[tsync]
+#[diesel(table_name = workflows)]
+pub struct Workflow {
+    pub id: i64,
+    pub tenant_id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub definition: WorkflowDefinition,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl FromSql<Jsonb, DB> for WorkflowDefinition {
+    fn from_sql(bytes: PgValue) -> diesel::deserialize::Result<Self> {
+        let bytes = bytes.as_bytes();
+
+        if bytes[0] != 1 {
+            return Err("Unsupported JSONB version".into());
+        }
+        let value = serde_json::from_slice(&bytes[1..])?;
+        WorkflowDefinition::from_json(value).map_err(Into::into)
+    }
+}
+
+impl ToSql<Jsonb, DB> for WorkflowDefinition {
+    fn to_sql(&self, out: &mut Output<DB>) -> serialize::Result {
+        out.write_all(&[1])?;
+        serde_json::to_writer(out, self)
+            .map(|_| IsNull::No)
+            .map_err(Into::into)
+    }
+}
+
+#[derive(AsExpression, Clone, Debug, Deserialize, FromSqlRow, JsonSchema, Serialize)]
+#[tsync]
+#[diesel(sql_type = Jsonb)]
+pub struct WorkflowDefinition {
+    /// The version of the shape the definition is written in, see
+    /// [`SCHEMA_VERSION`]. Definitions sent without one are taken to be in
+    /// the current shape.
+    #[serde(default = "default_u32::<SCHEMA_VERSION>")]
+    pub schema_version: u32,
+    pub initial_state: Uuid,
+    pub states: Vec<WorkflowState>,
+    pub metadata: WorkflowMetadata,
+    #[serde(default)]
+    pub data_schema: Option<DataSchema>,
+}
+
+impl WorkflowDefinition {
+    pub fn state(&self, id: Uuid) -> Option<&WorkflowState> {
+        self.states.iter().find(|s| s.id == id)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct WorkflowMetadata {
+    pub positions: HashMap<Uuid, WorkflowPosition>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct WorkflowState {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub is_end_state: bool,
+    pub entry_actions: Vec<WorkflowAction>,
+    pub exit_actions: Vec<WorkflowAction>,
+    pub transitions: Vec<WorkflowTransition>,
+    #[serde(default)]
+    pub sla: Option<StateSla>,
+}
+
+impl WorkflowState {
+    /// Returns the branches and join state when the state forks.
+    pub fn fork(&self) -> Option<(&[Uuid], Uuid)> {
+        self.transitions.iter().find_map(|t| match &t.definition {
+            TransitionDefinition::Parallel {
+                branch_state_ids,
+                join_state_id,
+            } => Some((branch_state_ids.as_slice(), *join_state_id)),
+            _ => None,
+        })
+    }
+
+    /// Returns the invoked workflow, options and outcome mapping when the
+    /// state starts a sub-workflow.
+    pub fn sub_workflow(&self) -> Option<(i64, &[TransitionOption], &[SubWorkflowOutcome])> {
+        self.transitions.iter().find_map(|t| match &t.definition {
+            TransitionDefinition::SubWorkflow {
+                workflow_id,
+                options,
+                outcomes,
+            } => Some((*workflow_id, options.as_slice(), outcomes.as_slice())),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct StateSla {
+    /// The number of seconds an instance may stay in the state before it is
+    /// overdue.
+    pub due_in: i64,
+    #[serde(default)]
+    pub escalations: Vec<StateEscalation>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct StateEscalation {
+    pub id: Uuid,
+    /// The number of seconds past the due date before the escalation fires.
+    #[serde(default)]
+    pub after: i64,
+    pub action: EscalationAction,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+#[serde(tag = "type")]
+pub enum EscalationAction {
+    Notify {
+        template_id: i64,
+        target: NotifyTarget,
+    },
+    Reassign {
+        user_id: i64,
+    },
+    Transition {
+        target_state_id: Uuid,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct WorkflowAction {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub definition: ActionDefinition,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+#[serde(tag = "type")]
+pub enum ActionDefinition {
+    AutoAssign {
+        #[serde(default)]
+        strategy: AutoAssignStrategy,
+        /// The users eligible for assignment. When empty, every active user in
+        /// the instance's tenant is a candidate.
+        #[serde(default)]
+        user_ids: Vec<i64>,
+    },
+    AssignTo {
+        user_id: i64,
+    },
+    Email {
+        template_id: i64,
+        email: String,
+    },
+    Notify {
+        template_id: i64,
+        target: NotifyTarget,
+    },
+    /// Calls an external endpoint. The URL, header values and body are
+    /// templates rendered against the instance data, and the host must be one
+    /// the tenant allows.
+    Http {
+        #[serde(default)]
+        method: HttpMethod,
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        body: Option<serde_json::Value>,
+        /// The key of the instance data the status and body of the response
+        /// are stored under.
+        result_key: Option<String>,
+        /// The state the instance moves to when the call fails. Without it, a
+        /// failed call fails the transition that ran the action.
+        failure_state_id: Option<Uuid>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[tsync]
+pub enum HttpMethod {
+    Get,
+    #[default]
+    Post,
+    Put,
+    Patch,
+    Delete,
+}
+
+impl HttpMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpMethod::Get => "GET",
+            HttpMethod::Post => "POST",
+            HttpMethod::Put => "PUT",
+            HttpMethod::Patch => "PATCH",
+            HttpMethod::Delete => "DELETE",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[tsync]
+pub enum AutoAssignStrategy {
+    /// Assign to the candidate who has gone the longest without receiving a
+    /// task from this workflow.
+    #[default]
+    RoundRobin,
+    /// Assign to the candidate with the fewest open instances in the tenant.
+    LeastLoaded,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+#[serde(tag = "type")]
+pub enum NotifyTarget {
+    Creator,
+    User { id: i64 },
+    Vendor,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct WorkflowTransition {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub definition: TransitionDefinition,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+#[serde(tag = "type")]
+pub enum TransitionDefinition {
+    /// Followed as soon as the state is entered. When a state has several
+    /// automatic transitions they are tried in order, and the first one
+    /// without a guard or whose guard holds for the instance data is taken.
+    Automatic {
+        target_state_id: Uuid,
+        #[serde(default)]
+        guard: Option<String>,
+    },
+    Approval {
+        approver_id: i64,
+        approval_option: TransitionOption,
+        rejection_option: TransitionOption,
+    },
+    Manual {
+        options: Vec<TransitionOption>,
+    },
+    VendorConfirmation {
+        target_state_id: Uuid,
+    },
+    /// Splits the instance into one branch per target state as soon as the
+    /// state is entered. The branches progress independently, and the
+    /// instance continues from the join state once every branch reaches it.
+    Parallel {
+        branch_state_ids: Vec<Uuid>,
+        join_state_id: Uuid,
+    },
+    /// Starts an instance of another workflow of the tenant as soon as the
+    /// state is entered and waits for it to complete. The end state the child
+    /// instance finishes in selects the option the instance leaves through.
+    SubWorkflow {
+        workflow_id: i64,
+        options: Vec<TransitionOption>,
+        outcomes: Vec<SubWorkflowOutcome>,
+    },
+}
+
+impl TransitionDefinition {
+    /// Returns the states this transition can lead to.
+    pub fn targets(&self) -> Vec<Uuid> {
+        match self {
+            TransitionDefinition::Automatic {
+                target_state_id, ..
+            } => vec![*target_state_id],
+            TransitionDefinition::Approval {
+                approval_option,
+                rejection_option,
+                ..
+            } => vec![
+                approval_option.target_state_id,
+                rejection_option.target_state_id,
+            ],
+            TransitionDefinition::Manual { options }
+            | TransitionDefinition::SubWorkflow { options, .. } => {
+                options.iter().map(|o| o.target_state_id).collect()
+            }
+            TransitionDefinition::VendorConfirmation { target_state_id } => {
+                vec![*target_state_id]
+            }
+            TransitionDefinition::Parallel {
+                branch_state_ids, ..
+            } => branch_state_ids.clone(),
+        }
+    }
+}
+
+/// Maps an end state of a sub-workflow to an option of the invoking state.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct SubWorkflowOutcome {
+    pub end_state_id: Uuid,
+    pub option_id: Uuid,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct TransitionOption {
+    pub id: Uuid,
+    pub label: String,
+    pub target_state_id: Uuid,
+    pub comment_required: bool,
+    pub data: Vec<TransitionOptionData>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+#[serde(tag = "type")]
+pub enum TransitionOptionData {
+    Date { id: Uuid, label: String },
+    UserId { id: Uuid, label: String },
+    VendorId { id: Uuid, label: String },
+}
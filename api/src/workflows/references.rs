@@ -0,0 +1,357 @@
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use super::{
+    ActionDefinition, EscalationAction, NotifyTarget, SubWorkflowOutcome, TransitionDefinition,
+    TransitionOption, TransitionOptionData, WorkflowDefinition,
+};
+
+/// A reference from a definition to something stored outside of it.
+#[derive(Debug)]
+pub enum Reference<'a> {
+    User(&'a mut i64),
+    Template(&'a mut i64),
+    /// A workflow invoked as a sub-workflow, along with the mapping of its end
+    /// states, which refer to states of that workflow.
+    Workflow {
+        workflow_id: &'a mut i64,
+        outcomes: &'a mut Vec<SubWorkflowOutcome>,
+    },
+}
+
+impl WorkflowDefinition {
+    /// Calls the function with every reference to a user, template or
+    /// workflow in the definition, which it may change.
+    pub fn visit_references(&mut self, mut f: impl FnMut(Reference)) {
+        for state in &mut self.states {
+            for action in state
+                .entry_actions
+                .iter_mut()
+                .chain(state.exit_actions.iter_mut())
+            {
+                match &mut action.definition {
+                    ActionDefinition::AutoAssign { user_ids, .. } => {
+                        user_ids.iter_mut().for_each(|id| f(Reference::User(id)));
+                    }
+                    ActionDefinition::AssignTo { user_id } => f(Reference::User(user_id)),
+                    ActionDefinition::Email { template_id, .. } => {
+                        f(Reference::Template(template_id))
+                    }
+                    ActionDefinition::Notify {
+                        template_id,
+                        target,
+                    } => {
+                        f(Reference::Template(template_id));
+                        if let NotifyTarget::User { id } = target {
+                            f(Reference::User(id));
+                        }
+                    }
+                    ActionDefinition::Http { .. } => {}
+                }
+            }
+
+            if let Some(sla) = &mut state.sla {
+                for escalation in &mut sla.escalations {
+                    match &mut escalation.action {
+                        EscalationAction::Notify {
+                            template_id,
+                            target,
+                        } => {
+                            f(Reference::Template(template_id));
+                            if let NotifyTarget::User { id } = target {
+                                f(Reference::User(id));
+                            }
+                        }
+                        EscalationAction::Reassign { user_id } => f(Reference::User(user_id)),
+                        EscalationAction::Transition { .. } => {}
+                    }
+                }
+            }
+
+            for transition in &mut state.transitions {
+                match &mut transition.definition {
+                    TransitionDefinition::Approval { approver_id, .. } => {
+                        f(Reference::User(approver_id))
+                    }
+                    TransitionDefinition::SubWorkflow {
+                        workflow_id,
+                        outcomes,
+                        ..
+                    } => f(Reference::Workflow {
+                        workflow_id,
+                        outcomes,
+                    }),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// The ids of the states, transitions, options, option fields, actions
+    /// and escalations the definition declares.
+    pub fn declared_ids(&self) -> HashSet<Uuid> {
+        let mut ids = HashSet::new();
+
+        for state in &self.states {
+            ids.insert(state.id);
+            ids.extend(
+                state
+                    .entry_actions
+                    .iter()
+                    .chain(&state.exit_actions)
+                    .map(|a| a.id),
+            );
+
+            if let Some(sla) = &state.sla {
+                ids.extend(sla.escalations.iter().map(|e| e.id));
+            }
+
+            for transition in &state.transitions {
+                ids.insert(transition.id);
+
+                let options = match &transition.definition {
+                    TransitionDefinition::Approval {
+                        approval_option,
+                        rejection_option,
+                        ..
+                    } => vec![approval_option, rejection_option],
+                    TransitionDefinition::Manual { options }
+                    | TransitionDefinition::SubWorkflow { options, .. } => options.iter().collect(),
+                    _ => vec![],
+                };
+
+                for option in options {
+                    ids.insert(option.id);
+                    ids.extend(option.data.iter().map(|data| match data {
+                        TransitionOptionData::Date { id, .. }
+                        | TransitionOptionData::UserId { id, .. }
+                        | TransitionOptionData::VendorId { id, .. } => *id,
+                    }));
+                }
+            }
+        }
+
+        ids
+    }
+
+    /// Gives every state, transition, option, action and escalation of the
+    /// definition a new id, and updates the references to them. Ids the
+    /// definition refers to but doesn't declare, such as the end states of
+    /// sub-workflows, are kept.
+    pub fn renew_ids(mut self) -> WorkflowDefinition {
+        let ids: HashMap<Uuid, Uuid> = self
+            .declared_ids()
+            .into_iter()
+            .map(|id| (id, Uuid::now_v7()))
+            .collect();
+
+        self.visit_ids(|id| {
+            if let Some(new) = ids.get(id) {
+                *id = *new;
+            }
+        });
+
+        self
+    }
+
+    /// Calls the function with every id the definition declares and every
+    /// reference to one of them, which it may change.
+    pub(super) fn visit_ids(&mut self, mut f: impl FnMut(&mut Uuid)) {
+        f(&mut self.initial_state);
+
+        let positions = std::mem::take(&mut self.metadata.positions);
+        for (mut id, position) in positions {
+            f(&mut id);
+            self.metadata.positions.insert(id, position);
+        }
+
+        for state in &mut self.states {
+            f(&mut state.id);
+
+            for action in state
+                .entry_actions
+                .iter_mut()
+                .chain(state.exit_actions.iter_mut())
+            {
+                f(&mut action.id);
+                if let ActionDefinition::Http {
+                    failure_state_id: Some(failure_state_id),
+                    ..
+                } = &mut action.definition
+                {
+                    f(failure_state_id);
+                }
+            }
+
+            if let Some(sla) = &mut state.sla {
+                for escalation in &mut sla.escalations {
+                    f(&mut escalation.id);
+                    if let EscalationAction::Transition { target_state_id } = &mut escalation.action
+                    {
+                        f(target_state_id);
+                    }
+                }
+            }
+
+            for transition in &mut state.transitions {
+                f(&mut transition.id);
+
+                match &mut transition.definition {
+                    TransitionDefinition::Automatic {
+                        target_state_id, ..
+                    }
+                    | TransitionDefinition::VendorConfirmation { target_state_id } => {
+                        f(target_state_id)
+                    }
+                    TransitionDefinition::Approval {
+                        approval_option,
+                        rejection_option,
+                        ..
+                    } => {
+                        visit_option_ids(approval_option, &mut f);
+                        visit_option_ids(rejection_option, &mut f);
+                    }
+                    TransitionDefinition::Manual { options } => {
+                        for option in options {
+                            visit_option_ids(option, &mut f);
+                        }
+                    }
+                    TransitionDefinition::Parallel {
+                        branch_state_ids,
+                        join_state_id,
+                    } => {
+                        branch_state_ids.iter_mut().for_each(&mut f);
+                        f(join_state_id);
+                    }
+                    TransitionDefinition::SubWorkflow {
+                        options, outcomes, ..
+                    } => {
+                        for option in options {
+                            visit_option_ids(option, &mut f);
+                        }
+                        // The end states belong to the invoked workflow
+                        for outcome in outcomes {
+                            f(&mut outcome.option_id);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn visit_option_ids(option: &mut TransitionOption, f: &mut impl FnMut(&mut Uuid)) {
+    f(&mut option.id);
+    f(&mut option.target_state_id);
+
+    for data in &mut option.data {
+        match data {
+            TransitionOptionData::Date { id, .. }
+            | TransitionOptionData::UserId { id, .. }
+            | TransitionOptionData::VendorId { id, .. } => f(id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::super::fixtures::{action, automatic, definition, end_state, id, state, transition};
+    use super::*;
+
+    #[test]
+    fn renews_declared_ids_and_references_to_them() {
+        let definition = definition(vec![
+            state(
+                1,
+                "Review",
+                json!({
+                    "entry_actions": [action(20, json!({
+                        "type": "Http",
+                        "url": "https://example.com",
+                        "failure_state_id": id(2),
+                    }))],
+                    "transitions": [automatic(10, 2)],
+                }),
+            ),
+            state(
+                2,
+                "Invoke",
+                json!({ "transitions": [transition(11, json!({
+                    "type": "SubWorkflow",
+                    "workflow_id": 7,
+                    "options": [{
+                        "id": id(30),
+                        "label": "Done",
+                        "target_state_id": id(3),
+                        "comment_required": false,
+                        "data": [],
+                    }],
+                    "outcomes": [{ "end_state_id": id(99), "option_id": id(30) }],
+                }))] }),
+            ),
+            end_state(3, "Done"),
+        ]);
+
+        let renewed = definition.renew_ids();
+        let declared = renewed.declared_ids();
+
+        assert!(!declared.contains(&id(1)) && !declared.contains(&id(30)));
+        assert!(declared.contains(&renewed.initial_state));
+
+        let ActionDefinition::Http {
+            failure_state_id, ..
+        } = &renewed.states[0].entry_actions[0].definition
+        else {
+            panic!("the action is not an HTTP action");
+        };
+        assert_eq!(*failure_state_id, Some(renewed.states[1].id));
+
+        let (_, options, outcomes) = renewed.states[1].sub_workflow().unwrap();
+        assert_eq!(options[0].target_state_id, renewed.states[2].id);
+        assert_eq!(outcomes[0].option_id, options[0].id);
+        assert_eq!(outcomes[0].end_state_id, id(99));
+    }
+
+    #[test]
+    fn leaves_strings_equal_to_ids_alone() {
+        let definition = definition(vec![
+            state(
+                1,
+                "Review",
+                json!({
+                    "description": id(2).to_string(),
+                    "entry_actions": [action(20, json!({
+                        "type": "Http",
+                        "url": format!("https://example.com/{}", id(2)),
+                        "headers": { "X-State": id(2).to_string() },
+                        "body": { "state": id(2), "key": { id(2).to_string(): true } },
+                    }))],
+                    "transitions": [automatic(10, 2)],
+                }),
+            ),
+            end_state(2, "Done"),
+        ]);
+
+        let renewed = definition.renew_ids();
+        let review = &renewed.states[0];
+
+        assert_ne!(renewed.states[1].id, id(2));
+        assert_eq!(review.description, Some(id(2).to_string()));
+
+        let ActionDefinition::Http {
+            url, headers, body, ..
+        } = &review.entry_actions[0].definition
+        else {
+            panic!("the action is not an HTTP action");
+        };
+        assert_eq!(url, &format!("https://example.com/{}", id(2)));
+        assert_eq!(headers["X-State"], id(2).to_string());
+        assert_eq!(
+            body,
+            &Some(json!({ "state": id(2), "key": { id(2).to_string(): true } }))
+        );
+    }
+}
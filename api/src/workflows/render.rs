@@ -0,0 +1,620 @@
+//! Renders a definition as a graph of its states, for reviewing a workflow
+//! outside of the editor.
+//!
+//! Edges are labelled with the name of the transition, followed by the label
+//! of the option when the transition has several. The DOT and SVG output
+//! place the states where the editor stored them, and otherwise lay them out
+//! in columns by their distance from the initial state.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tsync::tsync;
+use uuid::Uuid;
+
+use super::{TransitionDefinition, WorkflowDefinition, WorkflowPosition};
+
+const NODE_WIDTH: f32 = 160.0;
+const NODE_HEIGHT: f32 = 48.0;
+const COLUMN_GAP: f32 = 120.0;
+const ROW_GAP: f32 = 40.0;
+const MARGIN: f32 = 40.0;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, Serialize, ValueEnum)]
+#[tsync]
+#[serde(rename_all = "snake_case")]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+    #[default]
+    Svg,
+}
+
+impl GraphFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "text/vnd.graphviz",
+            GraphFormat::Mermaid => "text/plain; charset=utf-8",
+            GraphFormat::Svg => "image/svg+xml",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct RenderQuery {
+    #[serde(default)]
+    pub format: GraphFormat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EdgeKind {
+    Normal,
+    /// Followed without a user, such as an automatic transition.
+    Automatic,
+    /// The join of a parallel split, reached once every branch has.
+    Join,
+}
+
+struct Edge {
+    from: Uuid,
+    to: Uuid,
+    label: String,
+    kind: EdgeKind,
+}
+
+impl WorkflowDefinition {
+    /// Renders the definition as a graph titled with the name.
+    pub fn render(&self, name: &str, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(name),
+            GraphFormat::Mermaid => self.to_mermaid(name),
+            GraphFormat::Svg => self.to_svg(name),
+        }
+    }
+
+    fn edges(&self) -> Vec<Edge> {
+        let mut edges = vec![];
+
+        for state in &self.states {
+            for transition in &state.transitions {
+                let name = transition.name.as_str();
+                let mut edge = |to: Uuid, label: String, kind: EdgeKind| {
+                    edges.push(Edge {
+                        from: state.id,
+                        to,
+                        label,
+                        kind,
+                    })
+                };
+
+                match &transition.definition {
+                    TransitionDefinition::Automatic {
+                        target_state_id, ..
+                    } => edge(*target_state_id, name.into(), EdgeKind::Automatic),
+                    TransitionDefinition::VendorConfirmation { target_state_id } => {
+                        edge(*target_state_id, name.into(), EdgeKind::Normal)
+                    }
+                    TransitionDefinition::Approval {
+                        approval_option,
+                        rejection_option,
+                        ..
+                    } => {
+                        for option in [approval_option, rejection_option] {
+                            let label = format!("{}: {}", name, option.label);
+                            edge(option.target_state_id, label, EdgeKind::Normal);
+                        }
+                    }
+                    TransitionDefinition::Manual { options } => {
+                        for option in options {
+                            let label = match options.len() {
+                                1 if option.label == name => name.into(),
+                                _ => format!("{}: {}", name, option.label),
+                            };
+                            edge(option.target_state_id, label, EdgeKind::Normal);
+                        }
+                    }
+                    TransitionDefinition::SubWorkflow { options, .. } => {
+                        for option in options {
+                            let label = format!("{}: {}", name, option.label);
+                            edge(option.target_state_id, label, EdgeKind::Automatic);
+                        }
+                    }
+                    TransitionDefinition::Parallel {
+                        branch_state_ids,
+                        join_state_id,
+                    } => {
+                        for id in branch_state_ids {
+                            edge(*id, name.into(), EdgeKind::Automatic);
+                        }
+                        edge(*join_state_id, "join".into(), EdgeKind::Join);
+                    }
+                }
+            }
+        }
+
+        edges
+    }
+
+    /// Returns the top left corner of every state, from the stored positions
+    /// when every state has one.
+    fn layout(&self) -> HashMap<Uuid, WorkflowPosition> {
+        let positions = &self.metadata.positions;
+        if !self.states.is_empty() && self.states.iter().all(|s| positions.contains_key(&s.id)) {
+            return self
+                .states
+                .iter()
+                .map(|s| (s.id, positions[&s.id].clone()))
+                .collect();
+        }
+
+        // Columns by the shortest distance from the initial state, with the
+        // unreachable states in a column of their own at the end.
+        let edges = self.edges();
+        let mut columns = HashMap::from([(self.initial_state, 0)]);
+        let mut queue = VecDeque::from([self.initial_state]);
+        while let Some(id) = queue.pop_front() {
+            let column = columns[&id];
+            for edge in edges.iter().filter(|e| e.from == id) {
+                if let Entry::Vacant(entry) = columns.entry(edge.to) {
+                    entry.insert(column + 1);
+                    queue.push_back(edge.to);
+                }
+            }
+        }
+
+        let unreachable = columns.values().max().map_or(0, |c| c + 1);
+        let mut rows: HashMap<usize, usize> = HashMap::new();
+        self.states
+            .iter()
+            .map(|state| {
+                let column = columns.get(&state.id).copied().unwrap_or(unreachable);
+                let row = rows.entry(column).or_default();
+                let position = WorkflowPosition {
+                    x: column as f32 * (NODE_WIDTH + COLUMN_GAP),
+                    y: *row as f32 * (NODE_HEIGHT + ROW_GAP),
+                };
+                *row += 1;
+                (state.id, position)
+            })
+            .collect()
+    }
+
+    fn to_dot(&self, name: &str) -> String {
+        let positions = &self.metadata.positions;
+        let mut dot = String::new();
+
+        let _ = writeln!(dot, "digraph \"{}\" {{", dot_escape(name));
+        let _ = writeln!(dot, "  rankdir=LR;");
+        let _ = writeln!(dot, "  node [shape=box, style=rounded];");
+        let _ = writeln!(dot, "  start [shape=point];");
+
+        for state in &self.states {
+            let mut attributes = vec![format!("label=\"{}\"", dot_escape(&state.name))];
+            if state.is_end_state {
+                attributes.push("peripheries=2".into());
+            }
+            // Graphviz measures in points with y growing upwards, so the
+            // positions only take effect with a layout engine such as neato.
+            if let Some(position) = positions.get(&state.id) {
+                attributes.push(format!("pos=\"{},{}!\"", position.x, -position.y));
+            }
+            let _ = writeln!(dot, "  \"{}\" [{}];", state.id, attributes.join(", "));
+        }
+
+        let _ = writeln!(dot, "  start -> \"{}\";", self.initial_state);
+        for edge in self.edges() {
+            let style = match edge.kind {
+                EdgeKind::Normal => "",
+                EdgeKind::Automatic => ", style=dashed",
+                EdgeKind::Join => ", style=dotted",
+            };
+            let _ = writeln!(
+                dot,
+                "  \"{}\" -> \"{}\" [label=\"{}\"{}];",
+                edge.from,
+                edge.to,
+                dot_escape(&edge.label),
+                style
+            );
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn to_mermaid(&self, name: &str) -> String {
+        // Mermaid identifiers can't contain dashes, so the states are numbered.
+        let ids: HashMap<Uuid, String> = self
+            .states
+            .iter()
+            .enumerate()
+            .map(|(index, state)| (state.id, format!("s{}", index)))
+            .collect();
+        let id = |uuid: &Uuid| {
+            ids.get(uuid)
+                .cloned()
+                .unwrap_or_else(|| uuid.simple().to_string())
+        };
+
+        let mut mermaid = String::new();
+        let _ = writeln!(mermaid, "---\ntitle: {}\n---", mermaid_escape(name));
+        let _ = writeln!(mermaid, "stateDiagram-v2");
+
+        for state in &self.states {
+            let _ = writeln!(
+                mermaid,
+                "  state \"{}\" as {}",
+                mermaid_escape(&state.name),
+                id(&state.id)
+            );
+        }
+
+        let _ = writeln!(mermaid, "  [*] --> {}", id(&self.initial_state));
+        for edge in self.edges() {
+            let _ = writeln!(
+                mermaid,
+                "  {} --> {} : {}",
+                id(&edge.from),
+                id(&edge.to),
+                mermaid_escape(&edge.label)
+            );
+        }
+        for state in self.states.iter().filter(|s| s.is_end_state) {
+            let _ = writeln!(mermaid, "  {} --> [*]", id(&state.id));
+        }
+
+        mermaid
+    }
+
+    fn to_svg(&self, name: &str) -> String {
+        let layout = self.layout();
+        let min_x = layout.values().map(|p| p.x).fold(f32::INFINITY, f32::min);
+        let min_y = layout.values().map(|p| p.y).fold(f32::INFINITY, f32::min);
+        let (min_x, min_y) = match layout.is_empty() {
+            true => (0.0, 0.0),
+            false => (min_x, min_y),
+        };
+        // Shift the states so the top left one sits at the margin, below the
+        // title.
+        let shift = |p: &WorkflowPosition| (p.x - min_x + MARGIN, p.y - min_y + MARGIN * 2.0);
+        let width = layout
+            .values()
+            .map(|p| shift(p).0 + NODE_WIDTH + MARGIN)
+            .fold(MARGIN * 2.0, f32::max);
+        let height = layout
+            .values()
+            .map(|p| shift(p).1 + NODE_HEIGHT + MARGIN)
+            .fold(MARGIN * 3.0, f32::max);
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"12\">",
+            w = width,
+            h = height
+        );
+        let _ = writeln!(svg, "  <title>{}</title>", xml_escape(name));
+        let _ = writeln!(
+            svg,
+            "  <defs><marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"8\" markerHeight=\"8\" orient=\"auto-start-reverse\"><path d=\"M 0 0 L 10 5 L 0 10 z\" fill=\"#555\"/></marker></defs>"
+        );
+        let _ = writeln!(
+            svg,
+            "  <text x=\"{}\" y=\"{}\" font-size=\"16\" font-weight=\"bold\">{}</text>",
+            MARGIN,
+            MARGIN,
+            xml_escape(name)
+        );
+
+        // Edges between the same states share a line, so their labels are
+        // stacked.
+        let mut labels: HashMap<(Uuid, Uuid), f32> = HashMap::new();
+        for edge in self.edges() {
+            let (Some(from), Some(to)) = (layout.get(&edge.from), layout.get(&edge.to)) else {
+                continue;
+            };
+            let stacked = labels.entry((edge.from, edge.to)).or_default();
+            let label_offset = *stacked;
+            *stacked += 14.0;
+            let (fx, fy) = shift(from);
+            let (tx, ty) = shift(to);
+            let dash = match edge.kind {
+                EdgeKind::Normal => "",
+                EdgeKind::Automatic => " stroke-dasharray=\"6 4\"",
+                EdgeKind::Join => " stroke-dasharray=\"2 3\"",
+            };
+
+            let (path, label_x, mut label_y) = if edge.from == edge.to {
+                // A loop over the top of the state.
+                let (x, y) = (fx + NODE_WIDTH / 2.0, fy);
+                (
+                    format!(
+                        "M {} {} C {} {} {} {} {} {}",
+                        x - 20.0,
+                        y,
+                        x - 30.0,
+                        y - 40.0,
+                        x + 30.0,
+                        y - 40.0,
+                        x + 20.0,
+                        y
+                    ),
+                    x,
+                    y - 34.0,
+                )
+            } else {
+                let (start_x, start_y) = border_point((fx, fy), (tx, ty));
+                let (end_x, end_y) = border_point((tx, ty), (fx, fy));
+                (
+                    format!("M {} {} L {} {}", start_x, start_y, end_x, end_y),
+                    (start_x + end_x) / 2.0,
+                    (start_y + end_y) / 2.0 - 4.0,
+                )
+            };
+            label_y -= label_offset;
+
+            let _ = writeln!(
+                svg,
+                "  <path d=\"{}\" fill=\"none\" stroke=\"#555\"{} marker-end=\"url(#arrow)\"/>",
+                path, dash
+            );
+            let _ = writeln!(
+                svg,
+                "  <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" fill=\"#333\">{}</text>",
+                label_x,
+                label_y,
+                xml_escape(&edge.label)
+            );
+        }
+
+        for state in &self.states {
+            let (x, y) = shift(&layout[&state.id]);
+            let stroke_width = match state.id == self.initial_state {
+                true => 2.5,
+                false => 1.0,
+            };
+            let _ = writeln!(
+                svg,
+                "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"8\" fill=\"#fff\" stroke=\"#222\" stroke-width=\"{}\"/>",
+                x, y, NODE_WIDTH, NODE_HEIGHT, stroke_width
+            );
+            if state.is_end_state {
+                let _ = writeln!(
+                    svg,
+                    "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"6\" fill=\"none\" stroke=\"#222\"/>",
+                    x + 4.0,
+                    y + 4.0,
+                    NODE_WIDTH - 8.0,
+                    NODE_HEIGHT - 8.0
+                );
+            }
+            let _ = writeln!(
+                svg,
+                "  <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+                x + NODE_WIDTH / 2.0,
+                y + NODE_HEIGHT / 2.0,
+                xml_escape(&state.name)
+            );
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// Returns where the line from the centre of the state at `from` towards the
+/// state at `to` leaves its box.
+fn border_point(from: (f32, f32), to: (f32, f32)) -> (f32, f32) {
+    let (cx, cy) = (from.0 + NODE_WIDTH / 2.0, from.1 + NODE_HEIGHT / 2.0);
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    if dx == 0.0 && dy == 0.0 {
+        return (cx, cy);
+    }
+
+    // Dividing by a zero distance gives infinity, which the other side wins.
+    let scale = ((NODE_WIDTH / 2.0) / dx.abs()).min((NODE_HEIGHT / 2.0) / dy.abs());
+
+    (cx + dx * scale, cy + dy * scale)
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Escapes the semicolons before the quotes, whose entity ends in one.
+fn mermaid_escape(text: &str) -> String {
+    text.replace(';', "#59;")
+        .replace('"', "#quot;")
+        .replace('\n', " ")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::super::fixtures::{automatic, definition, end_state, id, manual, option, state};
+    use super::*;
+
+    const AWKWARD: &str = "Say \"hi\"; <b>\nthen";
+
+    /// Draft, submitted to Review, which is approved to Done or sent back.
+    fn review() -> WorkflowDefinition {
+        definition(vec![
+            state(
+                1,
+                "Draft",
+                json!({ "transitions": [manual(10, &[option(11, "Transition 10", 2)])] }),
+            ),
+            state(
+                2,
+                "Review",
+                json!({ "transitions": [manual(20, &[
+                    option(21, "Approve", 3),
+                    option(22, "Rework", 1),
+                ])] }),
+            ),
+            end_state(3, "Done"),
+        ])
+    }
+
+    fn labels(definition: &WorkflowDefinition) -> Vec<(Uuid, Uuid, String)> {
+        definition
+            .edges()
+            .into_iter()
+            .map(|e| (e.from, e.to, e.label))
+            .collect()
+    }
+
+    fn position(x: f32, y: f32) -> WorkflowPosition {
+        WorkflowPosition { x, y }
+    }
+
+    #[test]
+    fn labels_single_options_named_like_the_transition_by_the_transition_only() {
+        let mut definition = review();
+        assert_eq!(
+            labels(&definition),
+            vec![
+                (id(1), id(2), "Transition 10".to_string()),
+                (id(2), id(3), "Transition 20: Approve".to_string()),
+                (id(2), id(1), "Transition 20: Rework".to_string()),
+            ]
+        );
+
+        definition.states[0].transitions[0].name = "Submit".to_string();
+        assert_eq!(labels(&definition)[0].2, "Submit: Transition 10");
+    }
+
+    #[test]
+    fn escapes_names_in_dot() {
+        let mut definition = review();
+        definition.states[0].name = AWKWARD.to_string();
+
+        let dot = definition.render(AWKWARD, GraphFormat::Dot);
+
+        assert!(dot.starts_with("digraph \"Say \\\"hi\\\"; <b>\\nthen\" {\n"));
+        assert!(dot.contains(&format!(
+            "\"{}\" [label=\"Say \\\"hi\\\"; <b>\\nthen\"];",
+            id(1)
+        )));
+    }
+
+    #[test]
+    fn escapes_names_in_mermaid() {
+        let mut definition = review();
+        definition.states[0].name = AWKWARD.to_string();
+
+        let mermaid = definition.render(AWKWARD, GraphFormat::Mermaid);
+
+        assert!(mermaid.starts_with("---\ntitle: Say #quot;hi#quot;#59; <b> then\n---\n"));
+        assert!(mermaid.contains("  state \"Say #quot;hi#quot;#59; <b> then\" as s0\n"));
+        assert!(mermaid.contains("  [*] --> s0\n"));
+        assert!(mermaid.contains("  s2 --> [*]\n"));
+    }
+
+    #[test]
+    fn escapes_names_in_svg() {
+        let mut definition = review();
+        definition.states[0].name = AWKWARD.to_string();
+
+        let svg = definition.render("R&D", GraphFormat::Svg);
+
+        assert!(svg.contains("<title>R&amp;D</title>"));
+        assert!(svg.contains(">Say &quot;hi&quot;; &lt;b&gt;\nthen</text>"));
+        assert!(!svg.contains("<b>"));
+    }
+
+    #[test]
+    fn uses_stored_positions_only_when_every_state_has_one() {
+        let mut definition = review();
+        let positions = &mut definition.metadata.positions;
+        positions.insert(id(1), position(500.0, 10.0));
+        positions.insert(id(2), position(0.0, 0.0));
+
+        let layout = definition.layout();
+        assert_eq!(layout[&id(1)].x, 0.0);
+        assert_eq!(layout[&id(2)].x, NODE_WIDTH + COLUMN_GAP);
+
+        definition
+            .metadata
+            .positions
+            .insert(id(3), position(250.0, 90.0));
+        let layout = definition.layout();
+        assert_eq!((layout[&id(1)].x, layout[&id(1)].y), (500.0, 10.0));
+        assert_eq!((layout[&id(3)].x, layout[&id(3)].y), (250.0, 90.0));
+    }
+
+    #[test]
+    fn lays_out_columns_by_distance_with_unreachable_states_last() {
+        let definition = definition(vec![
+            state(
+                1,
+                "Start",
+                json!({ "transitions": [automatic(10, 2), automatic(11, 3)] }),
+            ),
+            state(2, "Left", json!({ "transitions": [automatic(20, 4)] })),
+            state(3, "Right", json!({ "transitions": [automatic(30, 4)] })),
+            end_state(4, "Done"),
+            state(5, "Orphan", json!({ "transitions": [automatic(50, 6)] })),
+            end_state(6, "Orphan end"),
+        ]);
+
+        let layout = definition.layout();
+        let column = |n: u128| (layout[&id(n)].x / (NODE_WIDTH + COLUMN_GAP)).round() as usize;
+        let row = |n: u128| (layout[&id(n)].y / (NODE_HEIGHT + ROW_GAP)).round() as usize;
+
+        assert_eq!([1, 2, 3, 4, 5, 6].map(column), [0, 1, 1, 2, 3, 3]);
+        assert_eq!([2, 3, 5, 6].map(row), [0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn draws_self_loops_over_the_state() {
+        let definition = definition(vec![
+            state(
+                1,
+                "Review",
+                json!({ "transitions": [manual(10, &[
+                    option(11, "Ask again", 1),
+                    option(12, "Done", 2),
+                ])] }),
+            ),
+            end_state(2, "Done"),
+        ]);
+
+        assert!(definition
+            .render("Loop", GraphFormat::Dot)
+            .contains(&format!("\"{0}\" -> \"{0}\"", id(1))));
+        assert!(definition
+            .render("Loop", GraphFormat::Mermaid)
+            .contains("  s0 --> s0 : Transition 10: Ask again\n"));
+
+        // The loop starts and ends on the top edge of the state, which sits
+        // below the title
+        let top = MARGIN * 2.0;
+        let (left, right) = (
+            MARGIN + NODE_WIDTH / 2.0 - 20.0,
+            MARGIN + NODE_WIDTH / 2.0 + 20.0,
+        );
+        let svg = definition.render("Loop", GraphFormat::Svg);
+        assert!(
+            svg.contains(&format!("d=\"M {} {} C ", left, top)),
+            "{}",
+            svg
+        );
+        assert!(svg.contains(&format!(" {} {}\" fill=\"none\"", right, top)));
+    }
+}
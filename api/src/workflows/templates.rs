@@ -0,0 +1,296 @@
+//! A catalogue of workflow definitions shared by every tenant. Instantiating a
+//! template creates a workflow in a tenant from a copy of its definition, with
+//! new ids for its states, transitions and other parts.
+//!
+//! The users and email templates a definition refers to belong to a tenant, so
+//! they are copied as they are and may need changing in the new workflow.
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tsync::tsync;
+
+use crate::database::{schema::workflow_templates, DbConnection, DB};
+use crate::defaults::{default_bool, default_i64};
+use crate::filters;
+use crate::pagination::{self, Page, SortDirection};
+use crate::result::{AppError, Result};
+use crate::sort_by;
+use crate::tenants::Tenant;
+
+use super::{NewWorkflow, Workflow, WorkflowDefinition, WorkflowVersion};
+
+#[derive(Clone, Debug, Deserialize, Identifiable, JsonSchema, Queryable, Selectable, Serialize)]
+#[tsync]
+#[diesel(table_name = workflow_templates)]
+pub struct WorkflowTemplate {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub definition: WorkflowDefinition,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Deserialize, Insertable, JsonSchema, Serialize)]
+#[tsync]
+#[diesel(table_name = workflow_templates)]
+pub struct NewWorkflowTemplate {
+    pub name: String,
+    pub description: Option<String>,
+    pub definition: WorkflowDefinition,
+}
+
+#[derive(AsChangeset, Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+#[diesel(table_name = workflow_templates)]
+pub struct UpdateWorkflowTemplate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub definition: Option<WorkflowDefinition>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateSort {
+    Id,
+    #[default]
+    Name,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct TemplateQuery {
+    /// Part of the name, matched without regard to case.
+    pub name: Option<String>,
+    #[serde(default = "default_bool::<true>")]
+    pub active: bool,
+    #[serde(default)]
+    pub sort: TemplateSort,
+    #[serde(default)]
+    pub direction: SortDirection,
+    /// The cursor of the page to fetch, returned with the page before it.
+    /// When set, `page` is ignored.
+    pub cursor: Option<String>,
+    /// Whether to count the matching templates.
+    #[serde(default = "default_bool::<true>")]
+    pub count: bool,
+    #[serde(default = "default_i64::<1>")]
+    pub page: i64,
+    #[serde(default = "default_i64::<10>")]
+    pub page_size: i64,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct InstantiateTemplate {
+    /// The tenant to create the workflow in.
+    pub tenant_id: i32,
+    /// The name of the new workflow, the template's name when not set.
+    pub name: Option<String>,
+    /// The description of the new workflow, the template's when not set.
+    pub description: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct CloneWorkflow {
+    /// The name of the copy, the workflow's name followed by "(copy)" when
+    /// not set.
+    pub name: Option<String>,
+    /// The version to copy, by default the current definition.
+    pub version: Option<i32>,
+}
+
+impl WorkflowTemplate {
+    pub fn find(conn: &mut DbConnection, id: i64) -> Result<Option<WorkflowTemplate>> {
+        Ok(workflow_templates::table
+            .select(WorkflowTemplate::as_select())
+            .filter(workflow_templates::id.eq(id))
+            .get_result(conn)
+            .optional()?)
+    }
+
+    pub fn list(conn: &mut DbConnection, params: TemplateQuery) -> Result<Page<WorkflowTemplate>> {
+        let mut query = Self::filtered(&params);
+        let TemplateQuery {
+            sort,
+            direction,
+            cursor,
+            page,
+            page_size,
+            ..
+        } = params;
+
+        let cursor = cursor.as_deref();
+        query = match sort {
+            TemplateSort::Id => sort_by!(
+                query,
+                workflow_templates::id,
+                workflow_templates::id as i64,
+                direction,
+                pagination::start::<i64>(cursor, sort, direction, page, page_size)?
+            ),
+            TemplateSort::Name => sort_by!(
+                query,
+                workflow_templates::name,
+                workflow_templates::id as i64,
+                direction,
+                pagination::start::<String>(cursor, sort, direction, page, page_size)?
+            ),
+            TemplateSort::CreatedAt => sort_by!(
+                query,
+                workflow_templates::created_at,
+                workflow_templates::id as i64,
+                direction,
+                pagination::start::<DateTime<Utc>>(cursor, sort, direction, page, page_size)?
+            ),
+            TemplateSort::UpdatedAt => sort_by!(
+                query,
+                workflow_templates::updated_at,
+                workflow_templates::id as i64,
+                direction,
+                pagination::start::<DateTime<Utc>>(cursor, sort, direction, page, page_size)?
+            ),
+        };
+
+        let rows = query
+            .select(WorkflowTemplate::as_select())
+            .limit(page_size + 1)
+            .get_results(conn)?;
+
+        Ok(pagination::page(rows, page_size, sort, direction, |t| {
+            (t.sort_key(sort), t.id)
+        }))
+    }
+
+    /// The value of the sort field, stored in cursors.
+    fn sort_key(&self, sort: TemplateSort) -> Value {
+        match sort {
+            TemplateSort::Id => json!(self.id),
+            TemplateSort::Name => json!(self.name),
+            TemplateSort::CreatedAt => json!(self.created_at),
+            TemplateSort::UpdatedAt => json!(self.updated_at),
+        }
+    }
+
+    pub fn count(conn: &mut DbConnection, query: TemplateQuery) -> Result<i64> {
+        Ok(Self::filtered(&query).count().get_result(conn)?)
+    }
+
+    pub fn create(
+        conn: &mut DbConnection,
+        template: NewWorkflowTemplate,
+    ) -> Result<WorkflowTemplate> {
+        template.definition.validate()?;
+
+        Ok(diesel::insert_into(workflow_templates::table)
+            .values(&template)
+            .returning(WorkflowTemplate::as_returning())
+            .get_result(conn)?)
+    }
+
+    pub fn update(
+        conn: &mut DbConnection,
+        id: i64,
+        template: UpdateWorkflowTemplate,
+    ) -> Result<WorkflowTemplate> {
+        if let Some(definition) = &template.definition {
+            definition.validate()?;
+        }
+
+        Ok(diesel::update(workflow_templates::table)
+            .filter(workflow_templates::id.eq(id))
+            .set(&template)
+            .returning(WorkflowTemplate::as_returning())
+            .get_result(conn)?)
+    }
+
+    /// Removes the template from the catalogue. Workflows created from it are
+    /// kept.
+    pub fn delete(conn: &mut DbConnection, id: i64) -> Result<WorkflowTemplate> {
+        Ok(diesel::update(workflow_templates::table)
+            .filter(workflow_templates::id.eq(id))
+            .set(workflow_templates::deleted_at.eq(Utc::now()))
+            .returning(WorkflowTemplate::as_returning())
+            .get_result(conn)?)
+    }
+
+    /// Creates a workflow in the tenant from a copy of the template's
+    /// definition with new ids.
+    pub fn instantiate(
+        conn: &mut DbConnection,
+        id: i64,
+        InstantiateTemplate {
+            tenant_id,
+            name,
+            description,
+        }: InstantiateTemplate,
+    ) -> Result<Workflow> {
+        let template = Self::find(conn, id)?
+            .filter(|t| t.deleted_at.is_none())
+            .ok_or_else(|| AppError::not_found("WorkflowTemplate", &id.to_string()))?;
+        Tenant::find(conn, tenant_id)?
+            .filter(|t| t.deleted_at.is_none())
+            .ok_or_else(|| AppError::not_found("Tenant", &tenant_id.to_string()))?;
+
+        Workflow::create(
+            conn,
+            NewWorkflow {
+                tenant_id,
+                name: name.unwrap_or(template.name),
+                description: description.or(template.description),
+                definition: template.definition.renew_ids(),
+            },
+        )
+    }
+
+    fn filtered(query: &TemplateQuery) -> workflow_templates::BoxedQuery<'static, DB> {
+        let mut q = workflow_templates::table.into_boxed();
+
+        if let Some(name) = &query.name {
+            let pattern = format!("%{}%", filters::escape_like(name));
+            q = q.filter(workflow_templates::name.ilike(pattern));
+        }
+
+        match query.active {
+            true => q.filter(workflow_templates::deleted_at.is_null()),
+            false => q.filter(workflow_templates::deleted_at.is_not_null()),
+        }
+    }
+}
+
+impl Workflow {
+    /// Creates a workflow in the same tenant from a copy of the workflow's
+    /// definition with new ids. Sub-workflows and users it refers to are the
+    /// same as the original's.
+    pub fn duplicate(
+        conn: &mut DbConnection,
+        id: i64,
+        CloneWorkflow { name, version }: CloneWorkflow,
+    ) -> Result<Workflow> {
+        let workflow = Self::find(conn, id)?
+            .filter(|w| w.deleted_at.is_none())
+            .ok_or_else(|| AppError::not_found("Workflow", &id.to_string()))?;
+        let definition = match version {
+            Some(version) => WorkflowVersion::get(conn, id, version)?.definition,
+            None => workflow.definition,
+        };
+
+        Self::create(
+            conn,
+            NewWorkflow {
+                tenant_id: workflow.tenant_id,
+                name: name.unwrap_or_else(|| format!("{} (copy)", workflow.name)),
+                description: workflow.description,
+                definition: definition.renew_ids(),
+            },
+        )
+    }
+}
@@ -0,0 +1,257 @@
+//! Stored definitions carry the version of the shape they were written in, so
+//! that changing the shape doesn't stop older rows from being read. When a
+//! definition is read, the upgrades from its version to the current one are
+//! applied to its JSON before it is deserialized.
+//!
+//! To change the shape in a way older definitions can't be read as, bump
+//! [`SCHEMA_VERSION`] and add the function rewriting the previous shape to
+//! [`UPGRADES`]. Definitions stored before versioning have no
+//! `schema_version` and are version 1.
+
+use diesel::prelude::*;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::database::schema::{workflow_templates, workflow_versions, workflows};
+use crate::database::DbConnection;
+use crate::result::{AppError, Result};
+
+use super::WorkflowDefinition;
+
+/// The version of the shape definitions are written in by this release.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Rewrites the JSON of a definition in the shape of one version to the shape
+/// of the next.
+type Upgrade = fn(&mut Map<String, Value>) -> std::result::Result<(), String>;
+
+/// The upgrade from each version to the next, starting with version 1.
+const UPGRADES: [Upgrade; SCHEMA_VERSION as usize - 1] = [];
+
+/// The outcome of rewriting the definitions of one table.
+#[derive(Clone, Debug, Serialize)]
+pub struct TableUpgrade {
+    pub table: &'static str,
+    /// The definitions that were rewritten, or would be for dry runs.
+    pub upgraded: usize,
+    /// The definitions already in the current shape.
+    pub current: usize,
+    /// The ids of the rows whose definition could not be upgraded, and why.
+    pub failed: Vec<(i64, String)>,
+}
+
+/// Applies the upgrades from the version of the definition's JSON to the
+/// current one, and sets its version. Returns the version it was in.
+pub fn upgrade(value: &mut Value) -> std::result::Result<u32, String> {
+    apply(value, &UPGRADES)
+}
+
+/// Applies the upgrades from the version of the definition's JSON to the
+/// version after the last of them.
+fn apply(value: &mut Value, upgrades: &[Upgrade]) -> std::result::Result<u32, String> {
+    let latest = upgrades.len() as u32 + 1;
+    let Value::Object(definition) = value else {
+        return Err("a definition must be a JSON object".to_string());
+    };
+
+    let version = match definition.get("schema_version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v >= 1)
+            .ok_or_else(|| format!("invalid schema version {}", version))?,
+    };
+    if version > latest {
+        return Err(format!(
+            "schema version {} is newer than version {}, the latest this release reads",
+            version, latest
+        ));
+    }
+
+    for upgrade in upgrades.iter().skip(version as usize - 1) {
+        upgrade(definition)?;
+    }
+    definition.insert("schema_version".to_string(), latest.into());
+
+    Ok(version)
+}
+
+/// Sorts the rows of a table into those already in the latest shape, those
+/// that upgrade, with their upgraded definition, and those that fail to.
+fn plan(
+    table: &'static str,
+    rows: Vec<(i64, Value)>,
+    upgrades: &[Upgrade],
+) -> (TableUpgrade, Vec<(i64, WorkflowDefinition)>) {
+    let latest = Value::from(upgrades.len() as u32 + 1);
+    let mut report = TableUpgrade {
+        table,
+        upgraded: 0,
+        current: 0,
+        failed: Vec::new(),
+    };
+    let mut definitions = Vec::new();
+
+    for (id, mut value) in rows {
+        if value.get("schema_version") == Some(&latest) {
+            report.current += 1;
+            continue;
+        }
+
+        let definition = apply(&mut value, upgrades)
+            .and_then(|_| serde_json::from_value(value).map_err(|e| e.to_string()));
+        match definition {
+            Ok(definition) => {
+                report.upgraded += 1;
+                definitions.push((id, definition));
+            }
+            Err(e) => report.failed.push((id, e)),
+        }
+    }
+
+    (report, definitions)
+}
+
+impl WorkflowDefinition {
+    /// Reads a definition in the shape of any version.
+    pub fn from_json(mut value: Value) -> std::result::Result<WorkflowDefinition, String> {
+        upgrade(&mut value)?;
+        serde_json::from_value(value).map_err(|e| e.to_string())
+    }
+}
+
+/// Rewrites every definition of the table that isn't stored in the current
+/// shape. The `updated_at` trigger of the table, if it has one, is disabled
+/// meanwhile: the definition means the same in its new shape, so the row
+/// keeps its ETag and clients holding it can still update it.
+macro_rules! upgrade_table {
+    ($conn:expr, $table:ident, $trigger:expr, $dry_run:expr) => {{
+        let rows: Vec<(i64, Value)> = $table::table
+            .select(($table::id, $table::definition))
+            .order($table::id.asc())
+            .load($conn)?;
+
+        let (report, definitions) = plan(stringify!($table), rows, &UPGRADES);
+
+        if !$dry_run && !definitions.is_empty() {
+            let trigger: Option<&str> = $trigger;
+            if let Some(trigger) = trigger {
+                diesel::sql_query(format!(
+                    "ALTER TABLE {} DISABLE TRIGGER {}",
+                    stringify!($table),
+                    trigger
+                ))
+                .execute($conn)?;
+            }
+
+            for (id, definition) in definitions {
+                diesel::update($table::table.filter($table::id.eq(id)))
+                    .set($table::definition.eq(definition))
+                    .execute($conn)?;
+            }
+
+            if let Some(trigger) = trigger {
+                diesel::sql_query(format!(
+                    "ALTER TABLE {} ENABLE TRIGGER {}",
+                    stringify!($table),
+                    trigger
+                ))
+                .execute($conn)?;
+            }
+        }
+
+        report
+    }};
+}
+
+/// Rewrites the stored definitions of workflows, their versions and the
+/// workflow templates in the current shape, in one transaction. Disabling the
+/// triggers takes the tables' owner, as running the migrations does.
+pub fn upgrade_stored(conn: &mut DbConnection, dry_run: bool) -> Result<Vec<TableUpgrade>> {
+    conn.transaction(|conn| {
+        Ok::<_, AppError>(vec![
+            upgrade_table!(conn, workflows, Some("update_workflow_updated_at"), dry_run),
+            upgrade_table!(conn, workflow_versions, None, dry_run),
+            upgrade_table!(
+                conn,
+                workflow_templates,
+                Some("update_workflow_templates_updated_at"),
+                dry_run
+            ),
+        ])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::super::fixtures::{end_state, id};
+    use super::*;
+
+    /// An upgrade from a version 1 that named the initial state `start`.
+    fn rename_start(definition: &mut Map<String, Value>) -> std::result::Result<(), String> {
+        let start = definition
+            .remove("start")
+            .ok_or_else(|| "the definition has no start".to_string())?;
+        definition.insert("initial_state".to_string(), start);
+        Ok(())
+    }
+
+    fn row(fields: Value) -> Value {
+        let mut definition = json!({
+            "states": [end_state(1, "Done")],
+            "metadata": { "positions": {} },
+        });
+        if let (Some(definition), Value::Object(fields)) = (definition.as_object_mut(), fields) {
+            definition.extend(fields);
+        }
+        definition
+    }
+
+    #[test]
+    fn upgrades_rows_without_a_version_and_reports_the_rest() {
+        let rows = vec![
+            (1, row(json!({ "start": id(1) }))),
+            (
+                2,
+                row(json!({ "schema_version": 2, "initial_state": id(1) })),
+            ),
+            (3, row(json!({ "initial_state": id(1) }))),
+        ];
+
+        let (report, definitions) = plan("workflows", rows, &[rename_start]);
+
+        assert_eq!(report.table, "workflows");
+        assert_eq!(report.upgraded, 1);
+        assert_eq!(report.current, 1);
+        assert_eq!(
+            report.failed,
+            vec![(3, "the definition has no start".to_string())]
+        );
+
+        assert_eq!(definitions.len(), 1);
+        let (row_id, definition) = &definitions[0];
+        assert_eq!(*row_id, 1);
+        assert_eq!(definition.schema_version, 2);
+        assert_eq!(definition.initial_state, id(1));
+    }
+
+    #[test]
+    fn rejects_versions_newer_than_the_latest() {
+        let mut value = row(json!({ "schema_version": 3, "initial_state": id(1) }));
+
+        let error = apply(&mut value, &[rename_start]).unwrap_err();
+
+        assert!(error.contains("newer than version 2"), "{}", error);
+    }
+
+    #[test]
+    fn marks_current_definitions_with_the_version() {
+        let mut value = row(json!({ "initial_state": id(1) }));
+
+        assert_eq!(upgrade(&mut value), Ok(1));
+        assert_eq!(value["schema_version"], json!(SCHEMA_VERSION));
+    }
+}
@@ -0,0 +1,710 @@
+use std::collections::{HashMap, HashSet};
+
+use diesel::prelude::*;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::database::schema::workflows;
+use crate::database::DbConnection;
+use crate::expressions::Expression;
+use crate::result::AppError;
+use crate::templating;
+
+use super::{
+    ActionDefinition, EscalationAction, SubWorkflowOutcome, TransitionDefinition, Workflow,
+    WorkflowAction, WorkflowDefinition, WorkflowState, SCHEMA_VERSION,
+};
+
+/// The longest an SLA may give a state, and the longest an escalation may
+/// wait past the due date, in seconds: a year.
+const MAX_SLA_SECONDS: i64 = 365 * 24 * 60 * 60;
+
+impl WorkflowDefinition {
+    /// Checks the definition for errors that would prevent instances from
+    /// running: unknown states, guard expressions that do not parse, SLAs out
+    /// of range or escalating to unknown states, and forks whose branches can
+    /// finish without reaching their join.
+    pub fn validate(&self) -> Result<(), AppError> {
+        if let Some(data_schema) = &self.data_schema {
+            data_schema.validate()?;
+        }
+
+        let mut errors = Vec::new();
+        let mut ids = HashSet::new();
+
+        if self.schema_version != SCHEMA_VERSION {
+            errors.push(format!(
+                "schema version {} is not the current version {}",
+                self.schema_version, SCHEMA_VERSION
+            ));
+        }
+
+        for state in &self.states {
+            if !ids.insert(state.id) {
+                errors.push(format!("state {} is declared more than once", state.id));
+            }
+        }
+
+        if self.state(self.initial_state).is_none() {
+            errors.push(format!(
+                "initial state {} does not exist",
+                self.initial_state
+            ));
+        }
+
+        for state in &self.states {
+            for action in state.entry_actions.iter().chain(&state.exit_actions) {
+                errors.extend(self.action_errors(state, action));
+            }
+
+            if let Some(sla) = &state.sla {
+                if !(1..=MAX_SLA_SECONDS).contains(&sla.due_in) {
+                    errors.push(format!(
+                        "SLA of state {} must be due in 1 to {} seconds",
+                        state.name, MAX_SLA_SECONDS
+                    ));
+                }
+
+                let mut escalation_ids = HashSet::new();
+                for escalation in &sla.escalations {
+                    if !escalation_ids.insert(escalation.id) {
+                        errors.push(format!(
+                            "escalation {} of state {} is declared more than once",
+                            escalation.id, state.name
+                        ));
+                    }
+
+                    if !(0..=MAX_SLA_SECONDS).contains(&escalation.after) {
+                        errors.push(format!(
+                            "escalation {} of state {} must fire 0 to {} seconds after the due date",
+                            escalation.id, state.name, MAX_SLA_SECONDS
+                        ));
+                    }
+
+                    if let EscalationAction::Transition { target_state_id } = escalation.action {
+                        if self.state(target_state_id).is_none() {
+                            errors.push(format!(
+                                "escalation {} of state {} targets unknown state {}",
+                                escalation.id, state.name, target_state_id
+                            ));
+                        }
+                    }
+                }
+            }
+
+            for transition in &state.transitions {
+                for target in transition.definition.targets() {
+                    if self.state(target).is_none() {
+                        errors.push(format!(
+                            "transition {} of state {} targets unknown state {}",
+                            transition.name, state.name, target
+                        ));
+                    }
+                }
+
+                if let TransitionDefinition::Parallel { join_state_id, .. } = transition.definition
+                {
+                    if self.state(join_state_id).is_none() {
+                        errors.push(format!(
+                            "fork state {} joins at unknown state {}",
+                            state.name, join_state_id
+                        ));
+                    }
+                }
+
+                if let TransitionDefinition::Automatic {
+                    guard: Some(guard), ..
+                } = &transition.definition
+                {
+                    if let Err(e) = Expression::parse(guard) {
+                        errors.push(format!(
+                            "invalid guard on transition {} of state {}: {}",
+                            transition.name, state.name, e
+                        ));
+                    }
+                }
+
+                if let TransitionDefinition::SubWorkflow {
+                    options, outcomes, ..
+                } = &transition.definition
+                {
+                    if state.transitions.len() > 1 {
+                        errors.push(format!(
+                            "state {} cannot have transitions other than its sub-workflow",
+                            state.name
+                        ));
+                    }
+
+                    let mut mapped = HashSet::new();
+                    for outcome in outcomes {
+                        if !options.iter().any(|o| o.id == outcome.option_id) {
+                            errors.push(format!(
+                                "sub-workflow of state {} maps to unknown option {}",
+                                state.name, outcome.option_id
+                            ));
+                        }
+
+                        if !mapped.insert(outcome.end_state_id) {
+                            errors.push(format!(
+                                "sub-workflow of state {} maps end state {} more than once",
+                                state.name, outcome.end_state_id
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Reachability checks are only meaningful once every target exists
+        if errors.is_empty() {
+            errors.extend(self.fork_errors());
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(AppError::validation_error(format!(
+                "Invalid workflow definition: {}",
+                errors.join("; ")
+            ))),
+        }
+    }
+
+    /// Checks the workflows invoked by sub-workflow states against the
+    /// database: they must belong to the same tenant, each of their end states
+    /// must be mapped to an option, and following the invocations must never
+    /// lead back to the workflow being saved.
+    pub fn validate_sub_workflows(
+        &self,
+        conn: &mut DbConnection,
+        tenant_id: i32,
+        workflow_id: Option<i64>,
+    ) -> Result<(), AppError> {
+        let errors = self.sub_workflow_errors(conn, tenant_id, workflow_id)?;
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(AppError::validation_error(format!(
+                "Invalid workflow definition: {}",
+                errors.join("; ")
+            ))),
+        }
+    }
+
+    fn sub_workflow_errors(
+        &self,
+        conn: &mut DbConnection,
+        tenant_id: i32,
+        workflow_id: Option<i64>,
+    ) -> Result<Vec<String>, AppError> {
+        let mut errors = Vec::new();
+
+        for (state, child_id, outcomes) in self.sub_workflows() {
+            let Some(child) = Workflow::find(conn, child_id)?
+                .filter(|w| w.tenant_id == tenant_id && w.deleted_at.is_none())
+            else {
+                errors.push(format!(
+                    "state {} invokes unknown workflow {}",
+                    state.name, child_id
+                ));
+                continue;
+            };
+
+            for end_state in child.definition.states.iter().filter(|s| s.is_end_state) {
+                if !outcomes.iter().any(|o| o.end_state_id == end_state.id) {
+                    errors.push(format!(
+                        "state {} does not map end state {} of workflow {}",
+                        state.name, end_state.name, child.name
+                    ));
+                }
+            }
+
+            for outcome in outcomes {
+                let is_end_state = child
+                    .definition
+                    .state(outcome.end_state_id)
+                    .is_some_and(|s| s.is_end_state);
+
+                if !is_end_state {
+                    errors.push(format!(
+                        "state {} maps {} which is not an end state of workflow {}",
+                        state.name, outcome.end_state_id, child.name
+                    ));
+                }
+            }
+        }
+
+        if let Some(workflow_id) = workflow_id {
+            if let Some(cycle) = self.invocation_cycle(conn, workflow_id)? {
+                errors.push(format!(
+                    "sub-workflows invoke workflow {} again through {}",
+                    workflow_id,
+                    cycle
+                        .iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" -> ")
+                ));
+            }
+        }
+
+        Ok(errors)
+    }
+
+    fn sub_workflows(&self) -> impl Iterator<Item = (&WorkflowState, i64, &[SubWorkflowOutcome])> {
+        self.states.iter().flat_map(|state| {
+            state
+                .transitions
+                .iter()
+                .filter_map(move |t| match &t.definition {
+                    TransitionDefinition::SubWorkflow {
+                        workflow_id,
+                        outcomes,
+                        ..
+                    } => Some((state, *workflow_id, outcomes.as_slice())),
+                    _ => None,
+                })
+        })
+    }
+
+    /// Follows the sub-workflow invocations depth first and returns the chain
+    /// of workflow ids that leads back to `workflow_id`, if any.
+    fn invocation_cycle(
+        &self,
+        conn: &mut DbConnection,
+        workflow_id: i64,
+    ) -> Result<Option<Vec<i64>>, AppError> {
+        let mut seen = HashSet::new();
+        let mut pending: Vec<Vec<i64>> = self
+            .sub_workflows()
+            .map(|(_, child_id, _)| vec![child_id])
+            .collect();
+
+        while let Some(path) = pending.pop() {
+            let current = path[path.len() - 1];
+
+            if current == workflow_id {
+                return Ok(Some(path));
+            }
+
+            if !seen.insert(current) {
+                continue;
+            }
+
+            let Some(workflow) = Workflow::find(conn, current)? else {
+                continue;
+            };
+
+            for (_, child_id, _) in workflow.definition.sub_workflows() {
+                let mut next = path.clone();
+                next.push(child_id);
+                pending.push(next);
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn action_errors(&self, state: &WorkflowState, action: &WorkflowAction) -> Vec<String> {
+        let ActionDefinition::Http {
+            url,
+            headers,
+            body,
+            failure_state_id,
+            ..
+        } = &action.definition
+        else {
+            return Vec::new();
+        };
+
+        let mut errors = Vec::new();
+        let templates = templating::check(url)
+            .and_then(|_| headers.values().try_for_each(|v| templating::check(v)))
+            .and_then(|_| body.iter().try_for_each(templating::check_value));
+
+        if let Err(e) = templates {
+            errors.push(format!(
+                "invalid template in action {} of state {}: {}",
+                action.name, state.name, e
+            ));
+        }
+
+        if let Some(failure_state_id) = failure_state_id {
+            if self.state(*failure_state_id).is_none() {
+                errors.push(format!(
+                    "action {} of state {} fails to unknown state {}",
+                    action.name, state.name, failure_state_id
+                ));
+            }
+
+            if state.is_end_state {
+                errors.push(format!(
+                    "action {} of end state {} cannot have a failure state",
+                    action.name, state.name
+                ));
+            }
+        }
+
+        errors
+    }
+
+    fn fork_errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut joins: HashMap<Uuid, &WorkflowState> = HashMap::new();
+
+        for state in &self.states {
+            let Some((branches, join_state_id)) = state.fork() else {
+                continue;
+            };
+
+            if state.transitions.len() > 1 {
+                errors.push(format!(
+                    "fork state {} cannot have transitions other than its fork",
+                    state.name
+                ));
+            }
+
+            if branches.len() < 2 {
+                errors.push(format!(
+                    "fork state {} needs at least two branches",
+                    state.name
+                ));
+            }
+
+            if let Some(other) = joins.insert(join_state_id, state) {
+                errors.push(format!(
+                    "fork states {} and {} share the same join state",
+                    other.name, state.name
+                ));
+            }
+
+            for branch in branches {
+                if let Some(problem) = self.branch_problem(*branch, join_state_id) {
+                    errors.push(format!(
+                        "branch {} of fork state {} {}",
+                        branch, state.name, problem
+                    ));
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Walks every path from the start of a branch and reports the first one
+    /// that can come to rest, or loop forever, without reaching the join.
+    fn branch_problem(&self, start: Uuid, join_state_id: Uuid) -> Option<String> {
+        let mut visited: Vec<&WorkflowState> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut pending = vec![start];
+
+        while let Some(state_id) = pending.pop() {
+            if state_id == join_state_id || !seen.insert(state_id) {
+                continue;
+            }
+
+            let state = self.state(state_id)?;
+
+            if state.is_end_state {
+                return Some(format!("can reach end state {} before joining", state.name));
+            }
+
+            if state.transitions.is_empty() {
+                return Some(format!("can stop at state {} before joining", state.name));
+            }
+
+            pending.extend(Self::branch_successors(state));
+            visited.push(state);
+        }
+
+        // Every state on the branch must still be able to reach the join,
+        // otherwise the branch can cycle without ever finishing
+        let mut joined = HashSet::from([join_state_id]);
+        loop {
+            let before = joined.len();
+            for state in &visited {
+                if Self::branch_successors(state)
+                    .iter()
+                    .any(|s| joined.contains(s))
+                {
+                    joined.insert(state.id);
+                }
+            }
+            if joined.len() == before {
+                break;
+            }
+        }
+
+        visited
+            .iter()
+            .find(|state| !joined.contains(&state.id))
+            .map(|state| format!("can loop through state {} without joining", state.name))
+    }
+
+    fn branch_successors(state: &WorkflowState) -> Vec<Uuid> {
+        state
+            .transitions
+            .iter()
+            .flat_map(|transition| match &transition.definition {
+                // A nested fork resumes from its own join
+                TransitionDefinition::Parallel { join_state_id, .. } => vec![*join_state_id],
+                definition => definition.targets(),
+            })
+            .collect()
+    }
+}
+
+impl Workflow {
+    /// Checks the workflows invoking the workflow as a sub-workflow against
+    /// its definition, once it has changed: each of them must still map every
+    /// end state of it and never be invoked again through it. Instances of a
+    /// workflow that no longer does could not resume once the sub-workflow
+    /// ends.
+    pub fn validate_invokers(&self, conn: &mut DbConnection) -> Result<(), AppError> {
+        let invokers = workflows::table
+            .select(Workflow::as_select())
+            .filter(workflows::tenant_id.eq(self.tenant_id))
+            .filter(workflows::deleted_at.is_null())
+            .filter(workflows::id.ne(self.id))
+            .filter(workflows::definition.contains(json!({
+                "states": [{
+                    "transitions": [{
+                        "definition": { "type": "SubWorkflow", "workflow_id": self.id },
+                    }],
+                }],
+            })))
+            .order(workflows::id.asc())
+            .get_results(conn)?;
+
+        let mut errors = Vec::new();
+        for invoker in invokers {
+            for error in
+                invoker
+                    .definition
+                    .sub_workflow_errors(conn, invoker.tenant_id, Some(invoker.id))?
+            {
+                errors.push(format!("workflow {} invoking it: {}", invoker.name, error));
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(AppError::validation_error(format!(
+                "Invalid workflow definition: {}",
+                errors.join("; ")
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::super::fixtures::{
+        automatic, definition, end_state, id, manual, option, parallel, state,
+    };
+    use super::*;
+
+    fn error(definition: &WorkflowDefinition) -> String {
+        match definition.validate() {
+            Ok(()) => panic!("definition is valid"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn escalating(escalations: serde_json::Value) -> WorkflowDefinition {
+        definition(vec![
+            state(
+                1,
+                "Review",
+                json!({
+                    "transitions": [automatic(10, 2)],
+                    "sla": { "due_in": 3600, "escalations": escalations },
+                }),
+            ),
+            end_state(2, "Done"),
+        ])
+    }
+
+    #[test]
+    fn accepts_escalations_to_known_states() {
+        let definition = escalating(json!([
+            { "id": id(20), "after": 0, "action": { "type": "Reassign", "user_id": 1 } },
+            {
+                "id": id(21),
+                "after": 60,
+                "action": { "type": "Transition", "target_state_id": id(2) },
+            },
+        ]));
+
+        assert!(definition.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_escalations_to_unknown_states() {
+        let definition = escalating(json!([{
+            "id": id(20),
+            "action": { "type": "Transition", "target_state_id": id(9) },
+        }]));
+
+        assert!(error(&definition).contains(&format!(
+            "escalation {} of state Review targets unknown state {}",
+            id(20),
+            id(9)
+        )));
+    }
+
+    #[test]
+    fn rejects_escalations_declared_twice() {
+        let definition = escalating(json!([
+            { "id": id(20), "action": { "type": "Reassign", "user_id": 1 } },
+            { "id": id(20), "after": 60, "action": { "type": "Reassign", "user_id": 2 } },
+        ]));
+
+        assert!(error(&definition).contains(&format!(
+            "escalation {} of state Review is declared more than once",
+            id(20)
+        )));
+    }
+
+    #[test]
+    fn rejects_forks_joining_at_unknown_states() {
+        let definition = definition(vec![
+            state(
+                1,
+                "Fork",
+                json!({ "transitions": [parallel(10, &[2, 3], 9)] }),
+            ),
+            state(2, "Legal", json!({ "transitions": [automatic(11, 4)] })),
+            state(3, "Finance", json!({ "transitions": [automatic(12, 4)] })),
+            end_state(4, "Done"),
+        ]);
+
+        assert!(error(&definition)
+            .contains(&format!("fork state Fork joins at unknown state {}", id(9))));
+    }
+
+    #[test]
+    fn accepts_nested_forks() {
+        let definition = definition(vec![
+            state(
+                1,
+                "Fork",
+                json!({ "transitions": [parallel(10, &[2, 3], 6)] }),
+            ),
+            state(
+                2,
+                "Inner fork",
+                json!({ "transitions": [parallel(11, &[4, 5], 7)] }),
+            ),
+            state(3, "Finance", json!({ "transitions": [automatic(12, 6)] })),
+            state(4, "Legal", json!({ "transitions": [automatic(13, 7)] })),
+            state(5, "Security", json!({ "transitions": [automatic(14, 7)] })),
+            state(6, "Join", json!({ "transitions": [automatic(15, 8)] })),
+            state(
+                7,
+                "Inner join",
+                json!({ "transitions": [automatic(16, 6)] }),
+            ),
+            end_state(8, "Done"),
+        ]);
+
+        assert!(definition.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_nested_forks_whose_branches_miss_the_inner_join() {
+        let definition = definition(vec![
+            state(
+                1,
+                "Fork",
+                json!({ "transitions": [parallel(10, &[2, 3], 6)] }),
+            ),
+            state(
+                2,
+                "Inner fork",
+                json!({ "transitions": [parallel(11, &[4, 5], 7)] }),
+            ),
+            state(3, "Finance", json!({ "transitions": [automatic(12, 6)] })),
+            state(4, "Legal", json!({ "transitions": [automatic(13, 7)] })),
+            state(5, "Security", json!({ "transitions": [automatic(14, 6)] })),
+            state(6, "Join", json!({ "transitions": [automatic(15, 8)] })),
+            state(
+                7,
+                "Inner join",
+                json!({ "transitions": [automatic(16, 6)] }),
+            ),
+            end_state(8, "Done"),
+        ]);
+
+        let error = error(&definition);
+
+        assert!(error.contains(&format!(
+            "branch {} of fork state Inner fork can reach end state Done before joining",
+            id(5)
+        )));
+    }
+
+    #[test]
+    fn rejects_branches_that_never_reach_the_join() {
+        let ends = definition(vec![
+            state(
+                1,
+                "Fork",
+                json!({ "transitions": [parallel(10, &[2, 3], 4)] }),
+            ),
+            state(2, "Legal", json!({ "transitions": [automatic(11, 4)] })),
+            state(
+                3,
+                "Finance",
+                json!({ "transitions": [manual(12, &[option(20, "Reject", 5), option(21, "Approve", 4)])] }),
+            ),
+            state(4, "Join", json!({ "transitions": [automatic(13, 5)] })),
+            end_state(5, "Done"),
+        ]);
+        let stops = definition(vec![
+            state(
+                1,
+                "Fork",
+                json!({ "transitions": [parallel(10, &[2, 3], 4)] }),
+            ),
+            state(2, "Legal", json!({ "transitions": [automatic(11, 4)] })),
+            state(3, "Finance", json!({})),
+            state(4, "Join", json!({ "transitions": [automatic(13, 5)] })),
+            end_state(5, "Done"),
+        ]);
+        let loops = definition(vec![
+            state(
+                1,
+                "Fork",
+                json!({ "transitions": [parallel(10, &[2, 3], 4)] }),
+            ),
+            state(2, "Legal", json!({ "transitions": [automatic(11, 4)] })),
+            state(
+                3,
+                "Finance",
+                json!({ "transitions": [manual(12, &[option(20, "Again", 6)])] }),
+            ),
+            state(4, "Join", json!({ "transitions": [automatic(13, 5)] })),
+            end_state(5, "Done"),
+            state(
+                6,
+                "Rework",
+                json!({ "transitions": [manual(14, &[option(21, "Back", 3)])] }),
+            ),
+        ]);
+
+        assert!(error(&ends).contains(&format!(
+            "branch {} of fork state Fork can reach end state Done before joining",
+            id(3)
+        )));
+        assert!(error(&stops).contains(&format!(
+            "branch {} of fork state Fork can stop at state Finance before joining",
+            id(3)
+        )));
+        assert!(error(&loops).contains(&format!(
+            "branch {} of fork state Fork can loop through state",
+            id(3)
+        )));
+    }
+}
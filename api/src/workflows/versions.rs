@@ -0,0 +1,102 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tsync::tsync;
+
+use crate::database::{schema::workflow_versions, DbConnection};
+use crate::result::{AppError, Result};
+
+use super::{Workflow, WorkflowDefinition};
+
+/// A definition the workflow has had. Versions are numbered from 1, and a new
+/// one is recorded whenever the definition changes.
+#[derive(Clone, Debug, Deserialize, Identifiable, JsonSchema, Queryable, Selectable, Serialize)]
+#[tsync]
+#[diesel(table_name = workflow_versions)]
+pub struct WorkflowVersion {
+    pub id: i64,
+    pub workflow_id: i64,
+    pub version: i32,
+    pub definition: WorkflowDefinition,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize)]
+#[tsync]
+pub struct VersionDiffQuery {
+    /// The version to compare with, by default the one before.
+    pub base: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = workflow_versions)]
+struct NewWorkflowVersion {
+    workflow_id: i64,
+    version: i32,
+    definition: WorkflowDefinition,
+}
+
+impl WorkflowVersion {
+    /// Records the workflow's definition as its next version, unless it is
+    /// the same as the latest one.
+    pub fn record(conn: &mut DbConnection, workflow: &Workflow) -> Result<Option<WorkflowVersion>> {
+        let latest = Self::latest(conn, workflow.id)?;
+        if let Some(latest) = &latest {
+            let unchanged = serde_json::to_value(&latest.definition).ok()
+                == serde_json::to_value(&workflow.definition).ok();
+            if unchanged {
+                return Ok(None);
+            }
+        }
+
+        let version = diesel::insert_into(workflow_versions::table)
+            .values(NewWorkflowVersion {
+                workflow_id: workflow.id,
+                version: latest.map_or(1, |v| v.version + 1),
+                definition: workflow.definition.clone(),
+            })
+            .returning(WorkflowVersion::as_returning())
+            .get_result(conn)?;
+
+        Ok(Some(version))
+    }
+
+    /// Lists the versions of the workflow, the latest first.
+    pub fn list(conn: &mut DbConnection, workflow_id: i64) -> Result<Vec<WorkflowVersion>> {
+        Ok(workflow_versions::table
+            .select(WorkflowVersion::as_select())
+            .filter(workflow_versions::workflow_id.eq(workflow_id))
+            .order(workflow_versions::version.desc())
+            .load(conn)?)
+    }
+
+    pub fn find(
+        conn: &mut DbConnection,
+        workflow_id: i64,
+        version: i32,
+    ) -> Result<Option<WorkflowVersion>> {
+        Ok(workflow_versions::table
+            .select(WorkflowVersion::as_select())
+            .filter(workflow_versions::workflow_id.eq(workflow_id))
+            .filter(workflow_versions::version.eq(version))
+            .get_result(conn)
+            .optional()?)
+    }
+
+    /// Finds the version, failing when the workflow has no such version.
+    pub fn get(conn: &mut DbConnection, workflow_id: i64, version: i32) -> Result<WorkflowVersion> {
+        Self::find(conn, workflow_id, version)?.ok_or_else(|| {
+            AppError::not_found("WorkflowVersion", &format!("{}/{}", workflow_id, version))
+        })
+    }
+
+    pub fn latest(conn: &mut DbConnection, workflow_id: i64) -> Result<Option<WorkflowVersion>> {
+        Ok(workflow_versions::table
+            .select(WorkflowVersion::as_select())
+            .filter(workflow_versions::workflow_id.eq(workflow_id))
+            .order(workflow_versions::version.desc())
+            .first(conn)
+            .optional()?)
+    }
+}
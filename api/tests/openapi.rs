@@ -0,0 +1,104 @@
+//! Checks the OpenAPI document against the routes the API serves.
+
+use actix_web::http::{Method, StatusCode};
+use actix_web::test::{call_service, init_service, read_body, TestRequest};
+use actix_web::web::scope;
+use actix_web::App;
+use serde_json::Value;
+
+use daedalus::api::{openapi, routes};
+
+/// Fills in the parameters of the path with values of their type.
+fn sample_path(path: &str, operation: &Value) -> String {
+    let parameters = operation["parameters"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+
+    path.split('/')
+        .map(
+            |segment| match segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                Some(name) => {
+                    let parameter = parameters
+                        .iter()
+                        .find(|p| p["in"] == "path" && p["name"] == name)
+                        .unwrap_or_else(|| {
+                            panic!("{} does not declare the parameter {}", path, name)
+                        });
+                    match parameter["schema"]["type"].as_str() {
+                        Some("integer") => "1".to_string(),
+                        _ => "sample".to_string(),
+                    }
+                }
+                None => segment.to_string(),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Every operation of the document is served by the route it describes, and
+/// not by another route matching the same path first.
+#[actix_web::test]
+async fn operations_are_served_by_their_routes() {
+    let app = init_service(App::new().service(routes(scope("/api")))).await;
+    let paths = openapi::document()["paths"].as_object().unwrap();
+
+    for (path, item) in paths {
+        for (method, operation) in item.as_object().unwrap() {
+            let uri = sample_path(path, operation);
+            let request = TestRequest::default()
+                .method(Method::from_bytes(method.to_uppercase().as_bytes()).unwrap())
+                .uri(&uri)
+                .to_request();
+
+            let response = call_service(&app, request).await;
+            let pattern = response.request().match_pattern();
+            let status = response.status();
+            let body = read_body(response).await;
+
+            // Requests no route accepts get an empty 404 from the router,
+            // while handlers respond to errors with a JSON body.
+            assert!(
+                status != StatusCode::NOT_FOUND || !body.is_empty(),
+                "{} {} is documented but not served",
+                method.to_uppercase(),
+                path
+            );
+            assert_eq!(
+                pattern.as_deref(),
+                Some(path.as_str()),
+                "{} {} is served by the route of {:?}",
+                method.to_uppercase(),
+                path,
+                pattern
+            );
+        }
+    }
+}
+
+/// Every schema the document refers to is one of its components.
+#[test]
+fn references_resolve() {
+    fn check(value: &Value, schemas: &serde_json::Map<String, Value>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(reference)) = map.get("$ref") {
+                    let name = reference
+                        .strip_prefix("#/components/schemas/")
+                        .unwrap_or_else(|| panic!("{} is not a component", reference));
+                    assert!(schemas.contains_key(name), "{} is not defined", reference);
+                }
+                map.values().for_each(|v| check(v, schemas));
+            }
+            Value::Array(items) => items.iter().for_each(|v| check(v, schemas)),
+            _ => {}
+        }
+    }
+
+    let document = openapi::document();
+    check(
+        document,
+        document["components"]["schemas"].as_object().unwrap(),
+    );
+}
@@ -230,6 +230,7 @@ export async function getWorkflow(_: number) {
       },
     },
     definition: {
+      schema_version: 1,
       initial_state: "018cdc83-8406-7eed-9139-6f2d57602053",
       states: [
         {
@@ -0,0 +1,9 @@
+/* Arbitrary JSON, used for `serde_json::Value` fields in rust.d.ts */
+
+type Value =
+  | null
+  | boolean
+  | number
+  | string
+  | Array<Value>
+  | { [key: string]: Value };
@@ -1,39 +1,503 @@
 /* This file is generated and managed by tsync */
 
 interface Paginated<T> {
-  total: number;
+  /** The number of matching rows, `None` when counting was skipped. */
+  total?: number;
   page: number;
-  per_page: number;
+  page_size: number;
+  /** The cursor of the next page, for lists that support cursors. */
+  next_cursor?: string;
   data: Array<T>;
 }
 
+interface BulkOptions {
+  /**
+   * Whether to roll back the whole batch when any operation fails. The
+   * default is `true`.
+   */
+  atomic: boolean;
+  /** Whether to only report what the batch would do. */
+  dry_run: boolean;
+}
+
+/** The outcome of one operation of a batch, at its position in the batch. */
+interface BulkRowResult<T> {
+  index: number;
+  result?: T;
+  error?: string;
+}
+
+interface BulkResult<T> {
+  dry_run: boolean;
+  /**
+   * Whether the changes were kept. Dry runs and atomic batches with a
+   * failed operation are rolled back.
+   */
+  committed: boolean;
+  succeeded: number;
+  failed: number;
+  rows: Array<BulkRowResult<T>>;
+}
+
+/** Declares the custom fields stored in the data of a workflow's instances. */
+interface DataSchema {
+  fields: Array<DataField>;
+}
+
+interface DataField {
+  /** The key of the field in the instance data. */
+  name: string;
+  label: string;
+  description?: string;
+  field_type: DataFieldType;
+  required: boolean;
+  /** The allowed values of an `Enum` field. */
+  options: Array<string>;
+  /** The value used when the field is missing from the data. */
+  default?: Value;
+}
+
+type DataFieldType =
+  | "Text" | "Number" | "Integer" | "Boolean" | "Date" | "UserId" | "Enum";
+
+type CallStatus =
+  | "Pending" | "Succeeded" | "Failed" | "Halted";
+
+/**
+ * An HTTP action a token is waiting on. Calls are made outside of the
+ * transaction that reached the action, and the token is moved on in another
+ * once the endpoint has responded.
+ */
+interface InstanceCall {
+  id: number;
+  instance_id: number;
+  token_id: number;
+  state_id: string;
+  action_id: string;
+  /**
+   * Whether the action is an exit action of the state rather than an
+   * entry action.
+   */
+  exiting: boolean;
+  /**
+   * The state an exiting token is leaving for, `None` when the state is a
+   * fork and the token is leaving down its branches.
+   */
+  target_state_id?: string;
+  completed_by?: number;
+  option_id?: string;
+  comment?: string;
+  status: CallStatus;
+  error?: string;
+  /**
+   * Until when the call is being made by a worker. Pending calls whose
+   * lease has run out are made again.
+   */
+  locked_until: Date;
+  created_at: Date;
+  updated_at: Date;
+  completed_at?: Date;
+}
+
+interface MigrateInstances {
+  /** The version to move the instances to, by default the latest. */
+  to_version?: number;
+  /** Only move instances running this version. */
+  from_version?: number;
+  /**
+   * The instances to move, by default every running instance of the
+   * workflow on another version.
+   */
+  instance_ids?: Array<number>;
+  /**
+   * The state of the target version each state is moved to. States that
+   * are not listed move to the state with the same id. No state may move
+   * to an end state.
+   */
+  state_mapping: Record<string, string>;
+}
+
+interface TokenMove {
+  token_id: number;
+  from_state_id: string;
+  to_state_id: string;
+}
+
+interface MigratedInstance {
+  instance_id: number;
+  from_version: number;
+  to_version: number;
+  /**
+   * The moves of the instance's active tokens, including those that stay
+   * in a state with the same id.
+   */
+  moves: Array<TokenMove>;
+}
+
+/** A record of a token moved from one version of the workflow to another. */
+interface InstanceMigration {
+  id: number;
+  instance_id: number;
+  token_id: number;
+  from_version: number;
+  to_version: number;
+  from_state_id: string;
+  to_state_id: string;
+  migrated_by?: number;
+  created_at: Date;
+}
+
+interface WorkflowInstance {
+  id: number;
+  tenant_id: number;
+  workflow_id: number;
+  /** The version of the workflow the instance runs. */
+  workflow_version: number;
+  current_state_id: string;
+  data: Value;
+  created_by?: number;
+  assignee_id?: number;
+  created_at: Date;
+  updated_at: Date;
+  completed_at?: Date;
+  deleted_at?: Date;
+  /** The instance whose sub-workflow state started this instance. */
+  parent_id?: number;
+  parent_token_id?: number;
+}
+
+interface StartInstance {
+  workflow_id: number;
+  data?: Value;
+}
+
+interface TransitionInstance {
+  transition_id: string;
+  option_id?: string;
+  comment?: string;
+  data?: Value;
+}
+
+interface AssignInstance {
+  user_id: number;
+}
+
+interface BulkAssign {
+  instance_ids: Array<number>;
+  /** The user to assign the instances to, or `None` to unassign them. */
+  user_id?: number;
+}
+
+interface BulkTransition {
+  instance_ids: Array<number>;
+  transition_id: string;
+  option_id?: string;
+  comment?: string;
+  data?: Value;
+}
+
+interface BulkTransitionResult {
+  /**
+   * The state the transition leads to, before any automatic transitions
+   * out of it are taken.
+   */
+  target_state_id: string;
+  /** The instance after the transition, not set for dry runs. */
+  instance?: WorkflowInstance;
+}
+
+interface InstanceQuery {
+  tenant_id?: number;
+  workflow_id?: number;
+  assignee_id?: number;
+  current_state_id?: string;
+  parent_id?: number;
+  completed?: boolean;
+  overdue?: boolean;
+  /** Search text, matched against the values in the instance data. */
+  q?: string;
+  active: boolean;
+  page: number;
+  page_size: number;
+}
+
+interface SimulateWorkflow {
+  /**
+   * A definition to simulate instead of the stored one, such as unsaved
+   * changes from the editor.
+   */
+  definition?: WorkflowDefinition;
+  /** The data the instance starts with. */
+  data?: Value;
+  /** The transitions to take in order, with the option chosen for each. */
+  steps: Array<TransitionInstance>;
+  /** The responses HTTP actions receive, by action id. */
+  http_responses: Record<string, SimulatedResponse>;
+}
+
+interface SimulatedResponse {
+  status: number;
+  body: Value;
+}
+
+type SimulationOutcome =
+  | "completed" | "waiting" | "stuck";
+
+type ActionPhase =
+  | "entry" | "exit";
+
+/**
+ * A state the instance entered. The step is the index of the step that led
+ * there, and is not set for the states entered when the instance starts.
+ */
+interface SimulatedVisit {
+  step?: number;
+  /**
+   * The branch of the instance, 0 for the instance itself and a higher
+   * number for each branch of a fork.
+   */
+  token: number;
+  state_id: string;
+  state_name: string;
+  /** The transition the state was entered through. */
+  transition_id?: string;
+  option_id?: string;
+}
+
+interface SimulatedAction {
+  step?: number;
+  state_id: string;
+  action_id: string;
+  phase: ActionPhase;
+  /** What the action would do. */
+  effect: string;
+  /** The state the instance is sent to when the action fails. */
+  failure_state_id?: string;
+}
+
+interface GuardEvaluation {
+  step?: number;
+  state_id: string;
+  transition_id: string;
+  guard: string;
+  result?: boolean;
+  error?: string;
+}
+
+interface SimulationStop {
+  /** The step that failed, not set when starting the instance failed. */
+  step?: number;
+  state_id?: string;
+  reason: string;
+}
+
+/** A state the instance rests in, with the choices that move it on. */
+interface SimulatedWait {
+  token: number;
+  state_id: string;
+  state_name: string;
+  choices: Array<SimulatedChoice>;
+}
+
+interface SimulatedChoice {
+  transition_id: string;
+  option_id?: string;
+  label: string;
+  target_state_id: string;
+}
+
+interface Simulation {
+  outcome: SimulationOutcome;
+  stuck?: SimulationStop;
+  path: Array<SimulatedVisit>;
+  actions: Array<SimulatedAction>;
+  guards: Array<GuardEvaluation>;
+  waiting: Array<SimulatedWait>;
+  /** The instance data after the last step. */
+  data: Value;
+}
+
+/**
+ * A record of an instance's stay in a single state. A task is opened when
+ * the instance enters a state and completed when it leaves.
+ */
+interface InstanceTask {
+  id: number;
+  instance_id: number;
+  state_id: string;
+  assignee_id?: number;
+  completed_by?: number;
+  option_id?: string;
+  comment?: string;
+  created_at: Date;
+  updated_at: Date;
+  completed_at?: Date;
+  due_at?: Date;
+  token_id: number;
+}
+
+/**
+ * A position of an instance in its workflow. Every instance has a root token,
+ * and a fork parks its token at the fork state while one child token runs
+ * each branch. The parent resumes at the join once every child has arrived.
+ */
+interface InstanceToken {
+  id: number;
+  instance_id: number;
+  parent_id?: number;
+  state_id: string;
+  created_at: Date;
+  updated_at: Date;
+  completed_at?: Date;
+}
+
+type SortDirection =
+  | "asc" | "desc";
+
+type SearchType =
+  | "workflow" | "instance" | "user";
+
+interface SearchQuery {
+  /** The search text. */
+  q: string;
+  /**
+   * A comma separated list of the types to search, all of them when not
+   * set.
+   */
+  types?: string;
+  tenant_id?: number;
+  limit: number;
+}
+
+/** A search result, along with its rank. Higher ranks are better matches. */
+type SearchHit =
+  | SearchHit__Workflow
+  | SearchHit__Instance
+  | SearchHit__User;
+
+type SearchHit__Workflow = {
+  type: "workflow";
+  rank: number;
+  workflow: Workflow;
+};
+type SearchHit__Instance = {
+  type: "instance";
+  rank: number;
+  instance: WorkflowInstance;
+};
+type SearchHit__User = {
+  type: "user";
+  rank: number;
+  user: User;
+};
+
 interface Tenant {
   id: number;
   name: string;
   created_at: Date;
   updated_at: Date;
   deleted_at?: Date;
+  /**
+   * The hosts the HTTP actions and webhooks of the tenant may call. A
+   * leading `*.` allows every subdomain of the host, and a trailing
+   * `:port` that port rather than the default one of the URL's scheme.
+   */
+  allowed_hosts: Array<string>;
 }
 
+type TenantSort =
+  | "id" | "name" | "created_at" | "updated_at";
+
 interface TenantQuery {
   name?: string;
+  /** Conditions on the fields, see [`crate::filters`]. */
+  filter?: string;
   active: boolean;
+  sort: TenantSort;
+  direction: SortDirection;
+  /**
+   * The cursor of the page to fetch, returned with the page before it.
+   * When set, `page` is ignored.
+   */
+  cursor?: string;
+  /** Whether to count the matching tenants. */
+  count: boolean;
   page: number;
   page_size: number;
 }
 
 interface CreateTenant {
   name: string;
+  allowed_hosts: Array<string>;
 }
 
 interface UpdateTenant {
   name?: string;
+  allowed_hosts?: Array<string>;
+}
+
+/** How a trigger authenticates requests. */
+type TriggerAuth =
+  | "Hmac" | "ApiKey";
+
+interface WorkflowTrigger {
+  id: number;
+  workflow_id: number;
+  name: string;
+  /**
+   * The state whose waiting instances the trigger resumes. Triggers
+   * without a state start new instances.
+   */
+  state_id?: string;
+  /** The transition taken out of the state when an instance is resumed. */
+  transition_id?: string;
+  /** Identifies the trigger in its URL. */
+  token: string;
+  secret: string;
+  auth: TriggerAuth;
+  created_at: Date;
+  updated_at: Date;
+  deleted_at?: Date;
+}
+
+interface CreateWorkflowTrigger {
+  name: string;
+  auth: TriggerAuth;
+  /**
+   * The transition resumed instances leave their state through. When
+   * omitted, the trigger starts new instances.
+   */
+  transition_id?: string;
+}
+
+/**
+ * A newly created trigger along with its secret, which is only ever returned
+ * here.
+ */
+interface TriggerCredentials {
+  trigger: WorkflowTrigger;
+  secret: string;
+}
+
+/** The body of a request to a trigger that resumes instances. */
+interface ResumeInstance {
+  instance_id: number;
+  option_id?: string;
+  comment?: string;
+  data?: Value;
 }
 
 interface CreateUser {
   tenant_id: number;
   email: string;
   password: string;
+  name?: string;
+}
+
+interface UpdateUser {
+  email?: string;
+  password?: string;
+  name?: string;
 }
 
 interface User {
@@ -41,19 +505,371 @@ interface User {
   tenant_id: number;
   email: string;
   password: string;
+  name?: string;
   created_at: Date;
   updated_at: Date;
   deleted_at?: Date;
 }
 
+type UserSort =
+  | "id" | "email" | "created_at" | "updated_at";
+
 interface UserQuery {
   tenant_id?: number;
   email?: string;
+  name?: string;
+  /** Search text, matched against the name and email. */
+  q?: string;
+  /** Conditions on the fields, see [`crate::filters`]. */
+  filter?: string;
+  active: boolean;
+  sort: UserSort;
+  direction: SortDirection;
+  /**
+   * The cursor of the page to fetch, returned with the page before it.
+   * When set, `page` is ignored.
+   */
+  cursor?: string;
+  /** Whether to count the matching users. */
+  count: boolean;
+  page: number;
+  page_size: number;
+}
+
+/** An operation of a bulk request on users. */
+type UserOperation =
+  | UserOperation__Create
+  | UserOperation__Update
+  | UserOperation__Deactivate;
+
+type UserOperation__Create = {
+  op: "create";
+  tenant_id: number;
+  email: string;
+  password: string;
+  name?: string;
+};
+type UserOperation__Update = {
+  op: "update";
+  id: number;
+  email?: string;
+  password?: string;
+  name?: string;
+};
+/** Deactivates the user, who can then no longer sign in. */
+type UserOperation__Deactivate = {
+  op: "deactivate";
+  id: number;
+};
+
+interface UserCredentials {
+  tenant_id: number;
+  email: string;
+  password: string;
+}
+
+type DeliveryStatus =
+  | "Pending" | "Delivered" | "Dead";
+
+/**
+ * A single event queued for a subscription, along with the outcome of its
+ * latest attempt.
+ */
+interface WebhookDelivery {
+  id: number;
+  subscription_id: number;
+  event: string;
+  payload: Value;
+  status: DeliveryStatus;
+  attempts: number;
+  next_attempt_at: Date;
+  /** The HTTP status the subscriber responded with on the latest attempt. */
+  response_status?: number;
+  error?: string;
+  created_at: Date;
+  updated_at: Date;
+  delivered_at?: Date;
+}
+
+interface DeliveryQuery {
+  status?: DeliveryStatus;
+  page: number;
+  page_size: number;
+}
+
+interface WebhookSubscription {
+  id: number;
+  tenant_id: number;
+  url: string;
+  /**
+   * The names of the events sent to the subscription. When empty, every
+   * event of the tenant is sent.
+   */
+  events: Array<string>;
+  secret: string;
+  created_at: Date;
+  updated_at: Date;
+  deleted_at?: Date;
+}
+
+interface CreateWebhookSubscription {
+  tenant_id: number;
+  url: string;
+  events: Array<string>;
+  /** The key payloads are signed with. */
+  secret: string;
+}
+
+interface UpdateWebhookSubscription {
+  url?: string;
+  events?: Array<string>;
+  secret?: string;
+}
+
+interface WebhookQuery {
+  tenant_id?: number;
   active: boolean;
   page: number;
   page_size: number;
 }
 
+interface DefinitionDiff {
+  initial_state?: InitialStateChange;
+  states: Array<StateChange>;
+  transitions: Array<TransitionChange>;
+  actions: Array<ActionChange>;
+  data_schema_changed: boolean;
+  positions: Array<PositionChange>;
+  /** Whether the positions of states are the only difference. */
+  layout_only: boolean;
+}
+
+type StateChange =
+  | StateChange__Added
+  | StateChange__Removed
+  | StateChange__Renamed
+  | StateChange__Modified;
+
+type StateChange__Added = {
+  change: "Added";
+  state_id: string;
+  name: string;
+};
+type StateChange__Removed = {
+  change: "Removed";
+  state_id: string;
+  name: string;
+};
+type StateChange__Renamed = {
+  change: "Renamed";
+  state_id: string;
+  from: string;
+  to: string;
+};
+/** The fields of the state that changed other than its name. */
+type StateChange__Modified = {
+  change: "Modified";
+  state_id: string;
+  name: string;
+  fields: Array<string>;
+};
+
+type TransitionChange =
+  | TransitionChange__Added
+  | TransitionChange__Removed
+  | TransitionChange__Renamed
+  | TransitionChange__Retargeted
+  | TransitionChange__Modified;
+
+type TransitionChange__Added = {
+  change: "Added";
+  state_id: string;
+  transition_id: string;
+  name: string;
+};
+type TransitionChange__Removed = {
+  change: "Removed";
+  state_id: string;
+  transition_id: string;
+  name: string;
+};
+type TransitionChange__Renamed = {
+  change: "Renamed";
+  state_id: string;
+  transition_id: string;
+  from: string;
+  to: string;
+};
+/** The states the transition leads to changed. */
+type TransitionChange__Retargeted = {
+  change: "Retargeted";
+  state_id: string;
+  transition_id: string;
+  name: string;
+  from: Array<string>;
+  to: Array<string>;
+};
+/**
+ * The fields of the transition that changed other than its name and
+ * targets, such as `definition.guard`.
+ */
+type TransitionChange__Modified = {
+  change: "Modified";
+  state_id: string;
+  transition_id: string;
+  name: string;
+  fields: Array<string>;
+};
+
+type ActionChange =
+  | ActionChange__Added
+  | ActionChange__Removed
+  | ActionChange__Renamed
+  | ActionChange__Modified;
+
+type ActionChange__Added = {
+  change: "Added";
+  state_id: string;
+  action_id: string;
+  name: string;
+};
+type ActionChange__Removed = {
+  change: "Removed";
+  state_id: string;
+  action_id: string;
+  name: string;
+};
+type ActionChange__Renamed = {
+  change: "Renamed";
+  state_id: string;
+  action_id: string;
+  from: string;
+  to: string;
+};
+/**
+ * The fields of the action that changed other than its name, such as
+ * `definition.url`, or `phase` when it moved between the entry and exit
+ * actions.
+ */
+type ActionChange__Modified = {
+  change: "Modified";
+  state_id: string;
+  action_id: string;
+  name: string;
+  fields: Array<string>;
+};
+
+interface InitialStateChange {
+  from: string;
+  to: string;
+}
+
+interface PositionChange {
+  state_id: string;
+  from?: WorkflowPosition;
+  to?: WorkflowPosition;
+}
+
+interface WorkflowExport {
+  /** The version of the document format. */
+  version: number;
+  exported_at: Date;
+  name: string;
+  description?: string;
+  definition: WorkflowDefinition;
+  /** The users the definition refers to. */
+  users: Array<ExportedUser>;
+  /**
+   * The ids of the templates the definition's emails and notifications
+   * use.
+   */
+  templates: Array<number>;
+  /** The workflows the definition invokes as sub-workflows. */
+  workflows: Array<ExportedWorkflow>;
+}
+
+interface ExportedUser {
+  id: number;
+  email: string;
+  name?: string;
+}
+
+interface ExportedWorkflow {
+  id: number;
+  name: string;
+  /** The end states of the workflow, which sub-workflow outcomes refer to. */
+  end_states: Array<ExportedState>;
+}
+
+interface ExportedState {
+  id: string;
+  name: string;
+}
+
+type ExportFormat =
+  | "json" | "yaml";
+
+interface ExportQuery {
+  format: ExportFormat;
+}
+
+interface ImportQuery {
+  /** The tenant to create the workflow in. */
+  tenant_id: number;
+  /** The name of the new workflow, the exported name when not set. */
+  name?: string;
+  /** Whether to only report how the references would be resolved. */
+  dry_run: boolean;
+  /**
+   * Whether to create the workflow even when some references cannot be
+   * resolved, in which case they keep their exported ids.
+   */
+  allow_unresolved: boolean;
+  /**
+   * Users of the target tenant to use in place of exported ones, as
+   * comma separated `exported:target` pairs of ids.
+   */
+  user_ids?: string;
+  /** Templates to use in place of exported ones, as `exported:target` pairs. */
+  template_ids?: string;
+  /**
+   * Workflows of the target tenant to invoke in place of exported ones, as
+   * `exported:target` pairs.
+   */
+  workflow_ids?: string;
+}
+
+type ReferenceKind =
+  | "user" | "template" | "workflow" | "state";
+
+interface UnresolvedReference {
+  kind: ReferenceKind;
+  /** The id of the reference in the document. */
+  id: string;
+  reason: string;
+}
+
+interface WorkflowImport {
+  /**
+   * The new workflow, not set for dry runs or when references could not be
+   * resolved.
+   */
+  workflow?: Workflow;
+  /** The definition with its ids replaced and references resolved. */
+  definition: WorkflowDefinition;
+  unresolved: Array<UnresolvedReference>;
+}
+
+type LintRule =
+  | "dead_end" | "end_state_transitions" | "invalid_approver" | "missing_assignee" | "duplicate_option_labels" | "inescapable_cycle";
+
+interface LintWarning {
+  rule: LintRule;
+  message: string;
+  state_id?: string;
+  transition_id?: string;
+}
+
 interface WorkflowPosition {
   x: number;
   y: number;
@@ -72,13 +888,29 @@ interface UpdateWorkflow {
   definition?: WorkflowDefinition;
 }
 
+type WorkflowSort =
+  | "id" | "name" | "created_at" | "updated_at";
+
 interface WorkflowQuery {
   tenant_id?: number;
   name?: string;
   description?: string;
+  /** Search text, matched against the name and description. */
+  q?: string;
+  /** Conditions on the fields, see [`crate::filters`]. */
+  filter?: string;
   active: boolean;
+  sort: WorkflowSort;
+  direction: SortDirection;
+  /**
+   * The cursor of the page to fetch, returned with the page before it.
+   * When set, `page` is ignored.
+   */
+  cursor?: string;
+  /** Whether to count the matching workflows. */
+  count: boolean;
   page: number;
-  per_page: number;
+  page_size: number;
 }
 
 interface Workflow {
@@ -87,19 +919,26 @@ interface Workflow {
   name: string;
   description?: string;
   definition: WorkflowDefinition;
-  editor_metadata: WorkflowEditorMetadata;
   created_at: Date;
   updated_at: Date;
   deleted_at?: Date;
 }
 
-interface WorkflowEditorMetadata {
-  positions: Record<string, WorkflowPosition>;
-}
-
 interface WorkflowDefinition {
+  /**
+   * The version of the shape the definition is written in, see
+   * [`SCHEMA_VERSION`]. Definitions sent without one are taken to be in
+   * the current shape.
+   */
+  schema_version: number;
   initial_state: string;
   states: Array<WorkflowState>;
+  metadata: WorkflowMetadata;
+  data_schema?: DataSchema;
+}
+
+interface WorkflowMetadata {
+  positions: Record<string, WorkflowPosition>;
 }
 
 interface WorkflowState {
@@ -110,8 +949,44 @@ interface WorkflowState {
   entry_actions: Array<WorkflowAction>;
   exit_actions: Array<WorkflowAction>;
   transitions: Array<WorkflowTransition>;
+  sla?: StateSla;
+}
+
+interface StateSla {
+  /**
+   * The number of seconds an instance may stay in the state before it is
+   * overdue.
+   */
+  due_in: number;
+  escalations: Array<StateEscalation>;
+}
+
+interface StateEscalation {
+  id: string;
+  /** The number of seconds past the due date before the escalation fires. */
+  after: number;
+  action: EscalationAction;
 }
 
+type EscalationAction =
+  | EscalationAction__Notify
+  | EscalationAction__Reassign
+  | EscalationAction__Transition;
+
+type EscalationAction__Notify = {
+  type: "Notify";
+  template_id: number;
+  target: NotifyTarget;
+};
+type EscalationAction__Reassign = {
+  type: "Reassign";
+  user_id: number;
+};
+type EscalationAction__Transition = {
+  type: "Transition";
+  target_state_id: string;
+};
+
 interface WorkflowAction {
   id: string;
   name: string;
@@ -123,10 +998,17 @@ type ActionDefinition =
   | ActionDefinition__AutoAssign
   | ActionDefinition__AssignTo
   | ActionDefinition__Email
-  | ActionDefinition__Notify;
+  | ActionDefinition__Notify
+  | ActionDefinition__Http;
 
 type ActionDefinition__AutoAssign = {
   type: "AutoAssign";
+  strategy: AutoAssignStrategy;
+  /**
+   * The users eligible for assignment. When empty, every active user in
+   * the instance's tenant is a candidate.
+   */
+  user_ids: Array<number>;
 };
 type ActionDefinition__AssignTo = {
   type: "AssignTo";
@@ -142,6 +1024,34 @@ type ActionDefinition__Notify = {
   template_id: number;
   target: NotifyTarget;
 };
+/**
+ * Calls an external endpoint. The URL, header values and body are
+ * templates rendered against the instance data, and the host must be one
+ * the tenant allows.
+ */
+type ActionDefinition__Http = {
+  type: "Http";
+  method: HttpMethod;
+  url: string;
+  headers: Record<string, string>;
+  body?: Value;
+  /**
+   * The key of the instance data the status and body of the response
+   * are stored under.
+   */
+  result_key?: string;
+  /**
+   * The state the instance moves to when the call fails. Without it, a
+   * failed call fails the transition that ran the action.
+   */
+  failure_state_id?: string;
+};
+
+type HttpMethod =
+  | "Get" | "Post" | "Put" | "Patch" | "Delete";
+
+type AutoAssignStrategy =
+  | "RoundRobin" | "LeastLoaded";
 
 type NotifyTarget =
   | NotifyTarget__Creator
@@ -170,11 +1080,19 @@ type TransitionDefinition =
   | TransitionDefinition__Automatic
   | TransitionDefinition__Approval
   | TransitionDefinition__Manual
-  | TransitionDefinition__VendorConfirmation;
+  | TransitionDefinition__VendorConfirmation
+  | TransitionDefinition__Parallel
+  | TransitionDefinition__SubWorkflow;
 
+/**
+ * Followed as soon as the state is entered. When a state has several
+ * automatic transitions they are tried in order, and the first one
+ * without a guard or whose guard holds for the instance data is taken.
+ */
 type TransitionDefinition__Automatic = {
   type: "Automatic";
   target_state_id: string;
+  guard?: string;
 };
 type TransitionDefinition__Approval = {
   type: "Approval";
@@ -190,6 +1108,33 @@ type TransitionDefinition__VendorConfirmation = {
   type: "VendorConfirmation";
   target_state_id: string;
 };
+/**
+ * Splits the instance into one branch per target state as soon as the
+ * state is entered. The branches progress independently, and the
+ * instance continues from the join state once every branch reaches it.
+ */
+type TransitionDefinition__Parallel = {
+  type: "Parallel";
+  branch_state_ids: Array<string>;
+  join_state_id: string;
+};
+/**
+ * Starts an instance of another workflow of the tenant as soon as the
+ * state is entered and waits for it to complete. The end state the child
+ * instance finishes in selects the option the instance leaves through.
+ */
+type TransitionDefinition__SubWorkflow = {
+  type: "SubWorkflow";
+  workflow_id: number;
+  options: Array<TransitionOption>;
+  outcomes: Array<SubWorkflowOutcome>;
+};
+
+/** Maps an end state of a sub-workflow to an option of the invoking state. */
+interface SubWorkflowOutcome {
+  end_state_id: string;
+  option_id: string;
+}
 
 interface TransitionOption {
   id: string;
@@ -205,17 +1150,102 @@ type TransitionOptionData =
   | TransitionOptionData__VendorId;
 
 type TransitionOptionData__Date = {
-  id: string;
   type: "Date";
+  id: string;
   label: string;
 };
 type TransitionOptionData__UserId = {
-  id: string;
   type: "UserId";
+  id: string;
   label: string;
 };
 type TransitionOptionData__VendorId = {
-  id: string;
   type: "VendorId";
+  id: string;
   label: string;
 };
+
+type GraphFormat =
+  | "dot" | "mermaid" | "svg";
+
+interface RenderQuery {
+  format: GraphFormat;
+}
+
+interface WorkflowTemplate {
+  id: number;
+  name: string;
+  description?: string;
+  definition: WorkflowDefinition;
+  created_at: Date;
+  updated_at: Date;
+  deleted_at?: Date;
+}
+
+interface NewWorkflowTemplate {
+  name: string;
+  description?: string;
+  definition: WorkflowDefinition;
+}
+
+interface UpdateWorkflowTemplate {
+  name?: string;
+  description?: string;
+  definition?: WorkflowDefinition;
+}
+
+type TemplateSort =
+  | "id" | "name" | "created_at" | "updated_at";
+
+interface TemplateQuery {
+  /** Part of the name, matched without regard to case. */
+  name?: string;
+  active: boolean;
+  sort: TemplateSort;
+  direction: SortDirection;
+  /**
+   * The cursor of the page to fetch, returned with the page before it.
+   * When set, `page` is ignored.
+   */
+  cursor?: string;
+  /** Whether to count the matching templates. */
+  count: boolean;
+  page: number;
+  page_size: number;
+}
+
+interface InstantiateTemplate {
+  /** The tenant to create the workflow in. */
+  tenant_id: number;
+  /** The name of the new workflow, the template's name when not set. */
+  name?: string;
+  /** The description of the new workflow, the template's when not set. */
+  description?: string;
+}
+
+interface CloneWorkflow {
+  /**
+   * The name of the copy, the workflow's name followed by \"(copy)\" when
+   * not set.
+   */
+  name?: string;
+  /** The version to copy, by default the current definition. */
+  version?: number;
+}
+
+/**
+ * A definition the workflow has had. Versions are numbered from 1, and a new
+ * one is recorded whenever the definition changes.
+ */
+interface WorkflowVersion {
+  id: number;
+  workflow_id: number;
+  version: number;
+  definition: WorkflowDefinition;
+  created_at: Date;
+}
+
+interface VersionDiffQuery {
+  /** The version to compare with, by default the one before. */
+  base?: number;
+}